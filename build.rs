@@ -0,0 +1,17 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the gRPC service and messages, with the protoc bundled by protoc-bin-vendored.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc");
+    std::env::set_var("PROTOC", protoc);
+    // the generated connect() needs the 2021 prelude, clients connect an Endpoint instead
+    tonic_build::configure()
+        .build_transport(false)
+        .compile_protos(&["proto/picontrol.proto"], &["proto"])
+        .expect("protoc failed");
+}
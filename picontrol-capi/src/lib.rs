@@ -0,0 +1,445 @@
+//! # picontrol-capi
+//!
+//! The C API of KUNBUS's `piControlIf.h`, implemented with the picontrol crate and built as
+//! `libpiControl.so` and `libpiControl.a`, the names of the C library. Programs written against
+//! `piControlIf.c` link against it without source changes.
+//!
+//! Like `piControlIf.c`, every function opens the backend on first use and returns a negative
+//! errno on failure, `-ENODEV` if the backend can not be opened. The backend is chosen by the
+//! environment when it is opened:
+//!
+//! * `PICONTROL_SIMULATE=config.rsc`: an in-process [`Emulator`] of the configuration, or a blank
+//!   process image without variables or devices if the value is empty. For tests of C programs
+//!   on machines without piControl.
+//! * `PICONTROL_SOCKET=path`: the `piemulator` listening on the Unix socket.
+//! * otherwise the piControl device, `/dev/piControl0`.
+//!
+//! Rust code, e.g. a test, can install any [`Driver`] with [`set_driver`].
+
+#![allow(non_upper_case_globals)]
+#![allow(non_snake_case)]
+
+use libc::{c_char, c_int, EFAULT, EINVAL, ENODEV, ENXIO, EOPNOTSUPP};
+use picontrol::config::Config;
+use picontrol::emulator::Emulator;
+use picontrol::socket::SocketDriver;
+use picontrol::{
+    Driver, Event, MemoryDriver, SDeviceInfo, SPIValue, SPIVariable, SharedRevPiControl,
+    KB_EVENT_RESET, PROCESS_IMAGE_SIZE, REV_PI_DEV_CNT_MAX,
+};
+use std::ffi::CStr;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The descriptor of the piControl device while it is open, -1 while closed.
+///
+/// Backends other than the device set it to `INT_MAX`, which is no valid descriptor.
+#[no_mangle]
+pub static PiControlHandle_g: AtomicI32 = AtomicI32::new(-1);
+
+struct Backend {
+    driver: Arc<dyn Driver>,
+    // the real device, for KB_GET_LAST_MESSAGE
+    device: Option<SharedRevPiControl>,
+}
+
+static BACKEND: Mutex<Option<Backend>> = Mutex::new(None);
+
+/// Replaces the backend with @driver, e.g. an [`Emulator`] in tests.
+pub fn set_driver(driver: Arc<dyn Driver>) {
+    install(Backend {
+        driver,
+        device: None,
+    });
+}
+
+fn install(backend: Backend) {
+    store(&mut BACKEND.lock().unwrap(), backend);
+}
+
+fn store(slot: &mut Option<Backend>, backend: Backend) {
+    let handle = match backend.device {
+        Some(ref device) => device.as_raw_fd(),
+        None => c_int::MAX,
+    };
+    *slot = Some(backend);
+    PiControlHandle_g.store(handle, Ordering::SeqCst);
+}
+
+fn open() -> io::Result<Backend> {
+    if let Ok(path) = std::env::var("PICONTROL_SIMULATE") {
+        let driver: Arc<dyn Driver> = if path.is_empty() {
+            Arc::new(MemoryDriver::new())
+        } else {
+            Arc::new(Config::load(&path).and_then(Emulator::from_config)?)
+        };
+        return Ok(Backend {
+            driver,
+            device: None,
+        });
+    }
+    if let Ok(path) = std::env::var("PICONTROL_SOCKET") {
+        return Ok(Backend {
+            driver: Arc::new(SocketDriver::connect(path)?),
+            device: None,
+        });
+    }
+    let device = SharedRevPiControl::open()?;
+    Ok(Backend {
+        driver: Arc::new(device.clone()),
+        device: Some(device),
+    })
+}
+
+/// The open backend's driver, opening it if needed. It is cloned out of the lock, so a
+/// blocking piControlWaitForEvent does not hold up other calls.
+fn driver() -> Option<Arc<dyn Driver>> {
+    let mut slot = BACKEND.lock().unwrap();
+    if slot.is_none() {
+        store(&mut slot, open().ok()?);
+    }
+    slot.as_ref().map(|backend| backend.driver.clone())
+}
+
+/// Runs @f on the driver, mapping errors to a negative errno.
+fn call<F>(f: F) -> c_int
+where
+    F: FnOnce(&dyn Driver) -> io::Result<c_int>,
+{
+    match driver() {
+        Some(driver) => f(&*driver).unwrap_or_else(|err| -errno(&err)),
+        None => -ENODEV,
+    }
+}
+
+/// The errno of @err; errors of the backends other than the device are mapped by kind.
+fn errno(err: &io::Error) -> c_int {
+    err.raw_os_error().unwrap_or(match err.kind() {
+        io::ErrorKind::NotFound => ENXIO,
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => EINVAL,
+        io::ErrorKind::PermissionDenied => libc::EACCES,
+        io::ErrorKind::Unsupported => EOPNOTSUPP,
+        _ => libc::EIO,
+    })
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Opens the backend if it is not open yet.
+#[no_mangle]
+pub extern "C" fn piControlOpen() {
+    driver();
+}
+
+/// Closes the backend; the next call opens it again.
+#[no_mangle]
+pub extern "C" fn piControlClose() {
+    *BACKEND.lock().unwrap() = None;
+    PiControlHandle_g.store(-1, Ordering::SeqCst);
+}
+
+/// Resets piControl, which reloads the configuration.
+#[no_mangle]
+pub extern "C" fn piControlReset() -> c_int {
+    call(|d| d.reset().map(|_| 0))
+}
+
+/// Blocks until the driver reports an event, returns its code.
+#[no_mangle]
+pub extern "C" fn piControlWaitForEvent() -> c_int {
+    call(|d| {
+        d.wait_for_event().map(|event| match event {
+            Event::Reset => KB_EVENT_RESET as c_int,
+            Event::Unknown(code) => code,
+        })
+    })
+}
+
+/// Reads @Length bytes of the process image at @Offset into @pData, returns the number of
+/// bytes read.
+///
+/// Like the driver, a read past the end of the process image is cut short and one starting
+/// behind it reads 0 bytes.
+///
+/// # Safety
+///
+/// @pData must be valid for writes of @Length bytes.
+#[no_mangle]
+pub unsafe extern "C" fn piControlRead(Offset: u32, Length: u32, pData: *mut u8) -> c_int {
+    if pData.is_null() {
+        return -EFAULT;
+    }
+    let available = (PROCESS_IMAGE_SIZE as u64).saturating_sub(u64::from(Offset));
+    let length = u64::from(Length).min(available) as usize;
+    if length == 0 {
+        return 0;
+    }
+    call(|d| {
+        let data = d.read(u64::from(Offset), length)?;
+        std::ptr::copy_nonoverlapping(data.as_ptr(), pData, data.len());
+        Ok(data.len() as c_int)
+    })
+}
+
+/// Writes @Length bytes of @pData to the process image at @Offset, returns the number of bytes
+/// written.
+///
+/// # Safety
+///
+/// @pData must be valid for reads of @Length bytes.
+#[no_mangle]
+pub unsafe extern "C" fn piControlWrite(Offset: u32, Length: u32, pData: *const u8) -> c_int {
+    if pData.is_null() {
+        return -EFAULT;
+    }
+    let data = std::slice::from_raw_parts(pData, Length as usize);
+    call(|d| d.write(u64::from(Offset), data).map(|_| Length as c_int))
+}
+
+/// Fills @pDev with the device of module type `i16uModuleType`, or at address `i8uAddress` if
+/// the type is 0.
+///
+/// # Safety
+///
+/// @pDev must point to a valid SDeviceInfo.
+#[no_mangle]
+pub unsafe extern "C" fn piControlGetDeviceInfo(pDev: *mut SDeviceInfo) -> c_int {
+    let dev = match pDev.as_mut() {
+        Some(dev) => dev,
+        None => return -EFAULT,
+    };
+    call(|d| {
+        let found = d.get_device_info_list()?.into_iter().find(|device| {
+            if dev.i16uModuleType != 0 {
+                device.i16uModuleType == dev.i16uModuleType
+            } else {
+                device.i8uAddress == dev.i8uAddress
+            }
+        });
+        match found {
+            Some(device) => {
+                *dev = device;
+                Ok(0)
+            }
+            None => Err(io::Error::from_raw_os_error(ENXIO)),
+        }
+    })
+}
+
+/// Fills @pDev with the connected devices, returns their number.
+///
+/// # Safety
+///
+/// @pDev must be valid for writes of REV_PI_DEV_CNT_MAX devices.
+#[no_mangle]
+pub unsafe extern "C" fn piControlGetDeviceInfoList(pDev: *mut SDeviceInfo) -> c_int {
+    if pDev.is_null() {
+        return -EFAULT;
+    }
+    call(|d| {
+        let devices = d.get_device_info_list()?;
+        let count = devices.len().min(REV_PI_DEV_CNT_MAX as usize);
+        std::ptr::copy_nonoverlapping(devices.as_ptr(), pDev, count);
+        Ok(count as c_int)
+    })
+}
+
+/// Gets the value of one bit into `i8uValue`; bits beyond 7 address the following bytes.
+///
+/// # Safety
+///
+/// @pSpiValue must point to a valid SPIValue.
+#[no_mangle]
+pub unsafe extern "C" fn piControlGetBitValue(pSpiValue: *mut SPIValue) -> c_int {
+    match normalized(pSpiValue) {
+        Ok(value) => call(|d| d.get_bit_value(value).map(|_| 0)),
+        Err(errno) => -errno,
+    }
+}
+
+/// Sets the value of one bit to `i8uValue`; bits beyond 7 address the following bytes.
+///
+/// # Safety
+///
+/// @pSpiValue must point to a valid SPIValue.
+#[no_mangle]
+pub unsafe extern "C" fn piControlSetBitValue(pSpiValue: *mut SPIValue) -> c_int {
+    match normalized(pSpiValue) {
+        Ok(value) => call(|d| d.set_bit_value(value).map(|_| 0)),
+        Err(errno) => -errno,
+    }
+}
+
+// Moves bits beyond 7 to the following bytes, failing with the errno if that is impossible.
+unsafe fn normalized<'a>(value: *mut SPIValue) -> Result<&'a mut SPIValue, c_int> {
+    let value = value.as_mut().ok_or(EFAULT)?;
+    value.i16uAddress = value
+        .i16uAddress
+        .checked_add(u16::from(value.i8uBit / 8))
+        .ok_or(EINVAL)?;
+    value.i8uBit %= 8;
+    Ok(value)
+}
+
+/// Fills @pSpiVariable with the address, bit and length of the variable named in it.
+///
+/// # Safety
+///
+/// @pSpiVariable must point to a valid SPIVariable.
+#[no_mangle]
+pub unsafe extern "C" fn piControlGetVariableInfo(pSpiVariable: *mut SPIVariable) -> c_int {
+    let variable = match pSpiVariable.as_mut() {
+        Some(variable) => variable,
+        None => return -EFAULT,
+    };
+    call(|d| {
+        let name = variable
+            .name()
+            .map_err(|_| invalid_input("invalid variable name"))?;
+        *variable = d.get_variable_info(name)?;
+        Ok(0)
+    })
+}
+
+/// Returns the address of the variable @name, names are cut to 31 characters.
+///
+/// # Safety
+///
+/// @name must be a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn piControlFindVariable(name: *const c_char) -> c_int {
+    if name.is_null() {
+        return -EFAULT;
+    }
+    let name = CStr::from_ptr(name).to_bytes();
+    let name = &name[..name.len().min(31)];
+    call(|d| {
+        let name = std::str::from_utf8(name).map_err(|_| invalid_input("invalid variable name"))?;
+        Ok(c_int::from(d.get_variable_info(name)?.i16uAddress))
+    })
+}
+
+/// Sets the counters or encoders selected by @bitfield of the DIO/DI module at @address to 0.
+///
+/// Like `piControlIf.c`, a failure is printed and reported as -1.
+#[no_mangle]
+pub extern "C" fn piControlResetCounter(address: c_int, bitfield: c_int) -> c_int {
+    call(|d| {
+        let result = d.reset_counter(address as u8, bitfield as u16);
+        if let Err(ref err) = result {
+            eprintln!("Counter reset not possible: {}", err);
+        }
+        Ok(if result.is_ok() { 0 } else { -1 })
+    })
+}
+
+/// Updates the firmware of modules, which this implementation does not support.
+#[no_mangle]
+pub extern "C" fn piControlUpdateFirmware(_addr_p: u32) -> c_int {
+    call(|_| Err(io::Error::from_raw_os_error(EOPNOTSUPP)))
+}
+
+/// Stops the I/O exchange if @stop is 1, restarts it if 0, returns whether it is stopped now.
+///
+/// Like `piControlIf.c`, a failure is printed and reported as -1.
+#[no_mangle]
+pub extern "C" fn piControlStopIO(stop: c_int) -> c_int {
+    call(|d| match d.stop_io(stop != 0) {
+        Ok(stopped) => Ok(stopped as c_int),
+        Err(err) => {
+            eprintln!("ioctl(KB_STOP_IO) returned error: {}", err);
+            Ok(-1)
+        }
+    })
+}
+
+/// Prints the message piControl left for the last call, if any.
+#[no_mangle]
+pub extern "C" fn piShowLastMessage() {
+    if let Some(ref backend) = *BACKEND.lock().unwrap() {
+        if let Some(Ok(Some(msg))) = backend.device.as_ref().map(|d| d.last_message()) {
+            println!("{}", msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn c_api() {
+        let config = concat!(env!("CARGO_MANIFEST_DIR"), "/../testdata/config.rsc");
+        std::env::set_var("PICONTROL_SIMULATE", config);
+        let name = CString::new("O_10").unwrap();
+        unsafe {
+            assert_eq!(piControlFindVariable(name.as_ptr()), 78);
+            assert_eq!(PiControlHandle_g.load(Ordering::SeqCst), c_int::MAX);
+
+            let mut variable = SPIVariable::default();
+            for (c, &b) in variable.strVarName.iter_mut().zip(b"O_10") {
+                *c = b as c_char;
+            }
+            assert_eq!(piControlGetVariableInfo(&mut variable), 0);
+            assert_eq!((variable.i16uAddress, variable.i8uBit), (78, 1));
+            let missing = CString::new("missing").unwrap();
+            assert!(piControlFindVariable(missing.as_ptr()) < 0);
+
+            // bit 9 of 77 is bit 1 of 78
+            let mut value = SPIValue {
+                i16uAddress: 77,
+                i8uBit: 9,
+                i8uValue: 1,
+            };
+            assert_eq!(piControlSetBitValue(&mut value), 0);
+            let mut data = [0u8; 2];
+            assert_eq!(piControlRead(77, 2, data.as_mut_ptr()), 2);
+            assert_eq!(data, [0, 2]);
+            assert_eq!(piControlWrite(77, 1, [0x81].as_ptr()), 1);
+            let mut value = SPIValue {
+                i16uAddress: 77,
+                i8uBit: 7,
+                ..Default::default()
+            };
+            assert_eq!(piControlGetBitValue(&mut value), 0);
+            assert_eq!(value.i8uValue, 1);
+            let mut beyond = SPIValue {
+                i16uAddress: u16::MAX,
+                i8uBit: 8,
+                ..Default::default()
+            };
+            assert_eq!(piControlGetBitValue(&mut beyond), -EINVAL);
+
+            // like the driver, reads are cut at the end of the process image
+            let mut data = [0u8; 4];
+            let end = PROCESS_IMAGE_SIZE as u32;
+            assert_eq!(piControlRead(end - 2, 4, data.as_mut_ptr()), 2);
+            assert_eq!(piControlRead(end, 4, data.as_mut_ptr()), 0);
+
+            let mut devices = [SDeviceInfo::default(); REV_PI_DEV_CNT_MAX as usize];
+            assert_eq!(piControlGetDeviceInfoList(devices.as_mut_ptr()), 3);
+            let mut dio = SDeviceInfo {
+                i8uAddress: 32,
+                ..Default::default()
+            };
+            assert_eq!(piControlGetDeviceInfo(&mut dio), 0);
+            assert_eq!(dio.i16uModuleType, 96);
+        }
+        assert_eq!(piControlStopIO(1), 1);
+        assert_eq!(piControlUpdateFirmware(0), -EOPNOTSUPP);
+        piControlClose();
+        assert_eq!(PiControlHandle_g.load(Ordering::SeqCst), -1);
+
+        let memory = Arc::new(MemoryDriver::new());
+        set_driver(memory.clone());
+        memory.push_event(Event::Reset);
+        assert_eq!(piControlWaitForEvent(), KB_EVENT_RESET as c_int);
+        assert_eq!(piControlResetCounter(32, 1), -1);
+        piControlClose();
+    }
+}
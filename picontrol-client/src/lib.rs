@@ -0,0 +1,469 @@
+//! # picontrol-client
+//!
+//! A client of the gRPC service of the picontrol crate, served by its `pigrpc` binary or by
+//! `picontrol::grpc::GrpcService`.
+//!
+//! [`Client`] offers the operations of `proto/picontrol.proto` as futures taking and returning
+//! the types of picontrol. [`RemoteDriver`] implements `picontrol::Driver` on top of it, so code
+//! written against a local driver, e.g. `Variable::read` or a `Mapping`, runs unchanged against
+//! a remote RevPi.
+//!
+//! A service started with a token, e.g. `pigrpc --token`, needs the same token on the client,
+//! see [`Client::token`] and [`RemoteDriver::token`].
+
+use picontrol::grpc::proto::pi_control_client::PiControlClient;
+use picontrol::grpc::{from_status, proto};
+use picontrol::{Driver, Event, SDeviceInfo, SPIValue, SPIVariable, Value};
+use std::convert::TryFrom;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Status, Streaming};
+
+pub use picontrol;
+
+/// Client calls the gRPC service of a RevPi.
+///
+/// It is cheap to clone; clones share the same connection.
+#[derive(Clone)]
+pub struct Client {
+    channel: Channel,
+    inner: PiControlClient<InterceptedService<Channel, Bearer>>,
+}
+
+/// Bearer adds the authorization metadata of a token to every call.
+#[derive(Clone)]
+struct Bearer(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for Bearer {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(value) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
+
+impl Client {
+    /// Connects to the service at @url, e.g. "http://revpi:50051".
+    pub async fn connect(url: &str) -> io::Result<Self> {
+        let endpoint = Endpoint::from_shared(url.to_owned())
+            .map_err(|e| invalid_input(format!("invalid url {}: {}", url, e)))?;
+        let channel = endpoint
+            .connect()
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e.to_string()))?;
+        Ok(Client {
+            inner: PiControlClient::with_interceptor(channel.clone(), Bearer(None)),
+            channel,
+        })
+    }
+
+    /// Sends @token as bearer token with every call.
+    pub fn token(self, token: &str) -> io::Result<Self> {
+        let value = format!("Bearer {}", token)
+            .parse()
+            .map_err(|_| invalid_input("the token is not printable ASCII".to_owned()))?;
+        Ok(Client {
+            inner: PiControlClient::with_interceptor(self.channel.clone(), Bearer(Some(value))),
+            channel: self.channel,
+        })
+    }
+
+    /// Gets a description of connected devices.
+    pub async fn devices(&self) -> io::Result<Vec<SDeviceInfo>> {
+        let response = self
+            .inner
+            .clone()
+            .list_devices(proto::ListDevicesRequest {})
+            .await
+            .map_err(io_error)?;
+        response
+            .get_ref()
+            .devices
+            .iter()
+            .map(SDeviceInfo::try_from)
+            .collect()
+    }
+
+    /// Get the info for a variable.
+    pub async fn variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        let response = self
+            .inner
+            .clone()
+            .get_variable_info(proto::GetVariableInfoRequest {
+                name: name.to_owned(),
+            })
+            .await
+            .map_err(io_error)?;
+        SPIVariable::try_from(response.get_ref())
+    }
+
+    /// Reads the variable @name, typed by its length.
+    pub async fn read_variable(&self, name: &str) -> io::Result<Value> {
+        let response = self
+            .inner
+            .clone()
+            .read_variable(proto::ReadVariableRequest {
+                name: name.to_owned(),
+            })
+            .await
+            .map_err(io_error)?;
+        value(response.get_ref())
+    }
+
+    /// Writes @value to the variable @name, the service converts it to the variable's type.
+    pub async fn write_variable(&self, name: &str, value: Value) -> io::Result<()> {
+        self.inner
+            .clone()
+            .write_variable(proto::VariableValue {
+                name: name.to_owned(),
+                value: Some(value.into()),
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Reads the variables @names from one snapshot of the process image, in the same order.
+    pub async fn read_variables(&self, names: &[&str]) -> io::Result<Vec<Value>> {
+        let response = self
+            .inner
+            .clone()
+            .read_variables(proto::ReadVariablesRequest {
+                names: names.iter().map(|&name| name.to_owned()).collect(),
+            })
+            .await
+            .map_err(io_error)?;
+        response.get_ref().values.iter().map(value).collect()
+    }
+
+    /// Reads @length bytes of process data starting at @offset.
+    pub async fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let length = u32::try_from(length)
+            .map_err(|_| invalid_input(format!("length {} is too long", length)))?;
+        let response = self
+            .inner
+            .clone()
+            .read(proto::ReadRequest { offset, length })
+            .await
+            .map_err(io_error)?;
+        Ok(response.into_inner().data)
+    }
+
+    /// Writes process data at a specific position.
+    pub async fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.inner
+            .clone()
+            .write(proto::WriteRequest {
+                offset,
+                data: data.to_vec(),
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Gets the value of @bit of the byte at @address.
+    pub async fn get_bit(&self, address: u16, bit: u8) -> io::Result<bool> {
+        let response = self
+            .inner
+            .clone()
+            .get_bit(proto::GetBitRequest {
+                address: u32::from(address),
+                bit: u32::from(bit),
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(response.get_ref().value)
+    }
+
+    /// Sets @bit of the byte at @address to @value.
+    pub async fn set_bit(&self, address: u16, bit: u8, value: bool) -> io::Result<()> {
+        self.inner
+            .clone()
+            .set_bit(proto::SetBitRequest {
+                address: u32::from(address),
+                bit: u32::from(bit),
+                value,
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Reads the whole process image.
+    pub async fn snapshot(&self) -> io::Result<Snapshot> {
+        let response = self
+            .inner
+            .clone()
+            .snapshot(proto::SnapshotRequest {})
+            .await
+            .map_err(io_error)?
+            .into_inner();
+        Ok(Snapshot {
+            image: response.image,
+            time: time(response.time_ms),
+        })
+    }
+
+    /// Reset Pi Control Interface.
+    pub async fn reset(&self) -> io::Result<()> {
+        self.inner
+            .clone()
+            .reset(proto::ResetRequest {})
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Stops or restarts the I/O update, returns whether it is stopped now.
+    pub async fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        let response = self
+            .inner
+            .clone()
+            .stop_io(proto::StopIoRequest { stop })
+            .await
+            .map_err(io_error)?;
+        Ok(response.get_ref().stopped)
+    }
+
+    /// Sets the counters or encoders selected by @bitfield of the DIO/DI module at @address
+    /// to 0.
+    pub async fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        self.inner
+            .clone()
+            .reset_counter(proto::ResetCounterRequest {
+                address: u32::from(address),
+                bitfield: u32::from(bitfield),
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Streams the events of the driver from now on.
+    pub async fn events(&self) -> io::Result<Events> {
+        let response = self
+            .inner
+            .clone()
+            .watch_events(proto::WatchEventsRequest {})
+            .await
+            .map_err(io_error)?;
+        Ok(Events {
+            stream: response.into_inner(),
+        })
+    }
+
+    /// Streams the variables @names, read every @interval but at most as often as the service
+    /// allows: first all of them, then those that changed.
+    pub async fn watch(&self, names: &[&str], interval: Duration) -> io::Result<Changes> {
+        let interval_ms = u32::try_from(interval.as_millis()).unwrap_or(u32::MAX);
+        let response = self
+            .inner
+            .clone()
+            .watch_variables(proto::WatchVariablesRequest {
+                names: names.iter().map(|&name| name.to_owned()).collect(),
+                interval_ms,
+            })
+            .await
+            .map_err(io_error)?;
+        Ok(Changes {
+            stream: response.into_inner(),
+        })
+    }
+}
+
+/// Snapshot is a copy of the whole process image.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub image: Vec<u8>,
+    /// When the service read the image.
+    pub time: SystemTime,
+}
+
+/// Events yields the events reported by the remote driver.
+pub struct Events {
+    stream: Streaming<proto::Event>,
+}
+
+impl Events {
+    /// Waits for the next event, `None` once the service ended the stream.
+    pub async fn next(&mut self) -> Option<io::Result<Event>> {
+        self.stream
+            .message()
+            .await
+            .map_err(io_error)
+            .transpose()
+            .map(|event| event.map(|event| Event::from(&event)))
+    }
+}
+
+/// VariableChange is one update of a [`Changes`] stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableChange {
+    /// When the service read the process image.
+    pub time: SystemTime,
+    /// The names and values of the variables that changed.
+    pub values: Vec<(String, Value)>,
+}
+
+/// Changes yields the updates of the variables watched with [`Client::watch`].
+pub struct Changes {
+    stream: Streaming<proto::VariableChanges>,
+}
+
+impl Changes {
+    /// Waits for the next update, `None` once the service ended the stream.
+    pub async fn next(&mut self) -> Option<io::Result<VariableChange>> {
+        let changes = match self.stream.message().await {
+            Ok(changes) => changes?,
+            Err(status) => return Some(Err(io_error(status))),
+        };
+        let values = changes
+            .values
+            .iter()
+            .map(|v| value(v).map(|value| (v.name.clone(), value)))
+            .collect::<io::Result<_>>();
+        Some(values.map(|values| VariableChange {
+            time: time(changes.time_ms),
+            values,
+        }))
+    }
+}
+
+/// RemoteDriver is a [`Driver`] whose operations are calls of the gRPC service.
+///
+/// It runs the calls on a runtime of its own and must therefore not be used from inside an
+/// async runtime; use [`Client`] there.
+pub struct RemoteDriver {
+    runtime: tokio::runtime::Runtime,
+    client: Client,
+    // opened by the first wait_for_event, so no event in between calls is missed
+    events: Mutex<Option<Events>>,
+}
+
+impl RemoteDriver {
+    /// Connects to the service at @url, e.g. "http://revpi:50051".
+    pub fn connect(url: &str) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let client = runtime.block_on(Client::connect(url))?;
+        Ok(RemoteDriver {
+            runtime,
+            client,
+            events: Mutex::new(None),
+        })
+    }
+
+    /// Sends @token as bearer token with every call.
+    pub fn token(mut self, token: &str) -> io::Result<Self> {
+        self.client = self.client.token(token)?;
+        Ok(self)
+    }
+
+    /// The async client the driver calls.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Driver for RemoteDriver {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        self.runtime.block_on(self.client.read(offset, length))
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.runtime.block_on(self.client.write(offset, data))
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        self.runtime.block_on(self.client.variable_info(name))
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        self.runtime.block_on(self.client.devices())
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        let bit = self
+            .runtime
+            .block_on(self.client.get_bit(value.i16uAddress, value.i8uBit))?;
+        value.i8uValue = bit as u8;
+        Ok(true)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        self.runtime.block_on(self.client.set_bit(
+            value.i16uAddress,
+            value.i8uBit,
+            value.i8uValue != 0,
+        ))?;
+        Ok(true)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        self.runtime.block_on(self.client.reset())
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        self.runtime
+            .block_on(self.client.reset_counter(address, bitfield))
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        self.runtime.block_on(self.client.stop_io(stop))
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        let mut events = self.events.lock().unwrap();
+        if events.is_none() {
+            *events = Some(self.runtime.block_on(self.client.events())?);
+        }
+        let next = self.runtime.block_on(events.as_mut().unwrap().next());
+        match next {
+            Some(Ok(event)) => Ok(event),
+            Some(Err(err)) => {
+                *events = None;
+                Err(err)
+            }
+            None => {
+                *events = None;
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the service ended the event stream",
+                ))
+            }
+        }
+    }
+}
+
+fn value(value: &proto::VariableValue) -> io::Result<Value> {
+    let v = value.value.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no value for {}", value.name),
+        )
+    })?;
+    Value::try_from(v)
+}
+
+fn time(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+fn io_error(status: Status) -> io::Error {
+    from_status(&status)
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
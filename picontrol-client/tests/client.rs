@@ -0,0 +1,123 @@
+use picontrol::grpc::GrpcService;
+use picontrol::{Driver, Event, MemoryDriver, SDeviceInfo, Value, Variable};
+use picontrol_client::{Client, RemoteDriver};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Serves @memory on a runtime of its own, for clients outside of any runtime.
+fn serve(memory: Arc<MemoryDriver>) -> SocketAddr {
+    serve_service(GrpcService::from_arc(memory))
+}
+
+fn serve_service(service: GrpcService<MemoryDriver>) -> SocketAddr {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = runtime
+        .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || runtime.block_on(service.serve(listener)).unwrap());
+    addr
+}
+
+fn memory() -> Arc<MemoryDriver> {
+    let memory = Arc::new(MemoryDriver::new());
+    memory.add_variable("I_1", 0, 0, 1);
+    memory.add_variable("Temperature", 2, 0, 16);
+    memory.add_device(SDeviceInfo {
+        i8uAddress: 32,
+        i16uModuleType: 96,
+        i16uInputOffset: 100,
+        i16uInputLength: 70,
+        ..Default::default()
+    });
+    memory
+}
+
+#[test]
+fn remote_driver() {
+    let memory = memory();
+    let addr = serve(memory.clone());
+    let driver = RemoteDriver::connect(&format!("http://{}", addr)).unwrap();
+
+    let temperature = Variable::resolve(&driver, "Temperature").unwrap();
+    temperature.write(&driver, Value::U16(215)).unwrap();
+    assert_eq!(memory.read(2, 2).unwrap(), vec![215, 0]);
+    let input = Variable::resolve(&driver, "I_1").unwrap();
+    memory.write(0, &[1]).unwrap();
+    assert_eq!(input.read(&driver).unwrap(), Value::Bool(true));
+
+    let err = driver.get_variable_info("missing").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let devices = driver.get_device_info_list().unwrap();
+    assert_eq!(devices[0].i8uAddress, 32);
+    assert!(driver.stop_io(true).unwrap());
+    assert!(memory.io_stopped());
+
+    let waiter = std::thread::spawn(move || driver.wait_for_event());
+    // the stream is open once the service sees the call, keep resetting until it arrives
+    while !waiter.is_finished() {
+        memory.push_event(Event::Reset);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(waiter.join().unwrap().unwrap(), Event::Reset);
+}
+
+#[test]
+fn async_client() {
+    let addr = serve(memory());
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let client = Client::connect(&format!("http://{}", addr)).await.unwrap();
+        let mut changes = client
+            .watch(&["I_1", "Temperature"], Duration::from_millis(10))
+            .await
+            .unwrap();
+        let first = changes.next().await.unwrap().unwrap();
+        assert_eq!(
+            first.values,
+            vec![
+                ("I_1".to_owned(), Value::Bool(false)),
+                ("Temperature".to_owned(), Value::U16(0)),
+            ]
+        );
+
+        client
+            .write_variable("Temperature", Value::F32(20.4))
+            .await
+            .unwrap();
+        let changed = changes.next().await.unwrap().unwrap();
+        assert_eq!(
+            changed.values,
+            vec![("Temperature".to_owned(), Value::U16(20))]
+        );
+        assert_eq!(
+            client
+                .read_variables(&["Temperature", "I_1"])
+                .await
+                .unwrap(),
+            vec![Value::U16(20), Value::Bool(false)]
+        );
+        let snapshot = client.snapshot().await.unwrap();
+        assert_eq!(snapshot.image[2], 20);
+        client.reset_counter(32, 1).await.unwrap();
+    });
+}
+
+#[test]
+fn token() {
+    let addr = serve_service(GrpcService::from_arc(memory()).token("secret"));
+    let url = format!("http://{}", addr);
+
+    let driver = RemoteDriver::connect(&url).unwrap();
+    let err = driver.read(0, 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let driver = RemoteDriver::connect(&url).unwrap().token("wrong").unwrap();
+    assert!(driver.read(0, 1).is_err());
+    let driver = RemoteDriver::connect(&url)
+        .unwrap()
+        .token("secret")
+        .unwrap();
+    assert_eq!(driver.read(0, 1).unwrap(), vec![0]);
+}
@@ -0,0 +1,128 @@
+//! # picontrol-derive
+//!
+//! `#[derive(ProcessImage)]` for the picontrol crate, enabled there with the `derive` feature.
+//!
+//! Every field of the struct maps to one piCtory variable:
+//!
+//! * `#[picontrol(name = "I_1")]` names the variable, without it the field name is used.
+//! * `#[picontrol(output)]` marks a field that `Mapping::write` writes back.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+#[proc_macro_derive(ProcessImage, attributes(picontrol))]
+pub fn derive_process_image(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct FieldAttrs {
+    name: Option<LitStr>,
+    output: bool,
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs {
+        name: None,
+        output: false,
+    };
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("picontrol"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                attrs.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("output") {
+                attrs.output = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"` or `output`"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "ProcessImage needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "ProcessImage can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut specs = Vec::new();
+    let mut decodes = Vec::new();
+    let mut encodes = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let attrs = field_attrs(field)?;
+        let field_name = ident.to_string();
+        let name = attrs
+            .name
+            .unwrap_or_else(|| LitStr::new(&field_name, ident.span()));
+        if name.value().len() >= 32 {
+            return Err(syn::Error::new_spanned(
+                name,
+                "piCtory variable names are at most 31 characters",
+            ));
+        }
+        let output = attrs.output;
+        specs.push(quote! {
+            ::picontrol::mapping::Field {
+                field: #field_name,
+                name: #name,
+                kind: <#ty as ::picontrol::mapping::FieldType>::KIND,
+                output: #output,
+            }
+        });
+        decodes.push(quote! {
+            #ident: ::picontrol::mapping::decode_field(&variables[#index], image)?
+        });
+        encodes.push(quote! {
+            ::picontrol::mapping::FieldType::to_value(&self.#ident)
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::picontrol::mapping::ProcessImage for #ident #ty_generics #where_clause {
+            const FIELDS: &'static [::picontrol::mapping::Field] = &[#(#specs),*];
+
+            fn decode(
+                variables: &[::picontrol::Variable],
+                image: &[u8],
+            ) -> ::std::io::Result<Self> {
+                ::std::result::Result::Ok(#ident {
+                    #(#decodes),*
+                })
+            }
+
+            fn encode(&self) -> ::std::vec::Vec<::picontrol::Value> {
+                ::std::vec![#(#encodes),*]
+            }
+        }
+    })
+}
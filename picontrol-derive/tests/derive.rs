@@ -0,0 +1,59 @@
+use picontrol::config::Config;
+use picontrol::{Driver, Mapping, MemoryDriver, ProcessImage};
+
+#[derive(ProcessImage, Debug, PartialEq)]
+struct Station {
+    #[picontrol(name = "I_1")]
+    start: bool,
+    #[picontrol(name = "RTDValue_1")]
+    temperature: i16,
+    #[picontrol(name = "Counter_2")]
+    pieces: u32,
+    #[picontrol(name = "O_2", output)]
+    heater: bool,
+    #[picontrol(name = "OutputValue_1", output)]
+    setpoint: i16,
+}
+
+#[derive(ProcessImage)]
+struct Mismatch {
+    #[picontrol(name = "RTDValue_1")]
+    _temperature: u8,
+}
+
+const CONFIG: &str = include_str!("../../testdata/config.rsc");
+
+#[test]
+fn derived_struct_round_trip() {
+    let config = Config::parse(CONFIG).unwrap();
+    let driver = MemoryDriver::new();
+    driver.write(7, &[0b1]).unwrap();
+    driver.write(114, &215i16.to_le_bytes()).unwrap();
+    driver.write(17, &12u32.to_le_bytes()).unwrap();
+
+    let mapping = Mapping::<Station>::from_config(&config).unwrap();
+    let mut station = mapping.read(&driver).unwrap();
+    assert_eq!(
+        station,
+        Station {
+            start: true,
+            temperature: 215,
+            pieces: 12,
+            heater: false,
+            setpoint: 0,
+        }
+    );
+
+    station.heater = true;
+    station.setpoint = -1000;
+    station.pieces = 0;
+    mapping.write(&driver, &station).unwrap();
+    assert_eq!(driver.read(77, 1).unwrap(), vec![0b10]);
+    assert_eq!(
+        driver.read(122, 2).unwrap(),
+        (-1000i16).to_le_bytes().to_vec()
+    );
+    assert_eq!(driver.read(17, 4).unwrap(), 12u32.to_le_bytes().to_vec());
+
+    assert!(Mapping::<Mismatch>::from_config(&config).is_err());
+}
@@ -0,0 +1,406 @@
+//! Typed access to the RevPi AIO module.
+//!
+//! The AIO module has four analog inputs, two RTD inputs and two analog outputs. Its input
+//! block holds, relative to the module's input offset:
+//!
+//! | offset | content                       |
+//! |--------|-------------------------------|
+//! | 0..8   | InputValue_1..4, INT16 each   |
+//! | 8..12  | InputStatus_1..4              |
+//! | 12..16 | RTDValue_1..2, INT16 each     |
+//! | 16..18 | RTDStatus_1..2                |
+//! | 18..20 | OutputStatus_1..2             |
+//!
+//! and its output block OutputValue_1..2, INT16 each. The offsets of the blocks are taken from
+//! the module's SDeviceInfo, so no piCtory variable names are needed.
+
+use crate::driver::Driver;
+use crate::find_module;
+use crate::picontrol::SDeviceInfo;
+use crate::value::{Quantity, Scaling, Variable, VariableType};
+use std::io;
+
+/// Module type of the RevPi AIO.
+pub const MODULE_TYPE: u32 = 103;
+/// Number of analog inputs.
+pub const INPUTS: usize = 4;
+/// Number of RTD inputs.
+pub const RTDS: usize = 2;
+/// Number of analog outputs.
+pub const OUTPUTS: usize = 2;
+
+const INPUT_STATUS_OFFSET: u16 = 8;
+const RTD_VALUE_OFFSET: u16 = 12;
+const RTD_STATUS_OFFSET: u16 = 16;
+const OUTPUT_STATUS_OFFSET: u16 = 18;
+const INPUT_LENGTH: usize = 20;
+
+/// How the values of one channel are presented.
+///
+/// The module itself already delivers inputs in mV or µA and RTD values in 0.1 °C, after the
+/// Multiplier/Divisor/Offset configured in piCtory. The scaling here is applied on top of that,
+/// e.g. to turn a 4-20 mA signal into percent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelConfig {
+    pub unit: String,
+    pub scaling: Scaling,
+}
+
+impl ChannelConfig {
+    /// A voltage range, values in mV.
+    pub fn voltage() -> Self {
+        Self::new("mV", Scaling::default())
+    }
+
+    /// A current range, values in µA.
+    pub fn current() -> Self {
+        Self::new("µA", Scaling::default())
+    }
+
+    /// A Pt100 or Pt1000 sensor, values in °C.
+    pub fn temperature() -> Self {
+        Self::new(
+            "°C",
+            Scaling::new(1.0, 10.0, 0.0).expect("0.1 °C steps are a valid scaling"),
+        )
+    }
+
+    pub fn new(unit: &str, scaling: Scaling) -> Self {
+        ChannelConfig {
+            unit: unit.to_owned(),
+            scaling,
+        }
+    }
+}
+
+/// The configuration of all channels of an AIO module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AioConfig {
+    pub inputs: [ChannelConfig; INPUTS],
+    pub rtds: [ChannelConfig; RTDS],
+    pub outputs: [ChannelConfig; OUTPUTS],
+}
+
+impl Default for AioConfig {
+    /// Voltage on all inputs and outputs, which is the module's factory setting.
+    fn default() -> Self {
+        AioConfig {
+            inputs: [
+                ChannelConfig::voltage(),
+                ChannelConfig::voltage(),
+                ChannelConfig::voltage(),
+                ChannelConfig::voltage(),
+            ],
+            rtds: [ChannelConfig::temperature(), ChannelConfig::temperature()],
+            outputs: [ChannelConfig::voltage(), ChannelConfig::voltage()],
+        }
+    }
+}
+
+/// The status byte of an analog or RTD input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStatus(pub u8);
+
+impl InputStatus {
+    /// The signal is below the measuring range, for RTDs a short circuit.
+    pub fn underflow(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// The signal is above the measuring range, for RTDs an open circuit.
+    pub fn overflow(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    /// The module could not talk to the channel's converter.
+    pub fn communication_error(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    /// No error flag is set.
+    pub fn is_ok(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The status byte of an analog output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputStatus(pub u8);
+
+impl OutputStatus {
+    /// The output driver is overheated.
+    pub fn temperature_error(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// Open load on a current output.
+    pub fn open_load(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    /// The output driver reports an internal error.
+    pub fn internal_error(self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    /// The requested value is outside of the configured range.
+    pub fn range_error(self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    /// The supply voltage is too low.
+    pub fn supply_too_low(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// The module could not talk to the output driver.
+    pub fn communication_error(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    /// No error flag is set.
+    pub fn is_ok(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The value of an input channel together with its status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub raw: i16,
+    pub value: Quantity,
+    pub status: InputStatus,
+}
+
+/// All inputs of the module, taken from one read of the input block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub inputs: Vec<Reading>,
+    pub rtds: Vec<Reading>,
+    pub output_status: Vec<OutputStatus>,
+}
+
+/// Aio gives channel-indexed access to one AIO module. Channels are numbered from 1, as in
+/// piCtory.
+pub struct Aio<D> {
+    driver: D,
+    device: SDeviceInfo,
+    config: AioConfig,
+}
+
+impl<D: Driver> Aio<D> {
+    /// Finds the AIO module at @address, or the first one if @address is None.
+    pub fn find(driver: D, address: Option<u8>, config: AioConfig) -> io::Result<Self> {
+        let devices = driver.get_device_info_list()?;
+        let device = find_module(&devices, &[MODULE_TYPE], address)?;
+        Ok(Aio {
+            driver,
+            device,
+            config,
+        })
+    }
+
+    /// The module's entry in the device list.
+    pub fn device(&self) -> &SDeviceInfo {
+        &self.device
+    }
+
+    /// The channel configuration.
+    pub fn config(&self) -> &AioConfig {
+        &self.config
+    }
+
+    /// Reads analog input @channel (1-4).
+    pub fn input(&self, channel: usize) -> io::Result<Reading> {
+        let index = channel_index(channel, INPUTS)?;
+        let data = self.read_inputs()?;
+        self.input_reading(&data, index)
+    }
+
+    /// Reads RTD input @channel (1-2).
+    pub fn rtd(&self, channel: usize) -> io::Result<Reading> {
+        let index = channel_index(channel, RTDS)?;
+        let data = self.read_inputs()?;
+        self.rtd_reading(&data, index)
+    }
+
+    /// Reads the status of analog output @channel (1-2).
+    pub fn output_status(&self, channel: usize) -> io::Result<OutputStatus> {
+        let index = channel_index(channel, OUTPUTS)?;
+        let offset = address(
+            self.device.i16uInputOffset,
+            OUTPUT_STATUS_OFFSET + index as u16,
+        )?;
+        let data = self.driver.read(u64::from(offset), 1)?;
+        Ok(OutputStatus(data[0]))
+    }
+
+    /// Reads all inputs and status bytes at once, so they come from the same cycle.
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        let data = self.read_inputs()?;
+        Ok(Snapshot {
+            inputs: (0..INPUTS)
+                .map(|i| self.input_reading(&data, i))
+                .collect::<io::Result<_>>()?,
+            rtds: (0..RTDS)
+                .map(|i| self.rtd_reading(&data, i))
+                .collect::<io::Result<_>>()?,
+            output_status: (0..OUTPUTS)
+                .map(|i| OutputStatus(data[OUTPUT_STATUS_OFFSET as usize + i]))
+                .collect(),
+        })
+    }
+
+    /// Reads back the value of analog output @channel (1-2).
+    pub fn output(&self, channel: usize) -> io::Result<Quantity> {
+        self.output_variable(channel)?.read_quantity(&self.driver)
+    }
+
+    /// Sets analog output @channel (1-2) to @value, given in the channel's unit.
+    pub fn set_output(&self, channel: usize, value: f64) -> io::Result<()> {
+        self.output_variable(channel)?
+            .write_quantity(&self.driver, value)
+    }
+
+    fn read_inputs(&self) -> io::Result<Vec<u8>> {
+        self.driver
+            .read(u64::from(self.device.i16uInputOffset), INPUT_LENGTH)
+    }
+
+    fn input_reading(&self, data: &[u8], index: usize) -> io::Result<Reading> {
+        let offset = 2 * index as u16;
+        reading(
+            data,
+            offset,
+            INPUT_STATUS_OFFSET + index as u16,
+            &format!("InputValue_{}", index + 1),
+            &self.config.inputs[index],
+        )
+    }
+
+    fn rtd_reading(&self, data: &[u8], index: usize) -> io::Result<Reading> {
+        reading(
+            data,
+            RTD_VALUE_OFFSET + 2 * index as u16,
+            RTD_STATUS_OFFSET + index as u16,
+            &format!("RTDValue_{}", index + 1),
+            &self.config.rtds[index],
+        )
+    }
+
+    fn output_variable(&self, channel: usize) -> io::Result<Variable> {
+        let index = channel_index(channel, OUTPUTS)?;
+        let address = address(self.device.i16uOutputOffset, 2 * index as u16)?;
+        channel_variable(
+            &format!("OutputValue_{}", channel),
+            address,
+            &self.config.outputs[index],
+        )
+    }
+}
+
+fn channel_index(channel: usize, count: usize) -> io::Result<usize> {
+    if channel == 0 || channel > count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "channel {} does not exist, valid are 1..={}",
+                channel, count
+            ),
+        ));
+    }
+    Ok(channel - 1)
+}
+
+// the address of the byte at @offset of an area starting at @start, which a device at the end
+// of the address space does not have
+fn address(start: u16, offset: u16) -> io::Result<u16> {
+    start.checked_add(offset).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the module lies beyond the process image",
+        )
+    })
+}
+
+fn channel_variable(name: &str, address: u16, config: &ChannelConfig) -> io::Result<Variable> {
+    Ok(Variable::new(name, address, 0, 16)?
+        .with_type(VariableType::I16)?
+        .with_scaling(config.scaling)
+        .with_unit(&config.unit))
+}
+
+fn reading(
+    data: &[u8],
+    value_offset: u16,
+    status_offset: u16,
+    name: &str,
+    config: &ChannelConfig,
+) -> io::Result<Reading> {
+    let variable = channel_variable(name, value_offset, config)?;
+    let value = variable.decode_quantity(data)?;
+    let start = value_offset as usize;
+    Ok(Reading {
+        raw: i16::from_le_bytes([data[start], data[start + 1]]),
+        value,
+        status: InputStatus(data[status_offset as usize]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    #[test]
+    fn aio_channels() {
+        let driver = MemoryDriver::new();
+        driver.add_device(SDeviceInfo {
+            i8uAddress: 31,
+            i16uModuleType: MODULE_TYPE as u16,
+            i16uInputOffset: 100,
+            i16uInputLength: 20,
+            i16uOutputOffset: 120,
+            i16uOutputLength: 4,
+            ..Default::default()
+        });
+        driver.write(102, &(-2500i16).to_le_bytes()).unwrap();
+        driver.write(109, &[0x02]).unwrap();
+        driver.write(114, &235i16.to_le_bytes()).unwrap();
+
+        let mut config = AioConfig::default();
+        config.outputs[1] = ChannelConfig::new("%", Scaling::new(100.0, 10000.0, 0.0).unwrap());
+        let aio = Aio::find(&driver, None, config).unwrap();
+
+        let input = aio.input(2).unwrap();
+        assert_eq!(input.raw, -2500);
+        assert_eq!(input.value.to_string(), "-2500 mV");
+        assert!(input.status.overflow());
+
+        let snapshot = aio.snapshot().unwrap();
+        assert_eq!(snapshot.rtds[1].value.to_string(), "23.5 °C");
+        assert!(snapshot.inputs[0].status.is_ok());
+
+        aio.set_output(2, 50.0).unwrap();
+        assert_eq!(driver.read(122, 2).unwrap(), 5000i16.to_le_bytes().to_vec());
+        assert_eq!(aio.output(2).unwrap().value, 50.0);
+
+        assert!(aio.input(5).is_err());
+        assert!(Aio::find(&driver, Some(32), AioConfig::default()).is_err());
+
+        driver.add_device(SDeviceInfo {
+            i8uAddress: 32,
+            i16uModuleType: MODULE_TYPE as u16,
+            i16uInputOffset: u16::MAX,
+            i16uOutputOffset: u16::MAX,
+            ..Default::default()
+        });
+        let aio = Aio::find(&driver, Some(32), AioConfig::default()).unwrap();
+        let err = aio.output_status(2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            aio.output(2).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! An async API on top of any [`Driver`], for use inside a tokio runtime.
+//!
+//! The piControl driver only offers blocking calls. Every operation is therefore moved to
+//! tokio's blocking thread pool, so a slow ioctl never stalls the runtime's worker threads.
+
+use crate::driver::{Driver, Event};
+use crate::picontrol::{SDeviceInfo, SPIValue, SPIVariable};
+use crate::SharedRevPiControl;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::broadcast;
+
+// io::Error can not be cloned, so the event thread broadcasts its kind and message.
+type Broadcast = Result<Event, (io::ErrorKind, String)>;
+
+/// AsyncRevPiControl offers the driver operations as futures.
+///
+/// It is cheap to clone; clones share the same driver and the same event thread.
+pub struct AsyncRevPiControl<D = SharedRevPiControl> {
+    driver: Arc<D>,
+    // the sender of the running event thread, None while there is none
+    events: Arc<Mutex<Option<broadcast::Sender<Broadcast>>>>,
+}
+
+impl<D> Clone for AsyncRevPiControl<D> {
+    fn clone(&self) -> Self {
+        AsyncRevPiControl {
+            driver: self.driver.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl AsyncRevPiControl<SharedRevPiControl> {
+    /// Opens the default piControl device.
+    pub async fn open() -> io::Result<Self> {
+        let driver = run_blocking(SharedRevPiControl::open).await?;
+        Ok(Self::new(driver))
+    }
+
+    /// Opens the piControl device at the given path.
+    pub async fn open_at(path: &str) -> io::Result<Self> {
+        let path = path.to_owned();
+        let driver = run_blocking(move || SharedRevPiControl::open_at(&path)).await?;
+        Ok(Self::new(driver))
+    }
+}
+
+impl<D: Driver + 'static> AsyncRevPiControl<D> {
+    /// Wraps a driver, e.g. a [`MemoryDriver`](crate::MemoryDriver) in tests.
+    pub fn new(driver: D) -> Self {
+        Self::from_arc(Arc::new(driver))
+    }
+
+    /// Wraps a driver that is shared with other code.
+    pub fn from_arc(driver: Arc<D>) -> Self {
+        AsyncRevPiControl {
+            driver,
+            events: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The wrapped driver.
+    pub fn driver(&self) -> &Arc<D> {
+        &self.driver
+    }
+
+    /// Reads @length bytes of process data starting at @offset.
+    pub async fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        self.blocking(move |d| d.read(offset, length)).await
+    }
+
+    /// Writes process data at a specific position.
+    pub async fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let data = data.to_vec();
+        self.blocking(move |d| d.write(offset, &data)).await
+    }
+
+    /// Get the info for a variable.
+    pub async fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        let name = name.to_owned();
+        self.blocking(move |d| d.get_variable_info(&name)).await
+    }
+
+    /// Gets a description of connected devices.
+    pub async fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        self.blocking(|d| d.get_device_info_list()).await
+    }
+
+    /// Gets the value of one bit in the process image, the result is in `i8uValue`.
+    pub async fn get_bit_value(&self, mut value: SPIValue) -> io::Result<SPIValue> {
+        self.blocking(move |d| d.get_bit_value(&mut value).map(|_| value))
+            .await
+    }
+
+    /// Sets the value of one bit in the process image.
+    pub async fn set_bit_value(&self, mut value: SPIValue) -> io::Result<()> {
+        self.blocking(move |d| d.set_bit_value(&mut value).map(|_| ()))
+            .await
+    }
+
+    /// Reset Pi Control Interface.
+    pub async fn reset(&self) -> io::Result<()> {
+        self.blocking(|d| d.reset()).await
+    }
+
+    /// Stops or restarts the I/O update, returns whether it is stopped now.
+    pub async fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        self.blocking(move |d| d.stop_io(stop)).await
+    }
+
+    /// Sets the counters or encoders selected by @bitfield of the DIO/DI module at @address
+    /// to 0.
+    pub async fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        self.blocking(move |d| d.reset_counter(address, bitfield))
+            .await
+    }
+
+    /// Returns a stream of driver events.
+    ///
+    /// A single thread waits in KB_WAIT_FOR_EVENT and forwards each event to all streams of
+    /// this control and its clones. After the last stream is dropped the thread ends once the
+    /// driver reports the next event. A stream that falls more than 16 events behind misses
+    /// the oldest of them.
+    pub fn events(&self) -> EventStream {
+        let mut sender = lock(&self.events);
+        if let Some(tx) = sender.as_ref() {
+            return EventStream::new(tx.subscribe());
+        }
+        let (tx, rx) = broadcast::channel(16);
+        *sender = Some(tx);
+        let driver = self.driver.clone();
+        let events = self.events.clone();
+        std::thread::spawn(move || loop {
+            let event = driver
+                .wait_for_event()
+                .map_err(|err| (err.kind(), err.to_string()));
+            let failed = event.is_err();
+            // subscribers take the same lock, so none is added after the last one is gone
+            let mut sender = lock(&events);
+            let delivered = sender.as_ref().is_some_and(|tx| tx.send(event).is_ok());
+            if !delivered || failed {
+                *sender = None;
+                return;
+            }
+        });
+        EventStream::new(rx)
+    }
+
+    async fn blocking<T, F>(&self, f: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&D) -> io::Result<T> + Send + 'static,
+    {
+        let driver = self.driver.clone();
+        run_blocking(move || f(&driver)).await
+    }
+}
+
+async fn run_blocking<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+type Next = Pin<Box<dyn Future<Output = NextEvent> + Send>>;
+type NextEvent = (Option<io::Result<Event>>, broadcast::Receiver<Broadcast>);
+
+/// EventStream yields the events reported by the driver.
+///
+/// An error ends the stream after it has been yielded.
+pub struct EventStream {
+    next: Next,
+}
+
+impl EventStream {
+    fn new(rx: broadcast::Receiver<Broadcast>) -> Self {
+        EventStream {
+            next: Box::pin(receive(rx)),
+        }
+    }
+
+    /// Waits for the next event, `None` once the stream has ended.
+    pub async fn next(&mut self) -> Option<io::Result<Event>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Event>>> {
+        let (event, rx) = match self.next.as_mut().poll(cx) {
+            Poll::Ready(next) => next,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.next = Box::pin(receive(rx));
+        Poll::Ready(event)
+    }
+}
+
+impl futures_core::Stream for EventStream {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next(cx)
+    }
+}
+
+/// Receives the next event of @rx and hands @rx back for the one after it.
+async fn receive(mut rx: broadcast::Receiver<Broadcast>) -> NextEvent {
+    loop {
+        let event = match rx.recv().await {
+            Ok(Ok(event)) => Some(Ok(event)),
+            Ok(Err((kind, msg))) => Some(Err(io::Error::new(kind, msg))),
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => None,
+        };
+        return (event, rx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    #[tokio::test]
+    async fn async_operations_on_memory_driver() {
+        let memory = Arc::new(MemoryDriver::new());
+        memory.add_variable("Output_1", 10, 0, 16);
+        let control = AsyncRevPiControl::new(memory.clone());
+
+        let variable = control.get_variable_info("Output_1").await.unwrap();
+        control
+            .write(u64::from(variable.i16uAddress), &[0x34, 0x12])
+            .await
+            .unwrap();
+        assert_eq!(control.read(10, 2).await.unwrap(), vec![0x34, 0x12]);
+
+        let value = SPIValue {
+            i16uAddress: 10,
+            i8uBit: 2,
+            ..Default::default()
+        };
+        assert_eq!(control.get_bit_value(value).await.unwrap().i8uValue, 1);
+
+        let mut events = control.events();
+        let mut more = control.clone().events();
+        control.reset().await.unwrap();
+        assert_eq!(events.next().await.unwrap().unwrap(), Event::Reset);
+        assert_eq!(more.next().await.unwrap().unwrap(), Event::Reset);
+        assert_eq!(
+            control
+                .events
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .receiver_count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use clap::{App, Arg};
+use picontrol::config::Config;
+use picontrol::emulator::{Emulator, Module, CORE_MODULE_TYPE};
+use picontrol::socket::{SocketServer, DEFAULT_SOCKET};
+
+use std::sync::Arc;
+
+fn main() {
+    let matches = App::new("piemulator")
+        .version("1.0")
+        .about("Emulates a RevPi and offers the piControl operations on a Unix socket")
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .help("Path of the Unix socket to listen on")
+                .takes_value(true)
+                .default_value(DEFAULT_SOCKET),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("Emulates the modules of this config.rsc")
+                .takes_value(true)
+                .conflicts_with("module"),
+        )
+        .arg(
+            Arg::with_name("module")
+                .short("m")
+                .long("module")
+                .value_name("type:position")
+                .help("Emulates a module, e.g. 96:32 for a DIO at address 32")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .get_matches();
+
+    let emulator = match matches.value_of("config") {
+        Some(path) => Config::load(path).and_then(Emulator::from_config),
+        None => {
+            let modules = match matches.values_of("module") {
+                Some(values) => match values.map(parse_module).collect() {
+                    Ok(modules) => modules,
+                    Err(err) => {
+                        println!("{}", err);
+                        return;
+                    }
+                },
+                None => vec![Module::new(CORE_MODULE_TYPE, 0)],
+            };
+            Emulator::new(&modules)
+        }
+    };
+    let emulator = match emulator {
+        Ok(emulator) => emulator,
+        Err(err) => {
+            println!("emulator error: {}", err);
+            return;
+        }
+    };
+
+    let path = matches.value_of("socket").unwrap();
+    let server = match SocketServer::bind(path, Arc::new(emulator)) {
+        Ok(server) => server,
+        Err(err) => {
+            println!("bind error: {}", err);
+            return;
+        }
+    };
+    println!("Listening on {}", path);
+    if let Err(err) = server.run() {
+        println!("server error: {}", err);
+    }
+}
+
+fn parse_module(value: &str) -> Result<Module, String> {
+    let mut parts = value.splitn(2, ':');
+    let module_type = parts.next().and_then(|t| t.parse().ok());
+    let position = parts.next().and_then(|p| p.parse().ok());
+    match (module_type, position) {
+        (Some(module_type), Some(position)) => Ok(Module::new(module_type, position)),
+        _ => Err(format!(
+            "invalid module {}, expected type:position, e.g. 96:32",
+            value
+        )),
+    }
+}
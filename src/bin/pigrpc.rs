@@ -0,0 +1,125 @@
+use clap::{App, Arg};
+use picontrol::grpc::GrpcService;
+use picontrol::socket::SocketDriver;
+use picontrol::{Driver, SharedRevPiControl};
+
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn main() {
+    let matches = App::new("pigrpc")
+        .version("1.0")
+        .about("Offers the RevPi process image over gRPC, see proto/picontrol.proto")
+        .arg(
+            Arg::with_name("listen")
+                .short("l")
+                .long("listen")
+                .help("Address to listen on, other than loopback only with a token")
+                .takes_value(true)
+                .default_value("127.0.0.1:50051"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .short("t")
+                .long("token")
+                .help("Requires this bearer token on every call, defaults to $PIGRPC_TOKEN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("token-file")
+                .long("token-file")
+                .value_name("path")
+                .help("Requires the bearer token stored in this file on every call")
+                .takes_value(true)
+                .conflicts_with("token"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .short("i")
+                .long("interval")
+                .value_name("ms")
+                .help("The shortest interval of WatchVariables streams")
+                .takes_value(true)
+                .default_value("100"),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .help("Uses the emulator listening on this Unix socket instead of piControl")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let interval = match matches.value_of("interval").unwrap().parse() {
+        Ok(ms) => Duration::from_millis(ms),
+        Err(err) => {
+            println!("invalid interval: {}", err);
+            return;
+        }
+    };
+    let token = match matches.value_of("token-file") {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(token) => Some(token.trim().to_owned()),
+            Err(err) => {
+                println!("token file error: {}", err);
+                return;
+            }
+        },
+        None => matches
+            .value_of("token")
+            .map(str::to_owned)
+            .or_else(|| std::env::var("PIGRPC_TOKEN").ok()),
+    };
+    let token = token.filter(|token| !token.is_empty());
+    let addr = matches.value_of("listen").unwrap();
+    let loopback = match addr.to_socket_addrs() {
+        Ok(mut addrs) => addrs.all(|addr| addr.ip().is_loopback()),
+        Err(err) => {
+            println!("invalid listen address {}: {}", addr, err);
+            return;
+        }
+    };
+    if !loopback && token.is_none() {
+        println!("refusing to listen on {} without a token", addr);
+        return;
+    }
+
+    let driver: Arc<dyn Driver> = match matches.value_of("socket") {
+        Some(path) => match SocketDriver::connect(path) {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("connect error: {}", err);
+                return;
+            }
+        },
+        None => match SharedRevPiControl::open() {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("open error: {}", err);
+                return;
+            }
+        },
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            println!("runtime error: {}", err);
+            return;
+        }
+    };
+    let mut service = GrpcService::new(driver).min_interval(interval);
+    if let Some(token) = &token {
+        service = service.token(token);
+    }
+    let result = runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        println!("Listening on {}", addr);
+        service.serve(listener).await
+    });
+    if let Err(err) = result {
+        println!("server error: {}", err);
+    }
+}
@@ -0,0 +1,140 @@
+use clap::{App, Arg};
+use picontrol::config::Config;
+use picontrol::http::HttpServer;
+use picontrol::metrics::Metrics;
+use picontrol::socket::SocketDriver;
+use picontrol::{Driver, SharedRevPiControl};
+
+use std::sync::Arc;
+
+fn main() {
+    let matches = App::new("pihttp")
+        .version("1.0")
+        .about("Offers the RevPi process image over an HTTP REST API")
+        .arg(
+            Arg::with_name("listen")
+                .short("l")
+                .long("listen")
+                .help("Address to listen on")
+                .takes_value(true)
+                .default_value("0.0.0.0:8080"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .short("t")
+                .long("token")
+                .help("Requires this bearer token on every request, defaults to $PIHTTP_TOKEN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("token-file")
+                .long("token-file")
+                .value_name("path")
+                .help("Requires the bearer token stored in this file on every request")
+                .takes_value(true)
+                .conflicts_with("token"),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .short("r")
+                .long("read-only")
+                .help("Refuses writes and resets"),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .help("Uses the emulator listening on this Unix socket instead of piControl")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("the config.rsc path, if empty the one of the driver is used")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metric")
+                .short("m")
+                .long("metric")
+                .value_name("name")
+                .help("Exports the variable on /metrics, besides device health and status")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .get_matches();
+
+    // a token on the command line is visible to every user of the machine
+    let token = match matches.value_of("token-file") {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(token) => Some(token.trim().to_owned()),
+            Err(err) => {
+                println!("token file error: {}", err);
+                return;
+            }
+        },
+        None => matches
+            .value_of("token")
+            .map(str::to_owned)
+            .or_else(|| std::env::var("PIHTTP_TOKEN").ok()),
+    };
+    let token = token.filter(|token| !token.is_empty());
+
+    let driver: Arc<dyn Driver> = match matches.value_of("socket") {
+        Some(path) => match SocketDriver::connect(path) {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("connect error: {}", err);
+                return;
+            }
+        },
+        None => match SharedRevPiControl::open() {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("open error: {}", err);
+                return;
+            }
+        },
+    };
+
+    let addr = matches.value_of("listen").unwrap();
+    let mut server = match HttpServer::bind(addr, driver) {
+        Ok(server) => server.read_only(matches.is_present("read-only")),
+        Err(err) => {
+            println!("bind error: {}", err);
+            return;
+        }
+    };
+    if let Some(token) = &token {
+        server = server.token(token);
+    }
+    // without a configuration only the raw image is offered
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let names: Vec<&str> = matches.values_of("metric").into_iter().flatten().collect();
+    match config {
+        Ok(config) => match Metrics::new(&config, &names) {
+            Ok(metrics) => server = server.metrics(metrics).config(config),
+            Err(err) => {
+                println!("metrics error: {}", err);
+                return;
+            }
+        },
+        Err(err) if names.is_empty() => {
+            println!("no JSON image, config error: {}", err);
+            server = server.metrics(Metrics::default());
+        }
+        Err(err) => {
+            println!("config error: {}", err);
+            return;
+        }
+    }
+    println!("Listening on {}", addr);
+    if let Err(err) = server.run() {
+        println!("server error: {}", err);
+    }
+}
@@ -0,0 +1,102 @@
+use clap::{App, Arg};
+use picontrol::config::Config;
+use picontrol::opcua::OpcUaServer;
+use picontrol::socket::SocketDriver;
+use picontrol::{Driver, SharedRevPiControl};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+fn main() {
+    let matches = App::new("piopcua")
+        .version("1.0")
+        .about("Offers the RevPi variables to OPC UA clients")
+        .arg(
+            Arg::with_name("listen")
+                .short("l")
+                .long("listen")
+                .help("Address to listen on")
+                .takes_value(true)
+                .default_value("127.0.0.1:4840"),
+        )
+        .arg(
+            Arg::with_name("writable")
+                .short("w")
+                .long("writable")
+                .help("Lets clients write outputs, the variables are read-only otherwise"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .short("i")
+                .long("interval")
+                .value_name("ms")
+                .help("The shortest publishing interval of subscriptions")
+                .takes_value(true)
+                .default_value("100"),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .help("Uses the emulator listening on this Unix socket instead of piControl")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("the config.rsc path, if empty the one of the driver is used")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let interval = match matches.value_of("interval").unwrap().parse() {
+        Ok(ms) => Duration::from_millis(ms),
+        Err(err) => {
+            println!("invalid interval: {}", err);
+            return;
+        }
+    };
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            println!("config error: {}", err);
+            return;
+        }
+    };
+    let driver: Arc<dyn Driver> = match matches.value_of("socket") {
+        Some(path) => match SocketDriver::connect(path) {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("connect error: {}", err);
+                return;
+            }
+        },
+        None => match SharedRevPiControl::open() {
+            Ok(driver) => Arc::new(driver),
+            Err(err) => {
+                println!("open error: {}", err);
+                return;
+            }
+        },
+    };
+
+    let addr = matches.value_of("listen").unwrap();
+    let server = match OpcUaServer::bind(addr, driver, &config) {
+        Ok(server) => server
+            .min_interval(interval)
+            .read_only(!matches.is_present("writable")),
+        Err(err) => {
+            println!("bind error: {}", err);
+            return;
+        }
+    };
+    println!("Listening on opc.tcp://{}", addr);
+    if let Err(err) = server.run() {
+        println!("server error: {}", err);
+    }
+}
@@ -1,7 +1,16 @@
-use byteorder::{ByteOrder, LittleEndian};
-use clap::{App, Arg, SubCommand};
-use picontrol::{get_module_name, is_module_connected,SDeviceInfo,SPIValue};
-
+use clap::{App, Arg, ArgMatches, SubCommand};
+use picontrol::config::Config;
+use picontrol::export;
+use picontrol::image_file::ImageFile;
+use picontrol::restore;
+use picontrol::socket::SocketDriver;
+use picontrol::{
+    get_module_name, is_module_connected, ConfigError, Driver, SDeviceInfo, Value, Variable,
+    VariableType, PROCESS_IMAGE_SIZE,
+};
+
+use std::convert::TryFrom;
+use std::io::Write;
 use std::str::FromStr;
 
 #[macro_use]
@@ -46,10 +55,25 @@ fn main() {
                 .short("f")
                 .help("Updates the firmware of a module"),
         )
+        .arg(
+            Arg::with_name("socket")
+                .long("socket")
+                .help("Connects to the piControl emulator listening on this Unix socket")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("image-source")
                 .short("s")
-                .help("The process image dumped file path, if empty the default is used"),
+                .help("The process image dumped file path, if empty the default is used")
+                .takes_value(true)
+                .conflicts_with("socket"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("Reads the dumped file given with -s offline, using this config.rsc")
+                .takes_value(true)
+                .requires("image-source"),
         )
         .subcommand(
             SubCommand::with_name("read")
@@ -68,7 +92,8 @@ fn main() {
                         .possible_values(&["d", "h", "b"])
                         .help("the variable format")
                         .takes_value(true),
-                ),
+                )
+                .arg(variable_type_arg()),
         )
         .subcommand(
             SubCommand::with_name("write")
@@ -84,7 +109,8 @@ fn main() {
                         .short("v")
                         .help("the variable value")
                         .takes_value(true),
-                ),
+                )
+                .arg(variable_type_arg()),
         )
         .subcommand(
             SubCommand::with_name("dump")
@@ -95,33 +121,112 @@ fn main() {
                         .help("the file path")
                         .default_value("revpi_proc_img.bin")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("t")
+                        .help("raw bytes, or every variable with its value as json or csv")
+                        .default_value("raw")
+                        .possible_values(&["raw", "json", "csv"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .short("c")
+                        .help("the piCtory configuration for json and csv, /etc/revpi/config.rsc if not given")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Writes the outputs of a dumped process image back, after showing the difference")
+                .arg(
+                    Arg::with_name("file-path")
+                        .short("f")
+                        .help("the file path")
+                        .default_value("revpi_proc_img.bin")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("variable-name")
+                        .short("n")
+                        .help("restores only this variable, may be repeated")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only shows the difference"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("applies the changes without asking"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Compares the piCtory configuration with the device list")
+                .arg(
+                    Arg::with_name("config")
+                        .short("c")
+                        .help("the config.rsc path, if empty the one of the driver is used")
+                        .takes_value(true),
                 ),
         )
         .get_matches();
 
     // this implements the drop trait, cleans up memory after going out of scope
     let mut picontrol = picontrol::RevPiControl::new();
-    
 
-    if matches.is_present("image-source") {
-        let m = matches.value_of("image-source").unwrap();
+    if let Some(m) = matches.value_of("image-source") {
         picontrol = picontrol::RevPiControl::new_at(m);
     }
 
-    if let Err(err) = picontrol.open() {
-        println!("open file error: {}", err);
-        return;
-    }
+    let driver: Box<dyn Driver> = match (matches.value_of("socket"), matches.value_of("config")) {
+        (Some(path), _) => match SocketDriver::connect(path) {
+            Ok(driver) => Box::new(driver),
+            Err(err) => {
+                println!("connect error: {}", err);
+                return;
+            }
+        },
+        (None, Some(config)) => {
+            let image = matches.value_of("image-source").unwrap();
+            match Config::load(config).and_then(|config| ImageFile::open(image, config)) {
+                Ok(driver) => Box::new(driver),
+                Err(err) => {
+                    println!("open file error: {}", err);
+                    return;
+                }
+            }
+        }
+        (None, None) => {
+            if let Err(err) = picontrol.open() {
+                println!("open file error: {}", err);
+                return;
+            }
+            Box::new(picontrol)
+        }
+    };
+    let driver = &*driver;
+    let image_source = matches.value_of("image-source");
 
     if matches.is_present("reset") {
-        if let Err(err) = picontrol.reset() {
-            println!("reset error: {}", err);
+        if let Err(err) = driver.reset() {
+            match ConfigError::from_io(&err) {
+                Some(config_error) => diagnose_reset(driver, config_error.clone()),
+                None => println!("reset error: {}", err),
+            }
         }
         return;
     }
 
     if matches.is_present("device-list") {
-        match picontrol.get_device_info_list() {
+        match driver.get_device_info_list() {
             Err(err) => {
                 println!("ls error: {}", err);
                 return;
@@ -144,11 +249,12 @@ fn main() {
                 println!("invalid read format: {}", err);
                 err.exit();
             });
+            let kind = variable_type(matches);
 
             println!("Value for variable name: {}", varname);
-            read_variable_value(&mut picontrol, varname, format, false).unwrap_or_else(|err| {
+            read_variable_value(driver, varname, kind, format, false).unwrap_or_else(|err| {
                 println!("error reading variable: {}", err);
-                return false;
+                false
             });
         } else {
             println!("no variable specified");
@@ -159,14 +265,15 @@ fn main() {
         if let Some(varname) = matches.value_of("variable-name") {
             println!("Value for variable name: {}", varname);
 
-            let value = value_t!(matches, "variable-value", u32).unwrap_or_else(|err| {
+            let value = value_t!(matches, "variable-value", String).unwrap_or_else(|err| {
                 println!("invalid write value: {}", err);
                 err.exit();
             });
+            let kind = variable_type(matches);
 
-            write_variable_value(&mut picontrol, varname, value).unwrap_or_else(|err| {
+            write_variable_value(driver, varname, kind, &value).unwrap_or_else(|err| {
                 println!("error writing variable: {}", err);
-                return false;
+                false
             });
         } else {
             println!("no variable specified");
@@ -175,155 +282,236 @@ fn main() {
 
     if let Some(matches) = matches.subcommand_matches("dump") {
         if let Some(fp) = matches.value_of("file-path") {
-            if let Err(err) = picontrol.dump(fp) {
+            let result = match matches.value_of("format").unwrap() {
+                "raw" => dump(driver, fp, image_length(image_source)),
+                format => export(driver, fp, format, matches.value_of("config")),
+            };
+            if let Err(err) = result {
                 println!("dump error: {}", err);
             }
-            return;
         } else {
             println!("no file path specified");
         }
     }
+
+    if let Some(matches) = matches.subcommand_matches("restore") {
+        let fp = matches.value_of("file-path").unwrap();
+        let names: Vec<&str> = matches
+            .values_of("variable-name")
+            .map(|names| names.collect())
+            .unwrap_or_default();
+        if let Err(err) = restore(
+            driver,
+            fp,
+            &names,
+            matches.is_present("dry-run"),
+            matches.is_present("yes"),
+        ) {
+            println!("restore error: {}", err);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("validate") {
+        let config = match matches.value_of("config") {
+            Some(path) => Config::load(path),
+            None => Config::load_default(),
+        };
+        match config.and_then(|config| picontrol::validate::validate_driver(driver, &config)) {
+            Ok(report) => print!("{}", report),
+            Err(err) => println!("validate error: {}", err),
+        }
+    }
+}
+
+fn diagnose_reset(driver: &dyn Driver, mut err: ConfigError) {
+    let report = Config::load_default()
+        .and_then(|config| picontrol::validate::validate_driver(driver, &config));
+    if let Ok(report) = &report {
+        err.locate(report);
+    }
+    println!("reset error: {}", err);
+    println!(
+        "the driver rejected the piCtory configuration, error code {}",
+        err.kind.code()
+    );
+    match report {
+        Ok(report) => print!("{}", report),
+        Err(e) => println!(
+            "can not compare the configuration with the device list: {}",
+            e
+        ),
+    }
+}
+
+fn variable_type_arg() -> Arg<'static, 'static> {
+    Arg::with_name("variable-type")
+        .short("t")
+        .possible_values(&["bool", "u8", "i8", "u16", "i16", "u32", "i32", "f32"])
+        .help("the variable type, unsigned of the variable length if not given")
+        .takes_value(true)
+}
+
+fn variable_type(matches: &ArgMatches) -> Option<VariableType> {
+    matches
+        .value_of("variable-type")
+        .map(|t| t.parse().expect("possible values are valid types"))
+}
+
+fn resolve_variable(
+    driver: &dyn Driver,
+    name: &str,
+    kind: Option<VariableType>,
+) -> Result<Variable, Box<dyn std::error::Error>> {
+    let variable = Variable::resolve(driver, name)?;
+    Ok(match kind {
+        Some(kind) => variable.with_type(kind)?,
+        None => variable,
+    })
 }
 
 fn read_variable_value(
-    picontrol: &mut picontrol::RevPiControl,
+    driver: &dyn Driver,
     name: &str,
     // cyclic: bool,
+    kind: Option<VariableType>,
     format: Formats,
     quiet: bool,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    let mut spivalue: SPIValue = SPIValue {
-        ..Default::default()
-    };
-
-    let spivariable = picontrol.get_variable_info(name)?;
+    let variable = resolve_variable(driver, name, kind)?;
+    let value = variable.read(driver)?;
 
-    if spivariable.i16uLength == 1 {
-        spivalue.i16uAddress = spivariable.i16uAddress;
-        spivalue.i8uBit = spivariable.i8uBit;
-
-        picontrol.get_bit_value(&mut spivalue)?;
+    if let Value::Bool(bit) = value {
         if !quiet {
-            println!("Bit value: {}", spivalue.i8uValue);
+            println!("Bit value: {}", bit as u8);
         } else {
-            println!("{}", spivalue.i8uValue);
+            println!("{}", bit as u8);
         }
-    } else {
-        let remainder = spivariable.i16uLength % 8;
-        if remainder != 0 {
-            return Err(From::from(format!(
-                "could not read variable {}. Internal Error",
-                name
-            )));
-        }
-        let size = spivariable.i16uLength / 8;
+        return Ok(true);
+    }
 
-        match spivariable.i16uLength {
-            8 | 16 | 32 => {
-                let data: Vec<u8> =
-                    picontrol.read(spivariable.i16uAddress as u64, size as usize)?;
+    let size = variable.byte_length();
+    let data = value.to_bytes();
+    // the raw little endian bits, what the hex and binary formats always printed
+    let raw = data.iter().rev().fold(0u32, |n, b| n << 8 | u32::from(*b));
+    println!(
+        "read from address {}, byte size {}, data: {:x?}",
+        variable.address, size, data
+    );
+
+    match format {
+        Formats::Hex => {
+            if !quiet {
                 println!(
-                    "read from address {}, byte size {}, data: {:x?}",
-                    spivariable.i16uAddress, size, data
+                    "{} byte-value of {}: {:x?} hex bytes (={} dec)",
+                    size, name, data, value
                 );
-                let u32_value = match spivariable.i16uLength {
-                    8 => data[0] as u32,
-                    16 => LittleEndian::read_u16(&data) as u32,
-                    32 => LittleEndian::read_u32(&data) as u32,
-                    _ => {
-                        return Err(From::from(format!(
-                            "invalid length for variable {}. Internal Error",
-                            name
-                        )));
-                    }
-                };
-
-                match format {
-                    Formats::Hex => {
-                        if !quiet {
-                            println!(
-                                "{} byte-value of {}: {:x?} hex bytes (={} dec)",
-                                size,
-                                name,
-                                data.as_ref() as &[u8],
-                                u32_value
-                            );
-                        } else {
-                            println!("{:x}", u32_value);
-                        }
-                    }
-                    Formats::Binary => {
-                        if !quiet {
-                            println!("{} byte value of {}: ", size, name);
-                        }
-
-                        let bn = picontrol::num_to_bytes(u32_value as u64, 32).unwrap();
-                        println!("binary value: {:x?}", bn);
-                    }
-                    _ => {
-                        if !quiet {
-                            println!(
-                                "{} byte-value of {}: {} dec (={:x?} hex bytes)",
-                                size,
-                                name,
-                                u32_value,
-                                data.as_ref() as &[u8]
-                            );
-                        } else {
-                            println!("{}", u32_value);
-                        }
-                    }
-                };
+            } else {
+                println!("{:x}", raw);
             }
-            _ => {
-                return Err(From::from(format!(
-                    "invalid byte size {} for variable {}",
-                    size, name
-                )));
+        }
+        Formats::Binary => {
+            if !quiet {
+                println!("{} byte value of {}: ", size, name);
             }
+
+            let bn = picontrol::num_to_bytes(u64::from(raw), 32)?;
+            println!("binary value: {:x?}", bn);
         }
-    }
+        _ => {
+            if !quiet {
+                println!(
+                    "{} byte-value of {}: {} dec (={:x?} hex bytes)",
+                    size, name, value, data
+                );
+            } else {
+                println!("{}", value);
+            }
+        }
+    };
 
     Ok(true)
 }
 
 fn write_variable_value(
-    picontrol: &mut picontrol::RevPiControl,
+    driver: &dyn Driver,
     name: &str,
-    i32u_value: u32,
+    kind: Option<VariableType>,
+    text: &str,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    let spivariable = picontrol.get_variable_info(name)?;
+    let variable = resolve_variable(driver, name, kind)?;
+    let value = variable.kind.parse_value(text)?;
 
-    let mut spivalue: SPIValue = SPIValue {
-        ..Default::default()
-    };
+    variable.write(driver, value)?;
 
-    if spivariable.i16uLength == 1 {
-        spivalue.i16uAddress = spivariable.i16uAddress;
-        spivalue.i8uBit = spivariable.i8uBit;
-        spivalue.i8uValue = i32u_value as u8;
-        picontrol.set_bit_value(&mut spivalue)?;
-    } else {
-        /*
-        match spivariable.i16uLength {
-        8 => data = i32u_value as u8,
-        16 => data = i32u_value as u16,
-        32 => data = i32u_value as u32
-        };
-        */
+    println!(
+        "written value {} {} (={:x?} hex bytes) to offset {}.\n",
+        value,
+        variable.kind,
+        value.to_bytes(),
+        variable.address
+    );
 
-        let bn = picontrol::num_to_bytes(i32u_value as u64, 32)?;
-        println!("binary value: {:x?}", bn);
+    Ok(true)
+}
 
-        picontrol.write(spivariable.i16uAddress as u64, &bn)?;
+fn dump(driver: &dyn Driver, fp: &str, length: usize) -> std::io::Result<()> {
+    let image = driver.read(0, length)?;
+    std::fs::write(fp, image)
+}
+
+/// The bytes to dump: all of a dumped image given with -s, whatever its size, otherwise the
+/// process image of the driver.
+fn image_length(source: Option<&str>) -> usize {
+    match source.map(std::fs::metadata) {
+        Some(Ok(metadata)) if metadata.is_file() => {
+            usize::try_from(metadata.len()).unwrap_or(usize::MAX)
+        }
+        _ => PROCESS_IMAGE_SIZE,
     }
+}
 
-    println!(
-        "written value {} dec (={:x?} hex) to offset {}.\n",
-        i32u_value, i32u_value, spivariable.i16uAddress
-    );
+fn export(
+    driver: &dyn Driver,
+    fp: &str,
+    format: &str,
+    config: Option<&str>,
+) -> std::io::Result<()> {
+    let config = match config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    let out = std::io::BufWriter::new(std::fs::File::create(fp)?);
+    export::export(driver, &config, format.parse()?, out)
+}
 
-    Ok(true)
+fn restore(
+    driver: &dyn Driver,
+    fp: &str,
+    names: &[&str],
+    dry_run: bool,
+    yes: bool,
+) -> std::io::Result<()> {
+    let image = std::fs::read(fp)?;
+    let plan = if names.is_empty() {
+        restore::plan(driver, &image)?
+    } else {
+        restore::plan_variables(driver, &image, names)?
+    };
+    print!("{}", plan);
+    if dry_run || plan.is_empty() {
+        return Ok(());
+    }
+    if !yes {
+        print!("Apply these changes? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Nothing restored.");
+            return Ok(());
+        }
+    }
+    plan.apply(driver)
 }
 
 fn show_device_list(as_dev_list: Vec<SDeviceInfo>) {
@@ -0,0 +1,369 @@
+//! Generation of Rust constants from config.rsc at build time.
+//!
+//! Call the [`Builder`] from the build.rs of the crate using the process image, the same way
+//! bindgen is used:
+//!
+//! ```ignore
+//! fn main() {
+//!     let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//!     let bindings = picontrol::codegen::Builder::default()
+//!         .config("config.rsc")
+//!         .variable_type("InputValue_1", picontrol::VariableType::I16)
+//!         .generate()
+//!         .expect("Unable to generate the I/O map");
+//!     for warning in bindings.warnings() {
+//!         println!("cargo:warning={}", warning);
+//!     }
+//!     bindings
+//!         .write_to_file(out.join("io.rs"))
+//!         .expect("Couldn't write the I/O map!");
+//!     println!("cargo:rerun-if-changed=config.rsc");
+//! }
+//! ```
+//!
+//! and include the result with `include!(concat!(env!("OUT_DIR"), "/io.rs"));`. Every variable
+//! becomes a [`Var`] constant named after the variable in upper case, e.g. `I_1` or
+//! `INPUTVALUE_1`, so a variable renamed in piCtory no longer compiles. A variable without a
+//! Rust type of its length, e.g. a 48 bit one, is skipped with a warning unless it is given a
+//! type with [`Builder::variable_type`].
+
+use crate::config::{Config, Device, Entry};
+use crate::driver::Driver;
+use crate::mapping::{decode_field, FieldType};
+use crate::value::{Scaling, Variable, VariableType};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// A process image variable with a Rust type, as emitted by the generator.
+#[derive(Debug)]
+pub struct Var<T> {
+    pub name: &'static str,
+    /// Address of the first byte in the process image.
+    pub address: u16,
+    /// 0-7 bit position for boolean variables.
+    pub bit: u8,
+    /// Length of the variable in bits.
+    pub length: u16,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Var<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Var<T> {}
+
+impl<T> Var<T> {
+    pub const fn new(name: &'static str, address: u16, bit: u8, length: u16) -> Self {
+        Var {
+            name,
+            address,
+            bit,
+            length,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<T: FieldType> Var<T> {
+    /// The variable in the untyped form used by the rest of the crate.
+    pub fn variable(&self) -> Variable {
+        Variable {
+            name: self.name.to_owned(),
+            address: self.address,
+            bit: self.bit,
+            length: self.length,
+            kind: T::KIND,
+            scaling: Scaling::default(),
+            unit: None,
+        }
+    }
+
+    /// Reads the current value.
+    pub fn read<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<T> {
+        let value = self.variable().read(driver)?;
+        Ok(T::from_value(value).expect("a variable reads values of its own type"))
+    }
+
+    /// Writes @value.
+    pub fn write<D: Driver + ?Sized>(&self, driver: &D, value: T) -> io::Result<()> {
+        self.variable().write(driver, value.to_value())
+    }
+
+    /// Decodes the variable from a snapshot of the whole process image.
+    pub fn decode(&self, image: &[u8]) -> io::Result<T> {
+        decode_field(&self.variable(), image)
+    }
+}
+
+/// Builder for the generated constants; configure it, then call [`Builder::generate`].
+#[derive(Debug, Default, Clone)]
+pub struct Builder {
+    config: Option<PathBuf>,
+    types: HashMap<String, VariableType>,
+}
+
+impl Builder {
+    /// The config.rsc to read, /etc/revpi/config.rsc if not set.
+    pub fn config<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.config = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Uses @kind for variable @name instead of the unsigned type of its length.
+    ///
+    /// config.rsc does not tell whether a value is signed, e.g. the AIO inputs are INT16.
+    pub fn variable_type(mut self, name: &str, kind: VariableType) -> Self {
+        self.types.insert(name.to_owned(), kind);
+        self
+    }
+
+    /// Reads the configuration and generates the constants.
+    pub fn generate(&self) -> io::Result<Bindings> {
+        let config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::load_default()?,
+        };
+        self.generate_from(&config)
+    }
+
+    /// Generates the constants for an already parsed configuration.
+    pub fn generate_from(&self, config: &Config) -> io::Result<Bindings> {
+        let mut code = String::new();
+        let mut warnings = Vec::new();
+        let mut idents = HashSet::new();
+        let mut used = HashSet::new();
+        writeln!(
+            code,
+            "// Generated by picontrol::codegen from config.rsc, do not edit."
+        )
+        .unwrap();
+        for device in &config.devices {
+            writeln!(code).unwrap();
+            writeln!(
+                code,
+                "// {} at position {}, offset {}",
+                one_line(&device.name),
+                device.position,
+                device.offset
+            )
+            .unwrap();
+            for entry in &device.entries {
+                let ident = ident(&entry.name);
+                if !idents.insert(ident.clone()) {
+                    return Err(invalid_input(format!(
+                        "variable {} gives the constant {} twice",
+                        entry.name, ident
+                    )));
+                }
+                let kind = match self.types.get(&entry.name) {
+                    Some(&kind) => {
+                        used.insert(entry.name.as_str());
+                        if kind.bit_length() != entry.length {
+                            return Err(invalid_input(format!(
+                                "variable {} has {} bits, {} has {}",
+                                entry.name,
+                                entry.length,
+                                kind,
+                                kind.bit_length()
+                            )));
+                        }
+                        kind
+                    }
+                    None => match VariableType::unsigned_for_length(entry.length) {
+                        Ok(kind) => kind,
+                        Err(_) => {
+                            let warning = format!(
+                                "variable {} of {} bits has no Rust type and is skipped",
+                                entry.name, entry.length
+                            );
+                            writeln!(code).unwrap();
+                            writeln!(code, "// {}", one_line(&warning)).unwrap();
+                            warnings.push(warning);
+                            continue;
+                        }
+                    },
+                };
+                write_entry(&mut code, device, entry, &ident, kind);
+            }
+        }
+        if let Some(name) = self.types.keys().find(|n| !used.contains(n.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("variable {} not found in configuration", name),
+            ));
+        }
+        Ok(Bindings { code, warnings })
+    }
+}
+
+/// The generated Rust source.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    code: String,
+    warnings: Vec<String>,
+}
+
+impl Bindings {
+    /// The variables that were skipped and why, for a build script to print as cargo:warning.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Writes the source to @path.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, &self.code)
+    }
+}
+
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+fn write_entry(code: &mut String, device: &Device, entry: &Entry, ident: &str, kind: VariableType) {
+    writeln!(code).unwrap();
+    writeln!(
+        code,
+        "/// {} {} of {}.",
+        kind,
+        entry.direction,
+        one_line(&device.name)
+    )
+    .unwrap();
+    if !entry.comment.is_empty() {
+        writeln!(code, "///").unwrap();
+        writeln!(code, "/// {}", one_line(&entry.comment)).unwrap();
+    }
+    writeln!(
+        code,
+        "pub const {}: ::picontrol::codegen::Var<{}> = ::picontrol::codegen::Var::new({:?}, {}, {}, {});",
+        ident,
+        rust_type(kind),
+        entry.name,
+        entry.address,
+        entry.bit,
+        entry.length
+    )
+    .unwrap();
+}
+
+// Comments end at a line break and rustc refuses a bare CR in them, e.g. from a CRLF file.
+fn one_line(text: &str) -> String {
+    text.replace(|c: char| c.is_control(), " ")
+}
+
+fn rust_type(kind: VariableType) -> &'static str {
+    match kind {
+        VariableType::Bool => "bool",
+        VariableType::U8 => "u8",
+        VariableType::I8 => "i8",
+        VariableType::U16 => "u16",
+        VariableType::I16 => "i16",
+        VariableType::U32 => "u32",
+        VariableType::I32 => "i32",
+        VariableType::F32 => "f32",
+    }
+}
+
+// piCtory allows any character in names, constants only letters, digits and underscores.
+fn ident(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::MemoryDriver;
+
+    #[test]
+    fn generate_constants() {
+        let config = Config::parse(CONFIG).unwrap();
+        let code = Builder::default()
+            .variable_type("RTDValue_1", VariableType::I16)
+            .generate_from(&config)
+            .unwrap()
+            .to_string();
+        assert!(code.contains(
+            "pub const O_10: ::picontrol::codegen::Var<bool> = \
+             ::picontrol::codegen::Var::new(\"O_10\", 78, 1, 1);"
+        ));
+        assert!(code.contains(
+            "pub const RTDVALUE_1: ::picontrol::codegen::Var<i16> = \
+             ::picontrol::codegen::Var::new(\"RTDValue_1\", 114, 0, 16);"
+        ));
+        assert!(code.contains("pub const COUNTER_16: ::picontrol::codegen::Var<u32>"));
+
+        assert!(Builder::default()
+            .variable_type("RTDValue_1", VariableType::I32)
+            .generate_from(&config)
+            .is_err());
+        assert!(Builder::default()
+            .variable_type("missing", VariableType::I32)
+            .generate_from(&config)
+            .is_err());
+        let mut odd = config.clone();
+        odd.devices[0].entries[0].length = 48;
+        let name = odd.devices[0].entries[0].name.clone();
+        let bindings = Builder::default().generate_from(&odd).unwrap();
+        assert_eq!(
+            bindings.warnings(),
+            [format!(
+                "variable {} of 48 bits has no Rust type and is skipped",
+                name
+            )]
+        );
+        assert!(!bindings.to_string().contains(&format!("{:?}", name)));
+        assert!(Builder::default()
+            .variable_type(&name, VariableType::I16)
+            .generate_from(&odd)
+            .is_err());
+        let mut crlf = config.clone();
+        crlf.devices[0].name = "RevPi\r\nCore".to_owned();
+        crlf.devices[0].entries[0].comment = "first\r\nsecond".to_owned();
+        let code = Builder::default().generate_from(&crlf).unwrap().to_string();
+        assert!(!code.contains('\r'));
+        assert!(code.contains("/// first  second"));
+        assert_eq!(ident("Input 1.a"), "INPUT_1_A");
+        assert_eq!(ident("1st"), "_1ST");
+    }
+
+    #[test]
+    fn typed_access() {
+        const O_10: Var<bool> = Var::new("O_10", 78, 1, 1);
+        const RTD: Var<i16> = Var::new("RTDValue_1", 114, 0, 16);
+        let driver = MemoryDriver::new();
+        driver.write(114, &(-15i16).to_le_bytes()).unwrap();
+
+        assert_eq!(RTD.read(&driver).unwrap(), -15);
+        O_10.write(&driver, true).unwrap();
+        assert!(O_10.read(&driver).unwrap());
+        assert_eq!(driver.read(78, 1).unwrap(), vec![0b10]);
+        assert!(O_10.decode(&driver.image()).unwrap());
+    }
+}
@@ -0,0 +1,315 @@
+//! Parser for the piCtory configuration file, config.rsc.
+//!
+//! config.rsc is a JSON document. Each entry of "Devices" describes one module with its
+//! position, its offset in the process image and the variables of its "inp", "out" and "mem"
+//! sections. A variable is an array of the form
+//! `[name, default, bit length, offset, exported, sort order, comment, bit position]`,
+//! where the offset is relative to the device offset.
+
+use crate::picontrol;
+use crate::value::Variable;
+use serde_json::Value as Json;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The section of a device a variable belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Input,
+    Output,
+    Memory,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Input => "input",
+            Direction::Output => "output",
+            Direction::Memory => "memory",
+        })
+    }
+}
+
+/// A variable of a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub direction: Direction,
+    /// Absolute address of the first byte in the process image.
+    pub address: u16,
+    /// 0-7 bit position for 1 bit variables, 0 otherwise.
+    pub bit: u8,
+    /// Length in bits.
+    pub length: u16,
+    pub default: i64,
+    pub exported: bool,
+    pub comment: String,
+}
+
+impl Entry {
+    /// Number of bytes the variable occupies.
+    pub fn byte_length(&self) -> u16 {
+        self.length.div_ceil(8)
+    }
+
+    /// The variable as the driver would describe it, unsigned and unscaled.
+    pub fn variable(&self) -> io::Result<Variable> {
+        Variable::new(&self.name, self.address, self.bit, self.length)
+    }
+}
+
+/// A module of the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    /// The piCtory identifier, e.g. device_RevPiDIO_20160818_1_0_001.
+    pub id: String,
+    /// Module type, as in SDeviceInfo.i16uModuleType.
+    pub product_type: u32,
+    /// Position in the configuration, as in SDeviceInfo.i8uAddress.
+    pub position: u8,
+    /// Offset of the device in the process image.
+    pub offset: u16,
+    pub comment: String,
+    pub entries: Vec<Entry>,
+}
+
+impl Device {
+    /// The variables of one section.
+    pub fn entries(&self, direction: Direction) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.direction == direction)
+    }
+
+    /// Offset and length in bytes of the process image area of one section, None if the
+    /// section has no variables.
+    ///
+    /// Like piControl reports it, the input area starts at the device offset even if its first
+    /// bytes have no variable, e.g. the unwired inputs of a DO.
+    pub fn area(&self, direction: Direction) -> Option<(u16, u16)> {
+        let start = self.entries(direction).map(|e| e.address).min()?;
+        let start = match direction {
+            Direction::Input => start.min(self.offset),
+            _ => start,
+        };
+        let end = self
+            .entries(direction)
+            .map(|e| e.address + e.byte_length())
+            .max()?;
+        Some((start, end - start))
+    }
+}
+
+/// A parsed config.rsc.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Config {
+    pub devices: Vec<Device>,
+}
+
+impl Config {
+    /// Loads the configuration piControl uses.
+    pub fn load_default() -> io::Result<Config> {
+        let path = CStr::from_bytes_with_nul(picontrol::PICONFIG_FILE).unwrap();
+        Self::load(path.to_str().unwrap())
+    }
+
+    /// Loads a configuration file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let text = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("can not read {}: {}", path.as_ref().display(), e),
+            )
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parses the text of a configuration file.
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let json: Json = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let devices = json
+            .get("Devices")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("no Devices list".to_owned()))?;
+        Ok(Config {
+            devices: devices
+                .iter()
+                .map(parse_device)
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    /// All variables with their device.
+    pub fn entries(&self) -> impl Iterator<Item = (&Device, &Entry)> {
+        self.devices
+            .iter()
+            .flat_map(|d| d.entries.iter().map(move |e| (d, e)))
+    }
+
+    /// Looks a variable up by name.
+    pub fn find(&self, name: &str) -> Option<(&Device, &Entry)> {
+        self.entries().find(|(_, e)| e.name == name)
+    }
+
+    /// Looks a variable up by name, like KB_FIND_VARIABLE does in the driver.
+    pub fn variable(&self, name: &str) -> io::Result<Variable> {
+        match self.find(name) {
+            Some((_, entry)) => entry.variable(),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("variable {} not found in configuration", name),
+            )),
+        }
+    }
+
+    /// Looks a device up by its position.
+    pub fn device(&self, position: u8) -> Option<&Device> {
+        self.devices.iter().find(|d| d.position == position)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid configuration: {}", msg),
+    )
+}
+
+// piCtory writes numbers sometimes as JSON numbers and sometimes as strings.
+fn number(value: Option<&Json>) -> Option<i64> {
+    match value? {
+        Json::Number(n) => n.as_i64(),
+        Json::String(s) if s.is_empty() => Some(0),
+        Json::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn text(value: Option<&Json>) -> String {
+    value.and_then(Json::as_str).unwrap_or_default().to_owned()
+}
+
+fn field<T: TryFrom<i64>>(device: &Json, key: &str) -> io::Result<T> {
+    number(device.get(key))
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or_else(|| {
+            invalid(format!(
+                "device {} has no valid {}",
+                text(device.get("id")),
+                key
+            ))
+        })
+}
+
+fn parse_device(device: &Json) -> io::Result<Device> {
+    let mut parsed = Device {
+        name: text(device.get("name")),
+        id: text(device.get("id")),
+        product_type: field(device, "productType")?,
+        position: field(device, "position")?,
+        offset: field(device, "offset")?,
+        comment: text(device.get("comment")),
+        entries: Vec::new(),
+    };
+    for (key, direction) in &[
+        ("inp", Direction::Input),
+        ("out", Direction::Output),
+        ("mem", Direction::Memory),
+    ] {
+        let section = match device.get(*key).and_then(Json::as_object) {
+            Some(section) => section,
+            None => continue,
+        };
+        let mut entries = section
+            .iter()
+            .map(|(index, entry)| {
+                let entry = parse_entry(&parsed, *direction, entry)?;
+                Ok((index.parse::<u32>().unwrap_or(u32::MAX), entry))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|(index, _)| *index);
+        parsed.entries.extend(entries.into_iter().map(|(_, e)| e));
+    }
+    Ok(parsed)
+}
+
+fn parse_entry(device: &Device, direction: Direction, entry: &Json) -> io::Result<Entry> {
+    let fields = entry.as_array().ok_or_else(|| {
+        invalid(format!(
+            "{} variable of device {} is not an array",
+            direction, device.id
+        ))
+    })?;
+    let name = text(fields.first());
+    let bad = |what: &str| invalid(format!("variable {} has no valid {}", name, what));
+    let length = number(fields.get(2))
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or_else(|| bad("length"))?;
+    let offset = number(fields.get(3))
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or_else(|| bad("offset"))?;
+    let bit_position = if length == 1 {
+        number(fields.get(7))
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or_else(|| bad("bit position"))?
+    } else {
+        0
+    };
+    // the variable has to end inside of the 16 bit address space
+    let address = device
+        .offset
+        .checked_add(offset)
+        .and_then(|address| address.checked_add(bit_position / 8))
+        .filter(|address| address.checked_add(length.div_ceil(8)).is_some())
+        .ok_or_else(|| bad("offset"))?;
+    Ok(Entry {
+        address,
+        bit: (bit_position % 8) as u8,
+        length,
+        direction,
+        default: number(fields.get(1)).unwrap_or(0),
+        exported: fields.get(4).and_then(Json::as_bool).unwrap_or(false),
+        comment: text(fields.get(6)),
+        name,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const CONFIG: &str = include_str!("../testdata/config.rsc");
+
+    #[test]
+    fn parse_config_rsc() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(config.devices.len(), 3);
+
+        let dio = config.device(32).unwrap();
+        assert_eq!(dio.product_type, 96);
+        assert_eq!(dio.area(Direction::Input), Some((7, 70)));
+        assert_eq!(dio.area(Direction::Output), Some((77, 18)));
+        assert_eq!(dio.area(Direction::Memory), Some((95, 7)));
+
+        let (device, o_10) = config.find("O_10").unwrap();
+        assert_eq!(device.name, "RevPi DIO");
+        assert_eq!((o_10.address, o_10.bit, o_10.length), (78, 1, 1));
+        assert_eq!(o_10.direction, Direction::Output);
+
+        let rtd = config.variable("RTDValue_2").unwrap();
+        assert_eq!((rtd.address, rtd.length), (116, 16));
+        assert!(config.variable("missing").is_err());
+        assert!(Config::parse("{}").is_err());
+
+        let beyond = r#"{"Devices": [{"id": "d", "productType": "96", "position": "32",
+            "offset": 65530, "inp": {"0": ["I_1", "0", "32", "4", true, "0000", "", ""]}}]}"#;
+        let err = Config::parse(beyond).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("variable I_1 has no valid offset"));
+    }
+}
@@ -0,0 +1,341 @@
+//! Typed access to the RevPi DIO, DI and DO modules.
+//!
+//! The three modules share one process image layout. Relative to the module's input offset:
+//!
+//! | offset | content                                    |
+//! |--------|--------------------------------------------|
+//! | 0..2   | digital inputs, bit n is input n + 1       |
+//! | 2..4   | InputStatus                                |
+//! | 4..6   | OutputStatus                               |
+//! | 6..70  | Counter_1..16, 32 bit each (DIO and DI)    |
+//!
+//! and relative to the module's output offset:
+//!
+//! | offset | content                                    |
+//! |--------|--------------------------------------------|
+//! | 0..2   | digital outputs, bit n is output n + 1     |
+//! | 2..18  | PWM_1..16 duty cycle in percent            |
+//!
+//! A module only has the parts that fit its input and output length from SDeviceInfo; the DI
+//! has no outputs, the DO no counters. Asking for a part the module does not have is an error.
+
+use crate::driver::Driver;
+use crate::find_module;
+use crate::picontrol::{SDeviceInfo, SPIValue};
+use std::io;
+
+/// Module type of the RevPi DIO.
+pub const DIO_MODULE_TYPE: u32 = 96;
+/// Module type of the RevPi DI.
+pub const DI_MODULE_TYPE: u32 = 97;
+/// Module type of the RevPi DO.
+pub const DO_MODULE_TYPE: u32 = 98;
+/// Channels addressable in the process image. The modules wire 14 of them.
+pub const CHANNELS: usize = 16;
+
+const INPUT_STATUS_OFFSET: u16 = 2;
+const OUTPUT_STATUS_OFFSET: u16 = 4;
+const COUNTER_OFFSET: u16 = 6;
+const PWM_OFFSET: u16 = 2;
+
+/// A status word of the input or output driver.
+///
+/// A non-zero value means the driver chip reports a fault, e.g. a missing 24 V supply or
+/// overtemperature; see the module documentation for the individual bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status(pub u16);
+
+impl Status {
+    /// No fault is reported.
+    pub fn is_ok(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Dio gives channel-indexed access to one DIO, DI or DO module. Channels are numbered from 1,
+/// as in piCtory.
+pub struct Dio<D> {
+    driver: D,
+    device: SDeviceInfo,
+}
+
+impl<D: Driver> Dio<D> {
+    /// Finds the DIO, DI or DO module at @address, or the first one if @address is None.
+    pub fn find(driver: D, address: Option<u8>) -> io::Result<Self> {
+        let devices = driver.get_device_info_list()?;
+        let device = find_module(
+            &devices,
+            &[DIO_MODULE_TYPE, DI_MODULE_TYPE, DO_MODULE_TYPE],
+            address,
+        )?;
+        Ok(Dio { driver, device })
+    }
+
+    /// The module's entry in the device list.
+    pub fn device(&self) -> &SDeviceInfo {
+        &self.device
+    }
+
+    /// Reads digital input @channel.
+    pub fn input(&self, channel: usize) -> io::Result<bool> {
+        let bit = channel_index(channel)?;
+        let address = self.input_address(0, 2)?;
+        self.get_bit(address, bit)
+    }
+
+    /// Reads all digital inputs, bit n is input n + 1.
+    pub fn inputs(&self) -> io::Result<u16> {
+        self.read_word(self.input_address(0, 2)?)
+    }
+
+    /// Reads back digital output @channel.
+    pub fn output(&self, channel: usize) -> io::Result<bool> {
+        let bit = channel_index(channel)?;
+        let address = self.output_address(0, 2)?;
+        self.get_bit(address, bit)
+    }
+
+    /// Switches digital output @channel.
+    ///
+    /// The bit is set by the driver, the other outputs are not touched.
+    pub fn set_output(&self, channel: usize, on: bool) -> io::Result<()> {
+        let bit = channel_index(channel)?;
+        let mut value = SPIValue {
+            i16uAddress: self.output_address(0, 2)?,
+            i8uBit: bit,
+            i8uValue: on as u8,
+        };
+        self.driver.set_bit_value(&mut value).map(|_| ())
+    }
+
+    /// Reads back all digital outputs, bit n is output n + 1.
+    pub fn outputs(&self) -> io::Result<u16> {
+        self.read_word(self.output_address(0, 2)?)
+    }
+
+    /// Sets all digital outputs at once, bit n is output n + 1.
+    pub fn set_outputs(&self, outputs: u16) -> io::Result<()> {
+        let address = self.output_address(0, 2)?;
+        self.driver
+            .write(u64::from(address), &outputs.to_le_bytes())
+    }
+
+    /// Reads the PWM duty cycle of output @channel in percent.
+    pub fn pwm(&self, channel: usize) -> io::Result<u8> {
+        let index = channel_index(channel)?;
+        let address = self.output_address(PWM_OFFSET + u16::from(index), 1)?;
+        Ok(self.driver.read(u64::from(address), 1)?[0])
+    }
+
+    /// Sets the PWM duty cycle of output @channel, @duty is in percent.
+    ///
+    /// The output has to be configured as PWM output in piCtory.
+    pub fn set_pwm(&self, channel: usize, duty: u8) -> io::Result<()> {
+        let index = channel_index(channel)?;
+        if duty > 100 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("duty cycle {}% is above 100%", duty),
+            ));
+        }
+        let address = self.output_address(PWM_OFFSET + u16::from(index), 1)?;
+        self.driver.write(u64::from(address), &[duty])
+    }
+
+    /// Reads the counter or encoder value of input @channel.
+    ///
+    /// Encoder values are two's complement, cast the result to i32 for them.
+    pub fn counter(&self, channel: usize) -> io::Result<u32> {
+        let index = channel_index(channel)?;
+        let address = counter_offset(&self.device, index)?;
+        let data = self.driver.read(address as u64, 4)?;
+        Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    /// Sets the counters or encoders of the given input channels to 0.
+    pub fn reset_counters(&self, channels: &[usize]) -> io::Result<()> {
+        let mut bitfield = 0u16;
+        for &channel in channels {
+            let index = channel_index(channel)?;
+            counter_offset(&self.device, index)?;
+            bitfield |= 1 << index;
+        }
+        self.driver.reset_counter(self.device.i8uAddress, bitfield)
+    }
+
+    /// Reads the status word of the input driver.
+    pub fn input_status(&self) -> io::Result<Status> {
+        let address = self.input_address(INPUT_STATUS_OFFSET, 2)?;
+        Ok(Status(self.read_word(address)?))
+    }
+
+    /// Reads the status word of the output driver.
+    pub fn output_status(&self) -> io::Result<Status> {
+        let address = self.input_address(OUTPUT_STATUS_OFFSET, 2)?;
+        Ok(Status(self.read_word(address)?))
+    }
+
+    fn get_bit(&self, address: u16, bit: u8) -> io::Result<bool> {
+        let mut value = SPIValue {
+            i16uAddress: address,
+            i8uBit: bit,
+            ..Default::default()
+        };
+        self.driver.get_bit_value(&mut value)?;
+        Ok(value.i8uValue != 0)
+    }
+
+    fn read_word(&self, address: u16) -> io::Result<u16> {
+        let data = self.driver.read(u64::from(address), 2)?;
+        Ok(u16::from_le_bytes([data[0], data[1]]))
+    }
+
+    fn input_address(&self, offset: u16, length: u16) -> io::Result<u16> {
+        area_address(
+            &self.device,
+            self.device.i16uInputOffset,
+            self.device.i16uInputLength,
+            offset,
+            length,
+        )
+    }
+
+    fn output_address(&self, offset: u16, length: u16) -> io::Result<u16> {
+        area_address(
+            &self.device,
+            self.device.i16uOutputOffset,
+            self.device.i16uOutputLength,
+            offset,
+            length,
+        )
+    }
+}
+
+fn channel_index(channel: usize) -> io::Result<u8> {
+    if channel == 0 || channel > CHANNELS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "channel {} does not exist, valid are 1..={}",
+                channel, CHANNELS
+            ),
+        ));
+    }
+    Ok((channel - 1) as u8)
+}
+
+fn area_address(
+    device: &SDeviceInfo,
+    start: u16,
+    size: u16,
+    offset: u16,
+    length: u16,
+) -> io::Result<u16> {
+    if u32::from(offset) + u32::from(length) > u32::from(size) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "module at address {} has no data at offset {} of its {} byte area",
+                device.i8uAddress, offset, size
+            ),
+        ));
+    }
+    start.checked_add(offset).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "module at address {} lies beyond the process image",
+                device.i8uAddress
+            ),
+        )
+    })
+}
+
+/// Absolute offset of the counter of input @index (0-based) of @device.
+pub(crate) fn counter_offset(device: &SDeviceInfo, index: u8) -> io::Result<usize> {
+    let address = area_address(
+        device,
+        device.i16uInputOffset,
+        device.i16uInputLength,
+        COUNTER_OFFSET + 4 * u16::from(index),
+        4,
+    )?;
+    Ok(address as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    #[test]
+    fn dio_channels_pwm_and_counters() {
+        let driver = MemoryDriver::new();
+        driver.add_device(SDeviceInfo {
+            i8uAddress: 32,
+            i16uModuleType: DI_MODULE_TYPE as u16,
+            i16uInputOffset: 0,
+            i16uInputLength: 70,
+            i16uOutputOffset: 70,
+            i16uOutputLength: 0,
+            ..Default::default()
+        });
+        driver.add_device(SDeviceInfo {
+            i8uAddress: 33,
+            i16uModuleType: DIO_MODULE_TYPE as u16,
+            i16uInputOffset: 70,
+            i16uInputLength: 70,
+            i16uOutputOffset: 140,
+            i16uOutputLength: 18,
+            ..Default::default()
+        });
+        driver.write(70, &[0b0000_0100, 0b0010_0000]).unwrap();
+        driver.write(70 + 6 + 4 * 2, &7u32.to_le_bytes()).unwrap();
+
+        let dio = Dio::find(&driver, Some(33)).unwrap();
+        assert!(dio.input(3).unwrap());
+        assert!(dio.input(14).unwrap());
+        assert!(!dio.input(1).unwrap());
+        assert_eq!(dio.inputs().unwrap(), 0x2004);
+
+        dio.set_output(10, true).unwrap();
+        dio.set_output(1, true).unwrap();
+        assert_eq!(dio.outputs().unwrap(), 0x0201);
+        assert!(dio.output(10).unwrap());
+
+        dio.set_pwm(2, 75).unwrap();
+        assert_eq!(driver.read(143, 1).unwrap(), vec![75]);
+        assert!(dio.set_pwm(2, 101).is_err());
+
+        assert_eq!(dio.counter(3).unwrap(), 7);
+        dio.reset_counters(&[3]).unwrap();
+        assert_eq!(dio.counter(3).unwrap(), 0);
+
+        let di = Dio::find(&driver, None).unwrap();
+        assert_eq!(di.device().i8uAddress, 32);
+        assert_eq!(
+            di.set_output(1, true).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert!(di.input(17).is_err());
+
+        driver.add_device(SDeviceInfo {
+            i8uAddress: 34,
+            i16uModuleType: DIO_MODULE_TYPE as u16,
+            i16uInputOffset: u16::MAX,
+            i16uInputLength: 70,
+            i16uOutputOffset: u16::MAX,
+            i16uOutputLength: 18,
+            ..Default::default()
+        });
+        let dio = Dio::find(&driver, Some(34)).unwrap();
+        assert_eq!(
+            dio.set_pwm(1, 50).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            dio.counter(1).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
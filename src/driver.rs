@@ -0,0 +1,245 @@
+//! The operations every piControl backend provides.
+
+use crate::error::config_error;
+use crate::picontrol::{self, SDeviceInfo, SPIValue, SPIVariable};
+use crate::{RevPiControl, SharedRevPiControl};
+use nix::libc::c_int;
+use std::io;
+
+/// Size in bytes of the piControl process image.
+pub const PROCESS_IMAGE_SIZE: usize = 4096;
+
+/// An event reported by KB_WAIT_FOR_EVENT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// piControl was reset, the configuration has to be reloaded.
+    Reset,
+    /// An event code this crate does not know about.
+    Unknown(c_int),
+}
+
+impl From<c_int> for Event {
+    fn from(code: c_int) -> Event {
+        match code as u32 {
+            picontrol::KB_EVENT_RESET => Event::Reset,
+            _ => Event::Unknown(code),
+        }
+    }
+}
+
+/// Driver is the set of operations of the piControl driver, implemented by the real device
+/// and by stand-ins such as [`MemoryDriver`](crate::MemoryDriver).
+///
+/// All methods take `&self`, implementations are expected to be usable from several threads.
+pub trait Driver: Send + Sync {
+    /// Reads @length bytes of process data starting at @offset.
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>>;
+
+    /// Writes process data at a specific position.
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+    /// Get the info for a variable.
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable>;
+
+    /// Gets a description of connected devices.
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>>;
+
+    /// Gets the value of one bit in the process image.
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool>;
+
+    /// Sets the value of one bit in the process image.
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool>;
+
+    /// Reset Pi Control Interface.
+    fn reset(&self) -> io::Result<()>;
+
+    /// Sets counters or encoders of the DIO/DI module at @address to 0, bit n of @bitfield
+    /// selects the counter on input n + 1.
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()>;
+
+    /// Stops or restarts the exchange of the process image with the modules, returns whether
+    /// it is stopped now.
+    fn stop_io(&self, stop: bool) -> io::Result<bool>;
+
+    /// Blocks until the driver reports an event.
+    fn wait_for_event(&self) -> io::Result<Event>;
+}
+
+pub(crate) fn nix_to_io(err: nix::Error) -> io::Error {
+    match err.as_errno() {
+        Some(errno) => errno.into(),
+        None => io::Error::other(err.to_string()),
+    }
+}
+
+impl Driver for SharedRevPiControl {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        SharedRevPiControl::read(self, offset, length)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        SharedRevPiControl::write(self, offset, data)
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        SharedRevPiControl::get_variable_info(self, name).map_err(nix_to_io)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        SharedRevPiControl::get_device_info_list(self).map_err(nix_to_io)
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        SharedRevPiControl::get_bit_value(self, value).map_err(nix_to_io)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        SharedRevPiControl::set_bit_value(self, value).map_err(nix_to_io)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        SharedRevPiControl::reset(self)
+            .map(|_| ())
+            .map_err(|e| config_error(e, self.last_message().unwrap_or(None)))
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        SharedRevPiControl::reset_counter(self, address, bitfield).map_err(nix_to_io)
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        SharedRevPiControl::stop_io(self, stop).map_err(nix_to_io)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        SharedRevPiControl::wait_for_event(self).map_err(nix_to_io)
+    }
+}
+
+impl Driver for RevPiControl {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        self.read_at(offset, length)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.write_at(offset, data)
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        RevPiControl::get_variable_info(self, name).map_err(nix_to_io)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        RevPiControl::get_device_info_list(self).map_err(nix_to_io)
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        RevPiControl::get_bit_value(self, value).map_err(nix_to_io)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        RevPiControl::set_bit_value(self, value).map_err(nix_to_io)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        RevPiControl::reset(self)
+            .map(|_| ())
+            .map_err(|e| config_error(e, self.last_message().unwrap_or(None)))
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        RevPiControl::reset_counter(self, address, bitfield).map_err(nix_to_io)
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        RevPiControl::stop_io(self, stop).map_err(nix_to_io)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        RevPiControl::wait_for_event(self).map_err(nix_to_io)
+    }
+}
+
+impl<D: Driver + ?Sized> Driver for std::sync::Arc<D> {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        (**self).read(offset, length)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        (**self).write(offset, data)
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        (**self).get_variable_info(name)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        (**self).get_device_info_list()
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        (**self).get_bit_value(value)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        (**self).set_bit_value(value)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        (**self).reset()
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        (**self).reset_counter(address, bitfield)
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        (**self).stop_io(stop)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        (**self).wait_for_event()
+    }
+}
+
+impl<D: Driver + ?Sized> Driver for &D {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        (**self).read(offset, length)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        (**self).write(offset, data)
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        (**self).get_variable_info(name)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        (**self).get_device_info_list()
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        (**self).get_bit_value(value)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        (**self).set_bit_value(value)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        (**self).reset()
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        (**self).reset_counter(address, bitfield)
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        (**self).stop_io(stop)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        (**self).wait_for_event()
+    }
+}
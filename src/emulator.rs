@@ -0,0 +1,573 @@
+//! An in-process emulation of a RevPi system.
+//!
+//! [`Emulator`] lays out the process image of a set of modules the way piCtory does, answers
+//! all [`Driver`] operations and lets a test drive the inputs along a virtual clock:
+//!
+//! ```ignore
+//! let emulator = Emulator::new(&[Module::new(CORE_MODULE_TYPE, 0), Module::new(DIO_MODULE_TYPE, 32)])?;
+//! emulator.schedule(Duration::from_millis(100), "I_1", Value::Bool(true))?;
+//! emulator.advance(Duration::from_millis(100))?;
+//! run_one_cycle(&emulator)?;
+//! emulator.assert_value("O_1", Value::Bool(true));
+//! ```
+
+use crate::aio;
+use crate::config::{Config, Device, Direction, Entry};
+use crate::dio::{DIO_MODULE_TYPE, DI_MODULE_TYPE, DO_MODULE_TYPE};
+use crate::driver::{Driver, Event};
+use crate::get_module_name;
+use crate::memory::MemoryDriver;
+use crate::picontrol::{SDeviceInfo, SPIValue, SPIVariable};
+use crate::value::{Value, Variable};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Module type of the RevPi Core.
+pub const CORE_MODULE_TYPE: u32 = 95;
+
+/// A module to emulate, of a type listed in [`Emulator::new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Module {
+    pub module_type: u32,
+    /// Position in the configuration, 0 for the Core, 32 and up for the modules to its right.
+    pub position: u8,
+}
+
+impl Module {
+    pub fn new(module_type: u32, position: u8) -> Self {
+        Module {
+            module_type,
+            position,
+        }
+    }
+}
+
+/// An input change scheduled at a point of the virtual clock.
+struct Step {
+    at: Duration,
+    variable: Variable,
+    value: Value,
+}
+
+struct Clock {
+    now: Duration,
+    steps: Vec<Step>,
+}
+
+/// Emulator is a [`Driver`] backed by a [`MemoryDriver`] that is set up from a configuration.
+///
+/// The process image starts with the default values of all variables and is set back to them
+/// by reset, which also reports [`Event::Reset`]. Inputs are changed by the test, either at
+/// once with [`Emulator::set`] or along the virtual clock with [`Emulator::schedule`]; while the
+/// I/O update is stopped, scheduled changes wait until it is restarted.
+pub struct Emulator {
+    driver: MemoryDriver,
+    config: Config,
+    clock: Mutex<Clock>,
+}
+
+impl Emulator {
+    /// Emulates @modules, placed in the process image in the given order.
+    ///
+    /// Known module types are the RevPi Core, DIO, DI, DO and AIO. Variables are named as in
+    /// piCtory; when a name is taken by an earlier module, the module's position is appended,
+    /// e.g. `I_1_33`.
+    pub fn new(modules: &[Module]) -> io::Result<Self> {
+        Self::from_config(module_config(modules)?)
+    }
+
+    /// Emulates the modules of a parsed config.rsc.
+    pub fn from_config(config: Config) -> io::Result<Self> {
+        let driver = MemoryDriver::new();
+        for device in &config.devices {
+            driver.add_device(device_info(device));
+            add_variables(&driver, device)?;
+        }
+        let emulator = Emulator {
+            driver,
+            config,
+            clock: Mutex::new(Clock {
+                now: Duration::from_secs(0),
+                steps: Vec::new(),
+            }),
+        };
+        emulator.write_defaults()?;
+        Ok(emulator)
+    }
+
+    /// The emulated configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The underlying memory driver.
+    pub fn driver(&self) -> &MemoryDriver {
+        &self.driver
+    }
+
+    /// Time passed on the virtual clock.
+    pub fn elapsed(&self) -> Duration {
+        self.clock().now
+    }
+
+    /// Sets variable @name to @value at once, @value has to fit the variable's length.
+    pub fn set(&self, name: &str, value: Value) -> io::Result<()> {
+        self.variable(name, &value)?.write(&self.driver, value)
+    }
+
+    /// Reads variable @name, interpreted as the type of @like.
+    pub fn value(&self, name: &str, like: &Value) -> io::Result<Value> {
+        self.variable(name, like)?.read(&self.driver)
+    }
+
+    /// Sets variable @name to @value once the virtual clock reaches @at.
+    pub fn schedule(&self, at: Duration, name: &str, value: Value) -> io::Result<()> {
+        let variable = self.variable(name, &value)?;
+        let mut clock = self.clock();
+        // steps for the same time keep the order they were scheduled in
+        let index = clock.steps.partition_point(|s| s.at <= at);
+        clock.steps.insert(
+            index,
+            Step {
+                at,
+                variable,
+                value,
+            },
+        );
+        Ok(())
+    }
+
+    /// Advances the virtual clock by @by and applies the scheduled changes that became due.
+    pub fn advance(&self, by: Duration) -> io::Result<()> {
+        let mut clock = self.clock();
+        clock.now += by;
+        if self.driver.io_stopped() {
+            return Ok(());
+        }
+        let now = clock.now;
+        let due = clock.steps.partition_point(|s| s.at <= now);
+        for step in clock.steps.drain(..due) {
+            step.variable.write(&self.driver, step.value)?;
+        }
+        Ok(())
+    }
+
+    /// Number of scheduled changes not applied yet.
+    pub fn pending(&self) -> usize {
+        self.clock().steps.len()
+    }
+
+    /// Asserts that variable @name holds @expected.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the actual value if it differs, or if the variable can not be read.
+    pub fn assert_value(&self, name: &str, expected: Value) {
+        match self.value(name, &expected) {
+            Ok(actual) => assert!(
+                actual == expected,
+                "variable {} is {}, expected {} (at {:?})",
+                name,
+                actual,
+                expected,
+                self.elapsed()
+            ),
+            Err(e) => panic!("can not read variable {}: {}", name, e),
+        }
+    }
+
+    fn variable(&self, name: &str, like: &Value) -> io::Result<Variable> {
+        Variable::resolve(&self.driver, name)?.with_type(like.kind())
+    }
+
+    fn write_defaults(&self) -> io::Result<()> {
+        for (_, entry) in self.config.entries() {
+            let variable = entry.variable()?;
+            let default = if entry.length == 1 {
+                Value::Bool(entry.default != 0)
+            } else {
+                let bytes = entry.default.to_le_bytes();
+                variable.kind.decode(&bytes[..variable.byte_length()])?
+            };
+            variable.write(&self.driver, default)?;
+        }
+        Ok(())
+    }
+
+    fn clock(&self) -> MutexGuard<'_, Clock> {
+        self.clock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Driver for Emulator {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        self.driver.read(offset, length)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.driver.write(offset, data)
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        self.driver.get_variable_info(name)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        self.driver.get_device_info_list()
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        self.driver.get_bit_value(value)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        self.driver.set_bit_value(value)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        self.write_defaults()?;
+        self.driver.push_event(Event::Reset);
+        Ok(())
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        self.driver.reset_counter(address, bitfield)
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        let stopped = self.driver.stop_io(stop)?;
+        if !stopped {
+            self.advance(Duration::from_secs(0))?;
+        }
+        Ok(stopped)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        self.driver.wait_for_event()
+    }
+}
+
+/// Registers the entries of @device with @driver, refusing names that do not fit SPIVariable.
+pub(crate) fn add_variables(driver: &MemoryDriver, device: &Device) -> io::Result<()> {
+    for entry in &device.entries {
+        if entry.name.len() >= 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "variable name {} of {} is longer than 31 bytes",
+                    entry.name, device.name
+                ),
+            ));
+        }
+        driver.add_variable(&entry.name, entry.address, entry.bit, entry.length);
+    }
+    Ok(())
+}
+
+/// The SDeviceInfo the driver would report for @device.
+///
+/// The areas are those of [`Device::area`], so the device list always matches the
+/// configuration; a section without variables is empty and follows the one before it.
+pub(crate) fn device_info(device: &Device) -> SDeviceInfo {
+    let (input_offset, input_length) = device.area(Direction::Input).unwrap_or((device.offset, 0));
+    let (output_offset, output_length) = device
+        .area(Direction::Output)
+        .unwrap_or((input_offset + input_length, 0));
+    let (config_offset, config_length) = device
+        .area(Direction::Memory)
+        .unwrap_or((output_offset + output_length, 0));
+    SDeviceInfo {
+        i8uAddress: device.position,
+        i8uActive: 1,
+        i16uModuleType: device.product_type as u16,
+        i16uInputOffset: input_offset,
+        i16uInputLength: input_length,
+        i16uOutputOffset: output_offset,
+        i16uOutputLength: output_length,
+        i16uConfigOffset: config_offset,
+        i16uConfigLength: config_length,
+        ..Default::default()
+    }
+}
+
+/// Name, section, offset relative to the module, bit position and length in bits.
+type ModuleVariable = (String, Direction, u16, u16, u16);
+
+/// The variables of a module type, None if it is not known.
+fn module_variables(module_type: u32) -> Option<Vec<ModuleVariable>> {
+    let mut variables = Vec::new();
+    let mut add = |name: String, direction, offset, bit, length| {
+        variables.push((name, direction, offset, bit, length))
+    };
+    match module_type {
+        CORE_MODULE_TYPE => {
+            add("RevPiStatus".into(), Direction::Input, 0, 0, 8);
+            add("RevPiIOCycle".into(), Direction::Input, 1, 0, 8);
+            add("RevPiWDT".into(), Direction::Input, 2, 0, 16);
+            add("RevPiCoreTemperature".into(), Direction::Input, 4, 0, 8);
+            add("RevPiCPUFrequency".into(), Direction::Input, 5, 0, 8);
+            add("RevPiLED".into(), Direction::Output, 6, 0, 8);
+        }
+        DIO_MODULE_TYPE | DI_MODULE_TYPE | DO_MODULE_TYPE => {
+            let has_inputs = module_type != DO_MODULE_TYPE;
+            let has_outputs = module_type != DI_MODULE_TYPE;
+            if has_inputs {
+                for i in 0..14 {
+                    add(format!("I_{}", i + 1), Direction::Input, 0, i, 1);
+                }
+            }
+            add("InputStatus".into(), Direction::Input, 2, 0, 16);
+            add("OutputStatus".into(), Direction::Input, 4, 0, 16);
+            if has_inputs {
+                for i in 0..16 {
+                    add(
+                        format!("Counter_{}", i + 1),
+                        Direction::Input,
+                        6 + 4 * i,
+                        0,
+                        32,
+                    );
+                }
+            }
+            if has_outputs {
+                let outputs = if has_inputs { 70 } else { 6 };
+                for i in 0..14 {
+                    add(format!("O_{}", i + 1), Direction::Output, outputs, i, 1);
+                }
+                for i in 0..16 {
+                    add(
+                        format!("PWM_{}", i + 1),
+                        Direction::Output,
+                        outputs + 2 + i,
+                        0,
+                        8,
+                    );
+                }
+                let memory = outputs + 18;
+                add("OutputPushPull".into(), Direction::Memory, memory, 0, 16);
+                add(
+                    "OutputOpenLoadDetect".into(),
+                    Direction::Memory,
+                    memory + 2,
+                    0,
+                    16,
+                );
+                add(
+                    "OutputPWMActive".into(),
+                    Direction::Memory,
+                    memory + 4,
+                    0,
+                    16,
+                );
+                add(
+                    "OutputPWMFrequency".into(),
+                    Direction::Memory,
+                    memory + 6,
+                    0,
+                    8,
+                );
+            }
+        }
+        aio::MODULE_TYPE => {
+            for i in 0..4 {
+                add(
+                    format!("InputValue_{}", i + 1),
+                    Direction::Input,
+                    2 * i,
+                    0,
+                    16,
+                );
+            }
+            for i in 0..4 {
+                add(
+                    format!("InputStatus_{}", i + 1),
+                    Direction::Input,
+                    8 + i,
+                    0,
+                    8,
+                );
+            }
+            for i in 0..2 {
+                add(
+                    format!("RTDValue_{}", i + 1),
+                    Direction::Input,
+                    12 + 2 * i,
+                    0,
+                    16,
+                );
+            }
+            for i in 0..2 {
+                add(
+                    format!("RTDStatus_{}", i + 1),
+                    Direction::Input,
+                    16 + i,
+                    0,
+                    8,
+                );
+            }
+            for i in 0..2 {
+                add(
+                    format!("OutputStatus_{}", i + 1),
+                    Direction::Input,
+                    18 + i,
+                    0,
+                    8,
+                );
+            }
+            for i in 0..2 {
+                add(
+                    format!("OutputValue_{}", i + 1),
+                    Direction::Output,
+                    20 + 2 * i,
+                    0,
+                    16,
+                );
+            }
+        }
+        _ => return None,
+    }
+    Some(variables)
+}
+
+/// Builds the configuration piCtory would write for @modules.
+fn module_config(modules: &[Module]) -> io::Result<Config> {
+    let mut config = Config::default();
+    let mut offset = 0u16;
+    for module in modules {
+        if config.device(module.position).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("two modules at position {}", module.position),
+            ));
+        }
+        let variables = module_variables(module.module_type).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("module type {} can not be emulated", module.module_type),
+            )
+        })?;
+        let mut device = Device {
+            name: get_module_name(module.module_type).to_owned(),
+            id: String::new(),
+            product_type: module.module_type,
+            position: module.position,
+            offset,
+            comment: String::new(),
+            entries: Vec::new(),
+        };
+        for (name, direction, relative, bit, length) in variables {
+            let name = if config.find(&name).is_some() {
+                format!("{}_{}", name, module.position)
+            } else {
+                name
+            };
+            device.entries.push(Entry {
+                name,
+                direction,
+                address: offset + relative + bit / 8,
+                bit: (bit % 8) as u8,
+                length,
+                default: 0,
+                exported: false,
+                comment: String::new(),
+            });
+        }
+        offset = device
+            .entries
+            .iter()
+            .map(|e| e.address + e.byte_length())
+            .max()
+            .unwrap_or(offset);
+        config.devices.push(device);
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::dio::Dio;
+
+    #[test]
+    fn emulate_modules() {
+        let emulator = Emulator::new(&[
+            Module::new(CORE_MODULE_TYPE, 0),
+            Module::new(DIO_MODULE_TYPE, 32),
+            Module::new(DIO_MODULE_TYPE, 33),
+        ])
+        .unwrap();
+        let devices = emulator.get_device_info_list().unwrap();
+        assert_eq!(
+            (devices[1].i16uInputOffset, devices[1].i16uInputLength),
+            (7, 70)
+        );
+        assert_eq!(
+            (devices[1].i16uOutputOffset, devices[1].i16uOutputLength),
+            (77, 18)
+        );
+        assert_eq!(devices[2].i16uInputOffset, 102);
+
+        // the unwired inputs of a DO are part of its input area, in the device list and the
+        // configuration alike
+        let emulator_do = Emulator::new(&[Module::new(DO_MODULE_TYPE, 32)]).unwrap();
+        let info = emulator_do.get_device_info_list().unwrap()[0];
+        assert_eq!((info.i16uInputOffset, info.i16uInputLength), (0, 6));
+        assert_eq!(
+            emulator_do.config().devices[0].area(Direction::Input),
+            Some((0, 6))
+        );
+        assert!(
+            crate::validate::validate_driver(&emulator_do, emulator_do.config())
+                .unwrap()
+                .problems
+                .is_empty()
+        );
+        assert_eq!(
+            emulator.get_variable_info("I_1_33").unwrap().i16uAddress,
+            102
+        );
+
+        let dio = Dio::find(&emulator, Some(32)).unwrap();
+        emulator
+            .schedule(Duration::from_millis(50), "I_3", Value::Bool(true))
+            .unwrap();
+        emulator
+            .schedule(Duration::from_millis(20), "Counter_1", Value::U32(4))
+            .unwrap();
+        emulator.advance(Duration::from_millis(30)).unwrap();
+        assert_eq!(dio.counter(1).unwrap(), 4);
+        assert!(!dio.input(3).unwrap());
+
+        emulator.stop_io(true).unwrap();
+        emulator.advance(Duration::from_millis(30)).unwrap();
+        assert_eq!(emulator.pending(), 1);
+        emulator.stop_io(false).unwrap();
+        assert!(dio.input(3).unwrap());
+
+        dio.set_output(2, true).unwrap();
+        emulator.assert_value("O_2", Value::Bool(true));
+        emulator.reset().unwrap();
+        assert_eq!(emulator.wait_for_event().unwrap(), Event::Reset);
+        emulator.assert_value("O_2", Value::Bool(false));
+
+        assert!(Emulator::new(&[Module::new(1, 31)]).is_err());
+    }
+
+    #[test]
+    fn emulate_config_rsc() {
+        let emulator = Emulator::from_config(Config::parse(CONFIG).unwrap()).unwrap();
+        emulator.assert_value("OutputPWMFrequency", Value::U8(1));
+        emulator.set("RTDValue_1", Value::I16(-20)).unwrap();
+        assert_eq!(emulator.read(114, 2).unwrap(), (-20i16).to_le_bytes());
+        assert!(emulator.set("RTDValue_1", Value::U32(1)).is_err());
+
+        let mut config = Config::parse(CONFIG).unwrap();
+        config.devices[1].entries[0].name = "A_name_that_does_not_fit_in_32_bytes".to_owned();
+        let err = Emulator::from_config(config).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
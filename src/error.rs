@@ -0,0 +1,235 @@
+//! Typed errors for the PICONTROL_CONFIG_ERROR_* codes.
+//!
+//! When KB_RESET finds that config.rsc does not fit the modules, the ioctl fails with one of
+//! the codes -10 to -16 as errno. These collide with ordinary errno values, e.g. -11 would
+//! read as EAGAIN, so they are only interpreted for calls that load the configuration.
+
+use crate::config::Direction;
+use crate::picontrol;
+use crate::validate::{Problem, Report};
+use std::error;
+use std::fmt;
+use std::io;
+
+/// What the driver found wrong with the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigErrorKind {
+    WrongModuleType,
+    WrongInputLength,
+    WrongOutputLength,
+    WrongConfigLength,
+    WrongInputOffset,
+    WrongOutputOffset,
+    WrongConfigOffset,
+}
+
+impl ConfigErrorKind {
+    /// Interprets a PICONTROL_CONFIG_ERROR_* code, or the positive errno the ioctl returns.
+    pub fn from_code(code: i32) -> Option<ConfigErrorKind> {
+        Some(match -code.checked_abs()? {
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_MODULE_TYPE => ConfigErrorKind::WrongModuleType,
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_INPUT_LENGTH => {
+                ConfigErrorKind::WrongInputLength
+            }
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_OUTPUT_LENGTH => {
+                ConfigErrorKind::WrongOutputLength
+            }
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_CONFIG_LENGTH => {
+                ConfigErrorKind::WrongConfigLength
+            }
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_INPUT_OFFSET => {
+                ConfigErrorKind::WrongInputOffset
+            }
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_OUTPUT_OFFSET => {
+                ConfigErrorKind::WrongOutputOffset
+            }
+            picontrol::PICONTROL_CONFIG_ERROR_WRONG_CONFIG_OFFSET => {
+                ConfigErrorKind::WrongConfigOffset
+            }
+            _ => return None,
+        })
+    }
+
+    /// The PICONTROL_CONFIG_ERROR_* code.
+    pub fn code(self) -> i32 {
+        match self {
+            ConfigErrorKind::WrongModuleType => picontrol::PICONTROL_CONFIG_ERROR_WRONG_MODULE_TYPE,
+            ConfigErrorKind::WrongInputLength => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_INPUT_LENGTH
+            }
+            ConfigErrorKind::WrongOutputLength => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_OUTPUT_LENGTH
+            }
+            ConfigErrorKind::WrongConfigLength => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_CONFIG_LENGTH
+            }
+            ConfigErrorKind::WrongInputOffset => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_INPUT_OFFSET
+            }
+            ConfigErrorKind::WrongOutputOffset => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_OUTPUT_OFFSET
+            }
+            ConfigErrorKind::WrongConfigOffset => {
+                picontrol::PICONTROL_CONFIG_ERROR_WRONG_CONFIG_OFFSET
+            }
+        }
+    }
+
+    /// Whether @problem, found by the validator, is a cause of this error.
+    pub fn matches(self, problem: &Problem) -> bool {
+        matches!(
+            (self, problem),
+            (
+                ConfigErrorKind::WrongModuleType,
+                Problem::TypeMismatch { .. }
+            ) | (
+                ConfigErrorKind::WrongInputLength | ConfigErrorKind::WrongInputOffset,
+                Problem::AreaMismatch {
+                    direction: Direction::Input,
+                    ..
+                },
+            ) | (
+                ConfigErrorKind::WrongOutputLength | ConfigErrorKind::WrongOutputOffset,
+                Problem::AreaMismatch {
+                    direction: Direction::Output,
+                    ..
+                },
+            )
+        )
+    }
+}
+
+impl fmt::Display for ConfigErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ConfigErrorKind::WrongModuleType => "a module is of another type than configured",
+            ConfigErrorKind::WrongInputLength => "the input length of a module is wrong",
+            ConfigErrorKind::WrongOutputLength => "the output length of a module is wrong",
+            ConfigErrorKind::WrongConfigLength => "the configuration length of a module is wrong",
+            ConfigErrorKind::WrongInputOffset => "the input offset of a module is wrong",
+            ConfigErrorKind::WrongOutputOffset => "the output offset of a module is wrong",
+            ConfigErrorKind::WrongConfigOffset => "the configuration offset of a module is wrong",
+        })
+    }
+}
+
+/// The driver rejected the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub kind: ConfigErrorKind,
+    /// Address of the affected module, if known.
+    pub module: Option<u8>,
+    /// The driver's message, from KB_GET_LAST_MESSAGE.
+    pub message: Option<String>,
+}
+
+impl ConfigError {
+    pub fn new(kind: ConfigErrorKind) -> Self {
+        ConfigError {
+            kind,
+            module: None,
+            message: None,
+        }
+    }
+
+    /// The ConfigError inside @err, if it is one.
+    pub fn from_io(err: &io::Error) -> Option<&ConfigError> {
+        err.get_ref().and_then(|e| e.downcast_ref())
+    }
+
+    /// Takes the affected module from the first problem of @report that explains the error.
+    pub fn locate(&mut self, report: &Report) {
+        let position = report
+            .problems
+            .iter()
+            .find(|p| self.kind.matches(p))
+            .map(|p| match p {
+                Problem::Missing { position, .. }
+                | Problem::Unconfigured { position, .. }
+                | Problem::TypeMismatch { position, .. }
+                | Problem::AreaMismatch { position, .. } => *position,
+            });
+        if position.is_some() {
+            self.module = position;
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid configuration: {}", self.kind)?;
+        if let Some(module) = self.module {
+            write!(f, " (module at address {})", module)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Converts the error of a call that loads the configuration, interpreting the
+/// PICONTROL_CONFIG_ERROR_* codes.
+pub(crate) fn config_error(err: nix::Error, message: Option<String>) -> io::Error {
+    match err
+        .as_errno()
+        .and_then(|errno| ConfigErrorKind::from_code(errno as i32))
+    {
+        Some(kind) => ConfigError {
+            kind,
+            module: None,
+            message,
+        }
+        .into(),
+        None => crate::driver::nix_to_io(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::errno::Errno;
+
+    #[test]
+    fn config_error_codes() {
+        assert_eq!(
+            ConfigErrorKind::from_code(-11),
+            Some(ConfigErrorKind::WrongInputLength)
+        );
+        assert_eq!(
+            ConfigErrorKind::from_code(16),
+            Some(ConfigErrorKind::WrongConfigOffset)
+        );
+        assert_eq!(ConfigErrorKind::from_code(-17), None);
+        assert_eq!(ConfigErrorKind::from_code(i32::MIN), None);
+        assert_eq!(ConfigErrorKind::WrongModuleType.code(), -10);
+
+        let err = config_error(nix::Error::Sys(Errno::EAGAIN), Some("DIO 32".to_owned()));
+        let mut error = ConfigError::from_io(&err).unwrap().clone();
+        assert_eq!(error.kind, ConfigErrorKind::WrongInputLength);
+        error.locate(&Report {
+            problems: vec![Problem::AreaMismatch {
+                position: 32,
+                direction: Direction::Input,
+                configured: (7, 70),
+                found: (7, 6),
+            }],
+        });
+        assert_eq!(
+            error.to_string(),
+            "invalid configuration: the input length of a module is wrong \
+             (module at address 32): DIO 32"
+        );
+
+        let other = config_error(nix::Error::Sys(Errno::ENODEV), None);
+        assert!(ConfigError::from_io(&other).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+//! Human readable dumps of the process image.
+//!
+//! Where [`RevPiControl::dump`](crate::RevPiControl::dump) saves the raw bytes, [`export`]
+//! writes every variable of the configuration with its module, direction, offset, bit,
+//! length and decoded value, as JSON or CSV.
+
+use crate::config::{Config, Direction};
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::value::{Value, VariableType};
+use serde_json::{json, Value as Json};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Output format of [`export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A JSON array with one object per variable.
+    Json,
+    /// A header line followed by one line per variable.
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown format {}, expected json or csv", s),
+            )),
+        }
+    }
+}
+
+/// A variable with its current value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// Name of the device the variable belongs to.
+    pub module: String,
+    /// Position of the device.
+    pub position: u8,
+    pub name: String,
+    pub direction: Direction,
+    /// Absolute address of the first byte in the process image.
+    pub offset: u16,
+    pub bit: u8,
+    /// Length in bits.
+    pub length: u16,
+    /// The value, as the unsigned type of the length.
+    pub value: Value,
+}
+
+const CSV_HEADER: &str = "module,position,name,direction,offset,bit,length,value";
+
+/// Decodes all variables of @config from one read of the process image of @driver.
+pub fn rows<D: Driver + ?Sized>(driver: &D, config: &Config) -> io::Result<Vec<Row>> {
+    let image = driver.read(0, PROCESS_IMAGE_SIZE)?;
+    config
+        .entries()
+        .map(|(device, entry)| {
+            Ok(Row {
+                module: device.name.clone(),
+                position: device.position,
+                name: entry.name.clone(),
+                direction: entry.direction,
+                offset: entry.address,
+                bit: entry.bit,
+                length: entry.length,
+                value: entry.variable()?.decode(&image)?,
+            })
+        })
+        .collect()
+}
+
+/// Writes the variables of @config with their current values to @out.
+pub fn export<D: Driver + ?Sized, W: Write>(
+    driver: &D,
+    config: &Config,
+    format: Format,
+    out: W,
+) -> io::Result<()> {
+    let rows = rows(driver, config)?;
+    match format {
+        Format::Json => write_json(&rows, out),
+        Format::Csv => write_csv(&rows, out),
+    }
+}
+
+/// Writes @rows as a JSON array.
+pub fn write_json<W: Write>(rows: &[Row], mut out: W) -> io::Result<()> {
+    let rows: Vec<Json> = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "module": row.module,
+                "position": row.position,
+                "name": row.name,
+                "direction": row.direction.to_string(),
+                "offset": row.offset,
+                "bit": row.bit,
+                "length": row.length,
+                "value": json_value(row.value),
+            })
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut out, &rows)?;
+    writeln!(out)
+}
+
+/// Writes @rows as CSV, with a header line.
+pub fn write_csv<W: Write>(rows: &[Row], mut out: W) -> io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)?;
+    for row in rows {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&row.module),
+            row.position,
+            csv_field(&row.name),
+            row.direction,
+            row.offset,
+            row.bit,
+            row.length,
+            row.value
+        )?;
+    }
+    Ok(())
+}
+
+/// Converts @value to a JSON boolean or number.
+pub fn json_value(value: Value) -> Json {
+    match value {
+        Value::Bool(v) => v.into(),
+        Value::U8(v) => v.into(),
+        Value::I8(v) => v.into(),
+        Value::U16(v) => v.into(),
+        Value::I16(v) => v.into(),
+        Value::U32(v) => v.into(),
+        Value::I32(v) => v.into(),
+        Value::F32(v) => v.into(),
+    }
+}
+
+/// Converts a JSON value, a boolean, number or string, to a value of type @kind.
+pub fn value_from_json(kind: VariableType, json: &Json) -> io::Result<Value> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {} value {}", kind, json),
+        )
+    };
+    match json {
+        Json::Bool(on) if kind == VariableType::Bool => Ok(Value::Bool(*on)),
+        Json::Number(number) => match number.as_f64() {
+            Some(n) if kind != VariableType::Bool || n == 0.0 || n == 1.0 => kind.from_f64(n),
+            _ => Err(invalid()),
+        },
+        Json::String(s) => kind.parse_value(s),
+        _ => Err(invalid()),
+    }
+}
+
+// piCtory names may contain commas, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn export_formats() {
+        let emulator = Emulator::from_config(Config::parse(CONFIG).unwrap()).unwrap();
+        emulator.set("O_10", Value::Bool(true)).unwrap();
+        let config = emulator.config().clone();
+
+        let mut csv = Vec::new();
+        export(&emulator, &config, Format::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert!(lines.any(|l| l == "RevPi DIO,32,O_10,output,78,1,1,1"));
+        assert_eq!(csv.lines().count(), config.entries().count() + 1);
+
+        let mut json = Vec::new();
+        export(&emulator, &config, Format::Json, &mut json).unwrap();
+        let json: Json = serde_json::from_slice(&json).unwrap();
+        let o_10 = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|row| row["name"] == "O_10")
+            .unwrap();
+        assert_eq!(o_10["value"], json!(true));
+        assert_eq!(o_10["direction"], "output");
+
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,846 @@
+//! A gRPC service for the process image, for fleet management backends.
+//!
+//! The service is defined in `proto/picontrol.proto`: device listing, variable reads and
+//! writes, batch reads and snapshots of the process image, streams of driver events and of
+//! variable changes, and the maintenance operations reset, stop IO and counter reset.
+//!
+//! [`GrpcService`] serves it for any [`Driver`]; the generated client is in [`proto`], and the
+//! conversions here between its messages and the types of this crate are shared with the
+//! `picontrol-client` crate. Driver errors are reported as gRPC status codes, see
+//! [`to_status`] and [`from_status`].
+//!
+//! With a [`token`](GrpcService::token), every call needs `authorization: Bearer <token>`
+//! metadata and is otherwise refused as UNAUTHENTICATED. The service offers no TLS, so a token
+//! only protects against other hosts if the network between them is trusted.
+
+// tonic's Status is large, and every handler returns it
+#![allow(clippy::result_large_err)]
+
+use crate::async_driver::AsyncRevPiControl;
+use crate::driver::{Driver, Event, PROCESS_IMAGE_SIZE};
+use crate::picontrol::{SDeviceInfo, SPIValue, SPIVariable, KB_EVENT_RESET};
+use crate::value::{Value, Variable, VariableType};
+use crate::SharedRevPiControl;
+use proto::pi_control_server::{PiControl, PiControlServer};
+use std::convert::TryFrom;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{Request, Response, Status};
+
+/// The messages, client and server generated from `proto/picontrol.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("picontrol");
+}
+
+/// Maps a driver error to the gRPC status reported to clients.
+pub fn to_status(err: &io::Error) -> Status {
+    let msg = err.to_string();
+    match err.kind() {
+        io::ErrorKind::NotFound => Status::not_found(msg),
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => Status::invalid_argument(msg),
+        io::ErrorKind::PermissionDenied => Status::permission_denied(msg),
+        io::ErrorKind::Unsupported => Status::unimplemented(msg),
+        _ => Status::internal(msg),
+    }
+}
+
+/// Maps a gRPC status received from the service back to a driver error.
+pub fn from_status(status: &Status) -> io::Error {
+    let kind = match status.code() {
+        tonic::Code::NotFound => io::ErrorKind::NotFound,
+        tonic::Code::InvalidArgument | tonic::Code::OutOfRange => io::ErrorKind::InvalidInput,
+        tonic::Code::PermissionDenied | tonic::Code::Unauthenticated => {
+            io::ErrorKind::PermissionDenied
+        }
+        tonic::Code::Unimplemented => io::ErrorKind::Unsupported,
+        tonic::Code::Unavailable => io::ErrorKind::ConnectionRefused,
+        tonic::Code::DeadlineExceeded => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, status.message().to_owned())
+}
+
+impl From<&SDeviceInfo> for proto::DeviceInfo {
+    fn from(device: &SDeviceInfo) -> Self {
+        proto::DeviceInfo {
+            address: u32::from(device.i8uAddress),
+            serial_number: device.i32uSerialnumber,
+            module_type: u32::from(device.i16uModuleType),
+            hw_revision: u32::from(device.i16uHW_Revision),
+            sw_major: u32::from(device.i16uSW_Major),
+            sw_minor: u32::from(device.i16uSW_Minor),
+            svn_revision: device.i32uSVN_Revision,
+            input_length: u32::from(device.i16uInputLength),
+            output_length: u32::from(device.i16uOutputLength),
+            config_length: u32::from(device.i16uConfigLength),
+            base_offset: u32::from(device.i16uBaseOffset),
+            input_offset: u32::from(device.i16uInputOffset),
+            output_offset: u32::from(device.i16uOutputOffset),
+            config_offset: u32::from(device.i16uConfigOffset),
+            first_entry: u32::from(device.i16uFirstEntry),
+            entries: u32::from(device.i16uEntries),
+            module_state: u32::from(device.i8uModuleState),
+            active: device.i8uActive != 0,
+            module_name: crate::get_module_name(u32::from(device.i16uModuleType)).to_owned(),
+        }
+    }
+}
+
+impl TryFrom<&proto::DeviceInfo> for SDeviceInfo {
+    type Error = io::Error;
+
+    fn try_from(device: &proto::DeviceInfo) -> io::Result<Self> {
+        Ok(SDeviceInfo {
+            i8uAddress: narrow(device.address, "address")?,
+            i32uSerialnumber: device.serial_number,
+            i16uModuleType: narrow(device.module_type, "module_type")?,
+            i16uHW_Revision: narrow(device.hw_revision, "hw_revision")?,
+            i16uSW_Major: narrow(device.sw_major, "sw_major")?,
+            i16uSW_Minor: narrow(device.sw_minor, "sw_minor")?,
+            i32uSVN_Revision: device.svn_revision,
+            i16uInputLength: narrow(device.input_length, "input_length")?,
+            i16uOutputLength: narrow(device.output_length, "output_length")?,
+            i16uConfigLength: narrow(device.config_length, "config_length")?,
+            i16uBaseOffset: narrow(device.base_offset, "base_offset")?,
+            i16uInputOffset: narrow(device.input_offset, "input_offset")?,
+            i16uOutputOffset: narrow(device.output_offset, "output_offset")?,
+            i16uConfigOffset: narrow(device.config_offset, "config_offset")?,
+            i16uFirstEntry: narrow(device.first_entry, "first_entry")?,
+            i16uEntries: narrow(device.entries, "entries")?,
+            i8uModuleState: narrow(device.module_state, "module_state")?,
+            i8uActive: device.active as u8,
+            ..Default::default()
+        })
+    }
+}
+
+impl From<&SPIVariable> for proto::VariableInfo {
+    fn from(variable: &SPIVariable) -> Self {
+        proto::VariableInfo {
+            name: variable.name().unwrap_or_default().to_owned(),
+            address: u32::from(variable.i16uAddress),
+            bit: u32::from(variable.i8uBit),
+            length: u32::from(variable.i16uLength),
+        }
+    }
+}
+
+impl TryFrom<&proto::VariableInfo> for SPIVariable {
+    type Error = io::Error;
+
+    fn try_from(variable: &proto::VariableInfo) -> io::Result<Self> {
+        if variable.name.len() >= 32 {
+            return Err(invalid_input(format!(
+                "variable name {} is too long",
+                variable.name
+            )));
+        }
+        Ok(SPIVariable {
+            strVarName: crate::byte_to_int8_array(&variable.name),
+            i16uAddress: narrow(variable.address, "address")?,
+            i8uBit: narrow(variable.bit, "bit")?,
+            i16uLength: narrow(variable.length, "length")?,
+        })
+    }
+}
+
+impl From<Value> for proto::Value {
+    fn from(value: Value) -> Self {
+        use proto::value::Value as V;
+        let value = match value {
+            Value::Bool(v) => V::BoolValue(v),
+            Value::U8(v) => V::U8Value(u32::from(v)),
+            Value::I8(v) => V::I8Value(i32::from(v)),
+            Value::U16(v) => V::U16Value(u32::from(v)),
+            Value::I16(v) => V::I16Value(i32::from(v)),
+            Value::U32(v) => V::U32Value(v),
+            Value::I32(v) => V::I32Value(v),
+            Value::F32(v) => V::F32Value(v),
+        };
+        proto::Value { value: Some(value) }
+    }
+}
+
+impl TryFrom<&proto::Value> for Value {
+    type Error = io::Error;
+
+    fn try_from(value: &proto::Value) -> io::Result<Self> {
+        use proto::value::Value as V;
+        let value = value
+            .value
+            .as_ref()
+            .ok_or_else(|| invalid_input("missing value".to_owned()))?;
+        Ok(match *value {
+            V::BoolValue(v) => Value::Bool(v),
+            V::U8Value(v) => Value::U8(narrow(v, "u8_value")?),
+            V::I8Value(v) => Value::I8(narrow(v, "i8_value")?),
+            V::U16Value(v) => Value::U16(narrow(v, "u16_value")?),
+            V::I16Value(v) => Value::I16(narrow(v, "i16_value")?),
+            V::U32Value(v) => Value::U32(v),
+            V::I32Value(v) => Value::I32(v),
+            V::F32Value(v) => Value::F32(v),
+        })
+    }
+}
+
+impl From<Event> for proto::Event {
+    fn from(event: Event) -> Self {
+        let (kind, code) = match event {
+            Event::Reset => (proto::event::Kind::Reset, KB_EVENT_RESET as i32),
+            Event::Unknown(code) => (proto::event::Kind::Unknown, code),
+        };
+        proto::Event {
+            kind: kind as i32,
+            code,
+        }
+    }
+}
+
+impl From<&proto::Event> for Event {
+    fn from(event: &proto::Event) -> Self {
+        match event.kind() {
+            proto::event::Kind::Reset => Event::Reset,
+            proto::event::Kind::Unknown => Event::Unknown(event.code),
+        }
+    }
+}
+
+/// GrpcService serves `proto/picontrol.proto` for a driver, see the module documentation.
+pub struct GrpcService<D = SharedRevPiControl> {
+    control: AsyncRevPiControl<D>,
+    min_interval: Duration,
+    token: Option<String>,
+}
+
+impl<D: Driver + 'static> GrpcService<D> {
+    /// Serves @driver, e.g. a [`MemoryDriver`](crate::MemoryDriver) in tests.
+    pub fn new(driver: D) -> Self {
+        Self::from_arc(Arc::new(driver))
+    }
+
+    /// Serves a driver that is shared with other code.
+    pub fn from_arc(driver: Arc<D>) -> Self {
+        GrpcService {
+            control: AsyncRevPiControl::from_arc(driver),
+            min_interval: Duration::from_millis(100),
+            token: None,
+        }
+    }
+
+    /// Sets the shortest interval at which WatchVariables reads the process image, 100 ms by
+    /// default.
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Requires `authorization: Bearer @token` metadata on every call.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    /// The service for a tonic [`Server`](tonic::transport::Server), to combine it with others.
+    pub fn into_server(self) -> InterceptedService<PiControlServer<Self>, TokenCheck> {
+        let check = TokenCheck {
+            token: self.token.clone(),
+        };
+        PiControlServer::with_interceptor(self, check)
+    }
+
+    /// Serves the clients connecting to @listener until an error occurs.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .map_err(io::Error::other)
+    }
+
+    async fn variable(&self, name: &str) -> Result<Variable, Status> {
+        let info = self
+            .control
+            .get_variable_info(variable_name(name)?)
+            .await
+            .map_err(status)?;
+        Variable::from_spi(&info).map_err(status)
+    }
+
+    async fn variables(&self, names: &[String]) -> Result<Vec<Variable>, Status> {
+        let mut variables = Vec::with_capacity(names.len());
+        for name in names {
+            variables.push(self.variable(name).await?);
+        }
+        Ok(variables)
+    }
+}
+
+/// TokenCheck refuses calls without the bearer token of a [`GrpcService`], if it has one.
+#[derive(Clone)]
+pub struct TokenCheck {
+    token: Option<String>,
+}
+
+impl Interceptor for TokenCheck {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return Ok(request),
+        };
+        let given = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if given.is_some_and(|given| crate::same_bytes(given.trim().as_bytes(), token.as_bytes())) {
+            Ok(request)
+        } else {
+            Err(Status::unauthenticated("missing or wrong token"))
+        }
+    }
+}
+
+/// Reads @variables with a single read of the part of the image they cover.
+async fn read_values<D: Driver + 'static>(
+    control: &AsyncRevPiControl<D>,
+    variables: &[Variable],
+) -> Result<Vec<Value>, Status> {
+    let start = variables.iter().map(|v| v.address).min().unwrap_or(0);
+    let end = variables
+        .iter()
+        .map(|v| v.address as usize + v.byte_length())
+        .max()
+        .unwrap_or(0);
+    let data = control
+        .read(u64::from(start), end - start as usize)
+        .await
+        .map_err(status)?;
+    variables
+        .iter()
+        .map(|v| {
+            let relative = Variable {
+                address: v.address - start,
+                ..v.clone()
+            };
+            relative.decode(&data).map_err(status)
+        })
+        .collect()
+}
+
+fn variable_values(variables: &[Variable], values: &[Value]) -> Vec<proto::VariableValue> {
+    variables
+        .iter()
+        .zip(values)
+        .map(|(variable, value)| proto::VariableValue {
+            name: variable.name.clone(),
+            value: Some((*value).into()),
+        })
+        .collect()
+}
+
+/// Converts @value to @kind; booleans only accept 0 and 1.
+fn convert(value: Value, kind: VariableType) -> io::Result<Value> {
+    if value.kind() == kind {
+        return Ok(value);
+    }
+    let number = value.as_f64();
+    if kind == VariableType::Bool && number != 0.0 && number != 1.0 {
+        return Err(invalid_input(format!("{} is not a BOOL value", value)));
+    }
+    kind.from_f64(number)
+}
+
+#[tonic::async_trait]
+impl<D: Driver + 'static> PiControl for GrpcService<D> {
+    type WatchEventsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send + 'static>>;
+    type WatchVariablesStream = ReceiverStream<Result<proto::VariableChanges, Status>>;
+
+    async fn list_devices(
+        &self,
+        _: Request<proto::ListDevicesRequest>,
+    ) -> Result<Response<proto::ListDevicesResponse>, Status> {
+        let devices = self.control.get_device_info_list().await.map_err(status)?;
+        Ok(Response::new(proto::ListDevicesResponse {
+            devices: devices.iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_variable_info(
+        &self,
+        request: Request<proto::GetVariableInfoRequest>,
+    ) -> Result<Response<proto::VariableInfo>, Status> {
+        let info = self
+            .control
+            .get_variable_info(variable_name(&request.get_ref().name)?)
+            .await
+            .map_err(status)?;
+        Ok(Response::new((&info).into()))
+    }
+
+    async fn read_variable(
+        &self,
+        request: Request<proto::ReadVariableRequest>,
+    ) -> Result<Response<proto::VariableValue>, Status> {
+        let variables = [self.variable(&request.get_ref().name).await?];
+        let values = read_values(&self.control, &variables).await?;
+        Ok(Response::new(
+            variable_values(&variables, &values).remove(0),
+        ))
+    }
+
+    async fn write_variable(
+        &self,
+        request: Request<proto::VariableValue>,
+    ) -> Result<Response<proto::WriteVariableResponse>, Status> {
+        let request = request.into_inner();
+        let variable = self.variable(&request.name).await?;
+        let value = request
+            .value
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("missing value"))?;
+        let value = Value::try_from(value)
+            .and_then(|value| convert(value, variable.kind))
+            .map_err(status)?;
+        let written = match value {
+            Value::Bool(bit) => {
+                self.control
+                    .set_bit_value(SPIValue {
+                        i16uAddress: variable.address,
+                        i8uBit: variable.bit,
+                        i8uValue: bit as u8,
+                    })
+                    .await
+            }
+            _ => {
+                self.control
+                    .write(u64::from(variable.address), &value.to_bytes())
+                    .await
+            }
+        };
+        written.map_err(status)?;
+        Ok(Response::new(proto::WriteVariableResponse {}))
+    }
+
+    async fn read_variables(
+        &self,
+        request: Request<proto::ReadVariablesRequest>,
+    ) -> Result<Response<proto::ReadVariablesResponse>, Status> {
+        let variables = self.variables(&request.get_ref().names).await?;
+        let values = read_values(&self.control, &variables).await?;
+        Ok(Response::new(proto::ReadVariablesResponse {
+            values: variable_values(&variables, &values),
+        }))
+    }
+
+    async fn read(
+        &self,
+        request: Request<proto::ReadRequest>,
+    ) -> Result<Response<proto::ReadResponse>, Status> {
+        let request = request.get_ref();
+        check_range(request.offset, request.length as usize)?;
+        let data = self
+            .control
+            .read(request.offset, request.length as usize)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::ReadResponse { data }))
+    }
+
+    async fn write(
+        &self,
+        request: Request<proto::WriteRequest>,
+    ) -> Result<Response<proto::WriteResponse>, Status> {
+        let request = request.get_ref();
+        check_range(request.offset, request.data.len())?;
+        self.control
+            .write(request.offset, &request.data)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::WriteResponse {}))
+    }
+
+    async fn get_bit(
+        &self,
+        request: Request<proto::GetBitRequest>,
+    ) -> Result<Response<proto::GetBitResponse>, Status> {
+        let request = request.get_ref();
+        let value = SPIValue {
+            i16uAddress: narrow(request.address, "address").map_err(status)?,
+            i8uBit: narrow(request.bit, "bit").map_err(status)?,
+            ..Default::default()
+        };
+        let value = self.control.get_bit_value(value).await.map_err(status)?;
+        Ok(Response::new(proto::GetBitResponse {
+            value: value.i8uValue != 0,
+        }))
+    }
+
+    async fn set_bit(
+        &self,
+        request: Request<proto::SetBitRequest>,
+    ) -> Result<Response<proto::SetBitResponse>, Status> {
+        let request = request.get_ref();
+        let value = SPIValue {
+            i16uAddress: narrow(request.address, "address").map_err(status)?,
+            i8uBit: narrow(request.bit, "bit").map_err(status)?,
+            i8uValue: request.value as u8,
+        };
+        self.control.set_bit_value(value).await.map_err(status)?;
+        Ok(Response::new(proto::SetBitResponse {}))
+    }
+
+    async fn snapshot(
+        &self,
+        _: Request<proto::SnapshotRequest>,
+    ) -> Result<Response<proto::SnapshotResponse>, Status> {
+        let image = self
+            .control
+            .read(0, PROCESS_IMAGE_SIZE)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::SnapshotResponse {
+            image,
+            time_ms: now_ms(),
+        }))
+    }
+
+    async fn watch_events(
+        &self,
+        _: Request<proto::WatchEventsRequest>,
+    ) -> Result<Response<Self::WatchEventsStream>, Status> {
+        let events = self
+            .control
+            .events()
+            .map(|event| event.map(proto::Event::from).map_err(status));
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn watch_variables(
+        &self,
+        request: Request<proto::WatchVariablesRequest>,
+    ) -> Result<Response<Self::WatchVariablesStream>, Status> {
+        let request = request.into_inner();
+        if request.names.is_empty() {
+            return Err(Status::invalid_argument("no variables to watch"));
+        }
+        let variables = self.variables(&request.names).await?;
+        let interval = Duration::from_millis(u64::from(request.interval_ms)).max(self.min_interval);
+        let control = self.control.clone();
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            let mut previous: Option<Vec<Value>> = None;
+            loop {
+                ticks.tick().await;
+                let values = match read_values(&control, &variables).await {
+                    Ok(values) => values,
+                    Err(err) => {
+                        let _ = tx.send(Err(err)).await;
+                        return;
+                    }
+                };
+                let changed: Vec<usize> = (0..values.len())
+                    .filter(|&i| previous.as_ref().is_none_or(|p| p[i] != values[i]))
+                    .collect();
+                if !changed.is_empty() {
+                    let update = proto::VariableChanges {
+                        time_ms: now_ms(),
+                        values: changed
+                            .iter()
+                            .map(|&i| proto::VariableValue {
+                                name: variables[i].name.clone(),
+                                value: Some(values[i].into()),
+                            })
+                            .collect(),
+                    };
+                    if tx.send(Ok(update)).await.is_err() {
+                        return;
+                    }
+                }
+                previous = Some(values);
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn reset(
+        &self,
+        _: Request<proto::ResetRequest>,
+    ) -> Result<Response<proto::ResetResponse>, Status> {
+        self.control.reset().await.map_err(status)?;
+        Ok(Response::new(proto::ResetResponse {}))
+    }
+
+    async fn stop_io(
+        &self,
+        request: Request<proto::StopIoRequest>,
+    ) -> Result<Response<proto::StopIoResponse>, Status> {
+        let stopped = self
+            .control
+            .stop_io(request.get_ref().stop)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::StopIoResponse { stopped }))
+    }
+
+    async fn reset_counter(
+        &self,
+        request: Request<proto::ResetCounterRequest>,
+    ) -> Result<Response<proto::ResetCounterResponse>, Status> {
+        let request = request.get_ref();
+        let address = narrow(request.address, "address").map_err(status)?;
+        let bitfield = narrow(request.bitfield, "bitfield").map_err(status)?;
+        self.control
+            .reset_counter(address, bitfield)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(proto::ResetCounterResponse {}))
+    }
+}
+
+/// Rejects ranges outside of the process image before the driver allocates for them.
+fn check_range(offset: u64, length: usize) -> Result<(), Status> {
+    match offset.checked_add(length as u64) {
+        Some(end) if end <= PROCESS_IMAGE_SIZE as u64 => Ok(()),
+        _ => Err(Status::invalid_argument(format!(
+            "{} bytes at offset {} are outside of the process image",
+            length, offset
+        ))),
+    }
+}
+
+// The name field of SPIVariable holds 31 bytes and the terminating NUL.
+fn variable_name(name: &str) -> Result<&str, Status> {
+    if name.len() >= 32 {
+        return Err(Status::invalid_argument(format!(
+            "variable name {} is too long",
+            name
+        )));
+    }
+    Ok(name)
+}
+
+fn status(err: io::Error) -> Status {
+    to_status(&err)
+}
+
+/// Converts the protobuf integer @value of @field to the narrower integer type of the driver.
+fn narrow<T: TryFrom<N>, N: Copy + std::fmt::Display>(value: N, field: &str) -> io::Result<T> {
+    T::try_from(value).map_err(|_| invalid_input(format!("{} {} is out of range", field, value)))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::pi_control_client::PiControlClient;
+    use super::*;
+    use crate::MemoryDriver;
+
+    fn value(v: Value) -> Option<proto::Value> {
+        Some(v.into())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_client() {
+        let memory = Arc::new(MemoryDriver::new());
+        memory.add_variable("I_1", 0, 0, 1);
+        memory.add_variable("Counter", 2, 0, 16);
+        memory.add_variable("O_1", 4, 0, 8);
+        memory.add_device(SDeviceInfo {
+            i8uAddress: 32,
+            i16uModuleType: 96,
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = GrpcService::from_arc(memory.clone()).min_interval(Duration::from_millis(10));
+        tokio::spawn(service.serve(listener));
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = PiControlClient::new(channel);
+
+        let devices = client
+            .list_devices(proto::ListDevicesRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .devices;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].address, 32);
+        assert_eq!(devices[0].module_name, "RevPi DIO");
+
+        let mut changes = client
+            .watch_variables(proto::WatchVariablesRequest {
+                names: vec!["I_1".to_owned(), "O_1".to_owned()],
+                interval_ms: 0,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let first = changes.message().await.unwrap().unwrap();
+        assert_eq!(first.values.len(), 2);
+
+        // integers are converted to the type of the variable
+        client
+            .write_variable(proto::VariableValue {
+                name: "O_1".to_owned(),
+                value: value(Value::U32(200)),
+            })
+            .await
+            .unwrap();
+        let changed = changes.message().await.unwrap().unwrap();
+        assert_eq!(
+            changed.values,
+            vec![proto::VariableValue {
+                name: "O_1".to_owned(),
+                value: value(Value::U8(200)),
+            }]
+        );
+        let err = client
+            .write_variable(proto::VariableValue {
+                name: "I_1".to_owned(),
+                value: value(Value::U8(2)),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        let err = client
+            .read_variable(proto::ReadVariableRequest {
+                name: "x".repeat(32),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        client
+            .write_variable(proto::VariableValue {
+                name: "I_1".to_owned(),
+                value: value(Value::Bool(true)),
+            })
+            .await
+            .unwrap();
+
+        memory.write(2, &[0x34, 0x12]).unwrap();
+        let values = client
+            .read_variables(proto::ReadVariablesRequest {
+                names: vec!["Counter".to_owned(), "I_1".to_owned()],
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .values;
+        assert_eq!(values[0].value, value(Value::U16(0x1234)));
+        assert_eq!(values[1].value, value(Value::Bool(true)));
+        let err = client
+            .read_variable(proto::ReadVariableRequest {
+                name: "missing".to_owned(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert_eq!(from_status(&err).kind(), io::ErrorKind::NotFound);
+
+        let snapshot = client
+            .snapshot(proto::SnapshotRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(snapshot.image.len(), PROCESS_IMAGE_SIZE);
+        assert_eq!(&snapshot.image[..5], &[1, 0, 0x34, 0x12, 200]);
+        let err = client
+            .read(proto::ReadRequest {
+                offset: 4000,
+                length: u32::MAX,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let stopped = client
+            .stop_io(proto::StopIoRequest { stop: true })
+            .await
+            .unwrap()
+            .into_inner()
+            .stopped;
+        assert!(stopped);
+        assert!(memory.io_stopped());
+
+        let mut events = client
+            .watch_events(proto::WatchEventsRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        client.reset(proto::ResetRequest {}).await.unwrap();
+        let event = events.message().await.unwrap().unwrap();
+        assert_eq!(Event::from(&event), Event::Reset);
+    }
+
+    #[test]
+    fn token_check() {
+        let mut open = TokenCheck { token: None };
+        assert!(open.call(Request::new(())).is_ok());
+
+        let mut check = TokenCheck {
+            token: Some("secret".to_owned()),
+        };
+        let status = check.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer wrong".parse().unwrap());
+        assert!(check.call(request).is_err());
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        assert!(check.call(request).is_ok());
+    }
+
+    #[test]
+    fn conversions() {
+        let device = SDeviceInfo {
+            i8uAddress: 31,
+            i32uSerialnumber: 4711,
+            i16uModuleType: 95,
+            i8uActive: 1,
+            ..Default::default()
+        };
+        let message = proto::DeviceInfo::from(&device);
+        assert_eq!(message.module_name, "RevPi Core");
+        let back = SDeviceInfo::try_from(&message).unwrap();
+        assert_eq!(back.i32uSerialnumber, 4711);
+        assert_eq!(back.i8uActive, 1);
+
+        let message = proto::Value {
+            value: Some(proto::value::Value::U8Value(256)),
+        };
+        assert!(Value::try_from(&message).is_err());
+        assert_eq!(
+            convert(Value::F32(2.6), VariableType::I16).unwrap(),
+            Value::I16(3)
+        );
+    }
+}
@@ -0,0 +1,475 @@
+//! Recording of variables to disk and querying the recordings.
+//!
+//! A [`Recorder`] samples a set of variables at a fixed interval and appends them with a
+//! timestamp to files in a directory, history-000001.csv, history-000002.csv and so on. A file
+//! is closed once it reaches the size limit and the oldest files are removed, so the recording
+//! takes a bounded amount of disk and always holds the most recent history:
+//!
+//! ```ignore
+//! let mut recorder = Recorder::new("/var/lib/history", variables)
+//!     .interval(Duration::from_millis(100))
+//!     .on_change(true)
+//!     .max_file_size(1 << 20)
+//!     .max_files(10);
+//! recorder.run(&driver, &stop)?;
+//! // later, maybe on another machine
+//! let samples = historian::query("/var/lib/history", from..to, &["I_1"])?;
+//! ```
+//!
+//! The CSV files have the columns `time_us,name,type,value`, the time in microseconds since
+//! the Unix epoch. The binary files start with [`BINARY_MAGIC`], followed by records: 0, a u16
+//! id and the name as u16 length and UTF-8 assign an id to a variable, 1, the time u64, the id
+//! u16, the type u8 and the little endian value are a sample. All numbers are little endian.
+
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::value::{Value, Variable, VariableType};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// First bytes of a binary history file.
+pub const BINARY_MAGIC: &[u8; 4] = b"PIH1";
+
+const CSV_HEADER: &str = "time_us,name,type,value";
+const FILE_PREFIX: &str = "history-";
+
+// Type codes of the binary format.
+const TYPES: [VariableType; 8] = [
+    VariableType::Bool,
+    VariableType::U8,
+    VariableType::I8,
+    VariableType::U16,
+    VariableType::I16,
+    VariableType::U32,
+    VariableType::I32,
+    VariableType::F32,
+];
+
+/// File format of a [`Recorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    /// The compact format described in the module documentation.
+    Binary,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Binary => "bin",
+        }
+    }
+}
+
+/// One recorded value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub name: String,
+    pub value: Value,
+}
+
+struct Output {
+    writer: BufWriter<File>,
+    size: u64,
+    /// Ids of the names defined in a binary file.
+    ids: HashMap<String, u16>,
+}
+
+/// Recorder samples variables and writes them to rotating files, see the module documentation.
+pub struct Recorder {
+    dir: PathBuf,
+    variables: Vec<Variable>,
+    format: Format,
+    interval: Duration,
+    on_change: bool,
+    max_file_size: u64,
+    max_files: usize,
+    last: Vec<Option<Value>>,
+    output: Option<Output>,
+    sequence: u64,
+}
+
+impl Recorder {
+    /// Records @variables to files in @dir, as CSV every second by default, into up to 10
+    /// files of 1 MiB.
+    pub fn new<P: AsRef<Path>>(dir: P, variables: Vec<Variable>) -> Self {
+        let last = vec![None; variables.len()];
+        Recorder {
+            dir: dir.as_ref().to_owned(),
+            variables,
+            format: Format::Csv,
+            interval: Duration::from_secs(1),
+            on_change: false,
+            max_file_size: 1 << 20,
+            max_files: 10,
+            last,
+            output: None,
+            sequence: 0,
+        }
+    }
+
+    /// The file format.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The time between two samples taken by [`Recorder::run`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Only writes a variable when its value differs from the last one written.
+    pub fn on_change(mut self, on_change: bool) -> Self {
+        self.on_change = on_change;
+        self
+    }
+
+    /// Starts a new file once the current one has @bytes, so a file exceeds the limit by at
+    /// most the values of one [`sample_at`](Self::sample_at). With
+    /// [`on_change`](Self::on_change), every file starts with the values of all variables.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Removes the oldest files when there are more than @count.
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count.max(1);
+        self
+    }
+
+    /// Samples the variables now; returns the number of values written.
+    pub fn sample<D: Driver + ?Sized>(&mut self, driver: &D) -> io::Result<usize> {
+        self.sample_at(driver, SystemTime::now())
+    }
+
+    /// Samples the variables, recording them with @time.
+    pub fn sample_at<D: Driver + ?Sized>(
+        &mut self,
+        driver: &D,
+        time: SystemTime,
+    ) -> io::Result<usize> {
+        let image = driver.read(0, PROCESS_IMAGE_SIZE)?;
+        let rotate = match &self.output {
+            Some(output) => output.size >= self.max_file_size,
+            None => true,
+        };
+        if rotate {
+            self.rotate()?;
+        }
+        let mut samples = Vec::new();
+        for (variable, last) in self.variables.iter().zip(self.last.iter_mut()) {
+            let value = variable.decode(&image)?;
+            if self.on_change && *last == Some(value) {
+                continue;
+            }
+            *last = Some(value);
+            samples.push(Sample {
+                time,
+                name: variable.name.clone(),
+                value,
+            });
+        }
+        self.write(&samples)?;
+        Ok(samples.len())
+    }
+
+    /// Samples at the configured interval until @stop is set.
+    pub fn run<D: Driver + ?Sized>(&mut self, driver: &D, stop: &AtomicBool) -> io::Result<()> {
+        let mut next = Instant::now();
+        while !stop.load(Ordering::Relaxed) {
+            self.sample(driver)?;
+            next += self.interval;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[Sample]) -> io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let format = self.format;
+        let output = self.output.as_mut().unwrap();
+        let mut data = Vec::new();
+        for sample in samples {
+            match format {
+                Format::Csv => writeln!(
+                    data,
+                    "{},{},{},{}",
+                    micros(sample.time),
+                    sample.name,
+                    sample.value.kind(),
+                    sample.value
+                )?,
+                Format::Binary => {
+                    let next = output.ids.len() as u16;
+                    let id = *output.ids.entry(sample.name.clone()).or_insert_with(|| {
+                        data.push(0);
+                        data.write_u16::<LittleEndian>(next).unwrap();
+                        write_string(&mut data, &sample.name);
+                        next
+                    });
+                    data.push(1);
+                    data.write_u64::<LittleEndian>(micros(sample.time))?;
+                    data.write_u16::<LittleEndian>(id)?;
+                    data.push(type_code(sample.value.kind()));
+                    data.extend(sample.value.to_bytes());
+                }
+            }
+        }
+        output.writer.write_all(&data)?;
+        output.writer.flush()?;
+        output.size += data.len() as u64;
+        Ok(())
+    }
+
+    /// Starts a new file, which forgets the values written so far so each file can be read on
+    /// its own.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.output = None;
+        self.last.iter_mut().for_each(|last| *last = None);
+        fs::create_dir_all(&self.dir)?;
+        let mut files = history_files(&self.dir)?;
+        self.sequence = self
+            .sequence
+            .max(files.last().map_or(0, |(sequence, _)| *sequence))
+            + 1;
+        let path = self.dir.join(format!(
+            "{}{:06}.{}",
+            FILE_PREFIX,
+            self.sequence,
+            self.format.extension()
+        ));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let header = match self.format {
+            Format::Csv => format!("{}\n", CSV_HEADER).into_bytes(),
+            Format::Binary => BINARY_MAGIC.to_vec(),
+        };
+        writer.write_all(&header)?;
+        self.output = Some(Output {
+            writer,
+            size: header.len() as u64,
+            ids: HashMap::new(),
+        });
+        files.push((self.sequence, path));
+        let excess = files.len().saturating_sub(self.max_files);
+        for (_, path) in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The samples of the variables @names, all if empty, recorded in @dir within @range, in
+/// the order they were taken.
+pub fn query<P: AsRef<Path>>(
+    dir: P,
+    range: Range<SystemTime>,
+    names: &[&str],
+) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (_, path) in history_files(dir.as_ref())? {
+        let file = BufReader::new(File::open(&path)?);
+        let mut keep = |sample: Sample| {
+            if range.contains(&sample.time)
+                && (names.is_empty() || names.contains(&sample.name.as_str()))
+            {
+                samples.push(sample);
+            }
+        };
+        let read = match path.extension().and_then(|e| e.to_str()) {
+            Some("bin") => read_binary(file, &mut keep),
+            _ => read_csv(file, &mut keep),
+        };
+        read.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    }
+    samples.sort_by_key(|s| s.time);
+    Ok(samples)
+}
+
+/// The history files in @dir with their sequence number, oldest first.
+fn history_files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let sequence = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.strip_prefix(FILE_PREFIX))
+            .and_then(|s| s.parse().ok());
+        let extension = path.extension().and_then(|e| e.to_str());
+        if let (Some(sequence), Some("csv" | "bin")) = (sequence, extension) {
+            files.push((sequence, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_csv<R: BufRead>(file: R, keep: &mut dyn FnMut(Sample)) -> io::Result<()> {
+    for line in file.lines() {
+        let line = line?;
+        if line.is_empty() || line == CSV_HEADER {
+            continue;
+        }
+        let invalid = || invalid_data(format!("invalid line {}", line));
+        // Names may contain commas, the other columns can not.
+        let mut columns = line.rsplitn(3, ',');
+        let value = columns.next().ok_or_else(invalid)?;
+        let kind: VariableType = columns.next().ok_or_else(invalid)?.parse()?;
+        let (time, name) = columns
+            .next()
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(invalid)?;
+        keep(Sample {
+            time: UNIX_EPOCH + Duration::from_micros(time.parse().map_err(|_| invalid())?),
+            name: name.to_owned(),
+            value: kind.parse_value(value)?,
+        });
+    }
+    Ok(())
+}
+
+fn read_binary<R: Read>(mut file: R, keep: &mut dyn FnMut(Sample)) -> io::Result<()> {
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(invalid_data("not a history file".to_owned()));
+    }
+    let mut names = HashMap::new();
+    loop {
+        let tag = match file.read_u8() {
+            Ok(tag) => tag,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match tag {
+            0 => {
+                let id = file.read_u16::<LittleEndian>()?;
+                let length = file.read_u16::<LittleEndian>()?;
+                let mut name = vec![0; length.into()];
+                file.read_exact(&mut name)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| invalid_data("invalid variable name".to_owned()))?;
+                names.insert(id, name);
+            }
+            1 => {
+                let time = file.read_u64::<LittleEndian>()?;
+                let id = file.read_u16::<LittleEndian>()?;
+                let kind = *TYPES
+                    .get(usize::from(file.read_u8()?))
+                    .ok_or_else(|| invalid_data("invalid type".to_owned()))?;
+                let mut data = vec![0; usize::from(kind.bit_length()).div_ceil(8)];
+                file.read_exact(&mut data)?;
+                let name = names
+                    .get(&id)
+                    .ok_or_else(|| invalid_data(format!("undefined variable id {}", id)))?;
+                keep(Sample {
+                    time: UNIX_EPOCH + Duration::from_micros(time),
+                    name: name.clone(),
+                    value: kind.decode(&data)?,
+                });
+            }
+            tag => return Err(invalid_data(format!("invalid record {}", tag))),
+        }
+    }
+}
+
+fn type_code(kind: VariableType) -> u8 {
+    TYPES.iter().position(|&t| t == kind).unwrap() as u8
+}
+
+fn write_string(data: &mut Vec<u8>, s: &str) {
+    data.write_u16::<LittleEndian>(s.len() as u16).unwrap();
+    data.extend_from_slice(s.as_bytes());
+}
+
+fn micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    fn record(format: Format) {
+        let dir = std::env::temp_dir().join(format!(
+            "picontrol-historian-{}-{}",
+            std::process::id(),
+            format.extension()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let driver = MemoryDriver::new();
+        let input = Variable::new("I_1", 7, 0, 1).unwrap();
+        let counter = Variable::new("Counter, 1", 13, 0, 32)
+            .unwrap()
+            .with_type(VariableType::I32)
+            .unwrap();
+        let mut recorder = Recorder::new(&dir, vec![input, counter])
+            .format(format)
+            .on_change(true)
+            .max_file_size(80)
+            .max_files(3);
+        let at = |s| UNIX_EPOCH + Duration::from_secs(s);
+
+        assert_eq!(recorder.sample_at(&driver, at(1)).unwrap(), 2);
+        assert_eq!(recorder.sample_at(&driver, at(2)).unwrap(), 0);
+        for s in 3..20 {
+            driver.write(13, &(-(s as i32)).to_le_bytes()).unwrap();
+            // the unchanged input again at the start of a new file
+            let written = recorder.sample_at(&driver, at(s)).unwrap();
+            assert!(written == 1 || written == 2);
+        }
+        assert_eq!(history_files(&dir).unwrap().len(), 3);
+        assert_eq!(query(&dir, at(0)..at(20), &["I_1"]).unwrap().len(), 3);
+
+        let samples = query(&dir, at(15)..at(17), &["Counter, 1"]).unwrap();
+        assert_eq!(
+            samples,
+            vec![
+                Sample {
+                    time: at(15),
+                    name: "Counter, 1".to_owned(),
+                    value: Value::I32(-15)
+                },
+                Sample {
+                    time: at(16),
+                    name: "Counter, 1".to_owned(),
+                    value: Value::I32(-16)
+                },
+            ]
+        );
+        // the first samples were rotated away
+        assert!(query(&dir, at(0)..at(3), &[]).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn record_and_query() {
+        record(Format::Csv);
+        record(Format::Binary);
+    }
+}
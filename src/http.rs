@@ -0,0 +1,481 @@
+//! An HTTP REST API for the process image.
+//!
+//! [`HttpServer`] offers any [`Driver`] to tools that can not link this library:
+//!
+//! | request                  | response                                                     |
+//! |--------------------------|--------------------------------------------------------------|
+//! | `GET /devices`           | the devices of `get_device_info_list`, as a JSON array       |
+//! | `GET /variables/{name}`  | the variable with its value, e.g. `{"name": "O_1", ..., "value": true}` |
+//! | `PUT /variables/{name}`  | writes the value of the body, `true`, `42` or `{"value": 42}`, and responds like `GET` |
+//! | `GET /image`             | the raw process image                                        |
+//! | `GET /image?format=json` | all variables of the configuration with their values         |
+//! | `POST /reset`            | resets the driver                                            |
+//! | `GET /metrics`           | the [`Metrics`] in the Prometheus text format                |
+//!
+//! With a token, every request needs an `Authorization: Bearer <token>` header, which is checked
+//! before the body is read. In read-only mode `PUT` and `POST` are refused. Errors are responded
+//! to with a JSON object such as `{"error": "variable O_99 not found"}`.
+//!
+//! Requests are served by a few worker threads, so a client that is slow to send its body only
+//! holds up one of them.
+
+use crate::config::Config;
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::export::{self, json_value, value_from_json};
+use crate::metrics::{self, Metrics};
+use crate::picontrol::SDeviceInfo;
+use crate::value::Variable;
+use serde_json::{json, Value as Json};
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// request bodies larger than this are refused, a value needs a few bytes
+const MAX_BODY: u64 = 4096;
+// the threads serving requests
+const WORKERS: usize = 4;
+
+/// A response before it is sent.
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, body: &Json) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, msg: &str) -> Reply {
+        Reply::json(status, &json!({ "error": msg }))
+    }
+
+    fn io_error(err: &io::Error) -> Reply {
+        let status = match err.kind() {
+            io::ErrorKind::NotFound => 404,
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => 400,
+            io::ErrorKind::PermissionDenied => 403,
+            _ => 500,
+        };
+        Reply::error(status, &err.to_string())
+    }
+}
+
+/// HttpServer serves the REST API, see the module documentation.
+pub struct HttpServer<D: ?Sized> {
+    server: Server,
+    driver: Arc<D>,
+    config: Option<Config>,
+    metrics: Option<Metrics>,
+    token: Option<String>,
+    read_only: bool,
+}
+
+impl<D: Driver + ?Sized> HttpServer<D> {
+    /// Listens on @addr, e.g. "0.0.0.0:8080", for requests to @driver.
+    pub fn bind<A: ToSocketAddrs>(addr: A, driver: Arc<D>) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        Ok(HttpServer {
+            server,
+            driver,
+            config: None,
+            metrics: None,
+            token: None,
+            read_only: false,
+        })
+    }
+
+    /// Decodes `GET /image?format=json` with @config.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Serves @metrics on `GET /metrics`.
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Requires `Authorization: Bearer @token` on every request.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    /// Refuses the requests that write or reset.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.server.server_addr().to_ip().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "not listening on an IP address")
+        })
+    }
+
+    /// Serves requests on WORKERS threads until the listener fails.
+    pub fn run(&self) -> io::Result<()> {
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..WORKERS).map(|_| scope.spawn(|| self.work())).collect();
+            for worker in workers {
+                worker
+                    .join()
+                    .map_err(|_| io::Error::other("worker panicked"))??;
+            }
+            Ok(())
+        })
+    }
+
+    fn work(&self) -> io::Result<()> {
+        loop {
+            let request = self.server.recv()?;
+            // a client that went away is no reason to stop serving the others
+            let _ = self.serve(request);
+        }
+    }
+
+    fn serve(&self, mut request: Request) -> io::Result<()> {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.as_str());
+        let reply = if !self.authorized(authorization) {
+            Reply::error(401, "missing or wrong token")
+        } else {
+            let mut body = Vec::new();
+            request
+                .as_reader()
+                .take(MAX_BODY + 1)
+                .read_to_end(&mut body)?;
+            if body.len() as u64 > MAX_BODY {
+                Reply::error(413, "request body too large")
+            } else {
+                self.handle(request.method(), request.url(), &body)
+            }
+        };
+        let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+        response.add_header(header("Content-Type", reply.content_type));
+        if reply.status == 401 {
+            response.add_header(header("WWW-Authenticate", "Bearer"));
+        }
+        request.respond(response)
+    }
+
+    /// Whether @authorization carries the token, if the server has one.
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return true,
+        };
+        let given = authorization.and_then(|a| a.strip_prefix("Bearer "));
+        given.is_some_and(|given| crate::same_bytes(given.trim().as_bytes(), token.as_bytes()))
+    }
+
+    fn handle(&self, method: &Method, url: &str, body: &[u8]) -> Reply {
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (url, ""),
+        };
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        let writes = *method != Method::Get && *method != Method::Head;
+        if writes && self.read_only {
+            return Reply::error(403, "the API is read-only");
+        }
+        let result = match (method, segments.as_slice()) {
+            (Method::Get, ["devices"]) => self.devices(),
+            (Method::Get, ["variables", name]) => self.variable(&decode(name), None),
+            (Method::Put, ["variables", name]) => self.variable(&decode(name), Some(body)),
+            (Method::Get, ["image"]) => self.image(query),
+            (Method::Get, ["metrics"]) => self.render_metrics(),
+            (Method::Post, ["reset"]) => self.driver.reset().map(|()| Reply {
+                status: 204,
+                content_type: "text/plain",
+                body: Vec::new(),
+            }),
+            (_, ["devices"])
+            | (_, ["variables", _])
+            | (_, ["image"])
+            | (_, ["reset"])
+            | (_, ["metrics"]) => return Reply::error(405, "method not allowed"),
+            _ => return Reply::error(404, "no such resource"),
+        };
+        result.unwrap_or_else(|e| Reply::io_error(&e))
+    }
+
+    fn devices(&self) -> io::Result<Reply> {
+        let devices: Vec<_> = self
+            .driver
+            .get_device_info_list()?
+            .iter()
+            .map(device_json)
+            .collect();
+        Ok(Reply::json(200, &Json::Array(devices)))
+    }
+
+    /// Reads the variable @name, after writing the value of @body if given.
+    fn variable(&self, name: &str, body: Option<&[u8]>) -> io::Result<Reply> {
+        let variable = Variable::resolve(&*self.driver, name)?;
+        if let Some(body) = body {
+            let json: Json = serde_json::from_slice(body).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("invalid value: {}", e))
+            })?;
+            let json = json.get("value").unwrap_or(&json);
+            variable.write(&*self.driver, value_from_json(variable.kind, json)?)?;
+        }
+        let value = variable.read(&*self.driver)?;
+        Ok(Reply::json(
+            200,
+            &json!({
+                "name": variable.name,
+                "address": variable.address,
+                "bit": variable.bit,
+                "length": variable.length,
+                "value": json_value(value),
+            }),
+        ))
+    }
+
+    fn image(&self, query: &str) -> io::Result<Reply> {
+        let format = query
+            .split('&')
+            .find_map(|p| p.strip_prefix("format="))
+            .unwrap_or("raw");
+        match format {
+            "raw" => Ok(Reply {
+                status: 200,
+                content_type: "application/octet-stream",
+                body: self.driver.read(0, PROCESS_IMAGE_SIZE)?,
+            }),
+            "json" => {
+                let config = self.config.as_ref().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no configuration to decode the image with",
+                    )
+                })?;
+                let mut body = Vec::new();
+                export::export(&*self.driver, config, export::Format::Json, &mut body)?;
+                Ok(Reply {
+                    status: 200,
+                    content_type: "application/json",
+                    body,
+                })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown format {}, expected raw or json", format),
+            )),
+        }
+    }
+
+    fn render_metrics(&self) -> io::Result<Reply> {
+        let metrics = self
+            .metrics
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no metrics are exported"))?;
+        let mut body = Vec::new();
+        metrics.render(&*self.driver, &mut body)?;
+        Ok(Reply {
+            status: 200,
+            content_type: metrics::CONTENT_TYPE,
+            body,
+        })
+    }
+}
+
+fn device_json(device: &SDeviceInfo) -> Json {
+    json!({
+        "address": device.i8uAddress,
+        "module_type": device.i16uModuleType,
+        "name": crate::get_module_name(device.i16uModuleType.into()),
+        "serial_number": device.i32uSerialnumber,
+        "hw_revision": device.i16uHW_Revision,
+        "sw_version": format!("{}.{}", device.i16uSW_Major, device.i16uSW_Minor),
+        "base_offset": device.i16uBaseOffset,
+        "input_offset": device.i16uInputOffset,
+        "input_length": device.i16uInputLength,
+        "output_offset": device.i16uOutputOffset,
+        "output_length": device.i16uOutputLength,
+        "config_offset": device.i16uConfigOffset,
+        "config_length": device.i16uConfigLength,
+        "active": device.i8uActive != 0,
+        "state": device.i8uModuleState,
+    })
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// Decodes the %XX escapes of a path segment.
+fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+    use crate::value::Value;
+    use crate::SharedRevPiControl;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::thread;
+
+    fn request(addr: SocketAddr, head: &str, body: &str) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            head,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = std::str::from_utf8(&response[9..12])
+            .unwrap()
+            .parse()
+            .unwrap();
+        (status, response[split + 4..].to_vec())
+    }
+
+    fn serve<D: Driver + Send + Sync + 'static>(server: HttpServer<D>) -> SocketAddr {
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    #[test]
+    fn rest_api() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Arc::new(Emulator::from_config(config.clone()).unwrap());
+        let server = HttpServer::bind("127.0.0.1:0", Arc::clone(&emulator))
+            .unwrap()
+            .metrics(Metrics::new(&config, &["PWM_1"]).unwrap())
+            .config(config)
+            .token("secret");
+        let addr = serve(server);
+        let auth = "Authorization: Bearer secret";
+
+        let (status, _) = request(addr, "GET /devices HTTP/1.1", "");
+        assert_eq!(status, 401);
+        let (status, body) = request(addr, &format!("GET /devices HTTP/1.1\r\n{}", auth), "");
+        assert_eq!(status, 200);
+        let devices: Json = serde_json::from_slice(&body).unwrap();
+        assert_eq!(devices[1]["name"], "RevPi DIO");
+
+        let put = format!("PUT /variables/PWM_1 HTTP/1.1\r\n{}", auth);
+        let (status, body) = request(addr, &put, r#"{"value": 42}"#);
+        assert_eq!(status, 200);
+        let variable: Json = serde_json::from_slice(&body).unwrap();
+        assert_eq!(variable["value"], 42);
+        assert_eq!(
+            emulator.value("PWM_1", &Value::U8(0)).unwrap(),
+            Value::U8(42)
+        );
+        assert_eq!(request(addr, &put, "300").0, 400);
+
+        let get = format!("GET /variables/O_99 HTTP/1.1\r\n{}", auth);
+        assert_eq!(request(addr, &get, "").0, 404);
+        let (status, body) = request(addr, &format!("GET /image HTTP/1.1\r\n{}", auth), "");
+        assert_eq!(
+            (status, body.len(), body[79]),
+            (200, PROCESS_IMAGE_SIZE, 42)
+        );
+        let get = format!("GET /image?format=json HTTP/1.1\r\n{}", auth);
+        let (status, body) = request(addr, &get, "");
+        assert_eq!(status, 200);
+        assert!(serde_json::from_slice::<Json>(&body).unwrap().is_array());
+        assert_eq!(
+            request(addr, &format!("POST /reset HTTP/1.1\r\n{}", auth), "").0,
+            204
+        );
+        assert_eq!(
+            request(addr, &format!("GET /reset HTTP/1.1\r\n{}", auth), "").0,
+            405
+        );
+
+        // a client that never sends the body it announced holds up one worker only, and
+        // without the token its body is not waited for at all
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        write!(stalled, "{}\r\nContent-Length: 100000\r\n\r\n", put).unwrap();
+        let mut unauthorized = TcpStream::connect(addr).unwrap();
+        write!(
+            unauthorized,
+            "PUT /variables/PWM_1 HTTP/1.1\r\nContent-Length: 100000\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = [0; 12];
+        unauthorized.read_exact(&mut response).unwrap();
+        assert_eq!(&response[9..], b"401");
+        let get = format!("GET /variables/PWM_1 HTTP/1.1\r\n{}", auth);
+        assert_eq!(request(addr, &get, "").0, 200);
+    }
+
+    #[test]
+    fn read_only() {
+        let emulator = Arc::new(Emulator::from_config(Config::parse(CONFIG).unwrap()).unwrap());
+        let addr = serve(
+            HttpServer::bind("127.0.0.1:0", emulator)
+                .unwrap()
+                .read_only(true),
+        );
+        assert_eq!(request(addr, "GET /variables/O_1 HTTP/1.1", "").0, 200);
+        assert_eq!(request(addr, "PUT /variables/O_1 HTTP/1.1", "true").0, 403);
+        assert_eq!(request(addr, "POST /reset HTTP/1.1", "").0, 403);
+        assert_eq!(decode("Temp%20A"), "Temp A");
+    }
+
+    #[test]
+    fn long_variable_name() {
+        let path = std::env::temp_dir().join(format!("picontrol-http-{}", std::process::id()));
+        std::fs::write(&path, vec![0u8; PROCESS_IMAGE_SIZE]).unwrap();
+        let control = Arc::new(SharedRevPiControl::open_at(path.to_str().unwrap()).unwrap());
+        let addr = serve(
+            HttpServer::bind("127.0.0.1:0", control)
+                .unwrap()
+                .read_only(true),
+        );
+        // more requests than workers, none of them may take one down
+        for length in 32..40 {
+            let get = format!("GET /variables/{} HTTP/1.1", "x".repeat(length));
+            assert_eq!(request(addr, &get, "").0, 400);
+        }
+        assert_eq!(request(addr, "GET /image HTTP/1.1", "").0, 200);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! A process image dump read back together with its configuration.
+//!
+//! `pitestrs dump` and [`RevPiControl::dump`](crate::RevPiControl::dump) only save the raw
+//! bytes; [`ImageFile`] gives them their meaning again from the config.rsc the RevPi was
+//! running, so an image captured in the field can be inspected with the usual APIs:
+//!
+//! ```ignore
+//! let image = ImageFile::open("revpi_proc_img.bin", Config::load("config.rsc")?)?;
+//! let rtd = Variable::resolve(&image, "RTDValue_1")?.with_type(VariableType::I16)?;
+//! println!("{}", rtd.read(&image)?);
+//! ```
+
+use crate::config::{Config, Direction};
+use crate::driver::{Driver, Event, PROCESS_IMAGE_SIZE};
+use crate::emulator::{add_variables, device_info};
+use crate::memory::MemoryDriver;
+use crate::picontrol::{SDeviceInfo, SPIValue, SPIVariable};
+use crate::value::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// ImageFile is a read-only [`Driver`] that answers from a dump file and a configuration.
+///
+/// Variables and the device list come from the configuration, the values from the file.
+/// Everything that would change the image or needs a running driver, i.e. write,
+/// set_bit_value, reset, reset_counter, stop_io and wait_for_event, fails.
+pub struct ImageFile {
+    path: PathBuf,
+    driver: MemoryDriver,
+    config: Config,
+}
+
+impl ImageFile {
+    /// Loads the dump at @path, written with the configuration @config.
+    ///
+    /// A dump shorter than the process image is padded with zeros, but it has to cover the
+    /// areas of all configured devices. A longer dump is kept whole, like `pitestrs -s file
+    /// dump` writes a dumped image whatever its size.
+    pub fn open<P: AsRef<Path>>(path: P, config: Config) -> io::Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("can not read {}: {}", path.display(), e))
+        })?;
+        Self::from_bytes(&data, config).map(|image| ImageFile {
+            path: path.to_owned(),
+            ..image
+        })
+    }
+
+    /// Like [`ImageFile::open`], for a dump already in memory.
+    pub fn from_bytes(data: &[u8], config: Config) -> io::Result<Self> {
+        let driver = MemoryDriver::with_size(data.len().max(PROCESS_IMAGE_SIZE));
+        for device in &config.devices {
+            let info = device_info(device);
+            let end = [
+                (info.i16uInputOffset, info.i16uInputLength),
+                (info.i16uOutputOffset, info.i16uOutputLength),
+                (info.i16uConfigOffset, info.i16uConfigLength),
+            ];
+            let end = end
+                .iter()
+                .map(|&(offset, length)| usize::from(offset) + usize::from(length))
+                .max()
+                .unwrap_or(0);
+            if end > data.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} at address {} ends at offset {}, the dump has only {} bytes",
+                        device.name,
+                        device.position,
+                        end,
+                        data.len()
+                    ),
+                ));
+            }
+            driver.add_device(info);
+            add_variables(&driver, device)?;
+        }
+        driver.write(0, data)?;
+        Ok(ImageFile {
+            path: PathBuf::new(),
+            driver,
+            config,
+        })
+    }
+
+    /// The dump file, empty if loaded with [`ImageFile::from_bytes`].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The configuration the dump was taken with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The value of variable @name, as the unsigned type of its length.
+    pub fn value(&self, name: &str) -> io::Result<Value> {
+        self.config.variable(name)?.read(self)
+    }
+
+    /// The bytes of one area of the device at @position.
+    pub fn area(&self, position: u8, direction: Direction) -> io::Result<Vec<u8>> {
+        let device = self.config.device(position).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no device at address {}", position),
+            )
+        })?;
+        match device.area(direction) {
+            Some((offset, length)) => self.driver.read(offset.into(), length.into()),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn read_only(operation: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} is not possible on a process image file", operation),
+    )
+}
+
+impl Driver for ImageFile {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        self.driver.read(offset, length)
+    }
+
+    fn write(&self, _offset: u64, _data: &[u8]) -> io::Result<()> {
+        Err(read_only("write"))
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        self.driver.get_variable_info(name)
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        self.driver.get_device_info_list()
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        self.driver.get_bit_value(value)
+    }
+
+    fn set_bit_value(&self, _value: &mut SPIValue) -> io::Result<bool> {
+        Err(read_only("set_bit_value"))
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        Err(read_only("reset"))
+    }
+
+    fn reset_counter(&self, _address: u8, _bitfield: u16) -> io::Result<()> {
+        Err(read_only("reset_counter"))
+    }
+
+    fn stop_io(&self, _stop: bool) -> io::Result<bool> {
+        Err(read_only("stop_io"))
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        Err(read_only("wait_for_event"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::value::{Variable, VariableType};
+
+    #[test]
+    fn read_dump_file() {
+        let mut data = vec![0; 133];
+        data[78] = 0b10;
+        data[114..116].copy_from_slice(&(-15i16).to_le_bytes());
+        let image = ImageFile::from_bytes(&data, Config::parse(CONFIG).unwrap()).unwrap();
+
+        assert_eq!(image.value("O_10").unwrap(), Value::Bool(true));
+        assert_eq!(image.value("O_9").unwrap(), Value::Bool(false));
+        let rtd = Variable::resolve(&image, "RTDValue_1")
+            .unwrap()
+            .with_type(VariableType::I16)
+            .unwrap();
+        assert_eq!(rtd.read(&image).unwrap(), Value::I16(-15));
+        assert_eq!(image.get_device_info_list().unwrap().len(), 3);
+        assert_eq!(image.area(33, Direction::Output).unwrap().len(), 4);
+        assert_eq!(image.area(32, Direction::Output).unwrap()[1], 0b10);
+        assert!(image.area(34, Direction::Input).is_err());
+        assert_eq!(
+            image.write(0, &[1]).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+
+        assert!(ImageFile::from_bytes(&data[..100], Config::parse(CONFIG).unwrap()).is_err());
+
+        // what pitestrs dumps from a dumped image larger than the process image
+        data.resize(PROCESS_IMAGE_SIZE + 10, 0xff);
+        let image = ImageFile::from_bytes(&data, Config::parse(CONFIG).unwrap()).unwrap();
+        assert_eq!(image.value("O_10").unwrap(), Value::Bool(true));
+        assert_eq!(image.read(0, data.len()).unwrap(), data);
+    }
+}
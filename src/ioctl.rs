@@ -6,6 +6,10 @@ pub const KB_GET_DEVICE_INFO: u32 = request_code_none!(picontrol::KB_IOC_MAGIC,
 pub const KB_FIND_VARIABLE: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 17) as u32; // find a varible defined in piCtory
 pub const KB_GET_VALUE: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 15) as u32; // get the value of one bit in the process image
 pub const KB_SET_VALUE: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 16) as u32; // set the value of one bit in the process image
+pub const KB_DIO_RESET_COUNTER: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 20) as u32; // set a counter or endocder to 0
+pub const KB_GET_LAST_MESSAGE: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 21) as u32; // copy the last error message
+pub const KB_STOP_IO: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 22) as u32; // stop/start IO communication, can be used for I/O simulation
+pub const KB_WAIT_FOR_EVENT: u32 = request_code_none!(picontrol::KB_IOC_MAGIC, 50) as u32; // wait for an event. This call is normally blocking
 
 ioctl_none_bad!(reset, KB_RESET);
 ioctl_read_bad!(
@@ -16,3 +20,15 @@ ioctl_read_bad!(
 ioctl_read_bad!(get_variable_info, KB_FIND_VARIABLE, picontrol::SPIVariable);
 ioctl_read_bad!(get_bit_value, KB_GET_VALUE, picontrol::SPIValue);
 ioctl_read_bad!(set_bit_value, KB_SET_VALUE, picontrol::SPIValue);
+ioctl_write_ptr_bad!(
+    reset_counter,
+    KB_DIO_RESET_COUNTER,
+    picontrol::SDIOResetCounter
+);
+ioctl_read_bad!(
+    get_last_message,
+    KB_GET_LAST_MESSAGE,
+    [u8; picontrol::REV_PI_ERROR_MSG_LEN as usize]
+);
+ioctl_write_ptr_bad!(stop_io, KB_STOP_IO, nix::libc::c_int);
+ioctl_read_bad!(wait_for_event, KB_WAIT_FOR_EVENT, nix::libc::c_int);
@@ -25,12 +25,59 @@ use std::io::ErrorKind;
 use std::io::SeekFrom;
 use std::io::Write;
 use std::iter;
-use std::os::unix::io::AsRawFd;
-
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+pub mod aio;
+#[cfg(feature = "async")]
+pub mod async_driver;
+pub mod codegen;
+pub mod config;
+pub mod dio;
+mod driver;
+pub mod emulator;
+pub mod error;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod historian;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod image_file;
 #[allow(dead_code)]
 mod ioctl;
+pub mod mapping;
+mod memory;
+pub mod metrics;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "modbus")]
+pub mod modbus_client;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "opcua")]
+pub mod opcua;
+#[allow(clippy::redundant_static_lifetimes)]
 mod picontrol;
+pub mod replay;
+pub mod restore;
+mod shared;
+pub mod socket;
+pub mod validate;
+mod value;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use driver::{Driver, Event, PROCESS_IMAGE_SIZE};
+pub use error::{ConfigError, ConfigErrorKind};
+pub use mapping::{Mapping, ProcessImage};
+pub use memory::MemoryDriver;
 pub use picontrol::*;
+pub use shared::SharedRevPiControl;
+pub use value::{Quantity, Scaling, Value, Variable, VariableType};
+
+#[cfg(feature = "derive")]
+pub use picontrol_derive::ProcessImage;
 
 #[derive(Debug)]
 pub enum CstrToStrError {
@@ -54,6 +101,11 @@ fn convert_cstr_to_str(
     cstr: &[::std::os::raw::c_char],
 ) -> std::result::Result<&str, CstrToStrError> {
     let u8slice = unsafe { &*(cstr as *const _ as *const [u8]) };
+    // the name is NUL padded, keep everything up to the first terminator
+    let u8slice = match u8slice.iter().position(|&b| b == 0) {
+        Some(end) => &u8slice[..=end],
+        None => u8slice,
+    };
     let c_str = CStr::from_bytes_with_nul(u8slice).map_err(CstrToStrError::FromBytesWithNul)?;
     c_str.to_str().map_err(CstrToStrError::Utf8)
 }
@@ -70,6 +122,12 @@ pub struct RevPiControl {
     handle: Option<File>,
 }
 
+impl Default for RevPiControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Default for picontrol::SDeviceInfo {
     fn default() -> picontrol::SDeviceInfo {
         unsafe { std::mem::zeroed() }
@@ -96,29 +154,119 @@ fn byte_to_int8_array(name: &str) -> [::std::os::raw::c_char; 32] {
     bname
 }
 
+/// Compares in time independent of where @a and @b differ, so a token can not be guessed
+/// byte by byte.
+#[cfg(any(feature = "http", feature = "grpc"))]
+pub(crate) fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn variable_info(fd: RawFd, name: &str) -> Result<picontrol::SPIVariable> {
+    // the driver needs the name NUL terminated in its 32 bytes
+    if name.len() >= 32 {
+        return Err(Sys(Errno::EINVAL));
+    }
+    let mut v = picontrol::SPIVariable {
+        strVarName: byte_to_int8_array(name),
+        ..Default::default()
+    };
+    let res = unsafe { ioctl::get_variable_info(fd, &mut v) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(v)
+}
+
+fn device_info_list(fd: RawFd) -> Result<Vec<picontrol::SDeviceInfo>> {
+    let mut pDev = [picontrol::SDeviceInfo {
+        ..Default::default()
+    }; picontrol::REV_PI_DEV_CNT_MAX as usize];
+    let res = unsafe { ioctl::get_device_info_list(fd, &mut pDev[0]) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(pDev[..res as usize].to_vec())
+}
+
+fn bit_value(
+    fd: RawFd,
+    pSpiValue: &mut picontrol::SPIValue,
+    func: unsafe fn(i32, *mut picontrol::SPIValueStr) -> std::result::Result<i32, nix::Error>,
+) -> Result<bool> {
+    pSpiValue.i16uAddress += (pSpiValue.i8uBit as u16) / 8;
+    pSpiValue.i8uBit %= 8;
+
+    let res = unsafe { func(fd, pSpiValue) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(true)
+}
+
+fn counter_reset(fd: RawFd, address: u8, bitfield: u16) -> Result<()> {
+    let tel = picontrol::SDIOResetCounter {
+        i8uAddress: address,
+        i16uBitfield: bitfield,
+    };
+    let res = unsafe { ioctl::reset_counter(fd, &tel) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(())
+}
+
+fn io_stop(fd: RawFd, stop: bool) -> Result<bool> {
+    let mode = stop as c_int;
+    let res = unsafe { ioctl::stop_io(fd, &mode) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(res != 0)
+}
+
+fn last_message(fd: RawFd) -> Result<Option<String>> {
+    let mut msg = [0u8; picontrol::REV_PI_ERROR_MSG_LEN as usize];
+    let res = unsafe { ioctl::get_last_message(fd, &mut msg) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    let end = msg.iter().position(|&c| c == 0).unwrap_or(msg.len());
+    let msg = String::from_utf8_lossy(&msg[..end]).trim_end().to_owned();
+    Ok(Some(msg).filter(|m| !m.is_empty()))
+}
+
+fn event(fd: RawFd) -> Result<Event> {
+    let mut code: c_int = 0;
+    let res = unsafe { ioctl::wait_for_event(fd, &mut code) }?;
+    if res < 0 {
+        return Err(Sys(Errno::last()));
+    }
+    Ok(Event::from(code))
+}
+
 // numToBytes converts a generic fixed-size value to its byte representation.
 pub fn num_to_bytes(
     num: u64,
     size: usize,
 ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
     match size {
-        8 => return Ok(vec![num as u8]),
+        8 => Ok(vec![num as u8]),
         16 => {
             let mut buf = [0; 2];
             LittleEndian::write_u16(&mut buf, num as u16);
-            return Ok(buf.to_vec());
+            Ok(buf.to_vec())
         }
         32 => {
             let mut buf = [0; 4];
             LittleEndian::write_u32(&mut buf, num as u32);
-            return Ok(buf.to_vec());
+            Ok(buf.to_vec())
         }
         64 => {
             let mut buf = [0; 8];
-            LittleEndian::write_u64(&mut buf, num as u64);
-            return Ok(buf.to_vec());
+            LittleEndian::write_u64(&mut buf, num);
+            Ok(buf.to_vec())
         }
-        _ => return Err(From::from(format!("invalid size {}", size))),
+        _ => Err(From::from(format!("invalid size {}", size))),
     }
 }
 
@@ -138,7 +286,7 @@ impl RevPiControl {
 
     /// Open the Pi Control interface.
     pub fn open(&mut self) -> io::Result<bool> {
-        if let Some(_) = self.handle.as_mut() {
+        if self.handle.is_some() {
             return Ok(true);
         }
         let file = OpenOptions::new()
@@ -146,13 +294,10 @@ impl RevPiControl {
             .write(true)
             .open(&self.path)
             .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!(
-                        "can not open picontrol file descriptor at {}, error: {}",
-                        &self.path, e
-                    ),
-                )
+                std::io::Error::other(format!(
+                    "can not open picontrol file descriptor at {}, error: {}",
+                    &self.path, e
+                ))
             })?;
         self.handle = Some(file);
         Ok(true)
@@ -160,22 +305,22 @@ impl RevPiControl {
 
     /// Close the Pi Control interface.
     pub fn close(&mut self) {
-        if let Some(f) = self.handle.as_mut() {
-            std::mem::drop(f);
-            self.handle = None;
-            return;
-        }
+        self.handle = None;
     }
 
     /// Reset Pi Control Interface.
+    ///
+    /// Returns the ioctl result as it is: a configuration the driver refuses fails with one of
+    /// the PICONTROL_CONFIG_ERROR_* codes as errno, which [`Driver::reset`] turns into a
+    /// [`ConfigError`].
     pub fn reset(&self) -> Result<c_int> {
         let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
-        return unsafe { ioctl::reset(f.as_raw_fd()) };
+        unsafe { ioctl::reset(f.as_raw_fd()) }
     }
 
     // Gets process data from a specific position, reads @length bytes from file.
     // Returns a result containing the bytes read or error.
-    pub fn read(&mut self, offset: u64, length: usize) -> std::io::Result<(Vec<u8>)> {
+    pub fn read(&mut self, offset: u64, length: usize) -> std::io::Result<Vec<u8>> {
         let f = self
             .handle
             .as_mut()
@@ -187,83 +332,101 @@ impl RevPiControl {
         Ok(v)
     }
 
-    /// Writes process data at a specific position and a returns a boolean result.
-    pub fn write(&mut self, offset: u64, data: &Vec<u8>) -> std::io::Result<(bool)> {
+    /// Writes all of @data at a specific position and a returns a boolean result.
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> std::io::Result<bool> {
         let f = self
             .handle
             .as_mut()
             .ok_or(io::Error::new(ErrorKind::NotFound, "error reading file"))?;
         /* seek */
         f.seek(SeekFrom::Start(offset))?;
-        f.write(data)?;
+        f.write_all(data)?;
         Ok(true)
     }
 
+    // Like read, but without moving the file position, for the shared Driver implementation.
+    pub(crate) fn read_at(&self, offset: u64, length: usize) -> std::io::Result<Vec<u8>> {
+        let f = self
+            .handle
+            .as_ref()
+            .ok_or(io::Error::new(ErrorKind::NotFound, "error reading file"))?;
+        let mut v = vec![0u8; length];
+        f.read_exact_at(&mut v, offset)?;
+        Ok(v)
+    }
+
+    // Like write, but without moving the file position, for the shared Driver implementation.
+    pub(crate) fn write_at(&self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+        let f = self
+            .handle
+            .as_ref()
+            .ok_or(io::Error::new(ErrorKind::NotFound, "error reading file"))?;
+        f.write_all_at(data, offset)
+    }
+
     /// Get the info for a variable.
     pub fn get_variable_info(&self, name: &str) -> Result<picontrol::SPIVariable> {
         let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
-        let mut v = picontrol::SPIVariable {
-            strVarName: byte_to_int8_array(name),
-            ..Default::default()
-        };
-        let res = unsafe { ioctl::get_variable_info(f.as_raw_fd(), &mut v) }?;
-        if res < 0 {
-            return Err(Sys(Errno::last()));
-        }
-        Ok(v)
+        variable_info(f.as_raw_fd(), name)
     }
 
     /// Gets a description of connected devices.
     pub fn get_device_info_list(&self) -> Result<Vec<picontrol::SDeviceInfo>> {
         let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
-        // let mut pDev: picontrol::SDeviceInfo = unsafe { mem::uninitialized() };
-        let mut pDev = [picontrol::SDeviceInfo {
-            ..Default::default()
-        }; picontrol::REV_PI_DEV_CNT_MAX as usize];
-        let res = unsafe { ioctl::get_device_info_list(f.as_raw_fd(), &mut pDev[0]) }?;
-        if res < 0 {
-            return Err(Sys(Errno::last()));
-        }
-        Ok(pDev[..res as usize].to_vec())
+        device_info_list(f.as_raw_fd())
     }
 
     /// Gets the value of one bit in the process image.
     pub fn get_bit_value(&self, pSpiValue: &mut picontrol::SPIValue) -> Result<bool> {
-        return self.handle_bit_value(pSpiValue, ioctl::get_bit_value);
+        let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        bit_value(f.as_raw_fd(), pSpiValue, ioctl::get_bit_value)
     }
 
     /// Sets the value of one bit in the process image.
     pub fn set_bit_value(&self, pSpiValue: &mut picontrol::SPIValue) -> Result<bool> {
-        return self.handle_bit_value(pSpiValue, ioctl::set_bit_value);
+        let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        bit_value(f.as_raw_fd(), pSpiValue, ioctl::set_bit_value)
     }
 
-    fn handle_bit_value(
-        &self,
-        pSpiValue: &mut picontrol::SPIValue,
-        func: unsafe fn(i32, *mut picontrol::SPIValueStr) -> std::result::Result<i32, nix::Error>,
-    ) -> Result<bool> {
+    const SMALL_BUFFER_SIZE: usize = 256;
+    const LARGE_BUFFER_SIZE: usize = 64 * 1024;
+
+    /// Sets counters or encoders of a DIO/DI module to 0.
+    ///
+    /// Bit n of @bitfield selects the counter on input n + 1.
+    pub fn reset_counter(&self, address: u8, bitfield: u16) -> Result<()> {
         let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        counter_reset(f.as_raw_fd(), address, bitfield)
+    }
 
-        pSpiValue.i16uAddress += (pSpiValue.i8uBit as u16) / 8;
-        pSpiValue.i8uBit %= 8;
+    /// Blocks until the driver reports an event, e.g. a reset.
+    pub fn wait_for_event(&self) -> Result<Event> {
+        let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        event(f.as_raw_fd())
+    }
 
-        let res = unsafe { func(f.as_raw_fd(), pSpiValue) }?;
-        if res < 0 {
-            return Err(Sys(Errno::last()));
-        }
-        Ok(true)
+    /// Stops or restarts the I/O update, returns whether it is stopped now.
+    ///
+    /// While stopped, the process image is not exchanged with the modules, so inputs can be
+    /// written by an application to simulate them.
+    pub fn stop_io(&self, stop: bool) -> Result<bool> {
+        let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        io_stop(f.as_raw_fd(), stop)
     }
 
-    const SMALL_BUFFER_SIZE: usize = 256;
-    const LARGE_BUFFER_SIZE: usize = 64 * 1024;
+    /// Gets the message the driver left for the last ioctl call, if any.
+    pub fn last_message(&self) -> Result<Option<String>> {
+        let f = self.handle.as_ref().ok_or(Sys(ENODEV))?;
+        last_message(f.as_raw_fd())
+    }
 
-    /// dumps the process image to a file.
+    /// dumps the process image to a file, truncating an existing one.
     ///
     /// # Arguments
     ///
     /// * `fp` - The file path
     ///
-    pub fn dump(&mut self, fp: &str) -> std::io::Result<(bool)> {
+    pub fn dump(&mut self, fp: &str) -> std::io::Result<bool> {
         let f = self
             .handle
             .as_mut()
@@ -275,6 +438,7 @@ impl RevPiControl {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open(fp)?;
         // f.write(data)?;
         let buffer = &mut vec![0; Self::SMALL_BUFFER_SIZE];
@@ -300,7 +464,7 @@ impl RevPiControl {
             writer.write_all(&buffer[..len_read])?;
 
             if len_read == buffer.len() && len_read < Self::LARGE_BUFFER_SIZE {
-                buffer.extend(iter::repeat(0).take(len_read));
+                buffer.extend(iter::repeat_n(0, len_read));
             }
         }
     }
@@ -315,7 +479,7 @@ impl Drop for RevPiControl {
 // get_module_name returns a friendly name for a RevPi module type.
 pub fn get_module_name(moduletype: u32) -> &'static str {
     let moduletype = moduletype & picontrol::PICONTROL_NOT_CONNECTED_MASK;
-    let moddes = match moduletype {
+    match moduletype {
         95 => "RevPi Core",
         96 => "RevPi DIO",
         97 => "RevPi DI",
@@ -336,14 +500,36 @@ pub fn get_module_name(moduletype: u32) -> &'static str {
         79 => "Gateway Profinet IRT",
         81 => "Gateway SercosIII",
         _ => "unknown moduletype",
-    };
+    }
+}
 
-    moddes
+// find_module returns the device of one of the given module types, the one at @address if given.
+pub(crate) fn find_module(
+    devices: &[picontrol::SDeviceInfo],
+    moduletypes: &[u32],
+    address: Option<u8>,
+) -> io::Result<picontrol::SDeviceInfo> {
+    devices
+        .iter()
+        .find(|d| {
+            moduletypes
+                .contains(&(u32::from(d.i16uModuleType) & picontrol::PICONTROL_NOT_CONNECTED_MASK))
+                && address.is_none_or(|a| a == d.i8uAddress)
+        })
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<&str> = moduletypes.iter().map(|t| get_module_name(*t)).collect();
+            let msg = match address {
+                Some(a) => format!("no {} module at address {}", names.join("/"), a),
+                None => format!("no {} module found", names.join("/")),
+            };
+            io::Error::new(ErrorKind::NotFound, msg)
+        })
 }
 
 // IsModuleConnected checks whether a RevPi module is conneted.
 pub fn is_module_connected(moduletype: u32) -> bool {
-    return moduletype & picontrol::PICONTROL_NOT_CONNECTED > 0;
+    moduletype & picontrol::PICONTROL_NOT_CONNECTED > 0
 }
 
 #[cfg(test)]
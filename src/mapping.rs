@@ -0,0 +1,307 @@
+//! Mapping of Rust structs onto piCtory variables.
+//!
+//! A struct implementing [`ProcessImage`], usually through `#[derive(ProcessImage)]` with the
+//! `derive` feature, names one variable per field:
+//!
+//! ```ignore
+//! #[derive(ProcessImage)]
+//! struct Conveyor {
+//!     #[picontrol(name = "I_1")]
+//!     running: bool,
+//!     #[picontrol(name = "InputValue_1")]
+//!     speed: i16,
+//!     #[picontrol(name = "O_1", output)]
+//!     lamp: bool,
+//! }
+//!
+//! let mapping = Mapping::<Conveyor>::resolve(&control)?;
+//! let mut conveyor = mapping.read(&control)?;
+//! conveyor.lamp = conveyor.running;
+//! mapping.write(&control, &conveyor)?;
+//! ```
+//!
+//! Field types must implement [`FieldType`], anything else fails to compile. Whether a field
+//! type has the length of its variable is checked when the mapping is resolved.
+
+use crate::config::Config;
+use crate::driver::Driver;
+use crate::value::{Value, Variable, VariableType};
+use std::io;
+use std::marker::PhantomData;
+
+/// A Rust type a process image variable can be mapped to.
+pub trait FieldType: Sized {
+    /// The variable type this Rust type stands for.
+    const KIND: VariableType;
+
+    /// Extracts the Rust value, None if @value is of another type.
+    fn from_value(value: Value) -> Option<Self>;
+
+    /// Wraps the Rust value.
+    fn to_value(&self) -> Value;
+}
+
+macro_rules! field_type {
+    ($t:ty, $variant:ident) => {
+        impl FieldType for $t {
+            const KIND: VariableType = VariableType::$variant;
+
+            fn from_value(value: Value) -> Option<Self> {
+                match value {
+                    Value::$variant(v) => Some(v),
+                    _ => None,
+                }
+            }
+
+            fn to_value(&self) -> Value {
+                Value::$variant(*self)
+            }
+        }
+    };
+}
+
+field_type!(bool, Bool);
+field_type!(u8, U8);
+field_type!(i8, I8);
+field_type!(u16, U16);
+field_type!(i16, I16);
+field_type!(u32, U32);
+field_type!(i32, I32);
+field_type!(f32, F32);
+
+/// The description of one mapped field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// The Rust field name.
+    pub field: &'static str,
+    /// The piCtory variable name.
+    pub name: &'static str,
+    pub kind: VariableType,
+    /// Whether the field is written back by [`Mapping::write`].
+    pub output: bool,
+}
+
+/// A struct whose fields are piCtory variables.
+pub trait ProcessImage: Sized {
+    /// The fields, in declaration order.
+    const FIELDS: &'static [Field];
+
+    /// Builds the struct from a process image; @variables are the resolved [`Self::FIELDS`].
+    fn decode(variables: &[Variable], image: &[u8]) -> io::Result<Self>;
+
+    /// The values of all fields, in the order of [`Self::FIELDS`].
+    fn encode(&self) -> Vec<Value>;
+}
+
+/// Decodes one field, used by the derived [`ProcessImage::decode`].
+pub fn decode_field<T: FieldType>(variable: &Variable, image: &[u8]) -> io::Result<T> {
+    T::from_value(variable.decode(image)?).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("variable {} is not of type {}", variable.name, T::KIND),
+        )
+    })
+}
+
+/// The resolved variables of a [`ProcessImage`] struct.
+pub struct Mapping<T> {
+    variables: Vec<Variable>,
+    start: u16,
+    // in usize, the last variable may end at the end of the address space
+    end: usize,
+    _image: PhantomData<fn() -> T>,
+}
+
+impl<T: ProcessImage> Mapping<T> {
+    /// Resolves all fields with KB_FIND_VARIABLE.
+    pub fn resolve<D: Driver + ?Sized>(driver: &D) -> io::Result<Self> {
+        Self::build(|name| Variable::resolve(driver, name))
+    }
+
+    /// Resolves all fields against a parsed config.rsc.
+    pub fn from_config(config: &Config) -> io::Result<Self> {
+        Self::build(|name| config.variable(name))
+    }
+
+    fn build<F>(lookup: F) -> io::Result<Self>
+    where
+        F: Fn(&str) -> io::Result<Variable>,
+    {
+        let variables = T::FIELDS
+            .iter()
+            .map(|field| {
+                lookup(field.name)?
+                    .with_type(field.kind)
+                    .map_err(|e| io::Error::new(e.kind(), format!("field {}: {}", field.field, e)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let start = variables.iter().map(|v| v.address).min().unwrap_or(0);
+        let end = variables
+            .iter()
+            .map(|v| usize::from(v.address) + v.byte_length())
+            .max()
+            .unwrap_or(0);
+        Ok(Mapping {
+            variables,
+            start,
+            end,
+            _image: PhantomData,
+        })
+    }
+
+    /// The resolved variables, in the order of the fields.
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// Reads all fields from one snapshot of the process image.
+    ///
+    /// The bytes spanned by the fields are read with a single call, so all values belong to
+    /// the same driver cycle.
+    pub fn read<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<T> {
+        let mut image = vec![0; self.end];
+        let span = driver.read(u64::from(self.start), self.end - self.start as usize)?;
+        image[self.start as usize..].copy_from_slice(&span);
+        self.decode(&image)
+    }
+
+    /// Builds the struct from a snapshot of the whole process image.
+    pub fn decode(&self, image: &[u8]) -> io::Result<T> {
+        T::decode(&self.variables, image)
+    }
+
+    /// Writes the output fields of @value; all other fields are left alone.
+    pub fn write<D: Driver + ?Sized>(&self, driver: &D, value: &T) -> io::Result<()> {
+        for ((field, variable), value) in T::FIELDS.iter().zip(&self.variables).zip(value.encode())
+        {
+            if field.output {
+                variable.write(driver, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::MemoryDriver;
+
+    // what #[derive(ProcessImage)] generates
+    struct Conveyor {
+        running: bool,
+        speed: i16,
+        lamp: bool,
+        pwm: u8,
+    }
+
+    impl ProcessImage for Conveyor {
+        const FIELDS: &'static [Field] = &[
+            Field {
+                field: "running",
+                name: "I_3",
+                kind: <bool as FieldType>::KIND,
+                output: false,
+            },
+            Field {
+                field: "speed",
+                name: "InputValue_1",
+                kind: <i16 as FieldType>::KIND,
+                output: false,
+            },
+            Field {
+                field: "lamp",
+                name: "O_10",
+                kind: <bool as FieldType>::KIND,
+                output: true,
+            },
+            Field {
+                field: "pwm",
+                name: "PWM_2",
+                kind: <u8 as FieldType>::KIND,
+                output: true,
+            },
+        ];
+
+        fn decode(variables: &[Variable], image: &[u8]) -> io::Result<Self> {
+            Ok(Conveyor {
+                running: decode_field(&variables[0], image)?,
+                speed: decode_field(&variables[1], image)?,
+                lamp: decode_field(&variables[2], image)?,
+                pwm: decode_field(&variables[3], image)?,
+            })
+        }
+
+        fn encode(&self) -> Vec<Value> {
+            vec![
+                self.running.to_value(),
+                self.speed.to_value(),
+                self.lamp.to_value(),
+                self.pwm.to_value(),
+            ]
+        }
+    }
+
+    #[test]
+    fn read_and_write_struct() {
+        let config = Config::parse(CONFIG).unwrap();
+        let driver = MemoryDriver::new();
+        driver.write(7, &[0b100]).unwrap();
+        driver.write(102, &(-40i16).to_le_bytes()).unwrap();
+
+        let mapping = Mapping::<Conveyor>::from_config(&config).unwrap();
+        let mut conveyor = mapping.read(&driver).unwrap();
+        assert!(conveyor.running);
+        assert_eq!(conveyor.speed, -40);
+        assert!(!conveyor.lamp);
+
+        conveyor.lamp = true;
+        conveyor.pwm = 30;
+        conveyor.speed = 0;
+        mapping.write(&driver, &conveyor).unwrap();
+        assert_eq!(driver.read(78, 1).unwrap(), vec![0b10]);
+        assert_eq!(driver.read(80, 1).unwrap(), vec![30]);
+        assert_eq!(
+            driver.read(102, 2).unwrap(),
+            (-40i16).to_le_bytes().to_vec()
+        );
+
+        // fields at the end of the address space
+        let mapping = Mapping::<Conveyor>::build(|name| {
+            let mut variable = config.variable(name)?;
+            variable.address = u16::MAX;
+            Ok(variable)
+        })
+        .unwrap();
+        assert!(mapping.read(&driver).is_err());
+    }
+
+    #[test]
+    fn field_type_checked_at_resolve() {
+        struct Wrong {
+            _value: u32,
+        }
+
+        impl ProcessImage for Wrong {
+            const FIELDS: &'static [Field] = &[Field {
+                field: "value",
+                name: "InputValue_1",
+                kind: <u32 as FieldType>::KIND,
+                output: false,
+            }];
+
+            fn decode(_: &[Variable], _: &[u8]) -> io::Result<Self> {
+                unreachable!()
+            }
+
+            fn encode(&self) -> Vec<Value> {
+                unreachable!()
+            }
+        }
+
+        let config = Config::parse(CONFIG).unwrap();
+        let err = Mapping::<Wrong>::from_config(&config).err().unwrap();
+        assert!(err.to_string().starts_with("field value:"));
+    }
+}
@@ -0,0 +1,263 @@
+//! An in-memory stand-in for the piControl driver.
+
+use crate::byte_to_int8_array;
+use crate::dio;
+use crate::driver::{Driver, Event, PROCESS_IMAGE_SIZE};
+use crate::picontrol::{SDeviceInfo, SPIValue, SPIVariable};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// MemoryDriver keeps a process image, a variable table and a device list in memory and
+/// answers the [`Driver`] operations from them, so code written against the driver can be
+/// tested without /dev/piControl0.
+///
+/// Variables and devices are registered by the test; nothing is derived from a configuration.
+pub struct MemoryDriver {
+    state: Mutex<State>,
+    event_ready: Condvar,
+}
+
+struct State {
+    image: Vec<u8>,
+    variables: HashMap<String, SPIVariable>,
+    devices: Vec<SDeviceInfo>,
+    events: VecDeque<Event>,
+    stopped: bool,
+}
+
+impl Default for MemoryDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryDriver {
+    /// Creates a zeroed process image of the driver's size.
+    pub fn new() -> Self {
+        Self::with_size(PROCESS_IMAGE_SIZE)
+    }
+
+    /// Creates a zeroed process image of @size bytes.
+    pub fn with_size(size: usize) -> Self {
+        MemoryDriver {
+            state: Mutex::new(State {
+                image: vec![0; size],
+                variables: HashMap::new(),
+                devices: Vec::new(),
+                events: VecDeque::new(),
+                stopped: false,
+            }),
+            event_ready: Condvar::new(),
+        }
+    }
+
+    /// Registers a variable as KB_FIND_VARIABLE would report it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if @name does not fit the 32 byte name field of SPIVariable.
+    pub fn add_variable(&self, name: &str, address: u16, bit: u8, length: u16) {
+        assert!(name.len() < 32, "variable name {} is too long", name);
+        let variable = SPIVariable {
+            strVarName: byte_to_int8_array(name),
+            i16uAddress: address,
+            i8uBit: bit,
+            i16uLength: length,
+        };
+        self.state().variables.insert(name.to_owned(), variable);
+    }
+
+    /// Appends a device to the list returned by get_device_info_list.
+    pub fn add_device(&self, device: SDeviceInfo) {
+        self.state().devices.push(device);
+    }
+
+    /// Queues an event for wait_for_event.
+    pub fn push_event(&self, event: Event) {
+        self.state().events.push_back(event);
+        self.event_ready.notify_all();
+    }
+
+    /// Whether the I/O update was stopped with stop_io.
+    pub fn io_stopped(&self) -> bool {
+        self.state().stopped
+    }
+
+    /// Returns a copy of the whole process image.
+    pub fn image(&self) -> Vec<u8> {
+        self.state().image.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The byte range of @length bytes at @offset, `None` if it does not fit a usize.
+fn range(offset: u64, length: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::try_from(offset).ok()?;
+    Some(start..start.checked_add(length)?)
+}
+
+fn out_of_range(offset: u64, length: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "{} bytes at offset {} are outside of the process image",
+            length, offset
+        ),
+    )
+}
+
+fn bit_position(image: &[u8], value: &mut SPIValue) -> io::Result<(usize, u8)> {
+    value.i16uAddress += (value.i8uBit as u16) / 8;
+    value.i8uBit %= 8;
+    let address = value.i16uAddress as usize;
+    if address >= image.len() {
+        return Err(out_of_range(address as u64, 1));
+    }
+    Ok((address, 1 << value.i8uBit))
+}
+
+impl Driver for MemoryDriver {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let state = self.state();
+        let range = range(offset, length).ok_or_else(|| out_of_range(offset, length))?;
+        match state.image.get(range) {
+            Some(data) => Ok(data.to_vec()),
+            None => Err(out_of_range(offset, length)),
+        }
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut state = self.state();
+        let range = range(offset, data.len()).ok_or_else(|| out_of_range(offset, data.len()))?;
+        match state.image.get_mut(range) {
+            Some(target) => {
+                target.copy_from_slice(data);
+                Ok(())
+            }
+            None => Err(out_of_range(offset, data.len())),
+        }
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        self.state().variables.get(name).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("variable {} not found", name),
+            )
+        })
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        Ok(self.state().devices.clone())
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        let state = self.state();
+        let (address, mask) = bit_position(&state.image, value)?;
+        value.i8uValue = (state.image[address] & mask != 0) as u8;
+        Ok(true)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        let mut state = self.state();
+        let (address, mask) = bit_position(&state.image, value)?;
+        if value.i8uValue != 0 {
+            state.image[address] |= mask;
+        } else {
+            state.image[address] &= !mask;
+        }
+        Ok(true)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        self.push_event(Event::Reset);
+        Ok(())
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        let mut state = self.state();
+        let device = state
+            .devices
+            .iter()
+            .find(|d| d.i8uAddress == address)
+            .copied()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no device at address {}", address),
+                )
+            })?;
+        for channel in (0..16).filter(|c| bitfield & (1 << c) != 0) {
+            let start = dio::counter_offset(&device, channel)?;
+            match state.image.get_mut(start..start + 4) {
+                Some(counter) => counter.fill(0),
+                None => return Err(out_of_range(start as u64, 4)),
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        self.state().stopped = stop;
+        Ok(stop)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        let mut state = self.state();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Ok(event);
+            }
+            state = self
+                .event_ready
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_driver_bits_and_variables() {
+        let driver = MemoryDriver::new();
+        driver.add_variable("O_1", 70, 9, 1);
+
+        let variable = driver.get_variable_info("O_1").unwrap();
+        assert_eq!(variable.name().unwrap(), "O_1");
+
+        let mut value = SPIValue {
+            i16uAddress: variable.i16uAddress,
+            i8uBit: variable.i8uBit,
+            i8uValue: 1,
+        };
+        driver.set_bit_value(&mut value).unwrap();
+        assert_eq!(driver.read(71, 1).unwrap(), vec![0b10]);
+
+        let mut value = SPIValue {
+            i16uAddress: 71,
+            i8uBit: 1,
+            ..Default::default()
+        };
+        driver.get_bit_value(&mut value).unwrap();
+        assert_eq!(value.i8uValue, 1);
+
+        assert!(driver.get_variable_info("missing").is_err());
+        assert!(driver.read(4095, 2).is_err());
+        assert!(driver.read(u64::MAX, 2).is_err());
+        assert!(driver.write(2, &[]).is_ok());
+        assert!(driver.read(1, usize::MAX).is_err());
+
+        driver.reset().unwrap();
+        assert_eq!(driver.wait_for_event().unwrap(), Event::Reset);
+    }
+}
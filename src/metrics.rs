@@ -0,0 +1,216 @@
+//! Metrics of the process image in the Prometheus text format.
+//!
+//! [`Metrics`] renders selected variables as gauges labelled with their module, position and
+//! name, the health of every device of `get_device_info_list` and the RevPi status bits
+//! (`PICONTROL_STATUS_*`) from the RevPiStatus byte of the base module:
+//!
+//! ```text
+//! revpi_variable{module="RevPi DIO",position="32",name="I_1"} 1
+//! revpi_module_active{module="RevPi DIO",position="32"} 1
+//! revpi_module_connected{module="RevPi DIO",position="32"} 1
+//! revpi_module_state{module="RevPi DIO",position="32"} 0
+//! revpi_status{flag="running"} 1
+//! ```
+//!
+//! The `module` label is the name of the module type for every gauge, so variables and module
+//! health join on `module` and `position` whatever the modules are called in piCtory.
+//!
+//! The `http` feature serves them on `GET /metrics`.
+
+use crate::config::Config;
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::picontrol::{self, SDeviceInfo};
+use crate::value::Variable;
+use std::io::{self, Write};
+
+/// The content type of the rendered metrics.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The status bits of the RevPiStatus byte with their label.
+const STATUS_FLAGS: [(u32, &str); 7] = [
+    (picontrol::PICONTROL_STATUS_RUNNING, "running"),
+    (picontrol::PICONTROL_STATUS_EXTRA_MODULE, "extra_module"),
+    (picontrol::PICONTROL_STATUS_MISSING_MODULE, "missing_module"),
+    (picontrol::PICONTROL_STATUS_SIZE_MISMATCH, "size_mismatch"),
+    (picontrol::PICONTROL_STATUS_LEFT_GATEWAY, "left_gateway"),
+    (picontrol::PICONTROL_STATUS_RIGHT_GATEWAY, "right_gateway"),
+    (picontrol::PICONTROL_STATUS_X2_DIN, "x2_din"),
+];
+
+/// A variable exported as a gauge.
+#[derive(Debug, Clone)]
+struct Gauge {
+    module: &'static str,
+    position: u8,
+    variable: Variable,
+}
+
+/// Metrics renders the exported values, see the module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    gauges: Vec<Gauge>,
+}
+
+impl Metrics {
+    /// Exports the variables of @config named in @names, besides device health and status.
+    pub fn new(config: &Config, names: &[&str]) -> io::Result<Metrics> {
+        let gauges = names
+            .iter()
+            .map(|name| {
+                let (device, _) = config.find(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("variable {} not found in configuration", name),
+                    )
+                })?;
+                Ok(Gauge {
+                    module: crate::get_module_name(device.product_type),
+                    position: device.position,
+                    variable: config.variable(name)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Metrics { gauges })
+    }
+
+    /// Writes the current metrics of @driver to @out.
+    pub fn render<D: Driver + ?Sized, W: Write>(&self, driver: &D, mut out: W) -> io::Result<()> {
+        let image = driver.read(0, PROCESS_IMAGE_SIZE)?;
+        let devices = driver.get_device_info_list()?;
+
+        if !self.gauges.is_empty() {
+            header(
+                &mut out,
+                "revpi_variable",
+                "Value of a process image variable.",
+            )?;
+        }
+        for gauge in &self.gauges {
+            writeln!(
+                out,
+                "revpi_variable{{module=\"{}\",position=\"{}\",name=\"{}\"}} {}",
+                escape(gauge.module),
+                gauge.position,
+                escape(&gauge.variable.name),
+                gauge.variable.decode(&image)?.as_f64()
+            )?;
+        }
+
+        module_gauge(
+            &mut out,
+            &devices,
+            "revpi_module_active",
+            "1 if the module is active.",
+            |d| u8::from(d.i8uActive != 0),
+        )?;
+        module_gauge(
+            &mut out,
+            &devices,
+            "revpi_module_connected",
+            "1 if the module is connected.",
+            |d| u8::from(connected(d)),
+        )?;
+        module_gauge(
+            &mut out,
+            &devices,
+            "revpi_module_state",
+            "The module state of the driver.",
+            |d| d.i8uModuleState,
+        )?;
+
+        // the base module, Core or Connect, is always at position 0
+        if let Some(base) = devices.iter().find(|d| d.i8uAddress == 0) {
+            let status = image
+                .get(usize::from(base.i16uInputOffset))
+                .copied()
+                .unwrap_or(0);
+            header(
+                &mut out,
+                "revpi_status",
+                "RevPi status bits of RevPiStatus.",
+            )?;
+            for (bit, flag) in &STATUS_FLAGS {
+                let on = u32::from(status) & bit != 0;
+                writeln!(out, "revpi_status{{flag=\"{}\"}} {}", flag, u8::from(on))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the gauge @name with the @value of every device.
+fn module_gauge<W: Write>(
+    out: &mut W,
+    devices: &[SDeviceInfo],
+    name: &str,
+    help: &str,
+    value: fn(&SDeviceInfo) -> u8,
+) -> io::Result<()> {
+    header(out, name, help)?;
+    for device in devices {
+        writeln!(
+            out,
+            "{}{{module=\"{}\",position=\"{}\"}} {}",
+            name,
+            escape(crate::get_module_name(device.i16uModuleType.into())),
+            device.i8uAddress,
+            value(device)
+        )?;
+    }
+    Ok(())
+}
+
+fn connected(device: &SDeviceInfo) -> bool {
+    u32::from(device.i16uModuleType) & picontrol::PICONTROL_NOT_CONNECTED == 0
+}
+
+fn header<W: Write>(out: &mut W, name: &str, help: &str) -> io::Result<()> {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} gauge", name)
+}
+
+// label values are quoted, piCtory names may contain anything
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+    use crate::value::Value;
+
+    #[test]
+    fn render_metrics() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Emulator::from_config(config.clone()).unwrap();
+        emulator.set("O_1", Value::Bool(true)).unwrap();
+        emulator.set("RevPiStatus", Value::U8(0b101)).unwrap();
+        let mut renamed = config.clone();
+        renamed.devices[1].name = "Pump".to_owned();
+        let metrics = Metrics::new(&renamed, &["O_1", "PWM_1"]).unwrap();
+        assert!(Metrics::new(&config, &["O_99"]).is_err());
+
+        let mut out = Vec::new();
+        metrics.render(&emulator, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        for line in &[
+            "# TYPE revpi_variable gauge",
+            "revpi_variable{module=\"RevPi DIO\",position=\"32\",name=\"O_1\"} 1",
+            "revpi_variable{module=\"RevPi DIO\",position=\"32\",name=\"PWM_1\"} 0",
+            "revpi_module_active{module=\"RevPi DIO\",position=\"32\"} 1",
+            "revpi_module_connected{module=\"RevPi AIO\",position=\"33\"} 1",
+            "revpi_status{flag=\"running\"} 1",
+            "revpi_status{flag=\"extra_module\"} 0",
+            "revpi_status{flag=\"missing_module\"} 1",
+        ] {
+            assert!(lines.contains(line), "{} missing in\n{}", line, text);
+        }
+        assert_eq!(escape("a\"b\\"), "a\\\"b\\\\");
+    }
+}
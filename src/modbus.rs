@@ -0,0 +1,664 @@
+//! A Modbus TCP server offering the process image.
+//!
+//! A [`Map`] assigns Modbus addresses of the four tables to byte ranges of the process image
+//! or to named variables. It is read from a JSON file:
+//!
+//! ```json
+//! {
+//!     "coils": [{ "address": 0, "offset": 77, "count": 14 }],
+//!     "discrete_inputs": [{ "address": 0, "variable": "I_1" }],
+//!     "holding_registers": [{ "address": 0, "offset": 79, "count": 8 }],
+//!     "input_registers": [{ "address": 10, "variable": "InputValue_1" }]
+//! }
+//! ```
+//!
+//! A range of coils or discrete inputs maps one bit each, starting at bit `bit`, 0 if not
+//! given, of the byte at `offset`; a range of registers maps one little endian 16 bit word
+//! each. A 1 bit variable is one coil or discrete input, an 8 or 16 bit variable is one
+//! register and a 32 bit variable two, the low word first. Only coils and holding registers
+//! can be written, as Modbus defines.
+
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::picontrol::SPIValue;
+use crate::value::Variable;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde_json::Value as Json;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub(crate) const READ_COILS: u8 = 1;
+pub(crate) const READ_DISCRETE_INPUTS: u8 = 2;
+pub(crate) const READ_HOLDING_REGISTERS: u8 = 3;
+pub(crate) const READ_INPUT_REGISTERS: u8 = 4;
+pub(crate) const WRITE_SINGLE_COIL: u8 = 5;
+pub(crate) const WRITE_SINGLE_REGISTER: u8 = 6;
+pub(crate) const WRITE_MULTIPLE_COILS: u8 = 15;
+pub(crate) const WRITE_MULTIPLE_REGISTERS: u8 = 16;
+
+pub(crate) const ILLEGAL_FUNCTION: u8 = 1;
+pub(crate) const ILLEGAL_DATA_ADDRESS: u8 = 2;
+pub(crate) const ILLEGAL_DATA_VALUE: u8 = 3;
+pub(crate) const SERVER_DEVICE_FAILURE: u8 = 4;
+
+// The largest counts that fit a Modbus PDU.
+pub(crate) const MAX_READ_BITS: u16 = 2000;
+pub(crate) const MAX_READ_REGISTERS: u16 = 125;
+pub(crate) const MAX_WRITE_BITS: u16 = 1968;
+pub(crate) const MAX_WRITE_REGISTERS: u16 = 123;
+
+// the longest a client may stay silent before its connection is closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The four Modbus tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl Table {
+    const ALL: [Table; 4] = [
+        Table::Coils,
+        Table::DiscreteInputs,
+        Table::HoldingRegisters,
+        Table::InputRegisters,
+    ];
+
+    /// The key of the table in the mapping file.
+    pub fn key(self) -> &'static str {
+        match self {
+            Table::Coils => "coils",
+            Table::DiscreteInputs => "discrete_inputs",
+            Table::HoldingRegisters => "holding_registers",
+            Table::InputRegisters => "input_registers",
+        }
+    }
+
+    /// Whether the table holds single bits rather than registers.
+    pub fn is_bits(self) -> bool {
+        matches!(self, Table::Coils | Table::DiscreteInputs)
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.key().replace('_', " "))
+    }
+}
+
+/// What a run of Modbus addresses is mapped onto.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// @count bits or registers starting at byte @offset, bit @bit for bits.
+    Range { offset: u16, bit: u8, count: u16 },
+    /// A variable, looked up in the driver when the server is bound.
+    Variable(String),
+}
+
+/// One mapping of a table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapEntry {
+    pub table: Table,
+    /// The first Modbus address, 0 based as on the wire.
+    pub address: u16,
+    pub target: Target,
+}
+
+/// The Modbus mapping, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Map {
+    pub entries: Vec<MapEntry>,
+}
+
+impl Map {
+    /// Loads a mapping file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Map> {
+        let text = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("can not read {}: {}", path.as_ref().display(), e),
+            )
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parses the text of a mapping file.
+    pub fn parse(text: &str) -> io::Result<Map> {
+        let json: Json = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        let mut entries = Vec::new();
+        for &table in &Table::ALL {
+            let list = match json.get(table.key()) {
+                Some(list) => list
+                    .as_array()
+                    .ok_or_else(|| invalid(format!("{} is not a list", table.key())))?,
+                None => continue,
+            };
+            for entry in list {
+                entries.push(parse_entry(table, entry)?);
+            }
+        }
+        Ok(Map { entries })
+    }
+
+    /// Resolves the variables in @driver and checks that the entries of a table do not
+    /// overlap.
+    fn resolve<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<Vec<Block>> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for entry in &self.entries {
+            let (count, source) = match &entry.target {
+                Target::Range { offset, bit, count } => (
+                    *count,
+                    if entry.table.is_bits() {
+                        Source::Bits(u32::from(*offset) * 8 + u32::from(*bit))
+                    } else {
+                        Source::Words(*offset)
+                    },
+                ),
+                Target::Variable(name) => {
+                    variable_source(entry.table, &Variable::resolve(driver, name)?)?
+                }
+            };
+            let block = Block {
+                table: entry.table,
+                start: entry.address,
+                count,
+                source,
+            };
+            let end = u32::from(block.start) + u32::from(count);
+            if count == 0 || end > 0x10000 {
+                return Err(invalid(format!(
+                    "{} {} to {} are out of range",
+                    entry.table, entry.address, end
+                )));
+            }
+            if let Some(other) = blocks
+                .iter()
+                .find(|b| b.table == block.table && block.overlaps(b))
+            {
+                return Err(invalid(format!(
+                    "{} at address {} overlap the ones at {}",
+                    entry.table, entry.address, other.start
+                )));
+            }
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+}
+
+fn parse_entry(table: Table, entry: &Json) -> io::Result<MapEntry> {
+    let number = |key: &str| -> io::Result<Option<u16>> {
+        match entry.get(key) {
+            None => Ok(None),
+            Some(value) => value
+                .as_u64()
+                .and_then(|n| u16::try_from(n).ok())
+                .map(Some)
+                .ok_or_else(|| invalid(format!("{} of a {} entry is invalid", key, table))),
+        }
+    };
+    let address =
+        number("address")?.ok_or_else(|| invalid(format!("a {} entry has no address", table)))?;
+    let target = match (entry.get("variable"), number("offset")?) {
+        (Some(name), None) => Target::Variable(
+            name.as_str()
+                .ok_or_else(|| invalid(format!("variable of {} {} is invalid", table, address)))?
+                .to_owned(),
+        ),
+        (None, Some(offset)) => {
+            let bit = number("bit")?.unwrap_or(0);
+            if bit > 7 || (bit > 0 && !table.is_bits()) {
+                return Err(invalid(format!("bit of {} {} is invalid", table, address)));
+            }
+            Target::Range {
+                offset,
+                bit: bit as u8,
+                count: number("count")?.unwrap_or(1),
+            }
+        }
+        _ => {
+            return Err(invalid(format!(
+                "{} {} needs either a variable or an offset",
+                table, address
+            )))
+        }
+    };
+    Ok(MapEntry {
+        table,
+        address,
+        target,
+    })
+}
+
+fn variable_source(table: Table, variable: &Variable) -> io::Result<(u16, Source)> {
+    let unfit = || {
+        invalid(format!(
+            "variable {} of {} bits can not be mapped to {}",
+            variable.name, variable.length, table
+        ))
+    };
+    match (table.is_bits(), variable.length) {
+        (true, 1) => Ok((
+            1,
+            Source::Bits(u32::from(variable.address) * 8 + u32::from(variable.bit)),
+        )),
+        (false, 8) => Ok((1, Source::Byte(variable.address))),
+        (false, 16) => Ok((1, Source::Words(variable.address))),
+        (false, 32) => Ok((2, Source::Words(variable.address))),
+        _ => Err(unfit()),
+    }
+}
+
+/// Where the values of a block are in the process image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// Consecutive bits starting at this bit number, i.e. byte * 8 + bit.
+    Bits(u32),
+    /// Consecutive little endian words starting at this byte.
+    Words(u16),
+    /// A single byte, read as a register 0-255.
+    Byte(u16),
+}
+
+/// A resolved run of Modbus addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    table: Table,
+    start: u16,
+    count: u16,
+    source: Source,
+}
+
+impl Block {
+    fn end(&self) -> u32 {
+        u32::from(self.start) + u32::from(self.count)
+    }
+
+    fn overlaps(&self, other: &Block) -> bool {
+        u32::from(self.start) < other.end() && u32::from(other.start) < self.end()
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A Modbus exception code, the reason a request failed.
+struct Exception(u8);
+
+impl From<io::Error> for Exception {
+    fn from(_: io::Error) -> Self {
+        Exception(SERVER_DEVICE_FAILURE)
+    }
+}
+
+/// The mapped Modbus addresses and how to reach them in the driver.
+struct Server<'a, D: ?Sized> {
+    driver: &'a D,
+    blocks: &'a [Block],
+}
+
+impl<'a, D: Driver + ?Sized> Server<'a, D> {
+    /// The block of each address from @start on, failing unless all @count are mapped.
+    fn locate(&self, table: Table, start: u16, count: u16) -> Result<Vec<(Block, u16)>, Exception> {
+        (u32::from(start)..u32::from(start) + u32::from(count))
+            .map(|address| {
+                self.blocks
+                    .iter()
+                    .find(|b| {
+                        b.table == table && u32::from(b.start) <= address && address < b.end()
+                    })
+                    .map(|b| (*b, (address - u32::from(b.start)) as u16))
+                    .ok_or(Exception(ILLEGAL_DATA_ADDRESS))
+            })
+            .collect()
+    }
+
+    fn read_bits(&self, table: Table, start: u16, count: u16) -> Result<Vec<u8>, Exception> {
+        if count == 0 || count > MAX_READ_BITS {
+            return Err(Exception(ILLEGAL_DATA_VALUE));
+        }
+        let locations = self.locate(table, start, count)?;
+        let image = self.driver.read(0, PROCESS_IMAGE_SIZE)?;
+        let mut bits = vec![0; usize::from(count).div_ceil(8)];
+        for (i, (block, index)) in locations.iter().enumerate() {
+            if let Source::Bits(first) = block.source {
+                let bit = first + u32::from(*index);
+                let byte = *image
+                    .get((bit / 8) as usize)
+                    .ok_or(Exception(ILLEGAL_DATA_ADDRESS))?;
+                if byte >> (bit % 8) & 1 != 0 {
+                    bits[i / 8] |= 1 << (i % 8);
+                }
+            }
+        }
+        Ok(bits)
+    }
+
+    fn read_registers(&self, table: Table, start: u16, count: u16) -> Result<Vec<u16>, Exception> {
+        if count == 0 || count > MAX_READ_REGISTERS {
+            return Err(Exception(ILLEGAL_DATA_VALUE));
+        }
+        let locations = self.locate(table, start, count)?;
+        let image = self.driver.read(0, PROCESS_IMAGE_SIZE)?;
+        locations
+            .iter()
+            .map(|(block, index)| {
+                let (offset, length) = match block.source {
+                    Source::Words(offset) => (usize::from(offset) + 2 * usize::from(*index), 2),
+                    Source::Byte(offset) => (usize::from(offset), 1),
+                    Source::Bits(_) => unreachable!("register blocks have no bit source"),
+                };
+                let data = image
+                    .get(offset..offset + length)
+                    .ok_or(Exception(ILLEGAL_DATA_ADDRESS))?;
+                Ok(if length == 2 {
+                    LittleEndian::read_u16(data)
+                } else {
+                    u16::from(data[0])
+                })
+            })
+            .collect()
+    }
+
+    fn write_bits(&self, start: u16, values: &[bool]) -> Result<(), Exception> {
+        let locations = self.locate(Table::Coils, start, values.len() as u16)?;
+        for ((block, index), &on) in locations.iter().zip(values) {
+            if let Source::Bits(first) = block.source {
+                let bit = first + u32::from(*index);
+                let mut value = SPIValue {
+                    i16uAddress: (bit / 8) as u16,
+                    i8uBit: (bit % 8) as u8,
+                    i8uValue: on as u8,
+                };
+                self.driver.set_bit_value(&mut value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_registers(&self, start: u16, values: &[u16]) -> Result<(), Exception> {
+        let locations = self.locate(Table::HoldingRegisters, start, values.len() as u16)?;
+        for ((block, _), &value) in locations.iter().zip(values) {
+            if let Source::Byte(_) = block.source {
+                if value > 0xff {
+                    return Err(Exception(ILLEGAL_DATA_VALUE));
+                }
+            }
+        }
+        for ((block, index), &value) in locations.iter().zip(values) {
+            match block.source {
+                Source::Words(offset) => {
+                    let offset = u64::from(offset) + 2 * u64::from(*index);
+                    self.driver.write(offset, &value.to_le_bytes())?;
+                }
+                Source::Byte(offset) => self.driver.write(offset.into(), &[value as u8])?,
+                Source::Bits(_) => unreachable!("register blocks have no bit source"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers one request PDU.
+    fn handle(&self, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu.first().copied().unwrap_or(0);
+        let mut response = vec![function];
+        match self.respond(pdu, &mut response) {
+            Ok(()) => response,
+            Err(Exception(code)) => vec![function | 0x80, code],
+        }
+    }
+
+    fn respond(&self, pdu: &[u8], out: &mut Vec<u8>) -> Result<(), Exception> {
+        let mut input = Cursor::new(pdu);
+        let malformed = |_| Exception(ILLEGAL_DATA_VALUE);
+        let function = input.read_u8().map_err(malformed)?;
+        if !matches!(
+            function,
+            READ_COILS
+                | READ_DISCRETE_INPUTS
+                | READ_HOLDING_REGISTERS
+                | READ_INPUT_REGISTERS
+                | WRITE_SINGLE_COIL
+                | WRITE_SINGLE_REGISTER
+                | WRITE_MULTIPLE_COILS
+                | WRITE_MULTIPLE_REGISTERS
+        ) {
+            return Err(Exception(ILLEGAL_FUNCTION));
+        }
+        let start = input.read_u16::<BigEndian>().map_err(malformed)?;
+        let count = input.read_u16::<BigEndian>().map_err(malformed)?;
+        match function {
+            READ_COILS | READ_DISCRETE_INPUTS => {
+                let table = if function == READ_COILS {
+                    Table::Coils
+                } else {
+                    Table::DiscreteInputs
+                };
+                let bits = self.read_bits(table, start, count)?;
+                out.push(bits.len() as u8);
+                out.extend(bits);
+            }
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                let table = if function == READ_HOLDING_REGISTERS {
+                    Table::HoldingRegisters
+                } else {
+                    Table::InputRegisters
+                };
+                let registers = self.read_registers(table, start, count)?;
+                out.push(2 * registers.len() as u8);
+                for register in registers {
+                    out.write_u16::<BigEndian>(register).unwrap();
+                }
+            }
+            WRITE_SINGLE_COIL => {
+                let on = match count {
+                    0xff00 => true,
+                    0x0000 => false,
+                    _ => return Err(Exception(ILLEGAL_DATA_VALUE)),
+                };
+                self.write_bits(start, &[on])?;
+                out.extend_from_slice(&pdu[1..5]);
+            }
+            WRITE_SINGLE_REGISTER => {
+                self.write_registers(start, &[count])?;
+                out.extend_from_slice(&pdu[1..5]);
+            }
+            WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS => {
+                let length = input.read_u8().map_err(malformed)?;
+                let mut data = vec![0; usize::from(length)];
+                input.read_exact(&mut data).map_err(malformed)?;
+                if function == WRITE_MULTIPLE_COILS {
+                    if count == 0
+                        || count > MAX_WRITE_BITS
+                        || data.len() != usize::from(count).div_ceil(8)
+                    {
+                        return Err(Exception(ILLEGAL_DATA_VALUE));
+                    }
+                    let values: Vec<bool> = (0..usize::from(count))
+                        .map(|i| data[i / 8] >> (i % 8) & 1 != 0)
+                        .collect();
+                    self.write_bits(start, &values)?;
+                } else {
+                    if count == 0
+                        || count > MAX_WRITE_REGISTERS
+                        || data.len() != 2 * usize::from(count)
+                    {
+                        return Err(Exception(ILLEGAL_DATA_VALUE));
+                    }
+                    let values: Vec<u16> = data.chunks(2).map(BigEndian::read_u16).collect();
+                    self.write_registers(start, &values)?;
+                }
+                out.extend_from_slice(&pdu[1..5]);
+            }
+            _ => unreachable!("unsupported functions are refused above"),
+        }
+        Ok(())
+    }
+}
+
+/// ModbusServer offers the process image of a [`Driver`] over Modbus TCP, as set up by a
+/// [`Map`].
+///
+/// Each connection is served by its own thread and closed after a minute without a request; the
+/// unit identifier of requests is ignored.
+pub struct ModbusServer<D> {
+    listener: TcpListener,
+    driver: Arc<D>,
+    blocks: Arc<Vec<Block>>,
+}
+
+impl<D: Driver + 'static> ModbusServer<D> {
+    /// Binds to @addr, usually port 502, resolving the variables of @map in @driver.
+    pub fn bind<A: ToSocketAddrs>(addr: A, driver: Arc<D>, map: &Map) -> io::Result<Self> {
+        let blocks = map.resolve(&*driver)?;
+        Ok(ModbusServer {
+            listener: TcpListener::bind(addr)?,
+            driver,
+            blocks: Arc::new(blocks),
+        })
+    }
+
+    /// The address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts and serves connections until accepting fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let driver = Arc::clone(&self.driver);
+            let blocks = Arc::clone(&self.blocks);
+            thread::spawn(move || {
+                let server = Server {
+                    driver: &*driver,
+                    blocks: &blocks,
+                };
+                serve(&server, stream)
+            });
+        }
+        Ok(())
+    }
+}
+
+// Serves one connection until the client closes it or stays silent for IDLE_TIMEOUT.
+fn serve<D: Driver + ?Sized>(server: &Server<D>, mut stream: TcpStream) {
+    if stream.set_read_timeout(Some(IDLE_TIMEOUT)).is_err() {
+        return;
+    }
+    while let Ok((transaction, unit, pdu)) = read_adu(&mut stream) {
+        let response = server.handle(&pdu);
+        if write_adu(&mut stream, transaction, unit, &response).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads a Modbus TCP frame: transaction id, unit id and PDU.
+pub(crate) fn read_adu<R: Read>(stream: &mut R) -> io::Result<(u16, u8, Vec<u8>)> {
+    let transaction = stream.read_u16::<BigEndian>()?;
+    let protocol = stream.read_u16::<BigEndian>()?;
+    let length = stream.read_u16::<BigEndian>()?;
+    if protocol != 0 || !(2..=254).contains(&length) {
+        return Err(invalid(format!(
+            "invalid Modbus TCP header, protocol {} length {}",
+            protocol, length
+        )));
+    }
+    let unit = stream.read_u8()?;
+    let mut pdu = vec![0; usize::from(length) - 1];
+    stream.read_exact(&mut pdu)?;
+    Ok((transaction, unit, pdu))
+}
+
+/// Writes a Modbus TCP frame.
+pub(crate) fn write_adu<W: Write>(
+    stream: &mut W,
+    transaction: u16,
+    unit: u8,
+    pdu: &[u8],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(7 + pdu.len());
+    frame.write_u16::<BigEndian>(transaction)?;
+    frame.write_u16::<BigEndian>(0)?;
+    frame.write_u16::<BigEndian>(pdu.len() as u16 + 1)?;
+    frame.push(unit);
+    frame.extend_from_slice(pdu);
+    stream.write_all(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    const MAP: &str = r#"{
+        "coils": [{ "address": 0, "offset": 77, "count": 14 }],
+        "discrete_inputs": [{ "address": 0, "variable": "I_1" }],
+        "holding_registers": [
+            { "address": 0, "offset": 79, "count": 2 },
+            { "address": 2, "variable": "Counter_1" }
+        ],
+        "input_registers": [{ "address": 10, "variable": "RevPiLED" }]
+    }"#;
+
+    fn request(stream: &mut TcpStream, pdu: &[u8]) -> Vec<u8> {
+        write_adu(stream, 7, 1, pdu).unwrap();
+        let (transaction, unit, response) = read_adu(stream).unwrap();
+        assert_eq!((transaction, unit), (7, 1));
+        response
+    }
+
+    #[test]
+    fn modbus_over_loopback() {
+        let driver = Arc::new(MemoryDriver::new());
+        driver.add_variable("I_1", 7, 0, 1);
+        driver.add_variable("Counter_1", 13, 0, 32);
+        driver.add_variable("RevPiLED", 6, 0, 8);
+        driver.write(6, &[5, 1]).unwrap();
+        driver.write(13, &0x0001_0002u32.to_le_bytes()).unwrap();
+        let map = Map::parse(MAP).unwrap();
+        let server = ModbusServer::bind("127.0.0.1:0", Arc::clone(&driver), &map).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        let mut stream = TcpStream::connect(addr).unwrap();
+
+        assert_eq!(request(&mut stream, &[2, 0, 0, 0, 1]), vec![2, 1, 1]);
+        assert_eq!(
+            request(&mut stream, &[5, 0, 9, 0xff, 0]),
+            vec![5, 0, 9, 0xff, 0]
+        );
+        assert_eq!(driver.read(78, 1).unwrap(), vec![0b10]);
+        assert_eq!(request(&mut stream, &[1, 0, 8, 0, 3]), vec![1, 1, 0b010]);
+        assert_eq!(
+            request(&mut stream, &[16, 0, 0, 0, 2, 4, 0x12, 0x34, 0, 9]),
+            vec![16, 0, 0, 0, 2]
+        );
+        assert_eq!(driver.read(79, 4).unwrap(), vec![0x34, 0x12, 9, 0]);
+        assert_eq!(
+            request(&mut stream, &[3, 0, 2, 0, 2]),
+            vec![3, 4, 0, 2, 0, 1]
+        );
+        assert_eq!(request(&mut stream, &[4, 0, 10, 0, 1]), vec![4, 2, 0, 5]);
+        // unmapped, read only and unknown
+        assert_eq!(request(&mut stream, &[4, 0, 11, 0, 1]), vec![0x84, 2]);
+        assert_eq!(request(&mut stream, &[5, 0, 20, 0xff, 0]), vec![0x85, 2]);
+        assert_eq!(request(&mut stream, &[43, 0, 0, 0, 0]), vec![43 | 0x80, 1]);
+        assert_eq!(request(&mut stream, &[43, 14]), vec![43 | 0x80, 1]);
+
+        let overlapping = r#"{"coils": [{ "address": 0, "offset": 77, "count": 14 },
+                                        { "address": 13, "variable": "I_1" }]}"#;
+        let map = Map::parse(overlapping).unwrap();
+        assert!(ModbusServer::bind("127.0.0.1:0", driver, &map).is_err());
+    }
+}
@@ -0,0 +1,822 @@
+//! A Modbus master polling remote devices into the process image.
+//!
+//! [`Client`] talks to one remote device over Modbus TCP or RTU. A [`Poller`] is set up from
+//! a JSON file and keeps a [`Session`] per device: on every interval it reads the configured
+//! remote data into the process image, usually the memory area of a virtual device, pushes
+//! process image data to remote coils or registers and records the [`Health`] of the
+//! communication in a status byte of the image:
+//!
+//! ```json
+//! {
+//!     "devices": [{
+//!         "name": "meter",
+//!         "tcp": "192.168.1.20:502",
+//!         "unit": 1,
+//!         "interval_ms": 1000,
+//!         "timeout_ms": 500,
+//!         "status": 200,
+//!         "read": [{ "table": "input_registers", "address": 0, "count": 4, "offset": 202 }],
+//!         "write": [{ "table": "coils", "address": 16, "count": 8, "offset": 77 }]
+//!     }, {
+//!         "name": "drive",
+//!         "rtu": { "path": "/dev/ttyUSB0", "baud": 19200 },
+//!         "unit": 3,
+//!         "read": [{ "table": "holding_registers", "address": 100, "count": 1, "offset": 210 }]
+//!     }]
+//! }
+//! ```
+//!
+//! Registers are stored as little endian words from `offset` on, bits from bit `bit`, 0 if
+//! not given, of the byte at `offset`. The interval defaults to 1 s, the timeout to 1 s and the
+//! unit to 1; without `status` no status byte is written. A transfer is one request, so its
+//! `count`, 1 if not given, is limited to what one Modbus request may carry, e.g. 125
+//! registers to read and 123 to write.
+
+use crate::driver::Driver;
+use crate::modbus::{self, Table};
+use crate::picontrol::SPIValue;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use nix::sys::termios::{self, BaudRate, SetArg, SpecialCharacterIndices};
+use serde_json::Value as Json;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A serial port, or anything else that carries Modbus RTU frames.
+pub trait Port: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Port for T {}
+
+enum Link {
+    Tcp(TcpStream),
+    Rtu(Box<dyn Port>),
+}
+
+/// The remote device answered with a Modbus exception.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusException {
+    pub function: u8,
+    /// The exception code, e.g. 2 for an illegal data address.
+    pub code: u8,
+}
+
+impl fmt::Display for ModbusException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "function {} failed with Modbus exception {}",
+            self.function, self.code
+        )
+    }
+}
+
+impl error::Error for ModbusException {}
+
+/// Client is a Modbus master for one remote device.
+pub struct Client {
+    link: Link,
+    unit: u8,
+    transaction: u16,
+}
+
+impl Client {
+    /// Connects to a Modbus TCP server; @timeout bounds the connection and every request.
+    pub fn connect_tcp<A: ToSocketAddrs>(
+        addr: A,
+        unit: u8,
+        timeout: Duration,
+    ) -> io::Result<Client> {
+        let mut last = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    stream.set_nodelay(true)?;
+                    return Ok(Client {
+                        link: Link::Tcp(stream),
+                        unit,
+                        transaction: 0,
+                    });
+                }
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    /// Opens a serial port for Modbus RTU at @baud, 8 data bits, no parity and one stop bit.
+    ///
+    /// The timeout is rounded up to tenths of a second, at most 25.5 s.
+    pub fn open_rtu<P: AsRef<Path>>(
+        path: P,
+        baud: u32,
+        unit: u8,
+        timeout: Duration,
+    ) -> io::Result<Client> {
+        let port = OpenOptions::new().read(true).write(true).open(&path)?;
+        let fd = port.as_raw_fd();
+        let mut settings = termios::tcgetattr(fd).map_err(crate::driver::nix_to_io)?;
+        termios::cfmakeraw(&mut settings);
+        termios::cfsetspeed(&mut settings, baud_rate(baud)?).map_err(crate::driver::nix_to_io)?;
+        let tenths = timeout.as_millis().div_ceil(100).clamp(1, 255) as u8;
+        settings.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+        settings.control_chars[SpecialCharacterIndices::VTIME as usize] = tenths;
+        termios::tcsetattr(fd, SetArg::TCSANOW, &settings).map_err(crate::driver::nix_to_io)?;
+        Ok(Self::rtu(port, unit))
+    }
+
+    /// Speaks Modbus RTU over an already opened @port.
+    ///
+    /// A read that returns no data is taken as the end of the timeout.
+    pub fn rtu<P: Port + 'static>(port: P, unit: u8) -> Client {
+        Client {
+            link: Link::Rtu(Box::new(port)),
+            unit,
+            transaction: 0,
+        }
+    }
+
+    /// Reads @count coils or discrete inputs.
+    pub fn read_bits(&mut self, table: Table, address: u16, count: u16) -> io::Result<Vec<bool>> {
+        let function = match table {
+            Table::Coils => modbus::READ_COILS,
+            Table::DiscreteInputs => modbus::READ_DISCRETE_INPUTS,
+            _ => return Err(wrong_table(table)),
+        };
+        if count == 0 || count > modbus::MAX_READ_BITS {
+            return Err(invalid_input(format!(
+                "can not read {} bits at once",
+                count
+            )));
+        }
+        let response = self.request(&read_pdu(function, address, count))?;
+        let data = payload(&response, usize::from(count).div_ceil(8))?;
+        Ok((0..usize::from(count))
+            .map(|i| data[i / 8] >> (i % 8) & 1 != 0)
+            .collect())
+    }
+
+    /// Reads @count holding or input registers.
+    pub fn read_registers(
+        &mut self,
+        table: Table,
+        address: u16,
+        count: u16,
+    ) -> io::Result<Vec<u16>> {
+        let function = match table {
+            Table::HoldingRegisters => modbus::READ_HOLDING_REGISTERS,
+            Table::InputRegisters => modbus::READ_INPUT_REGISTERS,
+            _ => return Err(wrong_table(table)),
+        };
+        if count == 0 || count > modbus::MAX_READ_REGISTERS {
+            return Err(invalid_input(format!(
+                "can not read {} registers at once",
+                count
+            )));
+        }
+        let response = self.request(&read_pdu(function, address, count))?;
+        let data = payload(&response, 2 * usize::from(count))?;
+        Ok(data.chunks(2).map(BigEndian::read_u16).collect())
+    }
+
+    /// Writes coils from @address on.
+    pub fn write_coils(&mut self, address: u16, values: &[bool]) -> io::Result<()> {
+        if values.is_empty() || values.len() > usize::from(modbus::MAX_WRITE_BITS) {
+            return Err(invalid_input(format!(
+                "can not write {} coils at once",
+                values.len()
+            )));
+        }
+        let mut data = vec![0; values.len().div_ceil(8)];
+        for (i, _) in values.iter().enumerate().filter(|(_, &on)| on) {
+            data[i / 8] |= 1 << (i % 8);
+        }
+        let mut pdu = read_pdu(modbus::WRITE_MULTIPLE_COILS, address, values.len() as u16);
+        pdu.push(data.len() as u8);
+        pdu.extend(data);
+        self.request(&pdu).map(drop)
+    }
+
+    /// Writes holding registers from @address on.
+    pub fn write_registers(&mut self, address: u16, values: &[u16]) -> io::Result<()> {
+        if values.is_empty() || values.len() > usize::from(modbus::MAX_WRITE_REGISTERS) {
+            return Err(invalid_input(format!(
+                "can not write {} registers at once",
+                values.len()
+            )));
+        }
+        let mut pdu = read_pdu(
+            modbus::WRITE_MULTIPLE_REGISTERS,
+            address,
+            values.len() as u16,
+        );
+        pdu.push(2 * values.len() as u8);
+        for &value in values {
+            pdu.write_u16::<BigEndian>(value)?;
+        }
+        self.request(&pdu).map(drop)
+    }
+
+    /// Sends one request PDU and returns the response PDU, checked for exceptions.
+    fn request(&mut self, pdu: &[u8]) -> io::Result<Vec<u8>> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let response = match &mut self.link {
+            Link::Tcp(stream) => {
+                modbus::write_adu(stream, self.transaction, self.unit, pdu)?;
+                let (transaction, unit, response) = modbus::read_adu(stream)?;
+                if transaction != self.transaction || unit != self.unit {
+                    return Err(invalid_data(format!(
+                        "response to transaction {} of unit {}, expected {} of {}",
+                        transaction, unit, self.transaction, self.unit
+                    )));
+                }
+                response
+            }
+            Link::Rtu(port) => {
+                let mut frame = vec![self.unit];
+                frame.extend_from_slice(pdu);
+                frame.write_u16::<LittleEndian>(crc16(&frame))?;
+                port.write_all(&frame)?;
+                port.flush()?;
+                read_rtu(&mut **port, self.unit).map_err(|e| match e.kind() {
+                    io::ErrorKind::UnexpectedEof => {
+                        io::Error::new(io::ErrorKind::TimedOut, "no response from the device")
+                    }
+                    _ => e,
+                })?
+            }
+        };
+        match response.first() {
+            Some(&function) if function == pdu[0] | 0x80 => {
+                Err(io::Error::other(ModbusException {
+                    function: pdu[0],
+                    code: response.get(1).copied().unwrap_or(0),
+                }))
+            }
+            Some(&function) if function == pdu[0] => Ok(response),
+            _ => Err(invalid_data(format!(
+                "unexpected response to function {}",
+                pdu[0]
+            ))),
+        }
+    }
+}
+
+fn read_pdu(function: u8, address: u16, count: u16) -> Vec<u8> {
+    let mut pdu = vec![function];
+    pdu.write_u16::<BigEndian>(address).unwrap();
+    pdu.write_u16::<BigEndian>(count).unwrap();
+    pdu
+}
+
+// The data of a read response: function, byte count, data.
+fn payload(response: &[u8], length: usize) -> io::Result<&[u8]> {
+    match response.get(2..) {
+        Some(data) if response[1] as usize == length && data.len() == length => Ok(data),
+        _ => Err(invalid_data(format!(
+            "expected {} bytes of data, got {}",
+            length,
+            response.len().saturating_sub(2)
+        ))),
+    }
+}
+
+/// Reads an RTU response frame from @unit and returns its PDU.
+fn read_rtu(port: &mut dyn Port, unit: u8) -> io::Result<Vec<u8>> {
+    let mut frame = vec![0; 2];
+    port.read_exact(&mut frame)?;
+    let rest = match frame[1] {
+        function if function & 0x80 != 0 => 1,
+        modbus::READ_COILS
+        | modbus::READ_DISCRETE_INPUTS
+        | modbus::READ_HOLDING_REGISTERS
+        | modbus::READ_INPUT_REGISTERS => {
+            let count = port.read_u8()?;
+            frame.push(count);
+            usize::from(count)
+        }
+        _ => 4,
+    };
+    let start = frame.len();
+    frame.resize(start + rest + 2, 0);
+    port.read_exact(&mut frame[start..])?;
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    if LittleEndian::read_u16(crc) != crc16(body) {
+        return Err(invalid_data("CRC error in RTU response".to_owned()));
+    }
+    if body[0] != unit {
+        return Err(invalid_data(format!(
+            "response of unit {}, expected {}",
+            body[0], unit
+        )));
+    }
+    Ok(body[1..].to_vec())
+}
+
+/// The Modbus RTU CRC of @data.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffff;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn baud_rate(baud: u32) -> io::Result<BaudRate> {
+    Ok(match baud {
+        1200 => BaudRate::B1200,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115_200 => BaudRate::B115200,
+        _ => return Err(invalid_input(format!("unsupported baud rate {}", baud))),
+    })
+}
+
+/// How the communication with a device went, as written to its status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// All transfers succeeded.
+    Ok,
+    /// The device could not be connected.
+    Unreachable,
+    /// The device did not answer in time.
+    Timeout,
+    /// The device answered with a Modbus exception.
+    Exception,
+    /// The response was malformed or the process image could not be accessed.
+    Error,
+}
+
+impl Health {
+    /// The value of the status byte, 0 for [`Health::Ok`].
+    pub fn code(self) -> u8 {
+        match self {
+            Health::Ok => 0,
+            Health::Unreachable => 1,
+            Health::Timeout => 2,
+            Health::Exception => 3,
+            Health::Error => 4,
+        }
+    }
+
+    fn of(err: &io::Error) -> Health {
+        if err.get_ref().is_some_and(|e| e.is::<ModbusException>()) {
+            return Health::Exception;
+        }
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Health::Timeout,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::NotFound => Health::Unreachable,
+            _ => Health::Error,
+        }
+    }
+}
+
+/// How a remote device is reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Connection {
+    /// Modbus TCP to host:port.
+    Tcp(String),
+    /// Modbus RTU on a serial port.
+    Rtu { path: PathBuf, baud: u32 },
+}
+
+/// A block copied between a remote table and the process image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    pub table: Table,
+    /// The first remote address.
+    pub address: u16,
+    /// Number of bits or registers.
+    pub count: u16,
+    /// Byte offset in the process image.
+    pub offset: u16,
+    /// First bit at @offset, for coils and discrete inputs.
+    pub bit: u8,
+}
+
+/// A remote device of a [`Poller`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteDevice {
+    pub name: String,
+    pub connection: Connection,
+    pub unit: u8,
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Offset of the status byte in the process image.
+    pub status: Option<u16>,
+    /// Remote data copied into the process image.
+    pub read: Vec<Transfer>,
+    /// Process image data copied to the remote device.
+    pub write: Vec<Transfer>,
+}
+
+/// The communication with one remote device.
+pub struct Session {
+    device: RemoteDevice,
+    client: Option<Client>,
+}
+
+impl Session {
+    pub fn new(device: RemoteDevice) -> Self {
+        Session {
+            device,
+            client: None,
+        }
+    }
+
+    /// The device polled.
+    pub fn device(&self) -> &RemoteDevice {
+        &self.device
+    }
+
+    /// Performs all transfers once and writes the status byte; on failure the connection
+    /// is dropped and opened again by the next poll.
+    pub fn poll<D: Driver + ?Sized>(&mut self, driver: &D) -> Health {
+        let health = match self.transfer(driver) {
+            Ok(()) => Health::Ok,
+            Err(err) => {
+                self.client = None;
+                Health::of(&err)
+            }
+        };
+        if let Some(status) = self.device.status {
+            // nothing to report it to if the image itself fails
+            let _ = driver.write(status.into(), &[health.code()]);
+        }
+        health
+    }
+
+    fn transfer<D: Driver + ?Sized>(&mut self, driver: &D) -> io::Result<()> {
+        if self.client.is_none() {
+            let device = &self.device;
+            self.client = Some(match &device.connection {
+                Connection::Tcp(addr) => {
+                    Client::connect_tcp(addr.as_str(), device.unit, device.timeout)
+                }
+                Connection::Rtu { path, baud } => {
+                    Client::open_rtu(path, *baud, device.unit, device.timeout)
+                }
+            }?);
+        }
+        let client = self.client.as_mut().unwrap();
+        for transfer in &self.device.read {
+            if transfer.table.is_bits() {
+                let bits = client.read_bits(transfer.table, transfer.address, transfer.count)?;
+                for (i, on) in bits.into_iter().enumerate() {
+                    let mut value = bit_value(transfer, i);
+                    value.i8uValue = on as u8;
+                    driver.set_bit_value(&mut value)?;
+                }
+            } else {
+                let registers =
+                    client.read_registers(transfer.table, transfer.address, transfer.count)?;
+                let data: Vec<u8> = registers.iter().flat_map(|r| r.to_le_bytes()).collect();
+                driver.write(transfer.offset.into(), &data)?;
+            }
+        }
+        for transfer in &self.device.write {
+            if transfer.table.is_bits() {
+                let mut bits = Vec::new();
+                for i in 0..usize::from(transfer.count) {
+                    let mut value = bit_value(transfer, i);
+                    driver.get_bit_value(&mut value)?;
+                    bits.push(value.i8uValue != 0);
+                }
+                client.write_coils(transfer.address, &bits)?;
+            } else {
+                let data = driver.read(transfer.offset.into(), 2 * usize::from(transfer.count))?;
+                let registers: Vec<u16> = data.chunks(2).map(LittleEndian::read_u16).collect();
+                client.write_registers(transfer.address, &registers)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn bit_value(transfer: &Transfer, index: usize) -> SPIValue {
+    let bit = usize::from(transfer.offset) * 8 + usize::from(transfer.bit) + index;
+    SPIValue {
+        i16uAddress: (bit / 8) as u16,
+        i8uBit: (bit % 8) as u8,
+        i8uValue: 0,
+    }
+}
+
+/// Poller polls a set of remote devices, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Poller {
+    pub devices: Vec<RemoteDevice>,
+}
+
+impl Poller {
+    /// Loads a poller configuration file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Poller> {
+        let text = fs::read_to_string(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("can not read {}: {}", path.as_ref().display(), e),
+            )
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parses the text of a poller configuration file.
+    pub fn parse(text: &str) -> io::Result<Poller> {
+        let json: Json = serde_json::from_str(text).map_err(|e| invalid_data(e.to_string()))?;
+        let devices = json
+            .get("devices")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid_data("no devices list".to_owned()))?;
+        Ok(Poller {
+            devices: devices
+                .iter()
+                .map(parse_device)
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    /// Polls every device on its own thread until @stop is set.
+    pub fn run<D: Driver + 'static>(self, driver: Arc<D>, stop: Arc<AtomicBool>) -> io::Result<()> {
+        let threads: Vec<_> = self
+            .devices
+            .into_iter()
+            .map(|device| {
+                let driver = Arc::clone(&driver);
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || {
+                    let interval = device.interval;
+                    let mut session = Session::new(device);
+                    let mut next = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        session.poll(&*driver);
+                        next += interval;
+                        let now = Instant::now();
+                        if next > now {
+                            thread::sleep(next - now);
+                        } else {
+                            next = now;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread
+                .join()
+                .map_err(|_| io::Error::other("a polling thread panicked"))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_device(device: &Json) -> io::Result<RemoteDevice> {
+    let name = device
+        .get("name")
+        .and_then(Json::as_str)
+        .unwrap_or("")
+        .to_owned();
+    let invalid = |what: &str| invalid_data(format!("{} of device {} is invalid", what, name));
+    let number = |json: &Json, key: &str| -> io::Result<Option<u64>> {
+        match json.get(key) {
+            None => Ok(None),
+            Some(value) => value.as_u64().map(Some).ok_or_else(|| invalid(key)),
+        }
+    };
+    let connection = match (device.get("tcp"), device.get("rtu")) {
+        (Some(addr), None) => {
+            Connection::Tcp(addr.as_str().ok_or_else(|| invalid("tcp"))?.to_owned())
+        }
+        (None, Some(rtu)) => Connection::Rtu {
+            path: rtu
+                .get("path")
+                .and_then(Json::as_str)
+                .ok_or_else(|| invalid("rtu path"))?
+                .into(),
+            baud: number(rtu, "baud")?
+                .map(|b| u32::try_from(b).map_err(|_| invalid("baud")))
+                .transpose()?
+                .unwrap_or(9600),
+        },
+        _ => {
+            return Err(invalid_data(format!(
+                "device {} needs either tcp or rtu",
+                name
+            )))
+        }
+    };
+    let u16_field = |json: &Json, key: &str| -> io::Result<Option<u16>> {
+        number(json, key)?
+            .map(|n| u16::try_from(n).map_err(|_| invalid(key)))
+            .transpose()
+    };
+    let transfers = |key: &str, writable: bool| -> io::Result<Vec<Transfer>> {
+        let list = match device.get(key) {
+            Some(list) => list.as_array().ok_or_else(|| invalid(key))?,
+            None => return Ok(Vec::new()),
+        };
+        list.iter()
+            .map(|t| {
+                let table = match t.get("table").and_then(Json::as_str) {
+                    Some("coils") => Table::Coils,
+                    Some("discrete_inputs") if !writable => Table::DiscreteInputs,
+                    Some("holding_registers") => Table::HoldingRegisters,
+                    Some("input_registers") if !writable => Table::InputRegisters,
+                    _ => return Err(invalid(&format!("table of a {} entry", key))),
+                };
+                let bit = number(t, "bit")?.unwrap_or(0);
+                if bit > 7 {
+                    return Err(invalid("bit"));
+                }
+                // one request has to carry the whole transfer
+                let max = match (table, writable) {
+                    (Table::Coils, true) => modbus::MAX_WRITE_BITS,
+                    (Table::HoldingRegisters, true) => modbus::MAX_WRITE_REGISTERS,
+                    (Table::Coils, false) | (Table::DiscreteInputs, _) => modbus::MAX_READ_BITS,
+                    _ => modbus::MAX_READ_REGISTERS,
+                };
+                let count = u16_field(t, "count")?.unwrap_or(1);
+                if count == 0 || count > max {
+                    return Err(invalid("count"));
+                }
+                Ok(Transfer {
+                    table,
+                    address: u16_field(t, "address")?.ok_or_else(|| invalid("address"))?,
+                    count,
+                    offset: u16_field(t, "offset")?.ok_or_else(|| invalid("offset"))?,
+                    bit: bit as u8,
+                })
+            })
+            .collect()
+    };
+    let millis = |key: &str| -> io::Result<Duration> {
+        Ok(Duration::from_millis(number(device, key)?.unwrap_or(1000)))
+    };
+    Ok(RemoteDevice {
+        connection,
+        unit: number(device, "unit")?
+            .map(|u| u8::try_from(u).map_err(|_| invalid("unit")))
+            .transpose()?
+            .unwrap_or(1),
+        interval: millis("interval_ms")?,
+        timeout: millis("timeout_ms")?,
+        status: u16_field(device, "status")?,
+        read: transfers("read", false)?,
+        write: transfers("write", true)?,
+        name,
+    })
+}
+
+fn wrong_table(table: Table) -> io::Error {
+    invalid_input(format!("{} can not be accessed this way", table))
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::{Map, ModbusServer};
+    use crate::MemoryDriver;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+
+    #[test]
+    fn poll_remote_device() {
+        let remote = Arc::new(MemoryDriver::new());
+        remote.write(0, &[0x34, 0x12, 7, 0]).unwrap();
+        let map = r#"{
+            "coils": [{ "address": 0, "offset": 10, "count": 4 }],
+            "input_registers": [{ "address": 0, "offset": 0, "count": 2 }]
+        }"#;
+        let server = ModbusServer::bind(
+            "127.0.0.1:0",
+            Arc::clone(&remote),
+            &Map::parse(map).unwrap(),
+        )
+        .unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let config = format!(
+            r#"{{"devices": [{{
+                "name": "remote", "tcp": "{}", "timeout_ms": 200, "status": 100,
+                "read": [{{ "table": "input_registers", "address": 0, "count": 2, "offset": 101 }}],
+                "write": [{{ "table": "coils", "address": 0, "count": 4, "offset": 77, "bit": 2 }}]
+            }}]}}"#,
+            addr
+        );
+        let poller = Poller::parse(&config).unwrap();
+        let local = MemoryDriver::new();
+        local.write(77, &[0b1000_0100]).unwrap();
+        let mut session = Session::new(poller.devices[0].clone());
+        assert_eq!(session.poll(&local), Health::Ok);
+        assert_eq!(local.read(100, 5).unwrap(), vec![0, 0x34, 0x12, 7, 0]);
+        assert_eq!(remote.read(10, 1).unwrap(), vec![0b0001]);
+
+        let mut device = poller.devices[0].clone();
+        device.read[0].address = 5;
+        assert_eq!(Session::new(device).poll(&local), Health::Exception);
+        assert_eq!(local.read(100, 1).unwrap(), vec![3]);
+
+        // a device that accepts the connection, but never answers
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut device = poller.devices[0].clone();
+        device.connection = Connection::Tcp(silent.local_addr().unwrap().to_string());
+        assert_eq!(Session::new(device).poll(&local), Health::Timeout);
+        drop(silent);
+
+        let too_many = r#"{"devices": [{ "name": "remote", "tcp": "localhost:502",
+            "read": [{ "table": "input_registers", "address": 0, "count": 126, "offset": 0 }]
+        }]}"#;
+        let err = Poller::parse(too_many).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("count of device remote"));
+        assert!(Poller::parse(&too_many.replace("126", "0")).is_err());
+        assert!(Poller::parse(&too_many.replace("126", "125")).is_ok());
+
+        let baud = r#"{"devices": [{ "name": "serial",
+            "rtu": { "path": "/dev/ttyUSB0", "baud": 4294967296 } }]}"#;
+        let err = Poller::parse(baud).unwrap_err();
+        assert!(err.to_string().contains("baud of device serial"));
+    }
+
+    struct Script {
+        response: Cursor<Vec<u8>>,
+        request: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.response.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.request.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rtu_frames() {
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0a]), 0xcdc5);
+        let mut response = vec![0x11, 0x03, 0x04, 0x00, 0x2a, 0x01, 0x00];
+        let crc = crc16(&response);
+        response.write_u16::<LittleEndian>(crc).unwrap();
+        let mut client = Client::rtu(
+            Script {
+                response: Cursor::new(response),
+                request: Vec::new(),
+            },
+            0x11,
+        );
+        assert_eq!(
+            client
+                .read_registers(Table::HoldingRegisters, 0x6b, 2)
+                .unwrap(),
+            vec![42, 256]
+        );
+        // the script is exhausted, as if the device stopped answering
+        let err = client
+            .read_registers(Table::HoldingRegisters, 0x6b, 2)
+            .unwrap_err();
+        assert_eq!(Health::of(&err), Health::Timeout);
+    }
+}
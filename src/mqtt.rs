@@ -0,0 +1,353 @@
+//! A bridge between the process image and an MQTT broker.
+//!
+//! A [`Bridge`] polls the process image and publishes every variable whose value changed
+//! since the previous cycle to `<prefix>/<variable>`, with a JSON payload such as
+//! `{"value": 1, "time": 1589288403012}`, the time in milliseconds since the Unix epoch. On the
+//! first cycle, and after every reconnection to the broker, all variables are published, so
+//! changes missed while the connection was down are not lost.
+//!
+//! Outputs are written by publishing to `<prefix>/set/<variable>`, with the value as payload,
+//! e.g. `true`, `42` or `{"value": 42}`. Only the outputs the bridge was created with are
+//! writable; other commands, and commands with a value the variable can not hold, are dropped.
+//!
+//! ```ignore
+//! let config = Config::load_default()?;
+//! let bridge = Bridge::new(&config, "revpi", &["O_1", "PWM_1"])?;
+//! let options = MqttOptions::new("revpi", "scada.local", 1883);
+//! bridge.run(&RevPiControl::new_at(PICONTROL_DEVICE)?, options, &stop)?;
+//! ```
+
+use crate::config::{Config, Direction};
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::export::{json_value, value_from_json};
+use crate::value::Variable;
+use rumqttc::{valid_topic, Client, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+use serde_json::{json, Value as Json};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Time to wait before the connection to the broker is tried again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A message for the broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Bridge publishes variable changes and writes outputs on command, see the module
+/// documentation.
+#[derive(Debug)]
+pub struct Bridge {
+    prefix: String,
+    variables: Vec<Variable>,
+    writable: Vec<Variable>,
+    interval: Duration,
+    /// The process image of the previous cycle.
+    previous: Mutex<Option<Vec<u8>>>,
+}
+
+impl Bridge {
+    /// Creates a bridge for the variables of @config, publishing below @prefix and accepting
+    /// writes of the outputs named in @writable.
+    ///
+    /// Fails if @prefix or a variable name contains `+` or `#`, which MQTT does not allow in
+    /// topics.
+    pub fn new(config: &Config, prefix: &str, writable: &[&str]) -> io::Result<Bridge> {
+        if let Some(topic) = std::iter::once(prefix)
+            .chain(config.entries().map(|(_, entry)| entry.name.as_str()))
+            .find(|topic| !valid_topic(topic))
+        {
+            return Err(invalid_input(format!(
+                "{} can not be part of a topic",
+                topic
+            )));
+        }
+        let writable = writable
+            .iter()
+            .map(|name| match config.find(name) {
+                Some((_, entry)) if entry.direction == Direction::Output => entry.variable(),
+                Some(_) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not an output", name),
+                )),
+                None => config.variable(name),
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Bridge {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            variables: config
+                .entries()
+                .map(|(_, entry)| entry.variable())
+                .collect::<io::Result<_>>()?,
+            writable,
+            interval: Duration::from_millis(100),
+            previous: Mutex::new(None),
+        })
+    }
+
+    /// Polls the process image every @interval, 100 ms by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The topic filter of the commands.
+    pub fn command_filter(&self) -> String {
+        format!("{}/set/#", self.prefix)
+    }
+
+    /// Reads the process image and returns a message for every variable that changed since
+    /// the previous call.
+    pub fn changes<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<Vec<Message>> {
+        let image = driver.read(0, PROCESS_IMAGE_SIZE)?;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut previous = self.previous.lock().unwrap();
+        let mut messages = Vec::new();
+        for variable in &self.variables {
+            let value = variable.decode(&image)?;
+            if let Some(previous) = &*previous {
+                if variable.decode(previous)? == value {
+                    continue;
+                }
+            }
+            messages.push(Message {
+                topic: format!("{}/{}", self.prefix, variable.name),
+                payload: json!({ "value": json_value(value), "time": time }).to_string(),
+            });
+        }
+        *previous = Some(image);
+        Ok(messages)
+    }
+
+    /// Makes the next call to [`changes`](Self::changes) return all variables again.
+    pub fn republish(&self) {
+        *self.previous.lock().unwrap() = None;
+    }
+
+    /// Executes the command published to @topic.
+    ///
+    /// Fails if @topic is not the command topic of a writable output or @payload is not a
+    /// value of it.
+    pub fn command<D: Driver + ?Sized>(
+        &self,
+        driver: &D,
+        topic: &str,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let name = topic
+            .strip_prefix(&self.prefix)
+            .and_then(|t| t.strip_prefix("/set/"))
+            .ok_or_else(|| invalid_input(format!("{} is not a command topic", topic)))?;
+        let variable = self
+            .writable
+            .iter()
+            .find(|v| v.name == name)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is not writable", name),
+                )
+            })?;
+        let json: Json = serde_json::from_slice(payload)
+            .map_err(|e| invalid_input(format!("invalid value for {}: {}", name, e)))?;
+        let json = json.get("value").unwrap_or(&json);
+        variable.write(driver, value_from_json(variable.kind, json)?)
+    }
+
+    /// Connects to the broker with @options and bridges @driver until @stop is set.
+    ///
+    /// The connection is kept up, a lost connection is opened again. Fails if the process
+    /// image can not be read or the client refuses a message.
+    pub fn run<D: Driver + ?Sized>(
+        &self,
+        driver: &D,
+        options: MqttOptions,
+        stop: &AtomicBool,
+    ) -> io::Result<()> {
+        let (client, mut connection) = Client::new(options, 64);
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let publisher = scope.spawn(|| {
+                let result = self.publish(driver, &client, stop, &done);
+                done.store(true, Ordering::Relaxed);
+                result
+            });
+            while !stop.load(Ordering::Relaxed) && !done.load(Ordering::Relaxed) {
+                match connection.recv_timeout(Duration::from_millis(100)) {
+                    // subscriptions are lost with a new clean session, and publications
+                    // while the connection was down
+                    Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                        let _ = client.try_subscribe(self.command_filter(), QoS::AtLeastOnce);
+                        self.republish();
+                    }
+                    Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                        let _ = self.command(driver, &publish.topic, &publish.payload);
+                    }
+                    Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+                    Ok(Err(_)) => thread::sleep(RECONNECT_DELAY),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+            // unblocks a publisher waiting for room in the request queue
+            drop(connection);
+            publisher.join().unwrap()
+        })
+    }
+
+    fn publish<D: Driver + ?Sized>(
+        &self,
+        driver: &D,
+        client: &Client,
+        stop: &AtomicBool,
+        done: &AtomicBool,
+    ) -> io::Result<()> {
+        let mut next = Instant::now();
+        while !stop.load(Ordering::Relaxed) && !done.load(Ordering::Relaxed) {
+            for message in self.changes(driver)? {
+                if let Err(e) =
+                    client.publish(message.topic, QoS::AtLeastOnce, false, message.payload)
+                {
+                    // the connection is only gone on purpose once stop or done is set
+                    if stop.load(Ordering::Relaxed) || done.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                    return Err(io::Error::other(format!("can not publish: {}", e)));
+                }
+            }
+            next += self.interval;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    #[test]
+    fn publish_changes_and_write_outputs() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Emulator::from_config(config.clone()).unwrap();
+        let bridge = Bridge::new(&config, "revpi/", &["O_1", "PWM_1"]).unwrap();
+        assert!(Bridge::new(&config, "revpi", &["I_1"]).is_err());
+        assert!(Bridge::new(&config, "revpi", &["O_99"]).is_err());
+        assert!(Bridge::new(&config, "revpi/+", &[]).is_err());
+        let mut odd = config.clone();
+        odd.devices[0].entries[0].name = "Temp#1".to_owned();
+        let err = Bridge::new(&odd, "revpi", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        assert_eq!(
+            bridge.changes(&emulator).unwrap().len(),
+            config.entries().count()
+        );
+        assert!(bridge.changes(&emulator).unwrap().is_empty());
+        bridge.republish();
+        assert_eq!(
+            bridge.changes(&emulator).unwrap().len(),
+            config.entries().count()
+        );
+
+        emulator.set("I_1", Value::Bool(true)).unwrap();
+        let changes = bridge.changes(&emulator).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].topic, "revpi/I_1");
+        let payload: Json = serde_json::from_str(&changes[0].payload).unwrap();
+        assert_eq!(payload["value"], json!(true));
+
+        bridge.command(&emulator, "revpi/set/O_1", b"true").unwrap();
+        bridge
+            .command(&emulator, "revpi/set/PWM_1", br#"{"value": 42}"#)
+            .unwrap();
+        assert_eq!(
+            emulator.value("O_1", &Value::Bool(false)).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            emulator.value("PWM_1", &Value::U8(0)).unwrap(),
+            Value::U8(42)
+        );
+        let topics: Vec<_> = bridge
+            .changes(&emulator)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.topic)
+            .collect();
+        assert_eq!(topics, vec!["revpi/O_1", "revpi/PWM_1"]);
+
+        assert!(bridge.command(&emulator, "revpi/set/O_2", b"true").is_err());
+        assert!(bridge
+            .command(&emulator, "revpi/set/PWM_1", b"300")
+            .is_err());
+        assert!(bridge.command(&emulator, "other/set/O_1", b"1").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs an MQTT broker on localhost:1883"]
+    fn local_broker() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Arc::new(Emulator::from_config(config.clone()).unwrap());
+        let stop = Arc::new(AtomicBool::new(false));
+        let bridge = thread::spawn({
+            let emulator = Arc::clone(&emulator);
+            let stop = Arc::clone(&stop);
+            move || {
+                let bridge = Bridge::new(&config, "picontrol-test", &["O_1"]).unwrap();
+                let options = MqttOptions::new("picontrol-bridge", "localhost", 1883);
+                bridge.run(&*emulator, options, &stop)
+            }
+        });
+
+        let (client, mut connection) =
+            Client::new(MqttOptions::new("picontrol-test", "localhost", 1883), 16);
+        client
+            .subscribe("picontrol-test/I_1", QoS::AtLeastOnce)
+            .unwrap();
+        thread::sleep(Duration::from_millis(500));
+        emulator.set("I_1", Value::Bool(true)).unwrap();
+        client
+            .publish("picontrol-test/set/O_1", QoS::AtLeastOnce, false, "1")
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut published = false;
+        while Instant::now() < deadline && !published {
+            if let Ok(Ok(Event::Incoming(Packet::Publish(p)))) =
+                connection.recv_timeout(Duration::from_millis(100))
+            {
+                let payload: Json = serde_json::from_slice(&p.payload).unwrap();
+                published = payload["value"] == json!(true);
+            }
+        }
+        assert!(published);
+        assert_eq!(
+            emulator.value("O_1", &Value::Bool(false)).unwrap(),
+            Value::Bool(true)
+        );
+
+        stop.store(true, Ordering::Relaxed);
+        bridge.join().unwrap().unwrap();
+    }
+}
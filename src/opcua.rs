@@ -0,0 +1,1984 @@
+//! An OPC UA server of the process image, speaking the binary protocol over TCP.
+//!
+//! The address space is built from the configuration: the Objects folder organizes one folder
+//! per device, `ns=1;s=<name> (<position>)`, which organizes one variable per entry of the
+//! device, `ns=1;s=<variable name>`. A variable has the data type of its length (Boolean, Byte,
+//! UInt16 or UInt32), its comment as description and is writable if it is an output, unless the
+//! server is [`read_only`](OpcUaServer::read_only).
+//!
+//! Values are read from and written to the driver, so any OPC UA client can browse, read, write
+//! and subscribe to them, e.g.:
+//!
+//! ```text
+//! piopcua -l 0.0.0.0:4840 --writable
+//! uaread --url opc.tcp://revpi:4840 --nodeid "ns=1;s=I_1"
+//! ```
+//!
+//! `piopcua` listens on loopback and is read-only unless told otherwise. Only the security
+//! policy None with anonymous users is offered, so anyone who can reach the port can read and,
+//! if the server is writable, write outputs. The services are those of
+//! the discovery, session, view, attribute, subscription and monitored item service sets a
+//! client needs for data access; a session lives as long as the connection it was created on.
+//! Monitored items are sampled at the publishing interval of their subscription, which is
+//! never shorter than the server's minimum, and queue only their latest value.
+
+mod nodes;
+mod types;
+
+use self::nodes::{AddressSpace, NodeClass, Reference};
+use self::types::{attributes, ids, now, status, DataValue, NodeId, Reader, Variant, Writer};
+use crate::config::Config;
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The application URI of the server.
+pub const APPLICATION_URI: &str = "urn:picontrol:server";
+
+const SECURITY_POLICY_NONE: &str = "http://opcfoundation.org/UA/SecurityPolicy#None";
+const TRANSPORT_PROFILE: &str = "http://opcfoundation.org/UA-Profile/Transport/uatcp-uasc-uabinary";
+
+// the largest chunk the server sends or receives
+const BUFFER_SIZE: u32 = 65536;
+// the smallest buffer the specification allows
+const MIN_BUFFER_SIZE: u32 = 8192;
+const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024;
+// the longest a client may take to say hello
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PUBLISH_REQUESTS: usize = 10;
+// the notification messages kept for Republish
+const MAX_RETRANSMISSIONS: usize = 10;
+const MAX_CONTINUATION_POINTS: usize = 10;
+// the sessions of a connection, the subscriptions of a session and the items of a subscription
+const MAX_SESSIONS: usize = 10;
+const MAX_SUBSCRIPTIONS: usize = 10;
+const MAX_MONITORED_ITEMS: usize = 1000;
+
+// channels and sessions are numbered across connections
+static NEXT_CHANNEL_ID: AtomicU32 = AtomicU32::new(1);
+static NEXT_SESSION_ID: AtomicU32 = AtomicU32::new(1);
+
+/// OpcUaServer serves the address space of a configuration, see the module documentation.
+pub struct OpcUaServer<D: ?Sized> {
+    listener: TcpListener,
+    driver: Arc<D>,
+    space: Arc<AddressSpace>,
+    min_interval: Duration,
+}
+
+impl<D: Driver + ?Sized + 'static> OpcUaServer<D> {
+    /// Listens on @addr for clients of the devices and variables of @config in @driver.
+    pub fn bind<A: ToSocketAddrs>(addr: A, driver: Arc<D>, config: &Config) -> io::Result<Self> {
+        Ok(OpcUaServer {
+            listener: TcpListener::bind(addr)?,
+            driver,
+            space: Arc::new(AddressSpace::new(config, APPLICATION_URI)?),
+            min_interval: Duration::from_millis(100),
+        })
+    }
+
+    /// The shortest publishing interval of subscriptions, 100 ms by default.
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Refuses writes to all variables, outputs included.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        if read_only {
+            // the space is only shared once run starts
+            if let Some(space) = Arc::get_mut(&mut self.space) {
+                space.read_only();
+            }
+        }
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts clients until the listener fails, serving each on its own thread.
+    pub fn run(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let endpoint_url = format!("opc.tcp://{}", stream.local_addr()?);
+            let mut connection = Connection {
+                driver: Arc::clone(&self.driver),
+                space: Arc::clone(&self.space),
+                min_interval: self.min_interval,
+                channel: Channel {
+                    stream,
+                    endpoint_url,
+                    send_buffer: BUFFER_SIZE,
+                    receive_buffer: BUFFER_SIZE,
+                    max_message: 0,
+                    id: 0,
+                    token_id: 0,
+                    sequence: 0,
+                },
+                incoming: Vec::new(),
+                chunks: Vec::new(),
+                sessions: Vec::new(),
+            };
+            thread::spawn(move || connection.serve());
+        }
+        Ok(())
+    }
+}
+
+/// The secure channel of a connection, which sends the messages.
+struct Channel {
+    stream: TcpStream,
+    endpoint_url: String,
+    /// The largest chunk the client receives.
+    send_buffer: u32,
+    /// The largest chunk the server receives.
+    receive_buffer: u32,
+    /// The largest message the client receives, 0 for any.
+    max_message: u32,
+    id: u32,
+    token_id: u32,
+    sequence: u32,
+}
+
+impl Channel {
+    /// Sends the @message of type @kind for @request_id, split into chunks.
+    fn send(&mut self, kind: &[u8; 3], request_id: u32, message: &[u8]) -> io::Result<()> {
+        let mut security = Writer::new();
+        security.u32(self.id);
+        if kind == b"OPN" {
+            security
+                .string(SECURITY_POLICY_NONE)
+                .byte_string(None)
+                .byte_string(None);
+        } else {
+            security.u32(self.token_id);
+        }
+        // message header, security header and sequence header
+        let overhead = 8 + security.data.len() + 8;
+        let max_body = self.send_buffer as usize - overhead;
+        let mut chunks = message.chunks(max_body).peekable();
+        while let Some(body) = chunks.next() {
+            self.sequence = self.sequence.wrapping_add(1);
+            let mut w = Writer::new();
+            w.bytes(kind)
+                .u8(if chunks.peek().is_some() { b'C' } else { b'F' })
+                .u32((overhead + body.len()) as u32)
+                .bytes(&security.data)
+                .u32(self.sequence)
+                .u32(request_id)
+                .bytes(body);
+            self.stream.write_all(&w.data)?;
+        }
+        Ok(())
+    }
+
+    /// Sends the response @body of type @type_id with @result to the request @request_id.
+    fn respond(
+        &mut self,
+        request_id: u32,
+        handle: u32,
+        type_id: u32,
+        result: u32,
+        body: &[u8],
+    ) -> io::Result<()> {
+        let mut w = Writer::new();
+        w.node_id(&NodeId::ns0(type_id))
+            .i64(now())
+            .u32(handle)
+            .u32(result)
+            .u8(0)
+            .i32(0)
+            .null_extension_object()
+            .bytes(body);
+        if self.max_message != 0 && w.data.len() > self.max_message as usize {
+            return self.fault(request_id, handle, status::BAD_RESPONSE_TOO_LARGE);
+        }
+        self.send(b"MSG", request_id, &w.data)
+    }
+
+    /// Answers the request @request_id with a ServiceFault of @code.
+    fn fault(&mut self, request_id: u32, handle: u32, code: u32) -> io::Result<()> {
+        self.respond(request_id, handle, ids::SERVICE_FAULT, code, &[])
+    }
+
+    /// Sends an error message, after which the connection is closed.
+    fn error(&mut self, code: u32, reason: &str) -> io::Result<()> {
+        let mut w = Writer::new();
+        w.bytes(b"ERRF")
+            .u32(16 + reason.len() as u32)
+            .u32(code)
+            .string(reason);
+        self.stream.write_all(&w.data)
+    }
+
+    /// Encodes the description of the server.
+    fn application(&self, w: &mut Writer) {
+        w.string(APPLICATION_URI)
+            .string("urn:picontrol")
+            .localized_text("picontrol OPC UA server")
+            .u32(0)
+            .null_string()
+            .null_string()
+            .array(&[&self.endpoint_url], |w, url| {
+                w.string(url);
+            });
+    }
+
+    /// Encodes the endpoint of the server at @url.
+    fn endpoint(&self, w: &mut Writer, url: &str) {
+        w.string(url);
+        self.application(w);
+        w.byte_string(None)
+            .u32(1)
+            .string(SECURITY_POLICY_NONE)
+            // anonymous user token policy
+            .i32(1)
+            .string("anonymous")
+            .u32(0)
+            .null_string()
+            .null_string()
+            .null_string()
+            .string(TRANSPORT_PROFILE)
+            .u8(0);
+    }
+}
+
+/// The request header fields the server uses.
+struct RequestHeader {
+    token: NodeId,
+    handle: u32,
+}
+
+impl RequestHeader {
+    fn decode(r: &mut Reader) -> io::Result<RequestHeader> {
+        let token = r.node_id()?;
+        r.i64()?;
+        let handle = r.u32()?;
+        r.u32()?;
+        r.string()?;
+        r.u32()?;
+        r.extension_object()?;
+        Ok(RequestHeader { token, handle })
+    }
+}
+
+/// What to answer a request with.
+enum Reply {
+    Response(u32, Writer),
+    Fault(u32),
+    /// A Publish request answered later.
+    Deferred,
+}
+
+/// A Publish request waiting for notifications.
+struct PublishRequest {
+    request_id: u32,
+    handle: u32,
+    /// The results of its acknowledgements.
+    results: Vec<u32>,
+}
+
+struct Session {
+    id: NodeId,
+    token: NodeId,
+    activated: bool,
+    /// The revised session timeout.
+    timeout: Duration,
+    /// When the client last sent a request of the session.
+    last_used: Instant,
+    subscriptions: Vec<Subscription>,
+    next_subscription_id: u32,
+    publish: VecDeque<PublishRequest>,
+    /// The references not returned yet by their continuation point.
+    continuations: VecDeque<(Vec<u8>, Vec<Reference>, u32, u32)>,
+    next_continuation: u32,
+}
+
+/// A monitored item, the attribute of a node sampled by a subscription.
+struct Item {
+    id: u32,
+    client_handle: u32,
+    node: usize,
+    attribute: u32,
+    /// 0 disabled, 1 sampling, 2 reporting.
+    mode: u32,
+    timestamps: u32,
+    /// 0 status, 1 status and value, 2 status, value and timestamp.
+    trigger: u32,
+    deadband: Option<f64>,
+    /// The last reported value.
+    last: Option<DataValue>,
+}
+
+impl Item {
+    fn changed(&self, value: &DataValue) -> bool {
+        let last = match &self.last {
+            Some(last) => last,
+            None => return true,
+        };
+        if last.status != value.status {
+            return true;
+        }
+        if self.trigger == 0 {
+            return false;
+        }
+        let numbers = (
+            last.value.as_ref().and_then(Variant::as_f64),
+            value.value.as_ref().and_then(Variant::as_f64),
+        );
+        let value_changed = match (self.deadband, numbers) {
+            (Some(deadband), (Some(a), Some(b))) => (a - b).abs() > deadband,
+            _ => last.value != value.value,
+        };
+        value_changed || (self.trigger == 2 && last.source_timestamp != value.source_timestamp)
+    }
+}
+
+struct Subscription {
+    id: u32,
+    interval: Duration,
+    keep_alive: u32,
+    lifetime: u32,
+    max_notifications: u32,
+    enabled: bool,
+    items: Vec<Item>,
+    next_item_id: u32,
+    next: Instant,
+    /// Publishing intervals without a notification.
+    idle: u32,
+    /// Publishing intervals without a waiting Publish request, the lifetime counter.
+    unanswered: u32,
+    keep_alive_due: bool,
+    sequence: u32,
+    /// Client handles and values not published yet.
+    pending: Vec<(u32, DataValue)>,
+    /// Sent notification messages not acknowledged yet.
+    sent: VecDeque<(u32, Vec<u8>)>,
+}
+
+impl Subscription {
+    fn ready(&self) -> bool {
+        (self.enabled && !self.pending.is_empty()) || self.keep_alive_due
+    }
+
+    /// Encodes the next notification message, a keep-alive if there are no notifications,
+    /// and whether more notifications are left.
+    fn message(&mut self) -> (Vec<u8>, bool) {
+        let mut w = Writer::new();
+        self.idle = 0;
+        self.keep_alive_due = false;
+        if !self.enabled || self.pending.is_empty() {
+            w.u32(self.sequence).i64(now()).i32(0);
+            return (w.data, false);
+        }
+        let count = match self.max_notifications as usize {
+            0 => self.pending.len(),
+            n => n.min(self.pending.len()),
+        };
+        let notifications: Vec<_> = self.pending.drain(..count).collect();
+        let mut data = Writer::new();
+        data.array(&notifications, |w, (handle, value)| {
+            w.u32(*handle).data_value(value);
+        })
+        .no_diagnostics();
+        w.u32(self.sequence)
+            .i64(now())
+            .i32(1)
+            .extension_object(ids::DATA_CHANGE_NOTIFICATION, &data.data);
+        self.sent.push_back((self.sequence, w.data.clone()));
+        if self.sent.len() > MAX_RETRANSMISSIONS {
+            self.sent.pop_front();
+        }
+        self.sequence = self.sequence.wrapping_add(1).max(1);
+        (w.data, !self.pending.is_empty())
+    }
+}
+
+/// The state of one connection.
+struct Connection<D: ?Sized> {
+    driver: Arc<D>,
+    space: Arc<AddressSpace>,
+    min_interval: Duration,
+    channel: Channel,
+    /// Received bytes of incomplete chunks.
+    incoming: Vec<u8>,
+    /// The bodies of the chunks of an incomplete message.
+    chunks: Vec<u8>,
+    sessions: Vec<Session>,
+}
+
+impl<D: Driver + ?Sized> Connection<D> {
+    fn serve(&mut self) -> io::Result<()> {
+        self.channel.stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+        let mut buffer = vec![0; BUFFER_SIZE as usize];
+        let mut hello = true;
+        loop {
+            if !hello {
+                self.tick()?;
+                let now = Instant::now();
+                let next = self
+                    .sessions
+                    .iter()
+                    .flat_map(|s| {
+                        s.subscriptions
+                            .iter()
+                            .map(|s| s.next)
+                            .chain(std::iter::once(s.last_used + s.timeout))
+                    })
+                    .min()
+                    .unwrap_or(now + Duration::from_secs(1));
+                self.channel.stream.set_read_timeout(Some(
+                    next.saturating_duration_since(now)
+                        .max(Duration::from_millis(1)),
+                ))?;
+            }
+            match self.channel.stream.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(e)
+                    if !hello
+                        && (e.kind() == io::ErrorKind::WouldBlock
+                            || e.kind() == io::ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            }
+            while self.incoming.len() >= 8 {
+                let size = u32::from_le_bytes(self.incoming[4..8].try_into().unwrap());
+                if size < 8 || size > self.channel.receive_buffer {
+                    return self
+                        .channel
+                        .error(status::BAD_TCP_MESSAGE_TOO_LARGE, "chunk too large");
+                }
+                if self.incoming.len() < size as usize {
+                    break;
+                }
+                let chunk: Vec<u8> = self.incoming.drain(..size as usize).collect();
+                let open = match (&chunk[..3], hello) {
+                    (b"HEL", true) => {
+                        hello = false;
+                        self.hello(&chunk[8..])
+                    }
+                    (b"OPN", false) => self.open(&chunk[8..]),
+                    (b"MSG", false) => self.chunk(chunk[3], &chunk[8..]),
+                    (b"CLO", false) => Ok(false),
+                    _ => {
+                        self.channel
+                            .error(status::BAD_TCP_MESSAGE_TYPE_INVALID, "unexpected message")?;
+                        Ok(false)
+                    }
+                };
+                match open {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(()),
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        return self
+                            .channel
+                            .error(status::BAD_DECODING_ERROR, &e.to_string())
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Negotiates the buffer sizes.
+    fn hello(&mut self, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        r.u32()?;
+        let receive = r.u32()?;
+        let send = r.u32()?;
+        self.channel.max_message = r.u32()?;
+        r.u32()?;
+        if let Some(url) = r.string()?.filter(|url| !url.is_empty()) {
+            self.channel.endpoint_url = url;
+        }
+        self.channel.send_buffer = receive.clamp(MIN_BUFFER_SIZE, BUFFER_SIZE);
+        self.channel.receive_buffer = send.clamp(MIN_BUFFER_SIZE, BUFFER_SIZE);
+        let mut w = Writer::new();
+        w.bytes(b"ACKF")
+            .u32(28)
+            .u32(0)
+            .u32(self.channel.receive_buffer)
+            .u32(self.channel.send_buffer)
+            .u32(MAX_MESSAGE_SIZE)
+            .u32(0);
+        self.channel.stream.write_all(&w.data)?;
+        Ok(true)
+    }
+
+    /// Opens or renews the secure channel.
+    fn open(&mut self, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        let id = r.u32()?;
+        let policy = r.string()?.unwrap_or_default();
+        r.byte_string()?;
+        r.byte_string()?;
+        r.u32()?;
+        let request_id = r.u32()?;
+        if policy != SECURITY_POLICY_NONE {
+            self.channel.error(
+                status::BAD_SECURITY_POLICY_REJECTED,
+                "only None is supported",
+            )?;
+            return Ok(false);
+        }
+        if r.node_id()? != NodeId::ns0(ids::OPEN_SECURE_CHANNEL_REQUEST) {
+            self.channel.error(
+                status::BAD_TCP_MESSAGE_TYPE_INVALID,
+                "expected OpenSecureChannel",
+            )?;
+            return Ok(false);
+        }
+        let header = RequestHeader::decode(&mut r)?;
+        r.u32()?;
+        let renew = r.u32()? == 1;
+        let mode = r.u32()?;
+        r.byte_string()?;
+        let lifetime = r.u32()?.clamp(10_000, 3_600_000);
+        if mode != 1 {
+            self.channel
+                .error(status::BAD_SECURITY_MODE_REJECTED, "only None is supported")?;
+            return Ok(false);
+        }
+        if renew {
+            if id != self.channel.id {
+                self.channel
+                    .error(status::BAD_SECURE_CHANNEL_ID_INVALID, "unknown channel")?;
+                return Ok(false);
+            }
+        } else {
+            self.channel.id = NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed);
+        }
+        self.channel.token_id = self.channel.token_id.wrapping_add(1);
+
+        let mut w = Writer::new();
+        w.node_id(&NodeId::ns0(ids::OPEN_SECURE_CHANNEL_RESPONSE))
+            .i64(now())
+            .u32(header.handle)
+            .u32(status::GOOD)
+            .u8(0)
+            .i32(0)
+            .null_extension_object()
+            .u32(0)
+            .u32(self.channel.id)
+            .u32(self.channel.token_id)
+            .i64(now())
+            .u32(lifetime)
+            .byte_string(Some(&[]));
+        self.channel.send(b"OPN", request_id, &w.data)?;
+        Ok(true)
+    }
+
+    /// Collects the chunks of a message and handles it once complete.
+    fn chunk(&mut self, kind: u8, body: &[u8]) -> io::Result<bool> {
+        let mut r = Reader::new(body);
+        if r.u32()? != self.channel.id || self.channel.id == 0 {
+            self.channel
+                .error(status::BAD_SECURE_CHANNEL_ID_INVALID, "unknown channel")?;
+            return Ok(false);
+        }
+        r.u32()?;
+        r.u32()?;
+        let request_id = r.u32()?;
+        match kind {
+            b'A' => self.chunks.clear(),
+            b'C' | b'F' => {
+                self.chunks.extend_from_slice(r.rest());
+                if self.chunks.len() > MAX_MESSAGE_SIZE as usize {
+                    self.channel
+                        .error(status::BAD_TCP_MESSAGE_TOO_LARGE, "message too large")?;
+                    return Ok(false);
+                }
+                if kind == b'F' {
+                    let message = std::mem::take(&mut self.chunks);
+                    self.message(request_id, &message)?;
+                }
+            }
+            _ => {
+                self.channel
+                    .error(status::BAD_TCP_MESSAGE_TYPE_INVALID, "invalid chunk type")?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Handles a service request.
+    fn message(&mut self, request_id: u32, body: &[u8]) -> io::Result<()> {
+        let mut r = Reader::new(body);
+        let (type_id, header) = match r
+            .node_id()
+            .and_then(|id| Ok((id, RequestHeader::decode(&mut r)?)))
+        {
+            Ok(request) => request,
+            Err(_) => {
+                return self
+                    .channel
+                    .fault(request_id, 0, status::BAD_DECODING_ERROR)
+            }
+        };
+        let reply = match type_id {
+            NodeId::Numeric(0, id) => self.service(id, &header, &mut r, request_id),
+            _ => Ok(Reply::Fault(status::BAD_SERVICE_UNSUPPORTED)),
+        };
+        match reply {
+            Ok(Reply::Response(type_id, body)) => {
+                self.channel
+                    .respond(request_id, header.handle, type_id, status::GOOD, &body.data)
+            }
+            Ok(Reply::Fault(code)) => self.channel.fault(request_id, header.handle, code),
+            Ok(Reply::Deferred) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                self.channel
+                    .fault(request_id, header.handle, status::BAD_DECODING_ERROR)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn service(
+        &mut self,
+        id: u32,
+        header: &RequestHeader,
+        r: &mut Reader,
+        request_id: u32,
+    ) -> io::Result<Reply> {
+        if let Some(session) = self.sessions.iter_mut().find(|s| s.token == header.token) {
+            session.last_used = Instant::now();
+        }
+        match id {
+            ids::GET_ENDPOINTS_REQUEST => return self.get_endpoints(r),
+            ids::FIND_SERVERS_REQUEST => return self.find_servers(r),
+            ids::CREATE_SESSION_REQUEST => return self.create_session(r),
+            ids::ACTIVATE_SESSION_REQUEST => return self.activate_session(header, r),
+            ids::CLOSE_SESSION_REQUEST => return self.close_session(header, r),
+            _ => {}
+        }
+        let session = match self.sessions.iter().position(|s| s.token == header.token) {
+            Some(i) if self.sessions[i].activated => i,
+            Some(_) => return Ok(Reply::Fault(status::BAD_SESSION_NOT_ACTIVATED)),
+            None => return Ok(Reply::Fault(status::BAD_SESSION_ID_INVALID)),
+        };
+        match id {
+            ids::READ_REQUEST => self.read(r),
+            ids::WRITE_REQUEST => self.write(r),
+            ids::BROWSE_REQUEST => self.browse(session, r),
+            ids::BROWSE_NEXT_REQUEST => self.browse_next(session, r),
+            ids::TRANSLATE_BROWSE_PATHS_REQUEST => self.translate(r),
+            ids::CREATE_SUBSCRIPTION_REQUEST => self.create_subscription(session, r),
+            ids::MODIFY_SUBSCRIPTION_REQUEST => self.modify_subscription(session, r),
+            ids::SET_PUBLISHING_MODE_REQUEST => self.set_publishing_mode(session, r),
+            ids::DELETE_SUBSCRIPTIONS_REQUEST => self.delete_subscriptions(session, r),
+            ids::CREATE_MONITORED_ITEMS_REQUEST => self.create_monitored_items(session, r),
+            ids::MODIFY_MONITORED_ITEMS_REQUEST => self.modify_monitored_items(session, r),
+            ids::SET_MONITORING_MODE_REQUEST => self.set_monitoring_mode(session, r),
+            ids::DELETE_MONITORED_ITEMS_REQUEST => self.delete_monitored_items(session, r),
+            ids::PUBLISH_REQUEST => self.publish(session, request_id, header.handle, r),
+            ids::REPUBLISH_REQUEST => self.republish(session, r),
+            _ => Ok(Reply::Fault(status::BAD_SERVICE_UNSUPPORTED)),
+        }
+    }
+
+    fn get_endpoints(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        let url = r.string()?.filter(|url| !url.is_empty());
+        r.array(Reader::string)?;
+        let profiles = r.array(Reader::string)?;
+        let url = url.unwrap_or_else(|| self.channel.endpoint_url.clone());
+        let mut w = Writer::new();
+        if profiles.is_empty()
+            || profiles
+                .iter()
+                .any(|p| p.as_deref() == Some(TRANSPORT_PROFILE))
+        {
+            w.i32(1);
+            self.channel.endpoint(&mut w, &url);
+        } else {
+            w.i32(0);
+        }
+        Ok(Reply::Response(ids::GET_ENDPOINTS_RESPONSE, w))
+    }
+
+    fn find_servers(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        r.string()?;
+        r.array(Reader::string)?;
+        let uris = r.array(Reader::string)?;
+        let mut w = Writer::new();
+        if uris.is_empty() || uris.iter().any(|u| u.as_deref() == Some(APPLICATION_URI)) {
+            w.i32(1);
+            self.channel.application(&mut w);
+        } else {
+            w.i32(0);
+        }
+        Ok(Reply::Response(ids::FIND_SERVERS_RESPONSE, w))
+    }
+
+    fn create_session(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        // client description
+        r.string()?;
+        r.string()?;
+        r.localized_text()?;
+        r.u32()?;
+        r.string()?;
+        r.string()?;
+        r.array(Reader::string)?;
+        // server URI, endpoint URL, session name, nonce and certificate
+        r.string()?;
+        r.string()?;
+        r.string()?;
+        r.byte_string()?;
+        r.byte_string()?;
+        let timeout = r.f64()?;
+        r.u32()?;
+        if self.sessions.len() >= MAX_SESSIONS {
+            return Ok(Reply::Fault(status::BAD_TOO_MANY_SESSIONS));
+        }
+
+        let timeout = if timeout.is_nan() {
+            60_000.0
+        } else {
+            timeout.clamp(10_000.0, 3_600_000.0)
+        };
+        let session = Session {
+            id: NodeId::Numeric(1, NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)),
+            token: NodeId::Opaque(1, random_bytes(16)),
+            activated: false,
+            timeout: Duration::from_secs_f64(timeout / 1000.0),
+            last_used: Instant::now(),
+            subscriptions: Vec::new(),
+            next_subscription_id: 1,
+            publish: VecDeque::new(),
+            continuations: VecDeque::new(),
+            next_continuation: 1,
+        };
+        let mut w = Writer::new();
+        w.node_id(&session.id)
+            .node_id(&session.token)
+            .f64(timeout)
+            .byte_string(Some(&random_bytes(32)))
+            .byte_string(None)
+            .i32(1);
+        let url = self.channel.endpoint_url.clone();
+        self.channel.endpoint(&mut w, &url);
+        w.i32(0)
+            .null_string()
+            .byte_string(None)
+            .u32(MAX_MESSAGE_SIZE);
+        self.sessions.push(session);
+        Ok(Reply::Response(ids::CREATE_SESSION_RESPONSE, w))
+    }
+
+    fn activate_session(&mut self, header: &RequestHeader, r: &mut Reader) -> io::Result<Reply> {
+        r.string()?;
+        r.byte_string()?;
+        let certificates = r.array(|r| {
+            r.byte_string()?;
+            r.byte_string()
+        })?;
+        r.array(Reader::string)?;
+        let (token_type, _) = r.extension_object()?;
+        let session = match self.sessions.iter_mut().find(|s| s.token == header.token) {
+            Some(session) => session,
+            None => return Ok(Reply::Fault(status::BAD_SESSION_ID_INVALID)),
+        };
+        if !token_type.is_null() && token_type != NodeId::ns0(ids::ANONYMOUS_IDENTITY_TOKEN) {
+            return Ok(Reply::Fault(status::BAD_IDENTITY_TOKEN_INVALID));
+        }
+        session.activated = true;
+        let mut w = Writer::new();
+        w.byte_string(Some(&random_bytes(32)))
+            .array(&certificates, |w, _| {
+                w.u32(status::GOOD);
+            })
+            .no_diagnostics();
+        Ok(Reply::Response(ids::ACTIVATE_SESSION_RESPONSE, w))
+    }
+
+    fn close_session(&mut self, header: &RequestHeader, r: &mut Reader) -> io::Result<Reply> {
+        r.bool()?;
+        let session = match self.sessions.iter().position(|s| s.token == header.token) {
+            Some(i) => self.sessions.remove(i),
+            None => return Ok(Reply::Fault(status::BAD_SESSION_ID_INVALID)),
+        };
+        for request in session.publish {
+            self.channel.fault(
+                request.request_id,
+                request.handle,
+                status::BAD_SESSION_CLOSED,
+            )?;
+        }
+        Ok(Reply::Response(ids::CLOSE_SESSION_RESPONSE, Writer::new()))
+    }
+
+    fn read(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        r.f64()?;
+        let timestamps = r.u32()?;
+        let nodes = r.array(|r| {
+            let id = r.node_id()?;
+            let attribute = r.u32()?;
+            let range = r.string()?;
+            r.qualified_name()?;
+            Ok((id, attribute, range))
+        })?;
+        if nodes.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        if timestamps > 3 {
+            return Ok(Reply::Fault(status::BAD_TIMESTAMPS_TO_RETURN_INVALID));
+        }
+        let image = self.driver.read(0, PROCESS_IMAGE_SIZE).ok();
+        let time = now();
+        let mut w = Writer::new();
+        w.array(&nodes, |w, (id, attribute, range)| {
+            let value = match self.space.find(id) {
+                None => DataValue::status(status::BAD_NODE_ID_UNKNOWN),
+                Some(_) if range.as_deref().is_some_and(|r| !r.is_empty()) => {
+                    DataValue::status(status::BAD_INDEX_RANGE_INVALID)
+                }
+                Some(node) => stamp(
+                    self.space.read(node, *attribute, image.as_deref()),
+                    *attribute,
+                    timestamps,
+                    time,
+                ),
+            };
+            w.data_value(&value);
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::READ_RESPONSE, w))
+    }
+
+    fn write(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        let nodes = r.array(|r| {
+            let id = r.node_id()?;
+            let attribute = r.u32()?;
+            let range = r.string()?;
+            Ok((id, attribute, range, r.data_value()?))
+        })?;
+        if nodes.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.array(&nodes, |w, (id, attribute, range, value)| {
+            w.u32(match self.space.find(id) {
+                None => status::BAD_NODE_ID_UNKNOWN,
+                Some(_) if range.as_deref().is_some_and(|r| !r.is_empty()) => {
+                    status::BAD_INDEX_RANGE_INVALID
+                }
+                Some(node) => self.space.write(&*self.driver, node, *attribute, value),
+            });
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::WRITE_RESPONSE, w))
+    }
+
+    fn browse(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        // view
+        r.node_id()?;
+        r.i64()?;
+        r.u32()?;
+        let max = r.u32()?;
+        let nodes = r.array(|r| {
+            Ok((
+                r.node_id()?,
+                r.u32()?,
+                r.node_id()?,
+                r.bool()?,
+                r.u32()?,
+                r.u32()?,
+            ))
+        })?;
+        if nodes.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.i32(nodes.len() as i32);
+        for (id, direction, kind, subtypes, class_mask, result_mask) in nodes {
+            let kind_known = kind.is_null()
+                || self
+                    .space
+                    .find(&kind)
+                    .is_some_and(|i| self.space.node(i).class == NodeClass::ReferenceType);
+            let node = match self.space.find(&id) {
+                Some(_) if direction > 2 => Err(status::BAD_BROWSE_DIRECTION_INVALID),
+                Some(_) if !kind_known => Err(status::BAD_REFERENCE_TYPE_ID_INVALID),
+                Some(node) => Ok(node),
+                None => Err(status::BAD_NODE_ID_UNKNOWN),
+            };
+            match node {
+                Ok(node) => {
+                    let references = self
+                        .space
+                        .references(node, direction, &kind, subtypes, class_mask);
+                    self.browse_result(session, &mut w, references, max, result_mask);
+                }
+                Err(code) => {
+                    w.u32(code).byte_string(None).i32(0);
+                }
+            }
+        }
+        w.no_diagnostics();
+        Ok(Reply::Response(ids::BROWSE_RESPONSE, w))
+    }
+
+    /// Encodes a BrowseResult with at most @max of @references, keeping the others for a
+    /// continuation point.
+    fn browse_result(
+        &mut self,
+        session: usize,
+        w: &mut Writer,
+        mut references: Vec<Reference>,
+        max: u32,
+        result_mask: u32,
+    ) {
+        let session = &mut self.sessions[session];
+        let space = &self.space;
+        w.u32(status::GOOD);
+        if max != 0 && references.len() > max as usize {
+            let rest = references.split_off(max as usize);
+            let point = session.next_continuation.to_le_bytes().to_vec();
+            session.next_continuation = session.next_continuation.wrapping_add(1);
+            w.byte_string(Some(&point));
+            session
+                .continuations
+                .push_back((point, rest, max, result_mask));
+            if session.continuations.len() > MAX_CONTINUATION_POINTS {
+                session.continuations.pop_front();
+            }
+        } else {
+            w.byte_string(None);
+        }
+        w.array(&references, |w, reference| {
+            space.encode_reference(w, reference, result_mask);
+        });
+    }
+
+    fn browse_next(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let release = r.bool()?;
+        let points = r.array(Reader::byte_string)?;
+        if points.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.i32(points.len() as i32);
+        for point in points {
+            let continuations = &mut self.sessions[session].continuations;
+            match continuations
+                .iter()
+                .position(|(p, ..)| Some(p) == point.as_ref())
+                .and_then(|i| continuations.remove(i))
+            {
+                Some(_) if release => {
+                    w.u32(status::GOOD).byte_string(None).i32(0);
+                }
+                Some((_, references, max, result_mask)) => {
+                    self.browse_result(session, &mut w, references, max, result_mask)
+                }
+                None => {
+                    w.u32(status::BAD_CONTINUATION_POINT_INVALID)
+                        .byte_string(None)
+                        .i32(0);
+                }
+            }
+        }
+        w.no_diagnostics();
+        Ok(Reply::Response(ids::BROWSE_NEXT_RESPONSE, w))
+    }
+
+    fn translate(&mut self, r: &mut Reader) -> io::Result<Reply> {
+        let paths = r.array(|r| {
+            let start = r.node_id()?;
+            let elements =
+                r.array(|r| Ok((r.node_id()?, r.bool()?, r.bool()?, r.qualified_name()?)))?;
+            Ok((start, elements))
+        })?;
+        if paths.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.array(&paths, |w, (start, elements)| {
+            let mut current: Vec<usize> = self.space.find(start).into_iter().collect();
+            let code = if current.is_empty() {
+                status::BAD_NODE_ID_UNKNOWN
+            } else if elements.is_empty() {
+                status::BAD_NOTHING_TO_DO
+            } else {
+                for (kind, inverse, subtypes, name) in elements {
+                    let kind = if kind.is_null() {
+                        NodeId::ns0(ids::HIERARCHICAL_REFERENCES)
+                    } else {
+                        kind.clone()
+                    };
+                    current = current
+                        .iter()
+                        .flat_map(|&node| {
+                            self.space.children(node, &kind, *subtypes, *inverse, name)
+                        })
+                        .collect();
+                }
+                if current.is_empty() {
+                    status::BAD_NO_MATCH
+                } else {
+                    status::GOOD
+                }
+            };
+            w.u32(code).array(&current, |w, &node| {
+                w.node_id(&self.space.node(node).id).u32(u32::MAX);
+            });
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::TRANSLATE_BROWSE_PATHS_RESPONSE, w))
+    }
+
+    /// Revises a requested publishing interval in ms.
+    fn interval(&self, requested: f64) -> Duration {
+        let min = self.min_interval.as_secs_f64() * 1000.0;
+        let ms = if requested.is_nan() || requested < min {
+            min
+        } else {
+            requested.min(3_600_000.0)
+        };
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    fn create_subscription(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let interval = self.interval(r.f64()?);
+        let lifetime = r.u32()?;
+        let keep_alive = r.u32()?.max(1);
+        let max_notifications = r.u32()?;
+        let enabled = r.bool()?;
+        r.u8()?;
+        let session = &mut self.sessions[session];
+        if session.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Ok(Reply::Fault(status::BAD_TOO_MANY_SUBSCRIPTIONS));
+        }
+        let subscription = Subscription {
+            id: session.next_subscription_id,
+            interval,
+            keep_alive,
+            lifetime: lifetime.max(keep_alive.saturating_mul(3)),
+            max_notifications,
+            enabled,
+            items: Vec::new(),
+            next_item_id: 1,
+            next: Instant::now() + interval,
+            // the first publishing interval sends a keep-alive if there is no notification
+            idle: keep_alive - 1,
+            unanswered: 0,
+            keep_alive_due: false,
+            sequence: 1,
+            pending: Vec::new(),
+            sent: VecDeque::new(),
+        };
+        session.next_subscription_id += 1;
+        let mut w = Writer::new();
+        w.u32(subscription.id)
+            .f64(interval.as_secs_f64() * 1000.0)
+            .u32(subscription.lifetime)
+            .u32(keep_alive);
+        session.subscriptions.push(subscription);
+        Ok(Reply::Response(ids::CREATE_SUBSCRIPTION_RESPONSE, w))
+    }
+
+    fn modify_subscription(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let interval = self.interval(r.f64()?);
+        let lifetime = r.u32()?;
+        let keep_alive = r.u32()?.max(1);
+        let max_notifications = r.u32()?;
+        r.u8()?;
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        subscription.interval = interval;
+        subscription.keep_alive = keep_alive;
+        subscription.lifetime = lifetime.max(keep_alive.saturating_mul(3));
+        subscription.max_notifications = max_notifications;
+        subscription.next = Instant::now() + interval;
+        let mut w = Writer::new();
+        w.f64(interval.as_secs_f64() * 1000.0)
+            .u32(subscription.lifetime)
+            .u32(keep_alive);
+        Ok(Reply::Response(ids::MODIFY_SUBSCRIPTION_RESPONSE, w))
+    }
+
+    fn set_publishing_mode(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let enabled = r.bool()?;
+        let ids = r.array(Reader::u32)?;
+        if ids.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let session = &mut self.sessions[session];
+        let mut w = Writer::new();
+        w.array(&ids, |w, &id| {
+            w.u32(match subscription(session, id) {
+                Some(subscription) => {
+                    subscription.enabled = enabled;
+                    status::GOOD
+                }
+                None => status::BAD_SUBSCRIPTION_ID_INVALID,
+            });
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::SET_PUBLISHING_MODE_RESPONSE, w))
+    }
+
+    fn delete_subscriptions(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let ids = r.array(Reader::u32)?;
+        if ids.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        let subscriptions = &mut self.sessions[session].subscriptions;
+        w.array(&ids, |w, &id| {
+            w.u32(match subscriptions.iter().position(|s| s.id == id) {
+                Some(i) => {
+                    subscriptions.remove(i);
+                    status::GOOD
+                }
+                None => status::BAD_SUBSCRIPTION_ID_INVALID,
+            });
+        })
+        .no_diagnostics();
+        // waiting Publish requests have nothing left to wait for
+        if subscriptions.is_empty() {
+            let requests: Vec<_> = self.sessions[session].publish.drain(..).collect();
+            for request in requests {
+                self.channel.fault(
+                    request.request_id,
+                    request.handle,
+                    status::BAD_NO_SUBSCRIPTION,
+                )?;
+            }
+        }
+        Ok(Reply::Response(ids::DELETE_SUBSCRIPTIONS_RESPONSE, w))
+    }
+
+    fn create_monitored_items(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let timestamps = r.u32()?;
+        let items = r.array(|r| {
+            let node = r.node_id()?;
+            let attribute = r.u32()?;
+            let range = r.string()?;
+            r.qualified_name()?;
+            let mode = r.u32()?;
+            let parameters = Parameters::decode(r)?;
+            Ok((node, attribute, range, mode, parameters))
+        })?;
+        if timestamps > 3 {
+            return Ok(Reply::Fault(status::BAD_TIMESTAMPS_TO_RETURN_INVALID));
+        }
+        let space = Arc::clone(&self.space);
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        if items.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let interval = subscription.interval.as_secs_f64() * 1000.0;
+        let mut w = Writer::new();
+        w.array(&items, |w, (node, attribute, range, mode, parameters)| {
+            let node = match space.find(node) {
+                Some(node) => node,
+                None => {
+                    w.u32(status::BAD_NODE_ID_UNKNOWN).u32(0).f64(0.0).u32(0);
+                    w.null_extension_object();
+                    return;
+                }
+            };
+            let code = if range.as_deref().is_some_and(|r| !r.is_empty()) {
+                status::BAD_INDEX_RANGE_INVALID
+            } else {
+                match space.read(node, *attribute, None).status {
+                    status::BAD_ATTRIBUTE_ID_INVALID => status::BAD_ATTRIBUTE_ID_INVALID,
+                    _ => parameters.status,
+                }
+            };
+            let code = match code {
+                status::GOOD if subscription.items.len() >= MAX_MONITORED_ITEMS => {
+                    status::BAD_TOO_MANY_MONITORED_ITEMS
+                }
+                code => code,
+            };
+            if code != status::GOOD {
+                w.u32(code).u32(0).f64(0.0).u32(0).null_extension_object();
+                return;
+            }
+            let item = Item {
+                id: subscription.next_item_id,
+                client_handle: parameters.client_handle,
+                node,
+                attribute: *attribute,
+                mode: (*mode).min(2),
+                timestamps,
+                trigger: parameters.trigger,
+                deadband: parameters.deadband,
+                last: None,
+            };
+            subscription.next_item_id += 1;
+            w.u32(status::GOOD)
+                .u32(item.id)
+                .f64(interval)
+                .u32(1)
+                .null_extension_object();
+            subscription.items.push(item);
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::CREATE_MONITORED_ITEMS_RESPONSE, w))
+    }
+
+    fn modify_monitored_items(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let timestamps = r.u32()?;
+        let items = r.array(|r| Ok((r.u32()?, Parameters::decode(r)?)))?;
+        if timestamps > 3 {
+            return Ok(Reply::Fault(status::BAD_TIMESTAMPS_TO_RETURN_INVALID));
+        }
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        if items.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let interval = subscription.interval.as_secs_f64() * 1000.0;
+        let mut w = Writer::new();
+        w.array(&items, |w, (id, parameters)| {
+            let code = match subscription.items.iter_mut().find(|i| i.id == *id) {
+                Some(item) if parameters.status == status::GOOD => {
+                    item.client_handle = parameters.client_handle;
+                    item.timestamps = timestamps;
+                    item.trigger = parameters.trigger;
+                    item.deadband = parameters.deadband;
+                    status::GOOD
+                }
+                Some(_) => parameters.status,
+                None => status::BAD_MONITORED_ITEM_ID_INVALID,
+            };
+            w.u32(code).f64(interval).u32(1).null_extension_object();
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::MODIFY_MONITORED_ITEMS_RESPONSE, w))
+    }
+
+    fn set_monitoring_mode(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let mode = r.u32()?.min(2);
+        let items = r.array(Reader::u32)?;
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        if items.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.array(&items, |w, id| {
+            w.u32(match subscription.items.iter_mut().find(|i| i.id == *id) {
+                Some(item) => {
+                    // the next sample is reported as the current value
+                    if item.mode != mode {
+                        item.last = None;
+                    }
+                    item.mode = mode;
+                    status::GOOD
+                }
+                None => status::BAD_MONITORED_ITEM_ID_INVALID,
+            });
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::SET_MONITORING_MODE_RESPONSE, w))
+    }
+
+    fn delete_monitored_items(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let items = r.array(Reader::u32)?;
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        if items.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NOTHING_TO_DO));
+        }
+        let mut w = Writer::new();
+        w.array(&items, |w, id| {
+            w.u32(match subscription.items.iter().position(|i| i.id == *id) {
+                Some(i) => {
+                    let item = subscription.items.remove(i);
+                    subscription
+                        .pending
+                        .retain(|(h, _)| *h != item.client_handle);
+                    status::GOOD
+                }
+                None => status::BAD_MONITORED_ITEM_ID_INVALID,
+            });
+        })
+        .no_diagnostics();
+        Ok(Reply::Response(ids::DELETE_MONITORED_ITEMS_RESPONSE, w))
+    }
+
+    fn publish(
+        &mut self,
+        session: usize,
+        request_id: u32,
+        handle: u32,
+        r: &mut Reader,
+    ) -> io::Result<Reply> {
+        let acknowledgements = r.array(|r| Ok((r.u32()?, r.u32()?)))?;
+        let s = &mut self.sessions[session];
+        let results = acknowledgements
+            .iter()
+            .map(|&(id, sequence)| match subscription(s, id) {
+                Some(subscription) => {
+                    match subscription.sent.iter().position(|m| m.0 == sequence) {
+                        Some(i) => {
+                            subscription.sent.remove(i);
+                            status::GOOD
+                        }
+                        None => status::BAD_SEQUENCE_NUMBER_UNKNOWN,
+                    }
+                }
+                None => status::BAD_SUBSCRIPTION_ID_INVALID,
+            })
+            .collect();
+        if s.subscriptions.is_empty() {
+            return Ok(Reply::Fault(status::BAD_NO_SUBSCRIPTION));
+        }
+        for subscription in &mut s.subscriptions {
+            subscription.unanswered = 0;
+        }
+        s.publish.push_back(PublishRequest {
+            request_id,
+            handle,
+            results,
+        });
+        if s.publish.len() > MAX_PUBLISH_REQUESTS {
+            let oldest = s.publish.pop_front().unwrap();
+            self.channel.fault(
+                oldest.request_id,
+                oldest.handle,
+                status::BAD_TOO_MANY_PUBLISH_REQUESTS,
+            )?;
+        }
+        self.flush(session)?;
+        Ok(Reply::Deferred)
+    }
+
+    fn republish(&mut self, session: usize, r: &mut Reader) -> io::Result<Reply> {
+        let id = r.u32()?;
+        let sequence = r.u32()?;
+        let subscription = match subscription(&mut self.sessions[session], id) {
+            Some(subscription) => subscription,
+            None => return Ok(Reply::Fault(status::BAD_SUBSCRIPTION_ID_INVALID)),
+        };
+        match subscription.sent.iter().find(|m| m.0 == sequence) {
+            Some((_, message)) => {
+                let mut w = Writer::new();
+                w.bytes(message);
+                Ok(Reply::Response(ids::REPUBLISH_RESPONSE, w))
+            }
+            None => Ok(Reply::Fault(status::BAD_MESSAGE_NOT_AVAILABLE)),
+        }
+    }
+
+    /// Answers waiting Publish requests of @session while a subscription has something to send.
+    fn flush(&mut self, session: usize) -> io::Result<()> {
+        let s = &mut self.sessions[session];
+        while !s.publish.is_empty() {
+            let subscription = match s.subscriptions.iter_mut().find(|s| s.ready()) {
+                Some(subscription) => subscription,
+                None => break,
+            };
+            let request = s.publish.pop_front().unwrap();
+            let (message, more) = subscription.message();
+            let mut w = Writer::new();
+            let available: Vec<u32> = subscription.sent.iter().map(|m| m.0).collect();
+            w.u32(subscription.id)
+                .array(&available, |w, &sequence| {
+                    w.u32(sequence);
+                })
+                .bool(more)
+                .bytes(&message)
+                .array(&request.results, |w, &code| {
+                    w.u32(code);
+                })
+                .no_diagnostics();
+            self.channel.respond(
+                request.request_id,
+                request.handle,
+                ids::PUBLISH_RESPONSE,
+                status::GOOD,
+                &w.data,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Closes the sessions whose timeout elapsed and samples the monitored items of the
+    /// subscriptions whose publishing interval elapsed.
+    fn tick(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while let Some(i) = self
+            .sessions
+            .iter()
+            .position(|s| s.last_used + s.timeout <= now)
+        {
+            for request in self.sessions.remove(i).publish {
+                self.channel.fault(
+                    request.request_id,
+                    request.handle,
+                    status::BAD_SESSION_CLOSED,
+                )?;
+            }
+        }
+        let due = self
+            .sessions
+            .iter()
+            .flat_map(|s| &s.subscriptions)
+            .any(|s| s.next <= now);
+        if !due {
+            return Ok(());
+        }
+        let image = self.driver.read(0, PROCESS_IMAGE_SIZE).ok();
+        let time = types::now();
+        for session in 0..self.sessions.len() {
+            let s = &mut self.sessions[session];
+            let waiting = !s.publish.is_empty();
+            for subscription in &mut s.subscriptions {
+                if subscription.next > now {
+                    continue;
+                }
+                if !waiting {
+                    subscription.unanswered += 1;
+                }
+                subscription.next += subscription.interval;
+                if subscription.next < now {
+                    subscription.next = now + subscription.interval;
+                }
+                for item in &mut subscription.items {
+                    if item.mode == 0 {
+                        continue;
+                    }
+                    let value = stamp(
+                        self.space.read(item.node, item.attribute, image.as_deref()),
+                        item.attribute,
+                        item.timestamps,
+                        time,
+                    );
+                    if !item.changed(&value) {
+                        continue;
+                    }
+                    item.last = Some(value.clone());
+                    if item.mode == 2 {
+                        let handle = item.client_handle;
+                        subscription.pending.retain(|(h, _)| *h != handle);
+                        subscription.pending.push((handle, value));
+                    }
+                }
+                if !subscription.enabled || subscription.pending.is_empty() {
+                    subscription.idle += 1;
+                    if subscription.idle >= subscription.keep_alive {
+                        subscription.keep_alive_due = true;
+                    }
+                }
+            }
+            // the client stopped publishing for the lifetime of the subscription
+            s.subscriptions.retain(|s| s.unanswered < s.lifetime);
+            self.flush(session)?;
+        }
+        Ok(())
+    }
+}
+
+/// The monitoring parameters of a monitored item.
+struct Parameters {
+    client_handle: u32,
+    trigger: u32,
+    deadband: Option<f64>,
+    /// The result of the filter.
+    status: u32,
+}
+
+impl Parameters {
+    fn decode(r: &mut Reader) -> io::Result<Parameters> {
+        let client_handle = r.u32()?;
+        r.f64()?;
+        let (filter, body) = r.extension_object()?;
+        r.u32()?;
+        r.bool()?;
+        let mut parameters = Parameters {
+            client_handle,
+            trigger: 1,
+            deadband: None,
+            status: status::GOOD,
+        };
+        if filter.is_null() {
+            return Ok(parameters);
+        }
+        if filter != NodeId::ns0(ids::DATA_CHANGE_FILTER) {
+            parameters.status = status::BAD_MONITORED_ITEM_FILTER_UNSUPPORTED;
+            return Ok(parameters);
+        }
+        let mut r = Reader::new(&body);
+        parameters.trigger = r.u32()?.min(2);
+        match (r.u32()?, r.f64()?) {
+            (0, _) => {}
+            (1, deadband) => parameters.deadband = Some(deadband),
+            // percent deadbands need the range of the variable
+            _ => parameters.status = status::BAD_MONITORED_ITEM_FILTER_UNSUPPORTED,
+        }
+        Ok(parameters)
+    }
+}
+
+fn subscription(session: &mut Session, id: u32) -> Option<&mut Subscription> {
+    session.subscriptions.iter_mut().find(|s| s.id == id)
+}
+
+/// Adds the timestamps selected by @timestamps to a @value of the Value attribute.
+fn stamp(mut value: DataValue, attribute: u32, timestamps: u32, time: i64) -> DataValue {
+    if attribute == attributes::VALUE {
+        if timestamps == 0 || timestamps == 2 {
+            value.source_timestamp = Some(time);
+        }
+        if timestamps == 1 || timestamps == 2 {
+            value.server_timestamp = Some(time);
+        }
+    }
+    value
+}
+
+// nonces and session tokens, there is no security to derive them from
+fn random_bytes(length: usize) -> Vec<u8> {
+    let state = RandomState::new();
+    (0..length.div_ceil(8))
+        .flat_map(|i| {
+            let mut hasher = state.build_hasher();
+            hasher.write_usize(i);
+            hasher.finish().to_le_bytes()
+        })
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+    use crate::value::Value;
+
+    /// A client of just enough of the protocol to test the server.
+    struct Client {
+        stream: TcpStream,
+        channel: u32,
+        token: NodeId,
+        request_id: u32,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> Client {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let url = format!("opc.tcp://{}", addr);
+            let mut w = Writer::new();
+            w.bytes(b"HELF")
+                .u32(32 + url.len() as u32)
+                .u32(0)
+                .u32(BUFFER_SIZE)
+                .u32(BUFFER_SIZE)
+                .u32(0)
+                .u32(0)
+                .string(&url);
+            stream.write_all(&w.data).unwrap();
+            let mut client = Client {
+                stream,
+                channel: 0,
+                token: NodeId::NULL,
+                request_id: 0,
+            };
+            assert_eq!(&client.receive()[..4], b"ACKF");
+
+            let mut w = Writer::new();
+            w.u32(0)
+                .string(SECURITY_POLICY_NONE)
+                .byte_string(None)
+                .byte_string(None)
+                .u32(1)
+                .u32(1);
+            client.header(&mut w, ids::OPEN_SECURE_CHANNEL_REQUEST);
+            w.u32(0).u32(0).u32(1).byte_string(None).u32(60_000);
+            client.send(b"OPN", &w.data);
+            let response = client.receive();
+            let mut r = Reader::new(&response[8..]);
+            client.channel = r.u32().unwrap();
+            client
+        }
+
+        fn header(&self, w: &mut Writer, type_id: u32) {
+            w.node_id(&NodeId::ns0(type_id))
+                .node_id(&self.token)
+                .i64(now())
+                .u32(self.request_id)
+                .u32(0)
+                .null_string()
+                .u32(10_000)
+                .null_extension_object();
+        }
+
+        fn send(&mut self, kind: &[u8; 3], message: &[u8]) {
+            let mut w = Writer::new();
+            w.bytes(kind)
+                .u8(b'F')
+                .u32(8 + message.len() as u32)
+                .bytes(message);
+            self.stream.write_all(&w.data).unwrap();
+        }
+
+        fn receive(&mut self) -> Vec<u8> {
+            let mut header = [0; 8];
+            self.stream.read_exact(&mut header).unwrap();
+            let size = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+            let mut chunk = header.to_vec();
+            chunk.resize(size, 0);
+            self.stream.read_exact(&mut chunk[8..]).unwrap();
+            chunk
+        }
+
+        /// Calls the service @type_id, returning the response type, status and body.
+        fn call<F: FnOnce(&mut Writer)>(&mut self, type_id: u32, body: F) -> (u32, u32, Vec<u8>) {
+            self.request_id += 1;
+            let mut w = Writer::new();
+            w.u32(self.channel)
+                .u32(1)
+                .u32(self.request_id)
+                .u32(self.request_id);
+            self.header(&mut w, type_id);
+            body(&mut w);
+            self.send(b"MSG", &w.data);
+            self.response()
+        }
+
+        fn response(&mut self) -> (u32, u32, Vec<u8>) {
+            let chunk = self.receive();
+            assert_eq!(&chunk[..4], b"MSGF");
+            let mut r = Reader::new(&chunk[8..]);
+            for _ in 0..4 {
+                r.u32().unwrap();
+            }
+            let type_id = match r.node_id().unwrap() {
+                NodeId::Numeric(0, id) => id,
+                id => panic!("unexpected response {:?}", id),
+            };
+            r.i64().unwrap();
+            r.u32().unwrap();
+            let result = r.u32().unwrap();
+            r.diagnostic_info().unwrap();
+            r.array(Reader::string).unwrap();
+            r.extension_object().unwrap();
+            (type_id, result, r.rest().to_vec())
+        }
+
+        fn read(&mut self, name: &str) -> DataValue {
+            let (_, _, body) = self.call(ids::READ_REQUEST, |w| {
+                w.f64(0.0).u32(2).i32(1);
+                w.node_id(&NodeId::String(1, name.to_owned()))
+                    .u32(attributes::VALUE)
+                    .null_string()
+                    .qualified_name(0, "");
+            });
+            let mut r = Reader::new(&body);
+            r.array(Reader::data_value).unwrap().remove(0)
+        }
+    }
+
+    /// The client handles and values of the data change notification of a Publish response.
+    fn data_changes(body: &[u8]) -> Vec<(u32, Option<Variant>)> {
+        let mut r = Reader::new(body);
+        r.u32().unwrap();
+        r.array(Reader::u32).unwrap();
+        r.bool().unwrap();
+        r.u32().unwrap();
+        r.i64().unwrap();
+        let notifications = r.array(Reader::extension_object).unwrap();
+        let mut changes = Vec::new();
+        for (type_id, body) in notifications {
+            assert_eq!(type_id, NodeId::ns0(ids::DATA_CHANGE_NOTIFICATION));
+            let mut r = Reader::new(&body);
+            changes.extend(r.array(|r| Ok((r.u32()?, r.data_value()?.value))).unwrap());
+        }
+        changes
+    }
+
+    /// The status of the ERR chunk the server answers @chunks with.
+    fn refusal(stream: &mut TcpStream, chunks: &[u8]) -> u32 {
+        stream.write_all(chunks).unwrap();
+        let mut chunk = [0; 12];
+        stream.read_exact(&mut chunk).unwrap();
+        assert_eq!(&chunk[..4], b"ERRF");
+        u32::from_le_bytes(chunk[8..].try_into().unwrap())
+    }
+
+    #[test]
+    fn hostile_chunks() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Arc::new(Emulator::from_config(config.clone()).unwrap());
+        let server = OpcUaServer::bind("127.0.0.1:0", emulator, &config).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        let refused = |chunks: &[u8]| refusal(&mut TcpStream::connect(addr).unwrap(), chunks);
+
+        // sizes shorter than the header or larger than the buffer
+        assert_eq!(
+            refused(b"HELF\x04\0\0\0"),
+            status::BAD_TCP_MESSAGE_TOO_LARGE
+        );
+        assert_eq!(
+            refused(b"HELF\xff\xff\xff\xff"),
+            status::BAD_TCP_MESSAGE_TOO_LARGE
+        );
+        // a truncated hello and one with an endpoint url longer than the chunk
+        assert_eq!(
+            refused(b"HELF\x0c\0\0\0\0\0\0\0"),
+            status::BAD_DECODING_ERROR
+        );
+        let mut w = Writer::new();
+        w.bytes(b"HELF")
+            .u32(32)
+            .u32(0)
+            .u32(BUFFER_SIZE)
+            .u32(BUFFER_SIZE);
+        w.u32(0).u32(0).i32(i32::MAX);
+        assert_eq!(refused(&w.data), status::BAD_DECODING_ERROR);
+        // a message before the hello
+        assert_eq!(
+            refused(b"MSGF\x08\0\0\0"),
+            status::BAD_TCP_MESSAGE_TYPE_INVALID
+        );
+
+        // a truncated message header, an unknown channel and an unknown chunk type
+        let mut client = Client::connect(addr);
+        assert_eq!(
+            refusal(&mut client.stream, b"MSGF\x0a\0\0\0\0\0"),
+            status::BAD_DECODING_ERROR
+        );
+        let mut client = Client::connect(addr);
+        let mut w = Writer::new();
+        w.bytes(b"MSGF")
+            .u32(24)
+            .u32(client.channel + 1)
+            .u32(1)
+            .u32(1)
+            .u32(1);
+        assert_eq!(
+            refusal(&mut client.stream, &w.data),
+            status::BAD_SECURE_CHANNEL_ID_INVALID
+        );
+        let mut client = Client::connect(addr);
+        let mut w = Writer::new();
+        w.bytes(b"MSGX")
+            .u32(24)
+            .u32(client.channel)
+            .u32(1)
+            .u32(1)
+            .u32(1);
+        assert_eq!(
+            refusal(&mut client.stream, &w.data),
+            status::BAD_TCP_MESSAGE_TYPE_INVALID
+        );
+
+        // a complete chunk whose message is truncated is a fault of the request only
+        let mut client = Client::connect(addr);
+        let mut w = Writer::new();
+        w.u32(client.channel).u32(1).u32(1).u32(1).u8(1);
+        client.send(b"MSG", &w.data);
+        let chunk = client.receive();
+        assert_eq!(&chunk[..4], b"MSGF");
+        let mut r = Reader::new(&chunk[24..]);
+        assert_eq!(r.node_id().unwrap(), NodeId::ns0(ids::SERVICE_FAULT));
+        r.i64().unwrap();
+        r.u32().unwrap();
+        assert_eq!(r.u32().unwrap(), status::BAD_DECODING_ERROR);
+    }
+
+    #[test]
+    fn serve_client() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Arc::new(Emulator::from_config(config.clone()).unwrap());
+        let server = OpcUaServer::bind("127.0.0.1:0", Arc::clone(&emulator), &config)
+            .unwrap()
+            .min_interval(Duration::from_millis(10));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        let mut client = Client::connect(addr);
+
+        let (_, result, _) = client.call(ids::READ_REQUEST, |w| {
+            w.f64(0.0).u32(0).i32(0);
+        });
+        assert_eq!(result, status::BAD_SESSION_ID_INVALID);
+
+        let (type_id, _, body) = client.call(ids::CREATE_SESSION_REQUEST, |w| {
+            w.null_string().null_string().localized_text("").u32(1);
+            w.null_string().null_string().i32(0);
+            w.null_string().null_string().string("test");
+            w.byte_string(None).byte_string(None).f64(60_000.0).u32(0);
+        });
+        assert_eq!(type_id, ids::CREATE_SESSION_RESPONSE);
+        let mut r = Reader::new(&body);
+        r.node_id().unwrap();
+        client.token = r.node_id().unwrap();
+        let (_, result, _) = client.call(ids::ACTIVATE_SESSION_REQUEST, |w| {
+            w.null_string().byte_string(None).i32(0).i32(0);
+            w.extension_object(ids::ANONYMOUS_IDENTITY_TOKEN, &[0xff; 4]);
+            w.null_string().byte_string(None);
+        });
+        assert_eq!(result, status::GOOD);
+
+        // browse the device folders of Objects
+        let (_, _, body) = client.call(ids::BROWSE_REQUEST, |w| {
+            w.node_id(&NodeId::NULL).i64(0).u32(0).u32(0).i32(1);
+            w.node_id(&NodeId::ns0(ids::OBJECTS_FOLDER))
+                .u32(0)
+                .node_id(&NodeId::ns0(ids::HIERARCHICAL_REFERENCES))
+                .bool(true)
+                .u32(0)
+                .u32(0x3f);
+        });
+        let mut r = Reader::new(&body);
+        r.i32().unwrap();
+        assert_eq!(r.u32().unwrap(), status::GOOD);
+        r.byte_string().unwrap();
+        let names: Vec<String> = r
+            .array(|r| {
+                r.node_id()?;
+                r.bool()?;
+                r.expanded_node_id()?;
+                let name = r.qualified_name()?.1;
+                r.localized_text()?;
+                r.u32()?;
+                r.expanded_node_id()?;
+                Ok(name)
+            })
+            .unwrap();
+        assert!(names.contains(&"RevPi DIO (32)".to_owned()));
+
+        // read an input and write an output
+        emulator.set("I_1", Value::Bool(true)).unwrap();
+        let value = client.read("I_1");
+        assert_eq!(value.value, Some(Variant::Boolean(true)));
+        assert!(value.source_timestamp.is_some() && value.server_timestamp.is_some());
+        let (_, _, body) = client.call(ids::WRITE_REQUEST, |w| {
+            w.i32(2);
+            for name in &["O_1", "I_2"] {
+                w.node_id(&NodeId::String(1, (*name).to_owned()))
+                    .u32(attributes::VALUE)
+                    .null_string()
+                    .data_value(&DataValue::value(Variant::Boolean(true)));
+            }
+        });
+        let mut r = Reader::new(&body);
+        assert_eq!(
+            r.array(Reader::u32).unwrap(),
+            vec![status::GOOD, status::BAD_NOT_WRITABLE]
+        );
+        assert_eq!(
+            emulator.value("O_1", &Value::Bool(false)).unwrap(),
+            Value::Bool(true)
+        );
+
+        // subscribe to changes of I_1
+        let (_, _, body) = client.call(ids::CREATE_SUBSCRIPTION_REQUEST, |w| {
+            w.f64(10.0).u32(300).u32(100).u32(0).bool(true).u8(0);
+        });
+        let subscription = Reader::new(&body).u32().unwrap();
+        let (_, _, body) = client.call(ids::CREATE_MONITORED_ITEMS_REQUEST, |w| {
+            w.u32(subscription).u32(0).i32(1);
+            w.node_id(&NodeId::String(1, "I_1".to_owned()))
+                .u32(attributes::VALUE)
+                .null_string()
+                .qualified_name(0, "")
+                .u32(2)
+                .u32(7)
+                .f64(0.0)
+                .null_extension_object()
+                .u32(1)
+                .bool(true);
+        });
+        let mut r = Reader::new(&body);
+        r.i32().unwrap();
+        assert_eq!(r.u32().unwrap(), status::GOOD);
+
+        // skips keep-alives
+        let publish = |client: &mut Client| loop {
+            let (type_id, _, body) = client.call(ids::PUBLISH_REQUEST, |w| {
+                w.i32(0);
+            });
+            assert_eq!(type_id, ids::PUBLISH_RESPONSE);
+            let changes = data_changes(&body);
+            if !changes.is_empty() {
+                return changes;
+            }
+        };
+        assert_eq!(
+            publish(&mut client),
+            vec![(7, Some(Variant::Boolean(true)))]
+        );
+        emulator.set("I_1", Value::Bool(false)).unwrap();
+        assert_eq!(
+            publish(&mut client),
+            vec![(7, Some(Variant::Boolean(false)))]
+        );
+
+        // a subscription without Publish requests for its lifetime is deleted
+        let (_, _, body) = client.call(ids::CREATE_SUBSCRIPTION_REQUEST, |w| {
+            w.f64(10.0).u32(3).u32(1).u32(0).bool(true).u8(0);
+        });
+        let expiring = Reader::new(&body).u32().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let (_, _, body) = client.call(ids::SET_PUBLISHING_MODE_REQUEST, |w| {
+            w.bool(true).i32(2).u32(subscription).u32(expiring);
+        });
+        let mut r = Reader::new(&body);
+        assert_eq!(
+            r.array(Reader::u32).unwrap(),
+            vec![status::GOOD, status::BAD_SUBSCRIPTION_ID_INVALID]
+        );
+
+        // the sessions and subscriptions of a connection are limited
+        for _ in 1..MAX_SUBSCRIPTIONS {
+            let (type_id, _, _) = client.call(ids::CREATE_SUBSCRIPTION_REQUEST, |w| {
+                w.f64(1000.0).u32(300).u32(100).u32(0).bool(false).u8(0);
+            });
+            assert_eq!(type_id, ids::CREATE_SUBSCRIPTION_RESPONSE);
+        }
+        let (_, result, _) = client.call(ids::CREATE_SUBSCRIPTION_REQUEST, |w| {
+            w.f64(1000.0).u32(300).u32(100).u32(0).bool(false).u8(0);
+        });
+        assert_eq!(result, status::BAD_TOO_MANY_SUBSCRIPTIONS);
+        let create_session = |client: &mut Client| {
+            client.call(ids::CREATE_SESSION_REQUEST, |w| {
+                w.null_string().null_string().localized_text("").u32(1);
+                w.null_string().null_string().i32(0);
+                w.null_string().null_string().string("test");
+                w.byte_string(None).byte_string(None).f64(60_000.0).u32(0);
+            })
+        };
+        for _ in 1..MAX_SESSIONS {
+            assert_eq!(create_session(&mut client).0, ids::CREATE_SESSION_RESPONSE);
+        }
+        assert_eq!(create_session(&mut client).1, status::BAD_TOO_MANY_SESSIONS);
+        let channel = client.channel.to_le_bytes();
+        client.send(b"CLO", &channel);
+    }
+}
@@ -0,0 +1,687 @@
+//! The address space: the standard nodes a client expects and one folder per device of the
+//! configuration with a variable node per process image variable.
+
+use super::types::{attributes, ids, now, status, DataValue, NodeId, Variant, Writer};
+use crate::config::{Config, Direction};
+use crate::driver::Driver;
+use crate::value::{Value, Variable, VariableType};
+use std::collections::HashMap;
+use std::io;
+
+/// The URI of namespace 1, which holds the devices and variables.
+pub(crate) const NAMESPACE_URI: &str = "urn:picontrol:revpi";
+
+/// The class of a node, with its encoded value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NodeClass {
+    Object = 1,
+    Variable = 2,
+    ObjectType = 8,
+    VariableType = 16,
+    ReferenceType = 32,
+    DataType = 64,
+}
+
+/// Where the value of a variable node comes from.
+#[derive(Debug, Clone)]
+pub(crate) enum Source {
+    Constant(Variant),
+    Process { variable: Variable, writable: bool },
+    CurrentTime,
+    State,
+    ServerStatus,
+}
+
+/// The attributes of variable nodes.
+#[derive(Debug, Clone)]
+pub(crate) struct VariableAttributes {
+    data_type: u32,
+    value_rank: i32,
+    source: Source,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub id: NodeId,
+    pub class: NodeClass,
+    pub browse_name: (u16, String),
+    pub display_name: String,
+    description: String,
+    is_abstract: bool,
+    value: Option<VariableAttributes>,
+}
+
+/// A reference of a node as returned by Browse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Reference {
+    pub kind: u32,
+    pub forward: bool,
+    pub target: usize,
+}
+
+// The standard nodes with their browse name and whether the type is abstract.
+#[rustfmt::skip]
+const STANDARD_NODES: &[(u32, NodeClass, &str, bool)] = &[
+    (84, NodeClass::Object, "Root", false),
+    (85, NodeClass::Object, "Objects", false),
+    (86, NodeClass::Object, "Types", false),
+    (87, NodeClass::Object, "Views", false),
+    (88, NodeClass::Object, "ObjectTypes", false),
+    (89, NodeClass::Object, "VariableTypes", false),
+    (90, NodeClass::Object, "DataTypes", false),
+    (91, NodeClass::Object, "ReferenceTypes", false),
+    (2253, NodeClass::Object, "Server", false),
+    (58, NodeClass::ObjectType, "BaseObjectType", false),
+    (61, NodeClass::ObjectType, "FolderType", false),
+    (2004, NodeClass::ObjectType, "ServerType", false),
+    (62, NodeClass::VariableType, "BaseVariableType", true),
+    (63, NodeClass::VariableType, "BaseDataVariableType", false),
+    (68, NodeClass::VariableType, "PropertyType", false),
+    (2138, NodeClass::VariableType, "ServerStatusType", false),
+    (24, NodeClass::DataType, "BaseDataType", true),
+    (1, NodeClass::DataType, "Boolean", false),
+    (26, NodeClass::DataType, "Number", true),
+    (27, NodeClass::DataType, "Integer", true),
+    (28, NodeClass::DataType, "UInteger", true),
+    (2, NodeClass::DataType, "SByte", false),
+    (3, NodeClass::DataType, "Byte", false),
+    (4, NodeClass::DataType, "Int16", false),
+    (5, NodeClass::DataType, "UInt16", false),
+    (6, NodeClass::DataType, "Int32", false),
+    (7, NodeClass::DataType, "UInt32", false),
+    (10, NodeClass::DataType, "Float", false),
+    (12, NodeClass::DataType, "String", false),
+    (13, NodeClass::DataType, "DateTime", false),
+    (294, NodeClass::DataType, "UtcTime", false),
+    (22, NodeClass::DataType, "Structure", true),
+    (29, NodeClass::DataType, "Enumeration", true),
+    (862, NodeClass::DataType, "ServerStatusDataType", false),
+    (852, NodeClass::DataType, "ServerState", false),
+    (31, NodeClass::ReferenceType, "References", true),
+    (32, NodeClass::ReferenceType, "NonHierarchicalReferences", true),
+    (33, NodeClass::ReferenceType, "HierarchicalReferences", true),
+    (34, NodeClass::ReferenceType, "HasChild", true),
+    (35, NodeClass::ReferenceType, "Organizes", false),
+    (40, NodeClass::ReferenceType, "HasTypeDefinition", false),
+    (44, NodeClass::ReferenceType, "Aggregates", true),
+    (45, NodeClass::ReferenceType, "HasSubtype", false),
+    (46, NodeClass::ReferenceType, "HasProperty", false),
+    (47, NodeClass::ReferenceType, "HasComponent", false),
+];
+
+// The standard variables with their browse name, data type and value rank.
+#[rustfmt::skip]
+const STANDARD_VARIABLES: &[(u32, &str, u32, i32)] = &[
+    (ids::SERVER_SERVER_ARRAY, "ServerArray", ids::STRING, 1),
+    (ids::SERVER_NAMESPACE_ARRAY, "NamespaceArray", ids::STRING, 1),
+    (ids::SERVER_SERVER_STATUS, "ServerStatus", ids::SERVER_STATUS_DATA_TYPE, -1),
+    (ids::SERVER_SERVER_STATUS_CURRENT_TIME, "CurrentTime", 294, -1),
+    (ids::SERVER_SERVER_STATUS_STATE, "State", 852, -1),
+];
+
+// The references between standard nodes as (source, reference type, target).
+#[rustfmt::skip]
+const STANDARD_REFERENCES: &[(u32, u32, u32)] = &[
+    (84, ids::ORGANIZES, 85), (84, ids::ORGANIZES, 86), (84, ids::ORGANIZES, 87),
+    (86, ids::ORGANIZES, 88), (86, ids::ORGANIZES, 89),
+    (86, ids::ORGANIZES, 90), (86, ids::ORGANIZES, 91),
+    (85, ids::ORGANIZES, ids::SERVER),
+    (ids::SERVER, ids::HAS_PROPERTY, ids::SERVER_SERVER_ARRAY),
+    (ids::SERVER, ids::HAS_PROPERTY, ids::SERVER_NAMESPACE_ARRAY),
+    (ids::SERVER, ids::HAS_COMPONENT, ids::SERVER_SERVER_STATUS),
+    (ids::SERVER_SERVER_STATUS, ids::HAS_COMPONENT, ids::SERVER_SERVER_STATUS_CURRENT_TIME),
+    (ids::SERVER_SERVER_STATUS, ids::HAS_COMPONENT, ids::SERVER_SERVER_STATUS_STATE),
+    (88, ids::ORGANIZES, 58), (58, ids::HAS_SUBTYPE, 61), (58, ids::HAS_SUBTYPE, 2004),
+    (89, ids::ORGANIZES, 62), (62, ids::HAS_SUBTYPE, 63), (62, ids::HAS_SUBTYPE, 68),
+    (63, ids::HAS_SUBTYPE, 2138),
+    (90, ids::ORGANIZES, 24),
+    (24, ids::HAS_SUBTYPE, 1), (24, ids::HAS_SUBTYPE, 26), (24, ids::HAS_SUBTYPE, 12),
+    (24, ids::HAS_SUBTYPE, 13), (24, ids::HAS_SUBTYPE, 22), (24, ids::HAS_SUBTYPE, 29),
+    (26, ids::HAS_SUBTYPE, 27), (26, ids::HAS_SUBTYPE, 28), (26, ids::HAS_SUBTYPE, 10),
+    (27, ids::HAS_SUBTYPE, 2), (27, ids::HAS_SUBTYPE, 4), (27, ids::HAS_SUBTYPE, 6),
+    (28, ids::HAS_SUBTYPE, 3), (28, ids::HAS_SUBTYPE, 5), (28, ids::HAS_SUBTYPE, 7),
+    (13, ids::HAS_SUBTYPE, 294), (22, ids::HAS_SUBTYPE, 862), (29, ids::HAS_SUBTYPE, 852),
+    (91, ids::ORGANIZES, 31),
+    (31, ids::HAS_SUBTYPE, 32), (31, ids::HAS_SUBTYPE, 33),
+    (32, ids::HAS_SUBTYPE, 40), (33, ids::HAS_SUBTYPE, 34), (33, ids::HAS_SUBTYPE, 35),
+    (34, ids::HAS_SUBTYPE, 44), (34, ids::HAS_SUBTYPE, 45),
+    (44, ids::HAS_SUBTYPE, 46), (44, ids::HAS_SUBTYPE, 47),
+    (84, ids::HAS_TYPE_DEFINITION, 61), (85, ids::HAS_TYPE_DEFINITION, 61),
+    (86, ids::HAS_TYPE_DEFINITION, 61), (87, ids::HAS_TYPE_DEFINITION, 61),
+    (88, ids::HAS_TYPE_DEFINITION, 61), (89, ids::HAS_TYPE_DEFINITION, 61),
+    (90, ids::HAS_TYPE_DEFINITION, 61), (91, ids::HAS_TYPE_DEFINITION, 61),
+    (ids::SERVER, ids::HAS_TYPE_DEFINITION, ids::SERVER_TYPE),
+    (ids::SERVER_SERVER_ARRAY, ids::HAS_TYPE_DEFINITION, ids::PROPERTY_TYPE),
+    (ids::SERVER_NAMESPACE_ARRAY, ids::HAS_TYPE_DEFINITION, ids::PROPERTY_TYPE),
+    (ids::SERVER_SERVER_STATUS, ids::HAS_TYPE_DEFINITION, ids::SERVER_STATUS_TYPE),
+    (ids::SERVER_SERVER_STATUS_CURRENT_TIME, ids::HAS_TYPE_DEFINITION, ids::BASE_DATA_VARIABLE_TYPE),
+    (ids::SERVER_SERVER_STATUS_STATE, ids::HAS_TYPE_DEFINITION, ids::BASE_DATA_VARIABLE_TYPE),
+];
+
+/// AddressSpace holds the nodes and their references.
+#[derive(Debug, Clone)]
+pub(crate) struct AddressSpace {
+    nodes: Vec<Node>,
+    index: HashMap<NodeId, usize>,
+    /// (source, reference type, target)
+    references: Vec<(usize, u32, usize)>,
+    application_uri: String,
+    start_time: i64,
+}
+
+impl AddressSpace {
+    /// Builds the address space of the devices and variables of @config.
+    ///
+    /// A device is the folder `ns=1;s=<name> (<position>)` and a variable `ns=1;s=<name>`.
+    pub(crate) fn new(config: &Config, application_uri: &str) -> io::Result<AddressSpace> {
+        let mut space = AddressSpace {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            references: Vec::new(),
+            application_uri: application_uri.to_owned(),
+            start_time: now(),
+        };
+        for &(id, class, name, is_abstract) in STANDARD_NODES {
+            space.add(Node {
+                id: NodeId::ns0(id),
+                class,
+                browse_name: (0, name.to_owned()),
+                display_name: name.to_owned(),
+                description: String::new(),
+                is_abstract,
+                value: None,
+            })?;
+        }
+        for &(id, name, data_type, value_rank) in STANDARD_VARIABLES {
+            let source = match id {
+                ids::SERVER_SERVER_ARRAY => Source::Constant(strings(&[application_uri])),
+                ids::SERVER_NAMESPACE_ARRAY => {
+                    Source::Constant(strings(&["http://opcfoundation.org/UA/", NAMESPACE_URI]))
+                }
+                ids::SERVER_SERVER_STATUS => Source::ServerStatus,
+                ids::SERVER_SERVER_STATUS_CURRENT_TIME => Source::CurrentTime,
+                _ => Source::State,
+            };
+            space.add(Node {
+                id: NodeId::ns0(id),
+                class: NodeClass::Variable,
+                browse_name: (0, name.to_owned()),
+                display_name: name.to_owned(),
+                description: String::new(),
+                is_abstract: false,
+                value: Some(VariableAttributes {
+                    data_type,
+                    value_rank,
+                    source,
+                }),
+            })?;
+        }
+        for &(source, kind, target) in STANDARD_REFERENCES {
+            space.reference(&NodeId::ns0(source), kind, &NodeId::ns0(target));
+        }
+
+        let objects = NodeId::ns0(ids::OBJECTS_FOLDER);
+        for device in &config.devices {
+            let name = format!("{} ({})", device.name, device.position);
+            let folder = NodeId::String(1, name.clone());
+            space.add(Node {
+                id: folder.clone(),
+                class: NodeClass::Object,
+                browse_name: (1, name.clone()),
+                display_name: name,
+                description: device.comment.clone(),
+                is_abstract: false,
+                value: None,
+            })?;
+            space.reference(&objects, ids::ORGANIZES, &folder);
+            space.reference(
+                &folder,
+                ids::HAS_TYPE_DEFINITION,
+                &NodeId::ns0(ids::FOLDER_TYPE),
+            );
+            for entry in &device.entries {
+                let variable = entry.variable()?;
+                let id = NodeId::String(1, entry.name.clone());
+                space.add(Node {
+                    id: id.clone(),
+                    class: NodeClass::Variable,
+                    browse_name: (1, entry.name.clone()),
+                    display_name: entry.name.clone(),
+                    description: entry.comment.clone(),
+                    is_abstract: false,
+                    value: Some(VariableAttributes {
+                        data_type: data_type(variable.kind),
+                        value_rank: -1,
+                        source: Source::Process {
+                            variable,
+                            writable: entry.direction == Direction::Output,
+                        },
+                    }),
+                })?;
+                space.reference(&folder, ids::ORGANIZES, &id);
+                space.reference(
+                    &id,
+                    ids::HAS_TYPE_DEFINITION,
+                    &NodeId::ns0(ids::BASE_DATA_VARIABLE_TYPE),
+                );
+            }
+        }
+        Ok(space)
+    }
+
+    fn add(&mut self, node: Node) -> io::Result<()> {
+        if self.index.contains_key(&node.id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("duplicate OPC UA node {}", node.display_name),
+            ));
+        }
+        self.index.insert(node.id.clone(), self.nodes.len());
+        self.nodes.push(node);
+        Ok(())
+    }
+
+    fn reference(&mut self, source: &NodeId, kind: u32, target: &NodeId) {
+        self.references
+            .push((self.index[source], kind, self.index[target]));
+    }
+
+    /// Makes the variables of the process image read-only, outputs included.
+    pub(crate) fn read_only(&mut self) {
+        for node in &mut self.nodes {
+            if let Some(VariableAttributes {
+                source: Source::Process { writable, .. },
+                ..
+            }) = &mut node.value
+            {
+                *writable = false;
+            }
+        }
+    }
+
+    /// The index of the node @id.
+    pub(crate) fn find(&self, id: &NodeId) -> Option<usize> {
+        self.index.get(id).copied()
+    }
+
+    pub(crate) fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    /// Whether the reference type @kind is @base or, with @subtypes, one of its subtypes.
+    pub(crate) fn is_reference_type(&self, kind: u32, base: &NodeId, subtypes: bool) -> bool {
+        if base.is_null() || *base == NodeId::ns0(kind) {
+            return true;
+        }
+        if !subtypes {
+            return false;
+        }
+        let mut current = kind;
+        // walks up the HasSubtype references to References
+        while let Some(&(parent, _, _)) = self.references.iter().find(|&&(_, k, target)| {
+            k == ids::HAS_SUBTYPE && self.nodes[target].id == NodeId::ns0(current)
+        }) {
+            match self.nodes[parent].id {
+                NodeId::Numeric(0, id) => current = id,
+                _ => return false,
+            }
+            if *base == NodeId::ns0(current) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The references of the node @index in @direction (0 forward, 1 inverse, 2 both) of the
+    /// type @kind, to nodes of the classes in @class_mask (0 for all).
+    pub(crate) fn references(
+        &self,
+        index: usize,
+        direction: u32,
+        kind: &NodeId,
+        subtypes: bool,
+        class_mask: u32,
+    ) -> Vec<Reference> {
+        let mut found = Vec::new();
+        for &(source, k, target) in &self.references {
+            if !self.is_reference_type(k, kind, subtypes) {
+                continue;
+            }
+            let reference = if source == index && direction != 1 {
+                Reference {
+                    kind: k,
+                    forward: true,
+                    target,
+                }
+            } else if target == index && direction != 0 {
+                Reference {
+                    kind: k,
+                    forward: false,
+                    target: source,
+                }
+            } else {
+                continue;
+            };
+            let class = self.nodes[reference.target].class as u32;
+            if class_mask == 0 || class_mask & class != 0 {
+                found.push(reference);
+            }
+        }
+        found
+    }
+
+    /// Encodes @reference as a ReferenceDescription with the fields of @result_mask.
+    pub(crate) fn encode_reference(&self, w: &mut Writer, reference: &Reference, result_mask: u32) {
+        let node = &self.nodes[reference.target];
+        let type_definition = self
+            .references
+            .iter()
+            .find(|&&(source, k, _)| source == reference.target && k == ids::HAS_TYPE_DEFINITION)
+            .map(|&(_, _, target)| self.nodes[target].id.clone());
+        if result_mask & 0x01 != 0 {
+            w.node_id(&NodeId::ns0(reference.kind));
+        } else {
+            w.node_id(&NodeId::NULL);
+        }
+        w.bool(reference.forward).node_id(&node.id);
+        if result_mask & 0x08 != 0 {
+            w.qualified_name(node.browse_name.0, &node.browse_name.1);
+        } else {
+            w.qualified_name(0, "");
+        }
+        w.localized_text(if result_mask & 0x10 != 0 {
+            &node.display_name
+        } else {
+            ""
+        });
+        w.u32(if result_mask & 0x04 != 0 {
+            node.class as u32
+        } else {
+            0
+        });
+        match type_definition {
+            Some(id) if result_mask & 0x20 != 0 => w.node_id(&id),
+            _ => w.node_id(&NodeId::NULL),
+        };
+    }
+
+    /// The targets of the hierarchical references of type @kind from @index whose browse name
+    /// is @name.
+    pub(crate) fn children(
+        &self,
+        index: usize,
+        kind: &NodeId,
+        subtypes: bool,
+        inverse: bool,
+        name: &(u16, String),
+    ) -> Vec<usize> {
+        let direction = if inverse { 1 } else { 0 };
+        self.references(index, direction, kind, subtypes, 0)
+            .into_iter()
+            .map(|r| r.target)
+            .filter(|&target| self.nodes[target].browse_name == *name)
+            .collect()
+    }
+
+    /// Reads the @attribute of the node @index, values of the process image from @image, which
+    /// is None if it could not be read.
+    pub(crate) fn read(&self, index: usize, attribute: u32, image: Option<&[u8]>) -> DataValue {
+        let node = &self.nodes[index];
+        let is_type = !matches!(node.class, NodeClass::Object | NodeClass::Variable);
+        let value = match attribute {
+            attributes::NODE_ID => Variant::NodeId(node.id.clone()),
+            attributes::NODE_CLASS => Variant::Int32(node.class as i32),
+            attributes::BROWSE_NAME => {
+                Variant::QualifiedName(node.browse_name.0, node.browse_name.1.clone())
+            }
+            attributes::DISPLAY_NAME => Variant::LocalizedText(node.display_name.clone()),
+            attributes::DESCRIPTION => Variant::LocalizedText(node.description.clone()),
+            attributes::WRITE_MASK | attributes::USER_WRITE_MASK => Variant::UInt32(0),
+            attributes::IS_ABSTRACT if is_type => Variant::Boolean(node.is_abstract),
+            attributes::SYMMETRIC if node.class == NodeClass::ReferenceType => {
+                Variant::Boolean(false)
+            }
+            attributes::EVENT_NOTIFIER if node.class == NodeClass::Object => Variant::Byte(0),
+            _ => match &node.value {
+                Some(value) => match self.variable_attribute(value, attribute, image) {
+                    Ok(v) => v,
+                    Err(status) => return DataValue::status(status),
+                },
+                None => return DataValue::status(status::BAD_ATTRIBUTE_ID_INVALID),
+            },
+        };
+        DataValue::value(value)
+    }
+
+    fn variable_attribute(
+        &self,
+        value: &VariableAttributes,
+        attribute: u32,
+        image: Option<&[u8]>,
+    ) -> Result<Variant, u32> {
+        let writable = match value.source {
+            Source::Process { writable, .. } => writable,
+            _ => false,
+        };
+        Ok(match attribute {
+            attributes::VALUE => self.value(&value.source, image)?,
+            attributes::DATA_TYPE => Variant::NodeId(NodeId::ns0(value.data_type)),
+            attributes::VALUE_RANK => Variant::Int32(value.value_rank),
+            attributes::ARRAY_DIMENSIONS if value.value_rank == 1 => {
+                Variant::Array(7, vec![Variant::UInt32(0)])
+            }
+            attributes::ACCESS_LEVEL | attributes::USER_ACCESS_LEVEL => {
+                Variant::Byte(if writable { 3 } else { 1 })
+            }
+            attributes::MINIMUM_SAMPLING_INTERVAL => Variant::Double(0.0),
+            attributes::HISTORIZING => Variant::Boolean(false),
+            _ => return Err(status::BAD_ATTRIBUTE_ID_INVALID),
+        })
+    }
+
+    fn value(&self, source: &Source, image: Option<&[u8]>) -> Result<Variant, u32> {
+        Ok(match source {
+            Source::Constant(value) => value.clone(),
+            Source::Process { variable, .. } => {
+                let image = image.ok_or(status::BAD_DEVICE_FAILURE)?;
+                variant(
+                    variable
+                        .decode(image)
+                        .map_err(|_| status::BAD_INTERNAL_ERROR)?,
+                )
+            }
+            Source::CurrentTime => Variant::DateTime(now()),
+            // Running
+            Source::State => Variant::Int32(0),
+            Source::ServerStatus => {
+                let mut w = Writer::new();
+                w.i64(self.start_time).i64(now()).i32(0);
+                // BuildInfo
+                w.string(&self.application_uri)
+                    .string("picontrol")
+                    .string("picontrol OPC UA server")
+                    .string(env!("CARGO_PKG_VERSION"))
+                    .string(env!("CARGO_PKG_VERSION"))
+                    .i64(self.start_time);
+                w.u32(0).localized_text("");
+                Variant::ExtensionObject(NodeId::ns0(ids::SERVER_STATUS_DATA_TYPE_ENCODING), w.data)
+            }
+        })
+    }
+
+    /// Writes @value to the @attribute of the node @index, returning the status code.
+    pub(crate) fn write<D: Driver + ?Sized>(
+        &self,
+        driver: &D,
+        index: usize,
+        attribute: u32,
+        value: &DataValue,
+    ) -> u32 {
+        let variable = match &self.nodes[index].value {
+            Some(VariableAttributes {
+                source: Source::Process { variable, writable },
+                ..
+            }) if attribute == attributes::VALUE => {
+                if !writable {
+                    return status::BAD_NOT_WRITABLE;
+                }
+                variable
+            }
+            _ if attribute == 0 || attribute > attributes::HISTORIZING => {
+                return status::BAD_ATTRIBUTE_ID_INVALID
+            }
+            _ => return status::BAD_NOT_WRITABLE,
+        };
+        let converted = match &value.value {
+            Some(Variant::Boolean(on)) if variable.kind == VariableType::Bool => {
+                Ok(Value::Bool(*on))
+            }
+            Some(v) => match v.as_f64() {
+                Some(n) if variable.kind != VariableType::Bool || n == 0.0 || n == 1.0 => {
+                    variable.kind.from_f64(n)
+                }
+                _ => return status::BAD_TYPE_MISMATCH,
+            },
+            None => return status::BAD_TYPE_MISMATCH,
+        };
+        match converted {
+            Ok(v) => match variable.write(driver, v) {
+                Ok(()) => status::GOOD,
+                Err(_) => status::BAD_DEVICE_FAILURE,
+            },
+            Err(_) => status::BAD_OUT_OF_RANGE,
+        }
+    }
+}
+
+/// The OPC UA data type of @kind.
+fn data_type(kind: VariableType) -> u32 {
+    match kind {
+        VariableType::Bool => ids::BOOLEAN,
+        VariableType::I8 => ids::SBYTE,
+        VariableType::U8 => ids::BYTE,
+        VariableType::I16 => ids::INT16,
+        VariableType::U16 => ids::UINT16,
+        VariableType::I32 => ids::INT32,
+        VariableType::U32 => ids::UINT32,
+        VariableType::F32 => ids::FLOAT,
+    }
+}
+
+/// The variant of a process image @value.
+pub(crate) fn variant(value: Value) -> Variant {
+    match value {
+        Value::Bool(v) => Variant::Boolean(v),
+        Value::I8(v) => Variant::SByte(v),
+        Value::U8(v) => Variant::Byte(v),
+        Value::I16(v) => Variant::Int16(v),
+        Value::U16(v) => Variant::UInt16(v),
+        Value::I32(v) => Variant::Int32(v),
+        Value::U32(v) => Variant::UInt32(v),
+        Value::F32(v) => Variant::Float(v),
+    }
+}
+
+fn strings(values: &[&str]) -> Variant {
+    Variant::Array(
+        12,
+        values
+            .iter()
+            .map(|s| Variant::String(Some((*s).to_owned())))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn address_space() {
+        let config = Config::parse(CONFIG).unwrap();
+        let space = AddressSpace::new(&config, "urn:test").unwrap();
+        let objects = space.find(&NodeId::ns0(ids::OBJECTS_FOLDER)).unwrap();
+        let organizes = NodeId::ns0(ids::HIERARCHICAL_REFERENCES);
+        let folders: Vec<_> = space
+            .references(objects, 0, &organizes, true, 0)
+            .iter()
+            .map(|r| space.node(r.target).display_name.clone())
+            .collect();
+        assert!(folders.contains(&"RevPi DIO (32)".to_owned()));
+        assert!(folders.contains(&"Server".to_owned()));
+
+        let dio = space
+            .find(&NodeId::String(1, "RevPi DIO (32)".to_owned()))
+            .unwrap();
+        let output = (1, "O_1".to_owned());
+        let o_1 = space.children(dio, &organizes, true, false, &output);
+        assert_eq!(o_1.len(), 1);
+        let parents = space.references(o_1[0], 1, &NodeId::ns0(ids::ORGANIZES), false, 0);
+        assert_eq!(parents[0].target, dio);
+
+        let emulator = Emulator::from_config(config.clone()).unwrap();
+        emulator.set("O_1", Value::Bool(true)).unwrap();
+        let image = emulator.read(0, crate::driver::PROCESS_IMAGE_SIZE).unwrap();
+        let read = |name: &str, attribute| {
+            let index = space.find(&NodeId::String(1, name.to_owned())).unwrap();
+            space.read(index, attribute, Some(&image)).value
+        };
+        assert_eq!(read("O_1", attributes::VALUE), Some(Variant::Boolean(true)));
+        assert_eq!(
+            read("PWM_1", attributes::DATA_TYPE),
+            Some(Variant::NodeId(NodeId::ns0(ids::BYTE)))
+        );
+        assert_eq!(
+            read("I_1", attributes::ACCESS_LEVEL),
+            Some(Variant::Byte(1))
+        );
+        assert_eq!(
+            read("O_1", attributes::ACCESS_LEVEL),
+            Some(Variant::Byte(3))
+        );
+
+        let i_1 = space.find(&NodeId::String(1, "I_1".to_owned())).unwrap();
+        let pwm_1 = space.find(&NodeId::String(1, "PWM_1".to_owned())).unwrap();
+        let value = |v| DataValue::value(v);
+        let write = |index, v| space.write(&emulator, index, attributes::VALUE, &value(v));
+        assert_eq!(write(i_1, Variant::Boolean(true)), status::BAD_NOT_WRITABLE);
+        assert_eq!(write(pwm_1, Variant::Double(42.0)), status::GOOD);
+        assert_eq!(write(pwm_1, Variant::Int32(300)), status::BAD_OUT_OF_RANGE);
+        assert_eq!(
+            write(pwm_1, Variant::String(Some("1".to_owned()))),
+            status::BAD_TYPE_MISMATCH
+        );
+        assert_eq!(
+            emulator.value("PWM_1", &Value::U8(0)).unwrap(),
+            Value::U8(42)
+        );
+        assert!(space.is_reference_type(
+            ids::HAS_PROPERTY,
+            &NodeId::ns0(ids::HIERARCHICAL_REFERENCES),
+            true
+        ));
+        assert!(!space.is_reference_type(
+            ids::HAS_TYPE_DEFINITION,
+            &NodeId::ns0(ids::HIERARCHICAL_REFERENCES),
+            true
+        ));
+
+        let mut space = space;
+        space.read_only();
+        assert_eq!(
+            space.write(
+                &emulator,
+                pwm_1,
+                attributes::VALUE,
+                &value(Variant::Byte(1))
+            ),
+            status::BAD_NOT_WRITABLE
+        );
+        assert_eq!(
+            space.read(pwm_1, attributes::ACCESS_LEVEL, None).value,
+            Some(Variant::Byte(1))
+        );
+    }
+}
@@ -0,0 +1,858 @@
+//! The OPC UA binary encoding of the built-in types, Part 6 of the specification.
+
+use std::convert::TryInto;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Status codes used by the server.
+pub(crate) mod status {
+    pub const GOOD: u32 = 0;
+    pub const BAD_INTERNAL_ERROR: u32 = 0x8002_0000;
+    pub const BAD_DECODING_ERROR: u32 = 0x8007_0000;
+    pub const BAD_SERVICE_UNSUPPORTED: u32 = 0x800B_0000;
+    pub const BAD_NOTHING_TO_DO: u32 = 0x800F_0000;
+    pub const BAD_IDENTITY_TOKEN_INVALID: u32 = 0x8020_0000;
+    pub const BAD_SECURE_CHANNEL_ID_INVALID: u32 = 0x8022_0000;
+    pub const BAD_SESSION_ID_INVALID: u32 = 0x8025_0000;
+    pub const BAD_SESSION_CLOSED: u32 = 0x8026_0000;
+    pub const BAD_SESSION_NOT_ACTIVATED: u32 = 0x8027_0000;
+    pub const BAD_SUBSCRIPTION_ID_INVALID: u32 = 0x8028_0000;
+    pub const BAD_NODE_ID_UNKNOWN: u32 = 0x8034_0000;
+    pub const BAD_ATTRIBUTE_ID_INVALID: u32 = 0x8035_0000;
+    pub const BAD_INDEX_RANGE_INVALID: u32 = 0x8036_0000;
+    pub const BAD_NOT_WRITABLE: u32 = 0x803B_0000;
+    pub const BAD_OUT_OF_RANGE: u32 = 0x803C_0000;
+    pub const BAD_MONITORED_ITEM_ID_INVALID: u32 = 0x8042_0000;
+    pub const BAD_MONITORED_ITEM_FILTER_UNSUPPORTED: u32 = 0x8044_0000;
+    pub const BAD_CONTINUATION_POINT_INVALID: u32 = 0x804A_0000;
+    pub const BAD_REFERENCE_TYPE_ID_INVALID: u32 = 0x804C_0000;
+    pub const BAD_BROWSE_DIRECTION_INVALID: u32 = 0x804D_0000;
+    pub const BAD_SECURITY_MODE_REJECTED: u32 = 0x8054_0000;
+    pub const BAD_SECURITY_POLICY_REJECTED: u32 = 0x8055_0000;
+    pub const BAD_TOO_MANY_SESSIONS: u32 = 0x8056_0000;
+    pub const BAD_TIMESTAMPS_TO_RETURN_INVALID: u32 = 0x806B_0000;
+    pub const BAD_NO_MATCH: u32 = 0x806F_0000;
+    pub const BAD_TYPE_MISMATCH: u32 = 0x8074_0000;
+    pub const BAD_TOO_MANY_SUBSCRIPTIONS: u32 = 0x8077_0000;
+    pub const BAD_TOO_MANY_PUBLISH_REQUESTS: u32 = 0x8078_0000;
+    pub const BAD_NO_SUBSCRIPTION: u32 = 0x8079_0000;
+    pub const BAD_SEQUENCE_NUMBER_UNKNOWN: u32 = 0x807A_0000;
+    pub const BAD_MESSAGE_NOT_AVAILABLE: u32 = 0x807B_0000;
+    pub const BAD_TCP_MESSAGE_TYPE_INVALID: u32 = 0x807E_0000;
+    pub const BAD_TCP_MESSAGE_TOO_LARGE: u32 = 0x8080_0000;
+    pub const BAD_DEVICE_FAILURE: u32 = 0x808B_0000;
+    pub const BAD_RESPONSE_TOO_LARGE: u32 = 0x80B9_0000;
+    pub const BAD_TOO_MANY_MONITORED_ITEMS: u32 = 0x80DB_0000;
+}
+
+/// Numeric identifiers of namespace 0.
+pub(crate) mod ids {
+    // data types
+    pub const BOOLEAN: u32 = 1;
+    pub const SBYTE: u32 = 2;
+    pub const BYTE: u32 = 3;
+    pub const INT16: u32 = 4;
+    pub const UINT16: u32 = 5;
+    pub const INT32: u32 = 6;
+    pub const UINT32: u32 = 7;
+    pub const FLOAT: u32 = 10;
+    pub const STRING: u32 = 12;
+    pub const SERVER_STATUS_DATA_TYPE: u32 = 862;
+
+    // reference types
+    pub const HIERARCHICAL_REFERENCES: u32 = 33;
+    pub const ORGANIZES: u32 = 35;
+    pub const HAS_TYPE_DEFINITION: u32 = 40;
+    pub const HAS_SUBTYPE: u32 = 45;
+    pub const HAS_PROPERTY: u32 = 46;
+    pub const HAS_COMPONENT: u32 = 47;
+
+    // types
+    pub const FOLDER_TYPE: u32 = 61;
+    pub const BASE_DATA_VARIABLE_TYPE: u32 = 63;
+    pub const PROPERTY_TYPE: u32 = 68;
+    pub const SERVER_TYPE: u32 = 2004;
+    pub const SERVER_STATUS_TYPE: u32 = 2138;
+
+    // instances
+    pub const OBJECTS_FOLDER: u32 = 85;
+    pub const SERVER: u32 = 2253;
+    pub const SERVER_SERVER_ARRAY: u32 = 2254;
+    pub const SERVER_NAMESPACE_ARRAY: u32 = 2255;
+    pub const SERVER_SERVER_STATUS: u32 = 2256;
+    pub const SERVER_SERVER_STATUS_CURRENT_TIME: u32 = 2258;
+    pub const SERVER_SERVER_STATUS_STATE: u32 = 2259;
+
+    // binary encodings of structures
+    pub const ANONYMOUS_IDENTITY_TOKEN: u32 = 321;
+    pub const SERVICE_FAULT: u32 = 397;
+    pub const FIND_SERVERS_REQUEST: u32 = 422;
+    pub const FIND_SERVERS_RESPONSE: u32 = 425;
+    pub const GET_ENDPOINTS_REQUEST: u32 = 428;
+    pub const GET_ENDPOINTS_RESPONSE: u32 = 431;
+    pub const OPEN_SECURE_CHANNEL_REQUEST: u32 = 446;
+    pub const OPEN_SECURE_CHANNEL_RESPONSE: u32 = 449;
+    pub const CREATE_SESSION_REQUEST: u32 = 461;
+    pub const CREATE_SESSION_RESPONSE: u32 = 464;
+    pub const ACTIVATE_SESSION_REQUEST: u32 = 467;
+    pub const ACTIVATE_SESSION_RESPONSE: u32 = 470;
+    pub const CLOSE_SESSION_REQUEST: u32 = 473;
+    pub const CLOSE_SESSION_RESPONSE: u32 = 476;
+    pub const BROWSE_REQUEST: u32 = 527;
+    pub const BROWSE_RESPONSE: u32 = 530;
+    pub const BROWSE_NEXT_REQUEST: u32 = 533;
+    pub const BROWSE_NEXT_RESPONSE: u32 = 536;
+    pub const TRANSLATE_BROWSE_PATHS_REQUEST: u32 = 554;
+    pub const TRANSLATE_BROWSE_PATHS_RESPONSE: u32 = 557;
+    pub const READ_REQUEST: u32 = 631;
+    pub const READ_RESPONSE: u32 = 634;
+    pub const WRITE_REQUEST: u32 = 673;
+    pub const WRITE_RESPONSE: u32 = 676;
+    pub const DATA_CHANGE_FILTER: u32 = 724;
+    pub const CREATE_MONITORED_ITEMS_REQUEST: u32 = 751;
+    pub const CREATE_MONITORED_ITEMS_RESPONSE: u32 = 754;
+    pub const MODIFY_MONITORED_ITEMS_REQUEST: u32 = 763;
+    pub const MODIFY_MONITORED_ITEMS_RESPONSE: u32 = 766;
+    pub const SET_MONITORING_MODE_REQUEST: u32 = 769;
+    pub const SET_MONITORING_MODE_RESPONSE: u32 = 772;
+    pub const DELETE_MONITORED_ITEMS_REQUEST: u32 = 781;
+    pub const DELETE_MONITORED_ITEMS_RESPONSE: u32 = 784;
+    pub const CREATE_SUBSCRIPTION_REQUEST: u32 = 787;
+    pub const CREATE_SUBSCRIPTION_RESPONSE: u32 = 790;
+    pub const MODIFY_SUBSCRIPTION_REQUEST: u32 = 793;
+    pub const MODIFY_SUBSCRIPTION_RESPONSE: u32 = 796;
+    pub const SET_PUBLISHING_MODE_REQUEST: u32 = 799;
+    pub const SET_PUBLISHING_MODE_RESPONSE: u32 = 802;
+    pub const DATA_CHANGE_NOTIFICATION: u32 = 811;
+    pub const PUBLISH_REQUEST: u32 = 826;
+    pub const PUBLISH_RESPONSE: u32 = 829;
+    pub const REPUBLISH_REQUEST: u32 = 832;
+    pub const REPUBLISH_RESPONSE: u32 = 835;
+    pub const DELETE_SUBSCRIPTIONS_REQUEST: u32 = 847;
+    pub const DELETE_SUBSCRIPTIONS_RESPONSE: u32 = 850;
+    pub const SERVER_STATUS_DATA_TYPE_ENCODING: u32 = 864;
+}
+
+/// Attribute identifiers.
+pub(crate) mod attributes {
+    pub const NODE_ID: u32 = 1;
+    pub const NODE_CLASS: u32 = 2;
+    pub const BROWSE_NAME: u32 = 3;
+    pub const DISPLAY_NAME: u32 = 4;
+    pub const DESCRIPTION: u32 = 5;
+    pub const WRITE_MASK: u32 = 6;
+    pub const USER_WRITE_MASK: u32 = 7;
+    pub const IS_ABSTRACT: u32 = 8;
+    pub const SYMMETRIC: u32 = 9;
+    pub const EVENT_NOTIFIER: u32 = 12;
+    pub const VALUE: u32 = 13;
+    pub const DATA_TYPE: u32 = 14;
+    pub const VALUE_RANK: u32 = 15;
+    pub const ARRAY_DIMENSIONS: u32 = 16;
+    pub const ACCESS_LEVEL: u32 = 17;
+    pub const USER_ACCESS_LEVEL: u32 = 18;
+    pub const MINIMUM_SAMPLING_INTERVAL: u32 = 19;
+    pub const HISTORIZING: u32 = 20;
+}
+
+/// A node identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NodeId {
+    Numeric(u16, u32),
+    String(u16, String),
+    Guid(u16, [u8; 16]),
+    Opaque(u16, Vec<u8>),
+}
+
+impl NodeId {
+    /// The null node identifier.
+    pub(crate) const NULL: NodeId = NodeId::Numeric(0, 0);
+
+    /// A numeric identifier of namespace 0.
+    pub(crate) fn ns0(id: u32) -> NodeId {
+        NodeId::Numeric(0, id)
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        *self == NodeId::NULL
+    }
+}
+
+/// The value of a variant, scalars of the built-in types the server needs and arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Variant {
+    Empty,
+    Boolean(bool),
+    SByte(i8),
+    Byte(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Float(f32),
+    Double(f64),
+    String(Option<String>),
+    DateTime(i64),
+    NodeId(NodeId),
+    StatusCode(u32),
+    QualifiedName(u16, String),
+    LocalizedText(String),
+    /// Type identifier and binary body.
+    ExtensionObject(NodeId, Vec<u8>),
+    Array(u8, Vec<Variant>),
+    /// A value of a built-in type the server does not interpret.
+    Other(u8),
+}
+
+impl Variant {
+    fn type_id(&self) -> u8 {
+        match self {
+            Variant::Empty => 0,
+            Variant::Boolean(_) => 1,
+            Variant::SByte(_) => 2,
+            Variant::Byte(_) => 3,
+            Variant::Int16(_) => 4,
+            Variant::UInt16(_) => 5,
+            Variant::Int32(_) => 6,
+            Variant::UInt32(_) => 7,
+            Variant::Int64(_) => 8,
+            Variant::UInt64(_) => 9,
+            Variant::Float(_) => 10,
+            Variant::Double(_) => 11,
+            Variant::String(_) => 12,
+            Variant::DateTime(_) => 13,
+            Variant::NodeId(_) => 17,
+            Variant::StatusCode(_) => 19,
+            Variant::QualifiedName(..) => 20,
+            Variant::LocalizedText(_) => 21,
+            Variant::ExtensionObject(..) => 22,
+            Variant::Array(type_id, _) | Variant::Other(type_id) => *type_id,
+        }
+    }
+
+    /// The number of a numeric or boolean scalar.
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        Some(match *self {
+            Variant::Boolean(v) => f64::from(u8::from(v)),
+            Variant::SByte(v) => v.into(),
+            Variant::Byte(v) => v.into(),
+            Variant::Int16(v) => v.into(),
+            Variant::UInt16(v) => v.into(),
+            Variant::Int32(v) => v.into(),
+            Variant::UInt32(v) => v.into(),
+            Variant::Int64(v) => v as f64,
+            Variant::UInt64(v) => v as f64,
+            Variant::Float(v) => v.into(),
+            Variant::Double(v) => v,
+            _ => return None,
+        })
+    }
+}
+
+/// A value with its status and timestamps.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DataValue {
+    pub value: Option<Variant>,
+    pub status: u32,
+    pub source_timestamp: Option<i64>,
+    pub server_timestamp: Option<i64>,
+}
+
+impl DataValue {
+    pub(crate) fn value(value: Variant) -> DataValue {
+        DataValue {
+            value: Some(value),
+            status: status::GOOD,
+            source_timestamp: None,
+            server_timestamp: None,
+        }
+    }
+
+    pub(crate) fn status(status: u32) -> DataValue {
+        DataValue {
+            value: None,
+            status,
+            source_timestamp: None,
+            server_timestamp: None,
+        }
+    }
+}
+
+/// The current time as an OPC UA DateTime, 100 ns intervals since 1601-01-01.
+pub(crate) fn now() -> i64 {
+    // seconds from 1601-01-01 to 1970-01-01
+    const EPOCH_OFFSET: i64 = 11_644_473_600;
+    let since_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (EPOCH_OFFSET + since_unix.as_secs() as i64) * 10_000_000
+        + i64::from(since_unix.subsec_nanos() / 100)
+}
+
+fn decoding_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "OPC UA decoding error")
+}
+
+// how deep variants, data values and diagnostic infos may nest, each level costs the sender
+// one byte but the decoder a stack frame
+const MAX_DEPTH: usize = 16;
+
+/// Reader decodes values from a message body.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader {
+            data,
+            position: 0,
+            depth: 0,
+        }
+    }
+
+    /// Runs @decode one nesting level deeper, failing past MAX_DEPTH.
+    fn nested<T>(&mut self, decode: impl FnOnce(&mut Self) -> io::Result<T>) -> io::Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(decoding_error());
+        }
+        self.depth += 1;
+        let result = decode(self);
+        self.depth -= 1;
+        result
+    }
+
+    pub(crate) fn bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(decoding_error)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// The bytes not read yet.
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position..];
+        self.position = self.data.len();
+        rest
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub(crate) fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn i64(&mut self) -> io::Result<i64> {
+        Ok(self.u64()? as i64)
+    }
+
+    pub(crate) fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub(crate) fn byte_string(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.i32()? {
+            length if length < 0 => Ok(None),
+            length => Ok(Some(self.bytes(length as usize)?.to_vec())),
+        }
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<Option<String>> {
+        match self.byte_string()? {
+            Some(bytes) => String::from_utf8(bytes)
+                .map(Some)
+                .map_err(|_| decoding_error()),
+            None => Ok(None),
+        }
+    }
+
+    /// An array, empty if it is null.
+    pub(crate) fn array<T, F>(&mut self, mut element: F) -> io::Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> io::Result<T>,
+    {
+        let length = self.i32()?;
+        // every element takes at least one byte
+        if length > 0 && length as usize > self.data.len() - self.position {
+            return Err(decoding_error());
+        }
+        (0..length.max(0)).map(|_| element(self)).collect()
+    }
+
+    pub(crate) fn node_id(&mut self) -> io::Result<NodeId> {
+        let encoding = self.u8()?;
+        self.node_id_body(encoding & 0x3f)
+    }
+
+    fn node_id_body(&mut self, encoding: u8) -> io::Result<NodeId> {
+        Ok(match encoding {
+            0 => NodeId::Numeric(0, self.u8()?.into()),
+            1 => NodeId::Numeric(self.u8()?.into(), self.u16()?.into()),
+            2 => NodeId::Numeric(self.u16()?, self.u32()?),
+            3 => NodeId::String(self.u16()?, self.string()?.unwrap_or_default()),
+            4 => NodeId::Guid(self.u16()?, self.bytes(16)?.try_into().unwrap()),
+            5 => NodeId::Opaque(self.u16()?, self.byte_string()?.unwrap_or_default()),
+            _ => return Err(decoding_error()),
+        })
+    }
+
+    /// An expanded node identifier; the namespace URI and server index are dropped.
+    pub(crate) fn expanded_node_id(&mut self) -> io::Result<NodeId> {
+        let encoding = self.u8()?;
+        let id = self.node_id_body(encoding & 0x3f)?;
+        if encoding & 0x80 != 0 {
+            self.string()?;
+        }
+        if encoding & 0x40 != 0 {
+            self.u32()?;
+        }
+        Ok(id)
+    }
+
+    pub(crate) fn qualified_name(&mut self) -> io::Result<(u16, String)> {
+        Ok((self.u16()?, self.string()?.unwrap_or_default()))
+    }
+
+    pub(crate) fn localized_text(&mut self) -> io::Result<String> {
+        let mask = self.u8()?;
+        if mask & 0x01 != 0 {
+            self.string()?;
+        }
+        let text = if mask & 0x02 != 0 {
+            self.string()?
+        } else {
+            None
+        };
+        Ok(text.unwrap_or_default())
+    }
+
+    /// An extension object as its type identifier and binary body.
+    pub(crate) fn extension_object(&mut self) -> io::Result<(NodeId, Vec<u8>)> {
+        let type_id = self.node_id()?;
+        let body = match self.u8()? {
+            0 => Vec::new(),
+            1 | 2 => self.byte_string()?.unwrap_or_default(),
+            _ => return Err(decoding_error()),
+        };
+        Ok((type_id, body))
+    }
+
+    pub(crate) fn diagnostic_info(&mut self) -> io::Result<()> {
+        self.nested(Self::diagnostic_info_body)
+    }
+
+    fn diagnostic_info_body(&mut self) -> io::Result<()> {
+        let mask = self.u8()?;
+        for bit in &[0x01, 0x02, 0x04, 0x08] {
+            if mask & bit != 0 {
+                self.i32()?;
+            }
+        }
+        if mask & 0x10 != 0 {
+            self.string()?;
+        }
+        if mask & 0x20 != 0 {
+            self.u32()?;
+        }
+        if mask & 0x40 != 0 {
+            self.diagnostic_info()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn data_value(&mut self) -> io::Result<DataValue> {
+        self.nested(Self::data_value_body)
+    }
+
+    fn data_value_body(&mut self) -> io::Result<DataValue> {
+        let mask = self.u8()?;
+        let value = if mask & 0x01 != 0 {
+            Some(self.variant()?)
+        } else {
+            None
+        };
+        let status = if mask & 0x02 != 0 { self.u32()? } else { 0 };
+        let source_timestamp = if mask & 0x04 != 0 {
+            Some(self.i64()?)
+        } else {
+            None
+        };
+        if mask & 0x10 != 0 {
+            self.u16()?;
+        }
+        let server_timestamp = if mask & 0x08 != 0 {
+            Some(self.i64()?)
+        } else {
+            None
+        };
+        if mask & 0x20 != 0 {
+            self.u16()?;
+        }
+        Ok(DataValue {
+            value,
+            status,
+            source_timestamp,
+            server_timestamp,
+        })
+    }
+
+    pub(crate) fn variant(&mut self) -> io::Result<Variant> {
+        self.nested(Self::variant_body)
+    }
+
+    fn variant_body(&mut self) -> io::Result<Variant> {
+        let mask = self.u8()?;
+        let type_id = mask & 0x3f;
+        let value = if mask & 0x80 != 0 {
+            Variant::Array(type_id, self.array(|r| r.scalar(type_id))?)
+        } else {
+            self.scalar(type_id)?
+        };
+        if mask & 0x40 != 0 {
+            self.array(Self::i32)?;
+        }
+        Ok(value)
+    }
+
+    fn scalar(&mut self, type_id: u8) -> io::Result<Variant> {
+        Ok(match type_id {
+            0 => Variant::Empty,
+            1 => Variant::Boolean(self.bool()?),
+            2 => Variant::SByte(self.u8()? as i8),
+            3 => Variant::Byte(self.u8()?),
+            4 => Variant::Int16(self.u16()? as i16),
+            5 => Variant::UInt16(self.u16()?),
+            6 => Variant::Int32(self.i32()?),
+            7 => Variant::UInt32(self.u32()?),
+            8 => Variant::Int64(self.i64()?),
+            9 => Variant::UInt64(self.u64()?),
+            10 => Variant::Float(f32::from_bits(self.u32()?)),
+            11 => Variant::Double(self.f64()?),
+            12 => Variant::String(self.string()?),
+            13 => Variant::DateTime(self.i64()?),
+            14 => {
+                self.bytes(16)?;
+                Variant::Other(type_id)
+            }
+            15 | 16 => {
+                self.byte_string()?;
+                Variant::Other(type_id)
+            }
+            17 => Variant::NodeId(self.node_id()?),
+            18 => Variant::NodeId(self.expanded_node_id()?),
+            19 => Variant::StatusCode(self.u32()?),
+            20 => {
+                let (namespace, name) = self.qualified_name()?;
+                Variant::QualifiedName(namespace, name)
+            }
+            21 => Variant::LocalizedText(self.localized_text()?),
+            22 => {
+                let (type_id, body) = self.extension_object()?;
+                Variant::ExtensionObject(type_id, body)
+            }
+            23 => {
+                self.data_value()?;
+                Variant::Other(type_id)
+            }
+            24 => self.variant()?,
+            25 => {
+                self.diagnostic_info()?;
+                Variant::Other(type_id)
+            }
+            _ => return Err(decoding_error()),
+        })
+    }
+}
+
+/// Writer encodes values into a message body.
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub data: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) -> &mut Self {
+        self.u8(value.into())
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub(crate) fn i64(&mut self, value: i64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub(crate) fn byte_string(&mut self, value: Option<&[u8]>) -> &mut Self {
+        match value {
+            Some(bytes) => self.i32(bytes.len() as i32).bytes(bytes),
+            None => self.i32(-1),
+        }
+    }
+
+    pub(crate) fn string(&mut self, value: &str) -> &mut Self {
+        self.byte_string(Some(value.as_bytes()))
+    }
+
+    pub(crate) fn null_string(&mut self) -> &mut Self {
+        self.i32(-1)
+    }
+
+    pub(crate) fn array<T, F>(&mut self, elements: &[T], mut element: F) -> &mut Self
+    where
+        F: FnMut(&mut Self, &T),
+    {
+        self.i32(elements.len() as i32);
+        for e in elements {
+            element(self, e);
+        }
+        self
+    }
+
+    pub(crate) fn node_id(&mut self, id: &NodeId) -> &mut Self {
+        match id {
+            NodeId::Numeric(0, n) if *n <= 0xff => self.u8(0).u8(*n as u8),
+            NodeId::Numeric(ns, n) if *ns <= 0xff && *n <= 0xffff => {
+                self.u8(1).u8(*ns as u8).u16(*n as u16)
+            }
+            NodeId::Numeric(ns, n) => self.u8(2).u16(*ns).u32(*n),
+            NodeId::String(ns, s) => self.u8(3).u16(*ns).string(s),
+            NodeId::Guid(ns, g) => self.u8(4).u16(*ns).bytes(g),
+            NodeId::Opaque(ns, b) => self.u8(5).u16(*ns).byte_string(Some(b)),
+        }
+    }
+
+    pub(crate) fn qualified_name(&mut self, namespace: u16, name: &str) -> &mut Self {
+        self.u16(namespace).string(name)
+    }
+
+    pub(crate) fn localized_text(&mut self, text: &str) -> &mut Self {
+        if text.is_empty() {
+            self.u8(0)
+        } else {
+            self.u8(0x02).string(text)
+        }
+    }
+
+    /// An extension object with the binary @body of the structure with encoding @type_id.
+    pub(crate) fn extension_object(&mut self, type_id: u32, body: &[u8]) -> &mut Self {
+        self.node_id(&NodeId::ns0(type_id))
+            .u8(1)
+            .byte_string(Some(body))
+    }
+
+    pub(crate) fn null_extension_object(&mut self) -> &mut Self {
+        self.node_id(&NodeId::NULL).u8(0)
+    }
+
+    pub(crate) fn variant(&mut self, value: &Variant) -> &mut Self {
+        match value {
+            Variant::Array(type_id, elements) => {
+                self.u8(type_id | 0x80);
+                self.array(elements, |w, e| {
+                    w.scalar(e);
+                })
+            }
+            _ => {
+                self.u8(value.type_id());
+                self.scalar(value)
+            }
+        }
+    }
+
+    fn scalar(&mut self, value: &Variant) -> &mut Self {
+        match value {
+            Variant::Empty | Variant::Array(..) | Variant::Other(_) => self,
+            Variant::Boolean(v) => self.bool(*v),
+            Variant::SByte(v) => self.u8(*v as u8),
+            Variant::Byte(v) => self.u8(*v),
+            Variant::Int16(v) => self.u16(*v as u16),
+            Variant::UInt16(v) => self.u16(*v),
+            Variant::Int32(v) => self.i32(*v),
+            Variant::UInt32(v) => self.u32(*v),
+            Variant::Int64(v) => self.i64(*v),
+            Variant::UInt64(v) => self.bytes(&v.to_le_bytes()),
+            Variant::Float(v) => self.bytes(&v.to_le_bytes()),
+            Variant::Double(v) => self.f64(*v),
+            Variant::String(Some(s)) => self.string(s),
+            Variant::String(None) => self.null_string(),
+            Variant::DateTime(v) => self.i64(*v),
+            Variant::NodeId(id) => self.node_id(id),
+            Variant::StatusCode(v) => self.u32(*v),
+            Variant::QualifiedName(ns, name) => self.qualified_name(*ns, name),
+            Variant::LocalizedText(text) => self.localized_text(text),
+            Variant::ExtensionObject(type_id, body) => {
+                self.node_id(type_id).u8(1).byte_string(Some(body))
+            }
+        }
+    }
+
+    pub(crate) fn data_value(&mut self, value: &DataValue) -> &mut Self {
+        let mut mask = 0;
+        if value.value.is_some() {
+            mask |= 0x01;
+        }
+        if value.status != status::GOOD {
+            mask |= 0x02;
+        }
+        if value.source_timestamp.is_some() {
+            mask |= 0x04;
+        }
+        if value.server_timestamp.is_some() {
+            mask |= 0x08;
+        }
+        self.u8(mask);
+        if let Some(v) = &value.value {
+            self.variant(v);
+        }
+        if value.status != status::GOOD {
+            self.u32(value.status);
+        }
+        if let Some(t) = value.source_timestamp {
+            self.i64(t);
+        }
+        if let Some(t) = value.server_timestamp {
+            self.i64(t);
+        }
+        self
+    }
+
+    /// An empty array of diagnostic infos.
+    pub(crate) fn no_diagnostics(&mut self) -> &mut Self {
+        self.i32(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let ids = [
+            NodeId::ns0(85),
+            NodeId::Numeric(1, 1000),
+            NodeId::Numeric(2, 100_000),
+            NodeId::String(1, "I_1".to_owned()),
+        ];
+        let values = [
+            Variant::Boolean(true),
+            Variant::UInt16(513),
+            Variant::Double(-1.5),
+            Variant::String(Some("RevPi".to_owned())),
+            Variant::Array(12, vec![Variant::String(Some("a".to_owned()))]),
+        ];
+        let mut w = Writer::new();
+        for id in &ids {
+            w.node_id(id);
+        }
+        for value in &values {
+            w.variant(value);
+        }
+        w.data_value(&DataValue {
+            value: Some(Variant::Byte(7)),
+            status: status::BAD_OUT_OF_RANGE,
+            source_timestamp: Some(1),
+            server_timestamp: Some(2),
+        });
+        assert_eq!(&w.data[..2], &[0x00, 85]);
+        assert_eq!(&w.data[2..6], &[0x01, 1, 0xe8, 0x03]);
+
+        let mut r = Reader::new(&w.data);
+        for id in &ids {
+            assert_eq!(&r.node_id().unwrap(), id);
+        }
+        for value in &values {
+            assert_eq!(&r.variant().unwrap(), value);
+        }
+        let value = r.data_value().unwrap();
+        assert_eq!(value.value, Some(Variant::Byte(7)));
+        assert_eq!(value.status, status::BAD_OUT_OF_RANGE);
+        assert_eq!(
+            (value.source_timestamp, value.server_timestamp),
+            (Some(1), Some(2))
+        );
+        assert!(r.u8().is_err());
+    }
+
+    #[test]
+    fn hostile_input() {
+        // a Variant of Variants nested far deeper than any real message
+        let nested = vec![24u8; 64 * 1024];
+        assert!(Reader::new(&nested).variant().is_err());
+        let mut diagnostics = vec![0x40u8; 64 * 1024];
+        diagnostics.push(0);
+        assert!(Reader::new(&diagnostics).diagnostic_info().is_err());
+        // a DataValue holding a Variant holding a DataValue ...
+        let mut data_values = Vec::new();
+        for _ in 0..1000 {
+            data_values.extend_from_slice(&[0x01, 23]);
+        }
+        assert!(Reader::new(&data_values).data_value().is_err());
+
+        // a few levels are fine
+        let mut w = Writer::new();
+        w.variant(&Variant::Byte(3));
+        let mut shallow = vec![24u8; 4];
+        shallow.extend_from_slice(&w.data);
+        assert_eq!(Reader::new(&shallow).variant().unwrap(), Variant::Byte(3));
+
+        // truncated values and arrays longer than the message
+        assert!(Reader::new(&[12, 5, 0, 0, 0, b'a']).variant().is_err());
+        assert!(Reader::new(&[0x83, 0xff, 0xff, 0xff, 0x7f])
+            .variant()
+            .is_err());
+        assert!(Reader::new(&[63]).variant().is_err());
+    }
+}
@@ -185,7 +185,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         concat!("Alignment of ", stringify!(SDeviceInfoStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i8uAddress as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i8uAddress),
         0usize,
         concat!(
             "Offset of field: ",
@@ -195,7 +195,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i32uSerialnumber as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i32uSerialnumber),
         4usize,
         concat!(
             "Offset of field: ",
@@ -205,7 +205,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uModuleType as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uModuleType),
         8usize,
         concat!(
             "Offset of field: ",
@@ -215,7 +215,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uHW_Revision as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uHW_Revision),
         10usize,
         concat!(
             "Offset of field: ",
@@ -225,7 +225,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uSW_Major as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uSW_Major),
         12usize,
         concat!(
             "Offset of field: ",
@@ -235,7 +235,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uSW_Minor as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uSW_Minor),
         14usize,
         concat!(
             "Offset of field: ",
@@ -245,7 +245,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i32uSVN_Revision as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i32uSVN_Revision),
         16usize,
         concat!(
             "Offset of field: ",
@@ -255,7 +255,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uInputLength as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uInputLength),
         20usize,
         concat!(
             "Offset of field: ",
@@ -265,7 +265,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uOutputLength as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uOutputLength),
         22usize,
         concat!(
             "Offset of field: ",
@@ -275,7 +275,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uConfigLength as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uConfigLength),
         24usize,
         concat!(
             "Offset of field: ",
@@ -285,7 +285,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uBaseOffset as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uBaseOffset),
         26usize,
         concat!(
             "Offset of field: ",
@@ -295,7 +295,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uInputOffset as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uInputOffset),
         28usize,
         concat!(
             "Offset of field: ",
@@ -305,7 +305,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uOutputOffset as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uOutputOffset),
         30usize,
         concat!(
             "Offset of field: ",
@@ -315,7 +315,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uConfigOffset as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uConfigOffset),
         32usize,
         concat!(
             "Offset of field: ",
@@ -325,7 +325,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uFirstEntry as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uFirstEntry),
         34usize,
         concat!(
             "Offset of field: ",
@@ -335,7 +335,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i16uEntries as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i16uEntries),
         36usize,
         concat!(
             "Offset of field: ",
@@ -345,7 +345,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i8uModuleState as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i8uModuleState),
         38usize,
         concat!(
             "Offset of field: ",
@@ -355,7 +355,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i8uActive as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i8uActive),
         39usize,
         concat!(
             "Offset of field: ",
@@ -365,7 +365,7 @@ fn bindgen_test_layout_SDeviceInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDeviceInfoStr>())).i8uReserve as *const _ as usize },
+        ::std::mem::offset_of!(SDeviceInfoStr, i8uReserve),
         40usize,
         concat!(
             "Offset of field: ",
@@ -401,7 +401,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         concat!("Alignment of ", stringify!(SEntryInfoStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i8uAddress as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i8uAddress),
         0usize,
         concat!(
             "Offset of field: ",
@@ -411,7 +411,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i8uType as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i8uType),
         1usize,
         concat!(
             "Offset of field: ",
@@ -421,7 +421,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i16uIndex as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i16uIndex),
         2usize,
         concat!(
             "Offset of field: ",
@@ -431,7 +431,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i16uBitLength as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i16uBitLength),
         4usize,
         concat!(
             "Offset of field: ",
@@ -441,7 +441,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i8uBitPos as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i8uBitPos),
         6usize,
         concat!(
             "Offset of field: ",
@@ -451,7 +451,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i16uOffset as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i16uOffset),
         8usize,
         concat!(
             "Offset of field: ",
@@ -461,7 +461,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).i32uDefault as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, i32uDefault),
         12usize,
         concat!(
             "Offset of field: ",
@@ -471,7 +471,7 @@ fn bindgen_test_layout_SEntryInfoStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SEntryInfoStr>())).strVarName as *const _ as usize },
+        ::std::mem::offset_of!(SEntryInfoStr, strVarName),
         16usize,
         concat!(
             "Offset of field: ",
@@ -502,7 +502,7 @@ fn bindgen_test_layout_SPIValueStr() {
         concat!("Alignment of ", stringify!(SPIValueStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIValueStr>())).i16uAddress as *const _ as usize },
+        ::std::mem::offset_of!(SPIValueStr, i16uAddress),
         0usize,
         concat!(
             "Offset of field: ",
@@ -512,7 +512,7 @@ fn bindgen_test_layout_SPIValueStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIValueStr>())).i8uBit as *const _ as usize },
+        ::std::mem::offset_of!(SPIValueStr, i8uBit),
         2usize,
         concat!(
             "Offset of field: ",
@@ -522,7 +522,7 @@ fn bindgen_test_layout_SPIValueStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIValueStr>())).i8uValue as *const _ as usize },
+        ::std::mem::offset_of!(SPIValueStr, i8uValue),
         3usize,
         concat!(
             "Offset of field: ",
@@ -554,7 +554,7 @@ fn bindgen_test_layout_SPIVariableStr() {
         concat!("Alignment of ", stringify!(SPIVariableStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIVariableStr>())).strVarName as *const _ as usize },
+        ::std::mem::offset_of!(SPIVariableStr, strVarName),
         0usize,
         concat!(
             "Offset of field: ",
@@ -564,7 +564,7 @@ fn bindgen_test_layout_SPIVariableStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIVariableStr>())).i16uAddress as *const _ as usize },
+        ::std::mem::offset_of!(SPIVariableStr, i16uAddress),
         32usize,
         concat!(
             "Offset of field: ",
@@ -574,7 +574,7 @@ fn bindgen_test_layout_SPIVariableStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIVariableStr>())).i8uBit as *const _ as usize },
+        ::std::mem::offset_of!(SPIVariableStr, i8uBit),
         34usize,
         concat!(
             "Offset of field: ",
@@ -584,7 +584,7 @@ fn bindgen_test_layout_SPIVariableStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SPIVariableStr>())).i16uLength as *const _ as usize },
+        ::std::mem::offset_of!(SPIVariableStr, i16uLength),
         36usize,
         concat!(
             "Offset of field: ",
@@ -614,7 +614,7 @@ fn bindgen_test_layout_SDIOResetCounterStr() {
         concat!("Alignment of ", stringify!(SDIOResetCounterStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SDIOResetCounterStr>())).i8uAddress as *const _ as usize },
+        ::std::mem::offset_of!(SDIOResetCounterStr, i8uAddress),
         0usize,
         concat!(
             "Offset of field: ",
@@ -624,9 +624,7 @@ fn bindgen_test_layout_SDIOResetCounterStr() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<SDIOResetCounterStr>())).i16uBitfield as *const _ as usize
-        },
+        ::std::mem::offset_of!(SDIOResetCounterStr, i16uBitfield),
         2usize,
         concat!(
             "Offset of field: ",
@@ -657,7 +655,7 @@ fn bindgen_test_layout_SConfigDataStr() {
         concat!("Alignment of ", stringify!(SConfigDataStr))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SConfigDataStr>())).bLeft as *const _ as usize },
+        ::std::mem::offset_of!(SConfigDataStr, bLeft),
         0usize,
         concat!(
             "Offset of field: ",
@@ -667,7 +665,7 @@ fn bindgen_test_layout_SConfigDataStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SConfigDataStr>())).i16uLen as *const _ as usize },
+        ::std::mem::offset_of!(SConfigDataStr, i16uLen),
         2usize,
         concat!(
             "Offset of field: ",
@@ -677,7 +675,7 @@ fn bindgen_test_layout_SConfigDataStr() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<SConfigDataStr>())).acData as *const _ as usize },
+        ::std::mem::offset_of!(SConfigDataStr, acData),
         4usize,
         concat!(
             "Offset of field: ",
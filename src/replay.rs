@@ -0,0 +1,304 @@
+//! Replay of recorded inputs into an [`Emulator`].
+//!
+//! A [`Replay`] takes the samples of a recording, e.g. from [`historian::query`], and sets the
+//! inputs of an emulated RevPi at the recorded times while the control code runs, capturing
+//! the outputs it produces. With [`Replay::run`] the control code is called once per cycle on
+//! the emulator's virtual clock, so an incident from the field plays out the same way on every
+//! build:
+//!
+//! ```ignore
+//! let recording = historian::query("/var/lib/history", from..to, &[])?;
+//! let emulator = Emulator::from_config(Config::load("config.rsc")?)?;
+//! let outputs = Replay::new(&recording)
+//!     .cycle(Duration::from_millis(10))
+//!     .run(&emulator, |emulator| run_one_cycle(emulator))?;
+//! ```
+//!
+//! [`Replay::play`] follows the wall clock instead, for control code running in another
+//! process, e.g. connected through a [`SocketServer`](crate::socket::SocketServer).
+//!
+//! [`historian::query`]: crate::historian::query
+
+use crate::config::{Config, Direction};
+use crate::emulator::Emulator;
+use crate::historian::Sample;
+use crate::value::{Value, Variable};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Replay of a recording, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// The recorded samples, ordered by time.
+    samples: Vec<Sample>,
+    speed: f64,
+    loops: usize,
+    cycle: Duration,
+}
+
+impl Replay {
+    /// Replays @samples once, in cycles of 10 ms at the recorded speed.
+    ///
+    /// Samples of variables other than inputs, e.g. recorded outputs, are ignored.
+    pub fn new(samples: &[Sample]) -> Self {
+        let mut samples = samples.to_vec();
+        samples.sort_by_key(|s| s.time);
+        Replay {
+            samples,
+            speed: 1.0,
+            loops: 1,
+            cycle: Duration::from_millis(10),
+        }
+    }
+
+    /// Plays @factor times faster than recorded, only used by [`Replay::play`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if @factor is not positive.
+    pub fn speed(mut self, factor: f64) -> Self {
+        assert!(factor > 0.0, "invalid replay speed {}", factor);
+        self.speed = factor;
+        self
+    }
+
+    /// Replays the recording @count times, 0 to repeat it until [`Replay::play`] is stopped.
+    pub fn loops(mut self, count: usize) -> Self {
+        self.loops = count;
+        self
+    }
+
+    /// The time between two cycles, in recorded time.
+    ///
+    /// Inputs are set and outputs are captured once per cycle, so changes closer together
+    /// than a cycle are applied at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if @cycle is zero.
+    pub fn cycle(mut self, cycle: Duration) -> Self {
+        assert!(cycle > Duration::from_secs(0), "the replay cycle is zero");
+        self.cycle = cycle;
+        self
+    }
+
+    /// The recorded time of one loop, from the first sample to one cycle after the last.
+    pub fn duration(&self) -> Duration {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => offset(first.time, last.time) + self.cycle,
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// Replays on the virtual clock of @emulator, calling @control once per cycle after the
+    /// inputs are set; returns the changes of the outputs.
+    ///
+    /// The outputs are captured with the recorded time, so they can be compared with the
+    /// outputs of the original recording; their values are as unsigned types of their length.
+    pub fn run<F>(&self, emulator: &Emulator, mut control: F) -> io::Result<Vec<Sample>>
+    where
+        F: FnMut(&Emulator) -> io::Result<()>,
+    {
+        if self.loops == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "replaying on the virtual clock needs a number of loops",
+            ));
+        }
+        self.drive(emulator, |emulator| control(emulator).map(|()| true))
+    }
+
+    /// Replays along the wall clock, sped up by the [`Replay::speed`] factor, until the loops
+    /// are done or @stop is set; returns the changes of the outputs like [`Replay::run`].
+    pub fn play(&self, emulator: &Emulator, stop: &AtomicBool) -> io::Result<Vec<Sample>> {
+        let cycle = self.cycle.div_f64(self.speed);
+        let mut next = Instant::now();
+        self.drive(emulator, |_| {
+            next += cycle;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            }
+            Ok(!stop.load(Ordering::Relaxed))
+        })
+    }
+
+    /// Steps through the loops cycle by cycle; @tick is called after the inputs of a cycle
+    /// are set and ends the replay when it returns false.
+    fn drive<F>(&self, emulator: &Emulator, mut tick: F) -> io::Result<Vec<Sample>>
+    where
+        F: FnMut(&Emulator) -> io::Result<bool>,
+    {
+        let start = match self.samples.first() {
+            Some(first) => first.time,
+            None => return Ok(Vec::new()),
+        };
+        let inputs = self.inputs(emulator.config())?;
+        let mut outputs = Outputs::new(emulator.config())?;
+        let length = self.duration();
+        // the last cycle may be cut short, it still has to apply the last sample
+        let cycles = length.as_nanos().div_ceil(self.cycle.as_nanos()) as u32;
+        let origin = emulator.elapsed();
+        let mut round = 0;
+        while self.loops == 0 || round < self.loops {
+            let round_start = length * round as u32;
+            for sample in &inputs {
+                let at = origin + round_start + offset(start, sample.time);
+                emulator.schedule(at, &sample.name, sample.value)?;
+            }
+            for k in 0..cycles {
+                let t = round_start + self.cycle * k;
+                emulator.advance((origin + t).saturating_sub(emulator.elapsed()))?;
+                let keep = tick(emulator)?;
+                outputs.capture(emulator, start + t)?;
+                if !keep {
+                    return Ok(outputs.samples);
+                }
+            }
+            round += 1;
+        }
+        Ok(outputs.samples)
+    }
+
+    /// The samples of inputs of @config.
+    fn inputs(&self, config: &Config) -> io::Result<Vec<&Sample>> {
+        let mut inputs = Vec::new();
+        for sample in &self.samples {
+            match config.find(&sample.name) {
+                Some((_, entry)) if entry.direction == Direction::Input => inputs.push(sample),
+                Some(_) => {}
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("recorded variable {} is not emulated", sample.name),
+                    ))
+                }
+            }
+        }
+        Ok(inputs)
+    }
+}
+
+/// The output variables with their last captured values.
+struct Outputs {
+    variables: Vec<(Variable, Option<Value>)>,
+    samples: Vec<Sample>,
+}
+
+impl Outputs {
+    fn new(config: &Config) -> io::Result<Self> {
+        Ok(Outputs {
+            variables: config
+                .entries()
+                .filter(|(_, e)| e.direction == Direction::Output)
+                .map(|(_, e)| Ok((e.variable()?, None)))
+                .collect::<io::Result<_>>()?,
+            samples: Vec::new(),
+        })
+    }
+
+    fn capture(&mut self, emulator: &Emulator, time: SystemTime) -> io::Result<()> {
+        for (variable, last) in &mut self.variables {
+            let value = variable.read(emulator)?;
+            if *last != Some(value) {
+                *last = Some(value);
+                self.samples.push(Sample {
+                    time,
+                    name: variable.name.clone(),
+                    value,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn offset(start: SystemTime, time: SystemTime) -> Duration {
+    time.duration_since(start).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dio::DIO_MODULE_TYPE;
+    use crate::emulator::Module;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn replay_inputs() {
+        let emulator = Emulator::new(&[Module::new(DIO_MODULE_TYPE, 32)]).unwrap();
+        let at = |ms: u64| UNIX_EPOCH + Duration::from_millis(1000 + ms);
+        let sample = |ms: u64, name: &str, on| Sample {
+            time: at(ms),
+            name: name.to_owned(),
+            value: Value::Bool(on),
+        };
+        let recording = [
+            sample(0, "I_1", false),
+            sample(100, "I_1", true),
+            sample(120, "O_2", true),
+            sample(250, "I_1", false),
+        ];
+        let replay = Replay::new(&recording)
+            .cycle(Duration::from_millis(50))
+            .loops(2);
+        assert_eq!(replay.duration(), Duration::from_millis(300));
+
+        let outputs = replay
+            .run(&emulator, |emulator| {
+                let on = emulator.value("I_1", &Value::Bool(false))?;
+                emulator.set("O_1", on)
+            })
+            .unwrap();
+        let o_1: Vec<_> = outputs
+            .iter()
+            .filter(|s| s.name == "O_1")
+            .map(|s| (s.time, s.value))
+            .collect();
+        assert_eq!(
+            o_1,
+            vec![
+                (at(0), Value::Bool(false)),
+                (at(100), Value::Bool(true)),
+                (at(250), Value::Bool(false)),
+                (at(400), Value::Bool(true)),
+                (at(550), Value::Bool(false)),
+            ]
+        );
+        assert!(outputs
+            .iter()
+            .all(|s| s.name != "O_2" || s.value == Value::Bool(false)));
+        assert_eq!(emulator.elapsed(), Duration::from_millis(550));
+
+        // the last sample is between two cycles
+        let emulator = Emulator::new(&[Module::new(DIO_MODULE_TYPE, 32)]).unwrap();
+        let recording = [sample(0, "I_1", false), sample(270, "I_1", true)];
+        let outputs = Replay::new(&recording)
+            .cycle(Duration::from_millis(50))
+            .loops(2)
+            .run(&emulator, |emulator| {
+                let on = emulator.value("I_1", &Value::Bool(false))?;
+                emulator.set("O_1", on)
+            })
+            .unwrap();
+        let o_1: Vec<_> = outputs
+            .iter()
+            .filter(|s| s.name == "O_1")
+            .map(|s| (s.time, s.value))
+            .collect();
+        assert_eq!(
+            o_1,
+            vec![
+                (at(0), Value::Bool(false)),
+                (at(300), Value::Bool(true)),
+                (at(320), Value::Bool(false)),
+                (at(620), Value::Bool(true)),
+            ]
+        );
+
+        let unknown = [sample(0, "I_99", true)];
+        assert!(Replay::new(&unknown).run(&emulator, |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Writing a process image dump back to the driver.
+//!
+//! Only the output and memory areas of the devices in the driver's device list are restored,
+//! the inputs of a dump are stale the moment it is taken. [`plan`] and [`plan_variables`]
+//! compare the dump with the current process image; print the [`Plan`] to preview the
+//! difference, then [`Plan::apply`] it:
+//!
+//! ```ignore
+//! let image = std::fs::read("revpi_proc_img.bin")?;
+//! let plan = restore::plan(&driver, &image)?;
+//! print!("{}", plan);
+//! plan.apply(&driver)?;
+//! ```
+
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::picontrol::SDeviceInfo;
+use crate::value::{Value, Variable};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+
+/// One difference between the process image and the dump.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A run of bytes of an output or memory area.
+    Bytes {
+        offset: u16,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// A variable that was restored by name.
+    Variable {
+        variable: Variable,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Bytes { offset, old, new } => {
+                write!(f, "offset {}: {:02x?} -> {:02x?}", offset, old, new)
+            }
+            Change::Variable { variable, old, new } => write!(
+                f,
+                "{} at offset {}: {} -> {}",
+                variable.name, variable.address, old, new
+            ),
+        }
+    }
+}
+
+/// The writes that restore a dump, as returned by [`plan`] or [`plan_variables`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+}
+
+impl Plan {
+    /// The process image already matches the dump.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Performs the writes.
+    pub fn apply<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<()> {
+        for change in &self.changes {
+            match change {
+                Change::Bytes { offset, new, .. } => driver.write((*offset).into(), new)?,
+                Change::Variable { variable, new, .. } => variable.write(driver, *new)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "the process image matches the dump");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// The output and memory areas of @devices as (offset, length), the only ones restored.
+pub fn writable_areas(devices: &[SDeviceInfo]) -> Vec<(u16, u16)> {
+    let mut areas: Vec<_> = devices
+        .iter()
+        .flat_map(|d| {
+            vec![
+                (d.i16uOutputOffset, d.i16uOutputLength),
+                (d.i16uConfigOffset, d.i16uConfigLength),
+            ]
+        })
+        .filter(|&(_, length)| length > 0)
+        .collect();
+    areas.sort_unstable();
+    areas
+}
+
+/// Compares the output and memory areas of @image with the driver's process image.
+pub fn plan<D: Driver + ?Sized>(driver: &D, image: &[u8]) -> io::Result<Plan> {
+    let current = driver.read(0, PROCESS_IMAGE_SIZE)?;
+    let mut changes = Vec::new();
+    for (offset, length) in writable_areas(&driver.get_device_info_list()?) {
+        let new = area(image, offset, length)?;
+        let start = usize::from(offset);
+        let old = current
+            .get(start..start + usize::from(length))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the area of {} bytes at offset {} is outside of the process image",
+                        length, offset
+                    ),
+                )
+            })?;
+        let mut i = 0;
+        while i < new.len() {
+            if old[i] == new[i] {
+                i += 1;
+                continue;
+            }
+            let run = i;
+            while i < new.len() && old[i] != new[i] {
+                i += 1;
+            }
+            changes.push(Change::Bytes {
+                offset: offset + run as u16,
+                old: old[run..i].to_vec(),
+                new: new[run..i].to_vec(),
+            });
+        }
+    }
+    Ok(Plan { changes })
+}
+
+/// Compares variables @names of @image with the driver's process image.
+///
+/// Fails if a variable is not in an output or memory area.
+pub fn plan_variables<D: Driver + ?Sized>(
+    driver: &D,
+    image: &[u8],
+    names: &[&str],
+) -> io::Result<Plan> {
+    let current = driver.read(0, PROCESS_IMAGE_SIZE)?;
+    let areas = writable_areas(&driver.get_device_info_list()?);
+    let mut changes = Vec::new();
+    for name in names {
+        let variable = Variable::resolve(driver, name)?;
+        let start = variable.address;
+        let end = u16::try_from(variable.byte_length())
+            .ok()
+            .and_then(|length| start.checked_add(length));
+        let inside = |&(offset, length): &(u16, u16)| match (end, offset.checked_add(length)) {
+            (Some(end), Some(area_end)) => offset <= start && end <= area_end,
+            _ => false,
+        };
+        if !areas.iter().any(inside) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("variable {} is not an output", name),
+            ));
+        }
+        let old = variable.decode(&current)?;
+        let new = variable.decode(image)?;
+        if old != new {
+            changes.push(Change::Variable { variable, old, new });
+        }
+    }
+    Ok(Plan { changes })
+}
+
+fn area(image: &[u8], offset: u16, length: u16) -> io::Result<&[u8]> {
+    let start = usize::from(offset);
+    image
+        .get(start..start + usize::from(length))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the dump has {} bytes, the area at offset {} ends at {}",
+                    image.len(),
+                    offset,
+                    start + usize::from(length)
+                ),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dio::DIO_MODULE_TYPE;
+    use crate::emulator::{Emulator, Module, CORE_MODULE_TYPE};
+
+    #[test]
+    fn restore_outputs() {
+        let emulator = Emulator::new(&[
+            Module::new(CORE_MODULE_TYPE, 0),
+            Module::new(DIO_MODULE_TYPE, 32),
+        ])
+        .unwrap();
+        emulator.set("O_10", Value::Bool(true)).unwrap();
+        emulator.set("PWM_1", Value::U8(40)).unwrap();
+        let image = emulator.read(0, PROCESS_IMAGE_SIZE).unwrap();
+        emulator.reset().unwrap();
+        emulator.set("I_1", Value::Bool(true)).unwrap();
+
+        let mut dump = image.clone();
+        dump[7] = 0;
+        let plan = plan(&emulator, &dump).unwrap();
+        assert_eq!(
+            plan.changes,
+            vec![Change::Bytes {
+                offset: 78,
+                old: vec![0, 0],
+                new: vec![0b10, 40]
+            }]
+        );
+        assert_eq!(plan.to_string(), "offset 78: [00, 00] -> [02, 28]\n");
+        plan.apply(&emulator).unwrap();
+        emulator.assert_value("O_10", Value::Bool(true));
+        emulator.assert_value("I_1", Value::Bool(true));
+
+        emulator.reset().unwrap();
+        let plan = plan_variables(&emulator, &image, &["O_10"]).unwrap();
+        assert_eq!(plan.changes.len(), 1);
+        plan.apply(&emulator).unwrap();
+        emulator.assert_value("O_10", Value::Bool(true));
+        emulator.assert_value("PWM_1", Value::U8(0));
+        assert!(plan_variables(&emulator, &image, &["O_10"])
+            .unwrap()
+            .is_empty());
+        assert!(plan_variables(&emulator, &image, &["I_1"]).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! A thread-safe handle to the piControl driver.
+
+use crate::{
+    bit_value, counter_reset, device_info_list, event, io_stop, ioctl, last_message, picontrol,
+    variable_info, Event, RevPiControl, PROCESS_IMAGE_SIZE,
+};
+use nix::libc::c_int;
+use nix::Result;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+/// SharedRevPiControl is a cloneable handle to the piControl driver that can be used from many
+/// threads at once.
+///
+/// Unlike [`RevPiControl`], every method takes `&self` and the handle is `Send + Sync`, so it can
+/// be cloned into worker threads without an outer `Mutex`. Clones share the same open file.
+///
+/// # Atomicity
+///
+/// The piControl driver copies data in and out of the process image while holding its image lock,
+/// and the I/O cycle exchanges data with the modules under the same lock. From this follows:
+///
+/// * A single [`read`](Self::read) or [`write`](Self::write) is atomic with respect to the driver
+///   cycle: all bytes come from, or land in, the same cycle. Reads and writes use positional I/O
+///   (`pread`/`pwrite`), so there is no shared file cursor and no lock is taken in this process.
+/// * [`get_bit_value`](Self::get_bit_value) and [`set_bit_value`](Self::set_bit_value) are a single
+///   ioctl each. Setting a bit is done by the driver and never disturbs the other bits of the byte.
+/// * [`get_variable_info`](Self::get_variable_info) and
+///   [`get_device_info_list`](Self::get_device_info_list) only read the driver configuration.
+/// * Two separate calls are not atomic together: they may straddle a cycle, and other writers may
+///   run in between. [`modify`](Self::modify) serializes read-modify-write sequences issued through
+///   this handle and its clones; it cannot exclude other processes writing the same bytes.
+#[derive(Clone)]
+pub struct SharedRevPiControl {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    path: String,
+    file: File,
+    modify: Mutex<()>,
+}
+
+impl SharedRevPiControl {
+    /// Opens the default piControl device.
+    pub fn open() -> io::Result<Self> {
+        let c_str = CStr::from_bytes_with_nul(picontrol::PICONTROL_DEVICE).unwrap();
+        Self::open_at(c_str.to_str().unwrap())
+    }
+
+    /// Opens the piControl device, or a process image file, at the given path.
+    pub fn open_at(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| {
+                io::Error::other(format!(
+                    "can not open picontrol file descriptor at {}, error: {}",
+                    path, e
+                ))
+            })?;
+        Ok(SharedRevPiControl {
+            inner: Arc::new(Inner {
+                path: path.to_owned(),
+                file,
+                modify: Mutex::new(()),
+            }),
+        })
+    }
+
+    /// The path this handle was opened at.
+    pub fn path(&self) -> &str {
+        &self.inner.path
+    }
+
+    /// Reset Pi Control Interface.
+    ///
+    /// Returns the ioctl result as it is: a configuration the driver refuses fails with one of
+    /// the PICONTROL_CONFIG_ERROR_* codes as errno, which [`Driver::reset`](crate::Driver::reset) turns into a
+    /// [`ConfigError`](crate::ConfigError).
+    pub fn reset(&self) -> Result<c_int> {
+        unsafe { ioctl::reset(self.inner.file.as_raw_fd()) }
+    }
+
+    /// Reads @length bytes of process data starting at @offset.
+    ///
+    /// Fails without allocating if the range is not within the [`PROCESS_IMAGE_SIZE`] bytes of
+    /// the process image.
+    pub fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        check_range(offset, length)?;
+        let mut v = vec![0u8; length];
+        self.read_into(offset, &mut v)?;
+        Ok(v)
+    }
+
+    /// Fills @buf with process data starting at @offset.
+    ///
+    /// Fails if the range is not within the process image, like [`read`](Self::read).
+    pub fn read_into(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        check_range(offset, buf.len())?;
+        self.inner.file.read_exact_at(buf, offset)
+    }
+
+    /// Writes process data at a specific position.
+    ///
+    /// Fails without writing anything if the range is not within the process image.
+    pub fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        check_range(offset, data.len())?;
+        self.inner.file.write_all_at(data, offset)
+    }
+
+    /// Reads @length bytes at @offset, lets @f change them and writes them back.
+    ///
+    /// Calls to `modify` through this handle and its clones are serialized, so concurrent
+    /// updates of the same bytes from different threads are not lost.
+    pub fn modify<F>(&self, offset: u64, length: usize, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        let _guard = self
+            .inner
+            .modify
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut data = self.read(offset, length)?;
+        f(&mut data);
+        self.write(offset, &data)
+    }
+
+    /// Get the info for a variable.
+    pub fn get_variable_info(&self, name: &str) -> Result<picontrol::SPIVariable> {
+        variable_info(self.inner.file.as_raw_fd(), name)
+    }
+
+    /// Gets a description of connected devices.
+    pub fn get_device_info_list(&self) -> Result<Vec<picontrol::SDeviceInfo>> {
+        device_info_list(self.inner.file.as_raw_fd())
+    }
+
+    /// Gets the value of one bit in the process image.
+    pub fn get_bit_value(&self, pSpiValue: &mut picontrol::SPIValue) -> Result<bool> {
+        bit_value(self.inner.file.as_raw_fd(), pSpiValue, ioctl::get_bit_value)
+    }
+
+    /// Sets the value of one bit in the process image.
+    pub fn set_bit_value(&self, pSpiValue: &mut picontrol::SPIValue) -> Result<bool> {
+        bit_value(self.inner.file.as_raw_fd(), pSpiValue, ioctl::set_bit_value)
+    }
+
+    /// Sets counters or encoders of a DIO/DI module to 0.
+    ///
+    /// Bit n of @bitfield selects the counter on input n + 1.
+    pub fn reset_counter(&self, address: u8, bitfield: u16) -> Result<()> {
+        counter_reset(self.inner.file.as_raw_fd(), address, bitfield)
+    }
+
+    /// Blocks until the driver reports an event, e.g. a reset.
+    pub fn wait_for_event(&self) -> Result<Event> {
+        event(self.inner.file.as_raw_fd())
+    }
+
+    /// Stops or restarts the I/O update, returns whether it is stopped now.
+    pub fn stop_io(&self, stop: bool) -> Result<bool> {
+        io_stop(self.inner.file.as_raw_fd(), stop)
+    }
+
+    /// Gets the message the driver left for the last ioctl call, if any.
+    pub fn last_message(&self) -> Result<Option<String>> {
+        last_message(self.inner.file.as_raw_fd())
+    }
+
+    /// dumps the process image to a file, truncating an existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `fp` - The file path
+    ///
+    pub fn dump(&self, fp: &str) -> io::Result<bool> {
+        // a second descriptor gets its own cursor, so the shared one is never moved
+        let mut image = File::open(&self.inner.path)?;
+        let mut outfile = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(fp)?;
+        let buffer = &mut vec![0; RevPiControl::SMALL_BUFFER_SIZE];
+        RevPiControl::redirect_stream(&mut image, &mut outfile, buffer)?;
+        Ok(true)
+    }
+}
+
+fn check_range(offset: u64, length: usize) -> io::Result<()> {
+    let end = offset.checked_add(length as u64);
+    if end.is_none_or(|end| end > PROCESS_IMAGE_SIZE as u64) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} bytes at offset {} are outside of the process image",
+                length, offset
+            ),
+        ));
+    }
+    Ok(())
+}
+
+impl AsRawFd for SharedRevPiControl {
+    /// The descriptor of the open device, shared by all clones.
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.file.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shared_handle_concurrent_access() {
+        assert_send_sync::<SharedRevPiControl>();
+
+        let path = std::env::temp_dir().join(format!("picontrol-shared-{}", std::process::id()));
+        std::fs::write(&path, vec![0u8; 64]).unwrap();
+        let control = SharedRevPiControl::open_at(path.to_str().unwrap()).unwrap();
+
+        let workers: Vec<_> = (0..8u8)
+            .map(|i| {
+                let control = control.clone();
+                thread::spawn(move || {
+                    control.write(u64::from(i) * 4, &[i; 4]).unwrap();
+                    for _ in 0..25 {
+                        control.modify(63, 1, |b| b[0] += 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+
+        for i in 0..8u8 {
+            assert_eq!(control.read(u64::from(i) * 4, 4).unwrap(), vec![i; 4]);
+        }
+        assert_eq!(control.read(63, 1).unwrap(), vec![200]);
+        assert!(control.read(4000, usize::MAX).is_err());
+        let err = control
+            .write(PROCESS_IMAGE_SIZE as u64 - 1, &[1, 2])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(control.read_into(u64::MAX, &mut [0]).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
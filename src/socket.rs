@@ -0,0 +1,622 @@
+//! The piControl operations over a Unix domain socket.
+//!
+//! [`SocketServer`] offers any [`Driver`], usually an [`Emulator`](crate::emulator::Emulator),
+//! on a socket and [`SocketDriver`] is the [`Driver`] that talks to it, so several processes
+//! can share one emulated RevPi.
+//!
+//! Every message is a frame of a little endian u32 length followed by that many bytes. A
+//! request starts with the operation code, followed by its arguments; the response starts with
+//! a status byte, [`STATUS_OK`] followed by the result, or an error:
+//!
+//! | operation              | arguments                      | result                     |
+//! |------------------------|--------------------------------|----------------------------|
+//! | 0 ping                 |                                |                            |
+//! | 1 read                 | offset u64, length u32         | length u32, bytes          |
+//! | 2 write                | offset u64, length u32, bytes  |                            |
+//! | 3 get_variable_info    | name                           | variable                   |
+//! | 4 get_device_info_list |                                | count u16, devices         |
+//! | 5 get_bit_value        | address u16, bit u8            | value u8                   |
+//! | 6 set_bit_value        | address u16, bit u8, value u8  |                            |
+//! | 7 reset                |                                |                            |
+//! | 8 reset_counter        | address u8, bitfield u16       |                            |
+//! | 9 stop_io              | stop u8                        | stopped u8                 |
+//! | 10 wait_for_event      |                                | event code i32             |
+//! | 11 subscribe_events    |                                |                            |
+//!
+//! Strings are a u16 length followed by UTF-8; a variable is its name, address u16, bit u8 and
+//! length u16; a device is the fields of SDeviceInfo in declaration order, without the reserve.
+//! An error is [`STATUS_ERROR`], an error kind u8 and the message, or [`STATUS_CONFIG_ERROR`],
+//! the PICONTROL_CONFIG_ERROR_* code i8, the module address u8 (255 if unknown) and the
+//! driver's message.
+
+use crate::byte_to_int8_array;
+use crate::driver::{Driver, Event};
+use crate::error::{ConfigError, ConfigErrorKind};
+use crate::picontrol::{self, SDeviceInfo, SPIValue, SPIVariable};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// Default socket path of the emulator daemon.
+pub const DEFAULT_SOCKET: &str = "/tmp/piControl0.sock";
+
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_ERROR: u8 = 1;
+pub const STATUS_CONFIG_ERROR: u8 = 2;
+
+const OP_PING: u8 = 0;
+const OP_READ: u8 = 1;
+const OP_WRITE: u8 = 2;
+const OP_GET_VARIABLE_INFO: u8 = 3;
+const OP_GET_DEVICE_INFO_LIST: u8 = 4;
+const OP_GET_BIT_VALUE: u8 = 5;
+const OP_SET_BIT_VALUE: u8 = 6;
+const OP_RESET: u8 = 7;
+const OP_RESET_COUNTER: u8 = 8;
+const OP_STOP_IO: u8 = 9;
+const OP_WAIT_FOR_EVENT: u8 = 10;
+const OP_SUBSCRIBE_EVENTS: u8 = 11;
+
+// frames larger than this are rejected, the biggest legitimate one is a full process image
+const MAX_FRAME: u32 = 1 << 20;
+
+// events queued for a connection that does not wait for them; later ones are dropped
+const MAX_QUEUED_EVENTS: usize = 64;
+
+fn read_frame(stream: &mut UnixStream) -> io::Result<Vec<u8>> {
+    let length = stream.read_u32::<LittleEndian>()?;
+    if length > MAX_FRAME {
+        return Err(invalid_data(format!(
+            "frame of {} bytes is too long",
+            length
+        )));
+    }
+    let mut frame = vec![0; length as usize];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+fn write_frame(stream: &mut UnixStream, frame: &[u8]) -> io::Result<()> {
+    let mut data = Vec::with_capacity(4 + frame.len());
+    data.write_u32::<LittleEndian>(frame.len() as u32)?;
+    data.extend_from_slice(frame);
+    stream.write_all(&data)
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    out.write_u16::<LittleEndian>(s.len() as u16).unwrap();
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(input: &mut Cursor<&[u8]>) -> io::Result<String> {
+    let length = input.read_u16::<LittleEndian>()?;
+    let mut data = vec![0; length as usize];
+    input.read_exact(&mut data)?;
+    String::from_utf8(data).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn read_bytes(input: &mut Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+    let length = input.read_u32::<LittleEndian>()?;
+    if length > MAX_FRAME {
+        return Err(invalid_data(format!("{} bytes are too many", length)));
+    }
+    let mut data = vec![0; length as usize];
+    input.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn write_variable(out: &mut Vec<u8>, variable: &SPIVariable) -> io::Result<()> {
+    let name = variable
+        .name()
+        .map_err(|e| invalid_data(format!("invalid variable name: {:?}", e)))?;
+    write_str(out, name);
+    out.write_u16::<LittleEndian>(variable.i16uAddress)?;
+    out.write_u8(variable.i8uBit)?;
+    out.write_u16::<LittleEndian>(variable.i16uLength)
+}
+
+fn read_variable(input: &mut Cursor<&[u8]>) -> io::Result<SPIVariable> {
+    let name = read_str(input)?;
+    if name.len() >= 32 {
+        return Err(invalid_data(format!("variable name {} is too long", name)));
+    }
+    Ok(SPIVariable {
+        strVarName: byte_to_int8_array(&name),
+        i16uAddress: input.read_u16::<LittleEndian>()?,
+        i8uBit: input.read_u8()?,
+        i16uLength: input.read_u16::<LittleEndian>()?,
+    })
+}
+
+fn write_device(out: &mut Vec<u8>, d: &SDeviceInfo) -> io::Result<()> {
+    out.write_u8(d.i8uAddress)?;
+    out.write_u32::<LittleEndian>(d.i32uSerialnumber)?;
+    for value in &[
+        d.i16uModuleType,
+        d.i16uHW_Revision,
+        d.i16uSW_Major,
+        d.i16uSW_Minor,
+    ] {
+        out.write_u16::<LittleEndian>(*value)?;
+    }
+    out.write_u32::<LittleEndian>(d.i32uSVN_Revision)?;
+    for value in &[
+        d.i16uInputLength,
+        d.i16uOutputLength,
+        d.i16uConfigLength,
+        d.i16uBaseOffset,
+        d.i16uInputOffset,
+        d.i16uOutputOffset,
+        d.i16uConfigOffset,
+        d.i16uFirstEntry,
+        d.i16uEntries,
+    ] {
+        out.write_u16::<LittleEndian>(*value)?;
+    }
+    out.write_u8(d.i8uModuleState)?;
+    out.write_u8(d.i8uActive)
+}
+
+fn read_device(input: &mut Cursor<&[u8]>) -> io::Result<SDeviceInfo> {
+    Ok(SDeviceInfo {
+        i8uAddress: input.read_u8()?,
+        i32uSerialnumber: input.read_u32::<LittleEndian>()?,
+        i16uModuleType: input.read_u16::<LittleEndian>()?,
+        i16uHW_Revision: input.read_u16::<LittleEndian>()?,
+        i16uSW_Major: input.read_u16::<LittleEndian>()?,
+        i16uSW_Minor: input.read_u16::<LittleEndian>()?,
+        i32uSVN_Revision: input.read_u32::<LittleEndian>()?,
+        i16uInputLength: input.read_u16::<LittleEndian>()?,
+        i16uOutputLength: input.read_u16::<LittleEndian>()?,
+        i16uConfigLength: input.read_u16::<LittleEndian>()?,
+        i16uBaseOffset: input.read_u16::<LittleEndian>()?,
+        i16uInputOffset: input.read_u16::<LittleEndian>()?,
+        i16uOutputOffset: input.read_u16::<LittleEndian>()?,
+        i16uConfigOffset: input.read_u16::<LittleEndian>()?,
+        i16uFirstEntry: input.read_u16::<LittleEndian>()?,
+        i16uEntries: input.read_u16::<LittleEndian>()?,
+        i8uModuleState: input.read_u8()?,
+        i8uActive: input.read_u8()?,
+        ..Default::default()
+    })
+}
+
+const KINDS: &[(u8, io::ErrorKind)] = &[
+    (1, io::ErrorKind::NotFound),
+    (2, io::ErrorKind::PermissionDenied),
+    (3, io::ErrorKind::InvalidInput),
+    (4, io::ErrorKind::InvalidData),
+    (5, io::ErrorKind::Unsupported),
+    (6, io::ErrorKind::UnexpectedEof),
+    (7, io::ErrorKind::WouldBlock),
+];
+
+fn write_error(out: &mut Vec<u8>, err: &io::Error) {
+    match ConfigError::from_io(err) {
+        Some(config_error) => {
+            out.push(STATUS_CONFIG_ERROR);
+            out.push(config_error.kind.code() as u8);
+            out.push(config_error.module.unwrap_or(255));
+            write_str(out, config_error.message.as_deref().unwrap_or(""));
+        }
+        None => {
+            out.push(STATUS_ERROR);
+            let kind = KINDS
+                .iter()
+                .find(|(_, kind)| *kind == err.kind())
+                .map_or(0, |(code, _)| *code);
+            out.push(kind);
+            write_str(out, &err.to_string());
+        }
+    }
+}
+
+fn read_error(status: u8, input: &mut Cursor<&[u8]>) -> io::Result<io::Error> {
+    match status {
+        STATUS_CONFIG_ERROR => {
+            let code = input.read_i8()?;
+            let module = input.read_u8()?;
+            let message = read_str(input)?;
+            let kind = ConfigErrorKind::from_code(i32::from(code))
+                .ok_or_else(|| invalid_data(format!("unknown configuration error {}", code)))?;
+            Ok(ConfigError {
+                kind,
+                module: Some(module).filter(|&m| m != 255),
+                message: Some(message).filter(|m| !m.is_empty()),
+            }
+            .into())
+        }
+        STATUS_ERROR => {
+            let code = input.read_u8()?;
+            let message = read_str(input)?;
+            let kind = KINDS
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map_or(io::ErrorKind::Other, |(_, kind)| *kind);
+            Ok(io::Error::new(kind, message))
+        }
+        _ => Err(invalid_data(format!("unknown response status {}", status))),
+    }
+}
+
+fn event_code(event: Event) -> i32 {
+    match event {
+        Event::Reset => picontrol::KB_EVENT_RESET as i32,
+        Event::Unknown(code) => code,
+    }
+}
+
+/// SocketServer offers a [`Driver`] on a Unix domain socket.
+///
+/// Each connection is served by its own thread. Driver events are delivered to every
+/// connection that subscribed to them before they happened, with subscribe_events or its
+/// first wait_for_event; connections that never wait for events do not queue any. A connection
+/// that stops waiting keeps at most 64 events, later ones are dropped for it.
+pub struct SocketServer<D> {
+    listener: UnixListener,
+    driver: Arc<D>,
+    subscribers: Arc<Subscribers>,
+}
+
+impl<D: Driver + 'static> SocketServer<D> {
+    /// Binds to @path. A socket file left behind by a server that is gone is replaced; any
+    /// other file at @path is left alone and binding fails.
+    pub fn bind<P: AsRef<Path>>(path: P, driver: Arc<D>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let listener = match UnixListener::bind(path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && is_stale_socket(path) => {
+                std::fs::remove_file(path)?;
+                UnixListener::bind(path)?
+            }
+            result => result?,
+        };
+        Ok(SocketServer {
+            listener,
+            driver,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// Accepts and serves connections until accepting fails.
+    pub fn run(self) -> io::Result<()> {
+        let driver = Arc::clone(&self.driver);
+        let subscribers = Arc::clone(&self.subscribers);
+        thread::spawn(move || {
+            while let Ok(event) = driver.wait_for_event() {
+                lock(&subscribers)
+                    .retain(|s| !matches!(s.try_send(event), Err(TrySendError::Disconnected(_))));
+            }
+        });
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let driver = Arc::clone(&self.driver);
+            let subscribers = Arc::clone(&self.subscribers);
+            thread::spawn(move || serve(&*driver, stream, &subscribers));
+        }
+        Ok(())
+    }
+}
+
+/// Whether @path is a socket no server accepts connections on.
+fn is_stale_socket(path: &Path) -> bool {
+    let is_socket = std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    is_socket && UnixStream::connect(path).is_err()
+}
+
+fn subscribe(subscribers: &Subscribers) -> Receiver<Event> {
+    let (sender, events) = mpsc::sync_channel(MAX_QUEUED_EVENTS);
+    lock(subscribers).push(sender);
+    events
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Serves one connection until the client closes it.
+type Subscribers = Mutex<Vec<SyncSender<Event>>>;
+
+fn serve<D: Driver + ?Sized>(driver: &D, mut stream: UnixStream, subscribers: &Subscribers) {
+    // subscribed on demand, so idle connections do not accumulate events
+    let mut events = None;
+    while let Ok(request) = read_frame(&mut stream) {
+        let mut response = vec![STATUS_OK];
+        if let Err(err) = handle(driver, &request, subscribers, &mut events, &mut response) {
+            response.clear();
+            write_error(&mut response, &err);
+        }
+        if write_frame(&mut stream, &response).is_err() {
+            return;
+        }
+    }
+}
+
+fn handle<D: Driver + ?Sized>(
+    driver: &D,
+    request: &[u8],
+    subscribers: &Subscribers,
+    events: &mut Option<Receiver<Event>>,
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    let mut input = Cursor::new(request);
+    match input.read_u8()? {
+        OP_PING => {}
+        OP_READ => {
+            let offset = input.read_u64::<LittleEndian>()?;
+            let length = input.read_u32::<LittleEndian>()?;
+            if length > MAX_FRAME / 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("can not read {} bytes at once", length),
+                ));
+            }
+            let data = driver.read(offset, length as usize)?;
+            out.write_u32::<LittleEndian>(data.len() as u32)?;
+            out.extend_from_slice(&data);
+        }
+        OP_WRITE => {
+            let offset = input.read_u64::<LittleEndian>()?;
+            let data = read_bytes(&mut input)?;
+            driver.write(offset, &data)?;
+        }
+        OP_GET_VARIABLE_INFO => {
+            let name = read_str(&mut input)?;
+            write_variable(out, &driver.get_variable_info(&name)?)?;
+        }
+        OP_GET_DEVICE_INFO_LIST => {
+            let devices = driver.get_device_info_list()?;
+            out.write_u16::<LittleEndian>(devices.len() as u16)?;
+            for device in &devices {
+                write_device(out, device)?;
+            }
+        }
+        OP_GET_BIT_VALUE => {
+            let mut value = SPIValue {
+                i16uAddress: input.read_u16::<LittleEndian>()?,
+                i8uBit: input.read_u8()?,
+                ..Default::default()
+            };
+            driver.get_bit_value(&mut value)?;
+            out.push(value.i8uValue);
+        }
+        OP_SET_BIT_VALUE => {
+            let mut value = SPIValue {
+                i16uAddress: input.read_u16::<LittleEndian>()?,
+                i8uBit: input.read_u8()?,
+                i8uValue: input.read_u8()?,
+            };
+            driver.set_bit_value(&mut value)?;
+        }
+        OP_RESET => driver.reset()?,
+        OP_RESET_COUNTER => {
+            let address = input.read_u8()?;
+            let bitfield = input.read_u16::<LittleEndian>()?;
+            driver.reset_counter(address, bitfield)?;
+        }
+        OP_STOP_IO => {
+            let stop = input.read_u8()? != 0;
+            out.push(driver.stop_io(stop)? as u8);
+        }
+        OP_SUBSCRIBE_EVENTS => {
+            events.get_or_insert_with(|| subscribe(subscribers));
+        }
+        OP_WAIT_FOR_EVENT => {
+            let event = events
+                .get_or_insert_with(|| subscribe(subscribers))
+                .recv()
+                .map_err(|_| io::Error::other("the server no longer receives driver events"))?;
+            out.write_i32::<LittleEndian>(event_code(event))?;
+        }
+        op => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unknown operation {}", op),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// SocketDriver is the [`Driver`] of a process image offered by a [`SocketServer`].
+pub struct SocketDriver {
+    path: PathBuf,
+    stream: Mutex<UnixStream>,
+    // wait_for_event blocks, so it has a connection of its own
+    events: Mutex<UnixStream>,
+}
+
+impl SocketDriver {
+    /// Connects to the server at @path.
+    ///
+    /// The connection receives the events that happen from the first
+    /// [`wait_for_event`](Driver::wait_for_event) on, so a client that never waits does not
+    /// make the server queue events for it.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let connect = || {
+            UnixStream::connect(path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("can not connect to {}: {}", path.display(), e),
+                )
+            })
+        };
+        Ok(SocketDriver {
+            path: path.to_owned(),
+            stream: Mutex::new(connect()?),
+            events: Mutex::new(connect()?),
+        })
+    }
+
+    /// The socket path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn call(&self, request: &[u8]) -> io::Result<Vec<u8>> {
+        call(&mut lock(&self.stream), request)
+    }
+}
+
+fn call(stream: &mut UnixStream, request: &[u8]) -> io::Result<Vec<u8>> {
+    write_frame(stream, request)?;
+    let response = read_frame(stream)?;
+    match response.first() {
+        Some(&STATUS_OK) => Ok(response[1..].to_vec()),
+        Some(&status) => Err(read_error(status, &mut Cursor::new(&response[1..]))?),
+        None => Err(invalid_data("empty response".to_owned())),
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Driver for SocketDriver {
+    fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let mut request = vec![OP_READ];
+        request.write_u64::<LittleEndian>(offset)?;
+        request.write_u32::<LittleEndian>(length as u32)?;
+        let response = self.call(&request)?;
+        read_bytes(&mut Cursor::new(&response))
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut request = vec![OP_WRITE];
+        request.write_u64::<LittleEndian>(offset)?;
+        request.write_u32::<LittleEndian>(data.len() as u32)?;
+        request.extend_from_slice(data);
+        self.call(&request).map(|_| ())
+    }
+
+    fn get_variable_info(&self, name: &str) -> io::Result<SPIVariable> {
+        let mut request = vec![OP_GET_VARIABLE_INFO];
+        write_str(&mut request, name);
+        let response = self.call(&request)?;
+        read_variable(&mut Cursor::new(&response))
+    }
+
+    fn get_device_info_list(&self) -> io::Result<Vec<SDeviceInfo>> {
+        let response = self.call(&[OP_GET_DEVICE_INFO_LIST])?;
+        let mut input = Cursor::new(response.as_slice());
+        let count = input.read_u16::<LittleEndian>()?;
+        (0..count).map(|_| read_device(&mut input)).collect()
+    }
+
+    fn get_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        let mut request = vec![OP_GET_BIT_VALUE];
+        request.write_u16::<LittleEndian>(value.i16uAddress)?;
+        request.push(value.i8uBit);
+        let response = self.call(&request)?;
+        value.i8uValue = Cursor::new(&response).read_u8()?;
+        Ok(true)
+    }
+
+    fn set_bit_value(&self, value: &mut SPIValue) -> io::Result<bool> {
+        let mut request = vec![OP_SET_BIT_VALUE];
+        request.write_u16::<LittleEndian>(value.i16uAddress)?;
+        request.push(value.i8uBit);
+        request.push(value.i8uValue);
+        self.call(&request).map(|_| true)
+    }
+
+    fn reset(&self) -> io::Result<()> {
+        self.call(&[OP_RESET]).map(|_| ())
+    }
+
+    fn reset_counter(&self, address: u8, bitfield: u16) -> io::Result<()> {
+        let mut request = vec![OP_RESET_COUNTER, address];
+        request.write_u16::<LittleEndian>(bitfield)?;
+        self.call(&request).map(|_| ())
+    }
+
+    fn stop_io(&self, stop: bool) -> io::Result<bool> {
+        let response = self.call(&[OP_STOP_IO, stop as u8])?;
+        Ok(Cursor::new(&response).read_u8()? != 0)
+    }
+
+    fn wait_for_event(&self) -> io::Result<Event> {
+        let response = call(&mut lock(&self.events), &[OP_WAIT_FOR_EVENT])?;
+        Ok(Event::from(
+            Cursor::new(&response).read_i32::<LittleEndian>()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::config::Config;
+    use crate::emulator::Emulator;
+    use crate::value::{Value, Variable};
+    use crate::MemoryDriver;
+
+    #[test]
+    fn socket_round_trip() {
+        let path = std::env::temp_dir().join(format!("picontrol-test-{}.sock", std::process::id()));
+        let emulator = Arc::new(Emulator::from_config(Config::parse(CONFIG).unwrap()).unwrap());
+        let server = SocketServer::bind(&path, Arc::clone(&emulator)).unwrap();
+        let subscribers = Arc::clone(&server.subscribers);
+        thread::spawn(move || server.run());
+
+        let client = SocketDriver::connect(&path).unwrap();
+        let other = SocketDriver::connect(&path).unwrap();
+        // nobody waits for events yet
+        assert!(lock(&subscribers).is_empty());
+        client.write(7, &[0b101]).unwrap();
+        assert_eq!(other.read(7, 1).unwrap(), vec![0b101]);
+        assert_eq!(emulator.read(7, 1).unwrap(), vec![0b101]);
+
+        let o_10 = Variable::resolve(&client, "O_10").unwrap();
+        assert_eq!((o_10.address, o_10.bit), (78, 1));
+        o_10.write(&client, Value::Bool(true)).unwrap();
+        assert_eq!(o_10.read(&other).unwrap(), Value::Bool(true));
+
+        let devices = other.get_device_info_list().unwrap();
+        assert_eq!(devices.len(), 3);
+        assert_eq!(
+            (devices[2].i8uAddress, devices[2].i16uModuleType),
+            (33, 103)
+        );
+        assert_eq!(devices[2].i16uOutputOffset, 122);
+        assert!(client.stop_io(true).unwrap());
+
+        let err = client.get_variable_info("missing").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(client.read(4095, 2).is_err());
+
+        let waiter = thread::spawn(move || other.wait_for_event().unwrap());
+        while lock(&subscribers).is_empty() {
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        client.reset().unwrap();
+        assert_eq!(waiter.join().unwrap(), Event::Reset);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bind_replaces_only_stale_sockets() {
+        let dir = std::env::temp_dir();
+        let file = dir.join(format!("picontrol-test-{}.rsc", std::process::id()));
+        std::fs::write(&file, "{}").unwrap();
+        let err = SocketServer::bind(&file, Arc::new(MemoryDriver::new())).err();
+        assert_eq!(err.unwrap().kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "{}");
+        std::fs::remove_file(&file).unwrap();
+
+        let path = dir.join(format!("picontrol-stale-{}.sock", std::process::id()));
+        drop(UnixListener::bind(&path).unwrap());
+        SocketServer::bind(&path, Arc::new(MemoryDriver::new())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Comparison of a piCtory configuration with the modules the driver found.
+//!
+//! The driver only sets PICONTROL_STATUS_MISSING_MODULE, PICONTROL_STATUS_EXTRA_MODULE or
+//! PICONTROL_STATUS_SIZE_MISMATCH; [`validate`] tells which module causes them.
+
+use crate::config::{Config, Direction};
+use crate::driver::Driver;
+use crate::get_module_name;
+use crate::picontrol::{self, SDeviceInfo};
+use std::fmt;
+use std::io;
+
+/// One disagreement between the configuration and the device list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A configured module is not present.
+    Missing { position: u8, name: String },
+    /// A module is present, but not configured.
+    Unconfigured { position: u8, module_type: u32 },
+    /// The module at a position is of another type than configured.
+    TypeMismatch {
+        position: u8,
+        configured: u32,
+        found: u32,
+    },
+    /// The offset or length of an input or output area disagree; areas are (offset, length).
+    AreaMismatch {
+        position: u8,
+        direction: Direction,
+        configured: (u16, u16),
+        found: (u16, u16),
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing { position, name } => {
+                write!(
+                    f,
+                    "{} at address {} is configured, but missing",
+                    name, position
+                )
+            }
+            Problem::Unconfigured {
+                position,
+                module_type,
+            } => write!(
+                f,
+                "{} at address {} is present, but not configured",
+                get_module_name(*module_type),
+                position
+            ),
+            Problem::TypeMismatch {
+                position,
+                configured,
+                found,
+            } => write!(
+                f,
+                "address {} is configured as {}, but a {} is present",
+                position,
+                get_module_name(*configured),
+                get_module_name(*found)
+            ),
+            Problem::AreaMismatch {
+                position,
+                direction,
+                configured,
+                found,
+            } => write!(
+                f,
+                "{} area of address {} is configured at offset {} length {}, \
+                 the driver has offset {} length {}",
+                direction, position, configured.0, configured.1, found.0, found.1
+            ),
+        }
+    }
+}
+
+/// The result of [`validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// The configuration matches the device list.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The PICONTROL_STATUS_* bits the problems account for.
+    pub fn status(&self) -> u32 {
+        self.problems.iter().fold(0, |status, p| {
+            status
+                | match p {
+                    Problem::Missing { .. } => picontrol::PICONTROL_STATUS_MISSING_MODULE,
+                    Problem::Unconfigured { .. } => picontrol::PICONTROL_STATUS_EXTRA_MODULE,
+                    Problem::TypeMismatch { .. } | Problem::AreaMismatch { .. } => {
+                        picontrol::PICONTROL_STATUS_SIZE_MISMATCH
+                    }
+                }
+        })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return writeln!(f, "configuration matches the device list");
+        }
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares @config with the device list @devices, as returned by get_device_info_list.
+pub fn validate(config: &Config, devices: &[SDeviceInfo]) -> Report {
+    let mut problems = Vec::new();
+    for device in &config.devices {
+        let found = match devices.iter().find(|d| d.i8uAddress == device.position) {
+            Some(found) => found,
+            None => {
+                problems.push(Problem::Missing {
+                    position: device.position,
+                    name: device.name.clone(),
+                });
+                continue;
+            }
+        };
+        let module_type = u32::from(found.i16uModuleType);
+        if found.i8uActive == 0 && not_connected(found) {
+            problems.push(Problem::Missing {
+                position: device.position,
+                name: device.name.clone(),
+            });
+            continue;
+        }
+        let module_type = module_type & picontrol::PICONTROL_NOT_CONNECTED_MASK;
+        if module_type != device.product_type {
+            problems.push(Problem::TypeMismatch {
+                position: device.position,
+                configured: device.product_type,
+                found: module_type,
+            });
+            continue;
+        }
+        let areas = [
+            (
+                Direction::Input,
+                (found.i16uInputOffset, found.i16uInputLength),
+            ),
+            (
+                Direction::Output,
+                (found.i16uOutputOffset, found.i16uOutputLength),
+            ),
+        ];
+        for &(direction, found) in &areas {
+            // a section without variables is an empty area, wherever the driver puts it
+            let (configured, mismatch) = match device.area(direction) {
+                Some(configured) => (configured, configured != found),
+                None => ((device.offset, 0), found.1 != 0),
+            };
+            if mismatch {
+                problems.push(Problem::AreaMismatch {
+                    position: device.position,
+                    direction,
+                    configured,
+                    found,
+                });
+            }
+        }
+    }
+    for found in devices {
+        let module_type = u32::from(found.i16uModuleType);
+        let unconfigured = config.device(found.i8uAddress).is_none()
+            || (found.i8uActive == 0 && !not_connected(found));
+        if unconfigured {
+            problems.push(Problem::Unconfigured {
+                position: found.i8uAddress,
+                module_type: module_type & picontrol::PICONTROL_NOT_CONNECTED_MASK,
+            });
+        }
+    }
+    Report { problems }
+}
+
+// piControl flags a configured module it did not find with PICONTROL_NOT_CONNECTED.
+fn not_connected(device: &SDeviceInfo) -> bool {
+    u32::from(device.i16uModuleType) & picontrol::PICONTROL_NOT_CONNECTED != 0
+}
+
+/// Compares @config with the device list of @driver.
+pub fn validate_driver<D: Driver + ?Sized>(driver: &D, config: &Config) -> io::Result<Report> {
+    Ok(validate(config, &driver.get_device_info_list()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+
+    fn device(address: u8, module_type: u16, input: (u16, u16), output: (u16, u16)) -> SDeviceInfo {
+        SDeviceInfo {
+            i8uAddress: address,
+            i8uActive: 1,
+            i16uModuleType: module_type,
+            i16uInputOffset: input.0,
+            i16uInputLength: input.1,
+            i16uOutputOffset: output.0,
+            i16uOutputLength: output.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validate_device_list() {
+        let config = Config::parse(CONFIG).unwrap();
+        let core = device(0, 95, (0, 6), (6, 1));
+        let dio = device(32, 96, (7, 70), (77, 18));
+        let aio = device(33, 103, (102, 20), (122, 4));
+        assert!(validate(&config, &[core, dio, aio]).is_ok());
+
+        let missing = SDeviceInfo {
+            i8uActive: 0,
+            i16uModuleType: 96 | picontrol::PICONTROL_NOT_CONNECTED as u16,
+            ..dio
+        };
+        let extra = SDeviceInfo {
+            i8uActive: 0,
+            ..device(34, 97, (0, 0), (0, 0))
+        };
+        let short = device(33, 103, (102, 18), (122, 4));
+        let report = validate(&config, &[core, missing, short, extra]);
+        assert_eq!(
+            report.problems,
+            vec![
+                Problem::Missing {
+                    position: 32,
+                    name: "RevPi DIO".to_owned()
+                },
+                Problem::AreaMismatch {
+                    position: 33,
+                    direction: Direction::Input,
+                    configured: (102, 20),
+                    found: (102, 18)
+                },
+                Problem::Unconfigured {
+                    position: 34,
+                    module_type: 97
+                },
+            ]
+        );
+        assert_eq!(
+            report.status(),
+            picontrol::PICONTROL_STATUS_MISSING_MODULE
+                | picontrol::PICONTROL_STATUS_EXTRA_MODULE
+                | picontrol::PICONTROL_STATUS_SIZE_MISMATCH
+        );
+        assert_eq!(
+            report.problems[2].to_string(),
+            "RevPi DI at address 34 is present, but not configured"
+        );
+
+        let mut no_outputs = config.clone();
+        no_outputs.devices[2]
+            .entries
+            .retain(|e| e.direction != Direction::Output);
+        assert_eq!(
+            validate(&no_outputs, &[core, dio, aio]).problems,
+            vec![Problem::AreaMismatch {
+                position: 33,
+                direction: Direction::Output,
+                configured: (102, 0),
+                found: (122, 4)
+            }]
+        );
+        let empty = device(33, 103, (102, 20), (122, 0));
+        assert!(validate(&no_outputs, &[core, dio, empty]).is_ok());
+    }
+}
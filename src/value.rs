@@ -0,0 +1,537 @@
+//! Typed and scaled access to process image variables.
+//!
+//! The driver only knows a variable's address, bit and length. [`Variable`] adds how the bytes
+//! are to be interpreted ([`VariableType`]) and how the raw number maps to a physical quantity
+//! ([`Scaling`] and a unit), e.g. an AIO temperature reported in 0.1 °C steps.
+
+use crate::driver::Driver;
+use crate::picontrol::{SPIValue, SPIVariable};
+use byteorder::{ByteOrder, LittleEndian};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// How the bytes of a variable are interpreted. All multi-byte values are little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VariableType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl VariableType {
+    /// The length of the type in bits, as reported by KB_FIND_VARIABLE.
+    pub fn bit_length(self) -> u16 {
+        match self {
+            VariableType::Bool => 1,
+            VariableType::U8 | VariableType::I8 => 8,
+            VariableType::U16 | VariableType::I16 => 16,
+            VariableType::U32 | VariableType::I32 | VariableType::F32 => 32,
+        }
+    }
+
+    /// The unsigned type the driver implies for a variable of @length bits.
+    pub fn unsigned_for_length(length: u16) -> io::Result<VariableType> {
+        match length {
+            1 => Ok(VariableType::Bool),
+            8 => Ok(VariableType::U8),
+            16 => Ok(VariableType::U16),
+            32 => Ok(VariableType::U32),
+            _ => Err(invalid_input(format!("invalid variable length {}", length))),
+        }
+    }
+
+    /// Decodes a value of this type from the start of @data.
+    ///
+    /// For [`VariableType::Bool`] the lowest bit of the first byte is used.
+    pub fn decode(self, data: &[u8]) -> io::Result<Value> {
+        let size = (self.bit_length() as usize).div_ceil(8);
+        if data.len() < size {
+            return Err(invalid_input(format!(
+                "{} bytes are too short for a {} value",
+                data.len(),
+                self
+            )));
+        }
+        Ok(match self {
+            VariableType::Bool => Value::Bool(data[0] & 1 != 0),
+            VariableType::U8 => Value::U8(data[0]),
+            VariableType::I8 => Value::I8(data[0] as i8),
+            VariableType::U16 => Value::U16(LittleEndian::read_u16(data)),
+            VariableType::I16 => Value::I16(LittleEndian::read_i16(data)),
+            VariableType::U32 => Value::U32(LittleEndian::read_u32(data)),
+            VariableType::I32 => Value::I32(LittleEndian::read_i32(data)),
+            VariableType::F32 => Value::F32(LittleEndian::read_f32(data)),
+        })
+    }
+
+    /// Converts @number to this type, rounding to the nearest integer for integer types.
+    ///
+    /// Fails if @number does not fit the type.
+    pub fn from_f64(self, number: f64) -> io::Result<Value> {
+        let rounded = number.round();
+        let out_of_range = || invalid_input(format!("{} is out of range for {}", number, self));
+        macro_rules! integer {
+            ($variant:ident, $t:ty) => {
+                if rounded >= <$t>::MIN as f64 && rounded <= <$t>::MAX as f64 {
+                    Value::$variant(rounded as $t)
+                } else {
+                    return Err(out_of_range());
+                }
+            };
+        }
+        Ok(match self {
+            VariableType::Bool => Value::Bool(number != 0.0),
+            VariableType::U8 => integer!(U8, u8),
+            VariableType::I8 => integer!(I8, i8),
+            VariableType::U16 => integer!(U16, u16),
+            VariableType::I16 => integer!(I16, i16),
+            VariableType::U32 => integer!(U32, u32),
+            VariableType::I32 => integer!(I32, i32),
+            VariableType::F32 => Value::F32(number as f32),
+        })
+    }
+
+    /// Parses the textual form of a value of this type, e.g. "-12" or "0x1f".
+    pub fn parse_value(self, s: &str) -> io::Result<Value> {
+        let invalid = || invalid_input(format!("invalid {} value {}", self, s));
+        let s = s.trim();
+        if self == VariableType::F32 {
+            return s.parse().map(Value::F32).map_err(|_| invalid());
+        }
+        if self == VariableType::Bool {
+            return match s {
+                "0" | "false" => Ok(Value::Bool(false)),
+                "1" | "true" => Ok(Value::Bool(true)),
+                _ => Err(invalid()),
+            };
+        }
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (radix, digits) = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(hex) => (16, hex),
+            None => (10, digits),
+        };
+        // from_str_radix takes a sign of its own, the one above is the only one allowed
+        if digits.starts_with(['+', '-']) {
+            return Err(invalid());
+        }
+        let magnitude = i64::from_str_radix(digits, radix).map_err(|_| invalid())?;
+        let number = if negative { -magnitude } else { magnitude };
+        self.from_f64(number as f64).map_err(|_| invalid())
+    }
+}
+
+impl FromStr for VariableType {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s {
+            "bool" => Ok(VariableType::Bool),
+            "u8" => Ok(VariableType::U8),
+            "i8" => Ok(VariableType::I8),
+            "u16" => Ok(VariableType::U16),
+            "i16" => Ok(VariableType::I16),
+            "u32" => Ok(VariableType::U32),
+            "i32" => Ok(VariableType::I32),
+            "f32" => Ok(VariableType::F32),
+            _ => Err(invalid_input(format!("unknown variable type {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            VariableType::Bool => "bool",
+            VariableType::U8 => "u8",
+            VariableType::I8 => "i8",
+            VariableType::U16 => "u16",
+            VariableType::I16 => "i16",
+            VariableType::U32 => "u32",
+            VariableType::I32 => "i32",
+            VariableType::F32 => "f32",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A decoded variable value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+}
+
+impl Value {
+    /// The type of the value.
+    pub fn kind(&self) -> VariableType {
+        match self {
+            Value::Bool(_) => VariableType::Bool,
+            Value::U8(_) => VariableType::U8,
+            Value::I8(_) => VariableType::I8,
+            Value::U16(_) => VariableType::U16,
+            Value::I16(_) => VariableType::I16,
+            Value::U32(_) => VariableType::U32,
+            Value::I32(_) => VariableType::I32,
+            Value::F32(_) => VariableType::F32,
+        }
+    }
+
+    /// The value as a floating point number, booleans are 0 or 1.
+    pub fn as_f64(&self) -> f64 {
+        match *self {
+            Value::Bool(v) => v as u8 as f64,
+            Value::U8(v) => v.into(),
+            Value::I8(v) => v.into(),
+            Value::U16(v) => v.into(),
+            Value::I16(v) => v.into(),
+            Value::U32(v) => v.into(),
+            Value::I32(v) => v.into(),
+            Value::F32(v) => v.into(),
+        }
+    }
+
+    /// The little endian byte representation, a single byte 0 or 1 for booleans.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            Value::Bool(v) => vec![v as u8],
+            Value::U8(v) => vec![v],
+            Value::I8(v) => vec![v as u8],
+            Value::U16(v) => v.to_le_bytes().to_vec(),
+            Value::I16(v) => v.to_le_bytes().to_vec(),
+            Value::U32(v) => v.to_le_bytes().to_vec(),
+            Value::I32(v) => v.to_le_bytes().to_vec(),
+            Value::F32(v) => v.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(v) => write!(f, "{}", *v as u8),
+            Value::U8(v) => write!(f, "{}", v),
+            Value::I8(v) => write!(f, "{}", v),
+            Value::U16(v) => write!(f, "{}", v),
+            Value::I16(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::I32(v) => write!(f, "{}", v),
+            Value::F32(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// A linear conversion between the raw value and the physical quantity:
+/// `physical = raw * multiplier / divisor + offset`.
+///
+/// This is the same scaling the AIO module applies with its Multiplier, Divisor and Offset
+/// settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scaling {
+    multiplier: f64,
+    divisor: f64,
+    offset: f64,
+}
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Scaling {
+            multiplier: 1.0,
+            divisor: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Scaling {
+    /// Fails if @multiplier or @divisor is 0, either would make the conversion one way.
+    pub fn new(multiplier: f64, divisor: f64, offset: f64) -> io::Result<Self> {
+        if multiplier == 0.0 || divisor == 0.0 {
+            return Err(invalid_input(format!(
+                "invalid scaling: multiplier {} divisor {}",
+                multiplier, divisor
+            )));
+        }
+        Ok(Scaling {
+            multiplier,
+            divisor,
+            offset,
+        })
+    }
+
+    /// The factor the raw value is multiplied with.
+    pub fn multiplier(&self) -> f64 {
+        self.multiplier
+    }
+
+    /// The divisor of the multiplied raw value.
+    pub fn divisor(&self) -> f64 {
+        self.divisor
+    }
+
+    /// Added after multiplying and dividing.
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Converts a raw value to the physical quantity.
+    pub fn to_physical(&self, raw: f64) -> f64 {
+        raw * self.multiplier / self.divisor + self.offset
+    }
+
+    /// Converts a physical quantity back to the raw value.
+    pub fn to_raw(&self, physical: f64) -> f64 {
+        (physical - self.offset) * self.divisor / self.multiplier
+    }
+}
+
+/// A physical quantity, the scaled value of a variable with its unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {}", self.value, unit),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Variable is a process image variable with its type, scaling and engineering unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// Address of the first byte in the process image.
+    pub address: u16,
+    /// 0-7 bit position for boolean variables.
+    pub bit: u8,
+    /// Length of the variable in bits.
+    pub length: u16,
+    pub kind: VariableType,
+    pub scaling: Scaling,
+    pub unit: Option<String>,
+}
+
+impl Variable {
+    /// Creates an unsigned, unscaled variable of @length bits.
+    pub fn new(name: &str, address: u16, bit: u8, length: u16) -> io::Result<Variable> {
+        Ok(Variable {
+            name: name.to_owned(),
+            address: address.checked_add(u16::from(bit / 8)).ok_or_else(|| {
+                invalid_input(format!("variable {} is beyond the address space", name))
+            })?,
+            bit: bit % 8,
+            length,
+            kind: VariableType::unsigned_for_length(length)?,
+            scaling: Scaling::default(),
+            unit: None,
+        })
+    }
+
+    /// Creates a variable from the driver's description of it.
+    pub fn from_spi(variable: &SPIVariable) -> io::Result<Variable> {
+        let name = variable
+            .name()
+            .map_err(|e| invalid_input(format!("invalid variable name: {:?}", e)))?;
+        Self::new(
+            name,
+            variable.i16uAddress,
+            variable.i8uBit,
+            variable.i16uLength,
+        )
+    }
+
+    /// Looks the variable up in the driver.
+    pub fn resolve<D: Driver + ?Sized>(driver: &D, name: &str) -> io::Result<Variable> {
+        Self::from_spi(&driver.get_variable_info(name)?)
+    }
+
+    /// Interprets the variable as @kind, which must have the variable's length.
+    pub fn with_type(mut self, kind: VariableType) -> io::Result<Variable> {
+        if kind.bit_length() != self.length {
+            return Err(invalid_input(format!(
+                "variable {} has {} bits, {} has {}",
+                self.name,
+                self.length,
+                kind,
+                kind.bit_length()
+            )));
+        }
+        self.kind = kind;
+        Ok(self)
+    }
+
+    /// Attaches a scaling to the variable.
+    pub fn with_scaling(mut self, scaling: Scaling) -> Variable {
+        self.scaling = scaling;
+        self
+    }
+
+    /// Attaches an engineering unit to the variable, e.g. "°C" or "mV".
+    pub fn with_unit(mut self, unit: &str) -> Variable {
+        self.unit = Some(unit.to_owned());
+        self
+    }
+
+    /// Number of bytes the variable occupies.
+    pub fn byte_length(&self) -> usize {
+        (self.length as usize).div_ceil(8)
+    }
+
+    /// Decodes the variable from a snapshot of the whole process image.
+    pub fn decode(&self, image: &[u8]) -> io::Result<Value> {
+        let start = self.address as usize;
+        let data = image
+            .get(start..start + self.byte_length())
+            .ok_or_else(|| {
+                invalid_input(format!("variable {} is outside of the image", self.name))
+            })?;
+        if self.kind == VariableType::Bool {
+            return Ok(Value::Bool(data[0] >> self.bit & 1 != 0));
+        }
+        self.kind.decode(data)
+    }
+
+    /// Decodes the variable from a snapshot of the whole process image and scales it.
+    pub fn decode_quantity(&self, image: &[u8]) -> io::Result<Quantity> {
+        Ok(self.quantity(self.decode(image)?))
+    }
+
+    /// Reads the current value.
+    pub fn read<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<Value> {
+        if self.kind == VariableType::Bool {
+            let mut value = SPIValue {
+                i16uAddress: self.address,
+                i8uBit: self.bit,
+                ..Default::default()
+            };
+            driver.get_bit_value(&mut value)?;
+            return Ok(Value::Bool(value.i8uValue != 0));
+        }
+        let data = driver.read(u64::from(self.address), self.byte_length())?;
+        self.kind.decode(&data)
+    }
+
+    /// Reads the current value and scales it to the physical quantity.
+    pub fn read_quantity<D: Driver + ?Sized>(&self, driver: &D) -> io::Result<Quantity> {
+        Ok(self.quantity(self.read(driver)?))
+    }
+
+    /// Writes @value, which must be of the variable's type.
+    pub fn write<D: Driver + ?Sized>(&self, driver: &D, value: Value) -> io::Result<()> {
+        if value.kind() != self.kind {
+            return Err(invalid_input(format!(
+                "variable {} is {}, can not write a {} value",
+                self.name,
+                self.kind,
+                value.kind()
+            )));
+        }
+        if let Value::Bool(bit) = value {
+            let mut spivalue = SPIValue {
+                i16uAddress: self.address,
+                i8uBit: self.bit,
+                i8uValue: bit as u8,
+            };
+            return driver.set_bit_value(&mut spivalue).map(|_| ());
+        }
+        driver.write(u64::from(self.address), &value.to_bytes())
+    }
+
+    /// Converts @physical to the raw value and writes it.
+    pub fn write_quantity<D: Driver + ?Sized>(&self, driver: &D, physical: f64) -> io::Result<()> {
+        let value = self.kind.from_f64(self.scaling.to_raw(physical))?;
+        self.write(driver, value)
+    }
+
+    fn quantity(&self, value: Value) -> Quantity {
+        Quantity {
+            value: self.scaling.to_physical(value.as_f64()),
+            unit: self.unit.clone(),
+        }
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryDriver;
+
+    #[test]
+    fn signed_scaled_variable() {
+        let driver = MemoryDriver::new();
+        driver.add_variable("RTDValue_1", 12, 0, 16);
+        driver.write(12, &(-123i16).to_le_bytes()).unwrap();
+
+        let temperature = Variable::resolve(&driver, "RTDValue_1")
+            .unwrap()
+            .with_type(VariableType::I16)
+            .unwrap()
+            .with_scaling(Scaling::new(1.0, 10.0, 0.0).unwrap())
+            .with_unit("°C");
+        assert_eq!(temperature.read(&driver).unwrap(), Value::I16(-123));
+        assert_eq!(
+            temperature.read_quantity(&driver).unwrap().to_string(),
+            "-12.3 °C"
+        );
+
+        temperature.write_quantity(&driver, 21.5).unwrap();
+        assert_eq!(driver.read(12, 2).unwrap(), 215i16.to_le_bytes().to_vec());
+        assert!(temperature.write_quantity(&driver, 4000.0).is_err());
+        assert!(temperature.clone().with_type(VariableType::F32).is_err());
+        assert!(Scaling::new(0.0, 10.0, 0.0).is_err());
+        assert!(Scaling::new(1.0, 0.0, 0.0).is_err());
+        assert!(Variable::new("beyond", u16::MAX, 8, 1).is_err());
+    }
+
+    #[test]
+    fn parse_and_decode_values() {
+        assert_eq!(VariableType::I8.parse_value("-5").unwrap(), Value::I8(-5));
+        assert_eq!(VariableType::I8.parse_value("+5").unwrap(), Value::I8(5));
+        for doubled in &["--5", "-+5", "+-5", "++5", "0x-5", "-0x+5"] {
+            assert!(
+                VariableType::I8.parse_value(doubled).is_err(),
+                "{}",
+                doubled
+            );
+        }
+        assert_eq!(
+            VariableType::U16.parse_value("0x1f").unwrap(),
+            Value::U16(31)
+        );
+        assert!(VariableType::U8.parse_value("256").is_err());
+        assert_eq!(
+            VariableType::F32.decode(&1.5f32.to_le_bytes()).unwrap(),
+            Value::F32(1.5)
+        );
+
+        let flag = Variable::new("I_10", 0, 9, 1).unwrap();
+        assert_eq!((flag.address, flag.bit), (1, 1));
+        assert_eq!(flag.decode(&[0, 0b10]).unwrap(), Value::Bool(true));
+    }
+}
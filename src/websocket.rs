@@ -0,0 +1,267 @@
+//! A WebSocket stream of variable changes, for browser dashboards.
+//!
+//! A client of the [`WebSocketServer`] subscribes to variables by sending a JSON text message,
+//! and may set how often it wants to be updated:
+//!
+//! ```json
+//! {"subscribe": ["I_1", "O_1"], "unsubscribe": ["O_2"], "interval_ms": 250}
+//! ```
+//!
+//! `"*"` subscribes to, or unsubscribes from, all variables of the configuration; unsubscribing
+//! is done first. Every client reads the process image on its own interval and compares it with
+//! its previous read; when subscribed variables changed, it is sent their new values:
+//!
+//! ```json
+//! {"time": 1589288403012, "values": {"I_1": true}}
+//! ```
+//!
+//! Newly subscribed variables are sent with the next update even if they did not change. The
+//! interval is never shorter than the server's minimum, which limits the rate of updates.
+//! Invalid messages are answered with `{"error": "..."}`.
+
+use crate::config::Config;
+use crate::driver::{Driver, PROCESS_IMAGE_SIZE};
+use crate::export::json_value;
+use crate::value::Variable;
+use serde_json::{json, Map, Value as Json};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tungstenite::{Message, WebSocket};
+
+// the longest a client may take to complete the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// WebSocketServer streams variable changes to its clients, see the module documentation.
+pub struct WebSocketServer<D: ?Sized> {
+    listener: TcpListener,
+    driver: Arc<D>,
+    variables: Arc<Vec<Variable>>,
+    min_interval: Duration,
+}
+
+impl<D: Driver + ?Sized + 'static> WebSocketServer<D> {
+    /// Listens on @addr for clients of the variables of @config in @driver.
+    pub fn bind<A: ToSocketAddrs>(addr: A, driver: Arc<D>, config: &Config) -> io::Result<Self> {
+        Ok(WebSocketServer {
+            listener: TcpListener::bind(addr)?,
+            driver,
+            variables: Arc::new(
+                config
+                    .entries()
+                    .map(|(_, entry)| entry.variable())
+                    .collect::<io::Result<_>>()?,
+            ),
+            min_interval: Duration::from_millis(100),
+        })
+    }
+
+    /// The shortest interval a client may ask for, 100 ms by default; it is also the
+    /// interval of clients that do not ask.
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts clients until the listener fails, serving each on its own thread.
+    pub fn run(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let mut client = Client {
+                driver: Arc::clone(&self.driver),
+                variables: Arc::clone(&self.variables),
+                subscribed: vec![false; self.variables.len()],
+                fresh: vec![false; self.variables.len()],
+                min_interval: self.min_interval,
+                interval: self.min_interval,
+                previous: None,
+            };
+            thread::spawn(move || client.serve(stream));
+        }
+        Ok(())
+    }
+}
+
+/// The state of one connection.
+struct Client<D: ?Sized> {
+    driver: Arc<D>,
+    variables: Arc<Vec<Variable>>,
+    subscribed: Vec<bool>,
+    /// Subscribed since the last update.
+    fresh: Vec<bool>,
+    min_interval: Duration,
+    interval: Duration,
+    previous: Option<Vec<u8>>,
+}
+
+impl<D: Driver + ?Sized> Client<D> {
+    fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut socket =
+            tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+        let mut next = Instant::now();
+        loop {
+            let now = Instant::now();
+            if now >= next {
+                if let Some(update) = self.update()? {
+                    send(&mut socket, &update)?;
+                }
+                next += self.interval;
+                if next < now {
+                    next = now + self.interval;
+                }
+            }
+            socket.get_ref().set_read_timeout(Some(
+                next.saturating_duration_since(Instant::now())
+                    .max(Duration::from_millis(1)),
+            ))?;
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    if let Err(err) = self.request(&text) {
+                        send(&mut socket, &json!({ "error": err.to_string() }))?;
+                    }
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => return Ok(()),
+                Err(e) => return Err(io::Error::other(e.to_string())),
+            }
+        }
+    }
+
+    /// Applies a subscription message.
+    fn request(&mut self, text: &str) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let json: Json = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        if let Some(interval) = json.get("interval_ms") {
+            let ms = interval
+                .as_u64()
+                .ok_or_else(|| invalid(format!("invalid interval {}", interval)))?;
+            self.interval = Duration::from_millis(ms).max(self.min_interval);
+        }
+        for (key, subscribe) in &[("unsubscribe", false), ("subscribe", true)] {
+            let names = match json.get(*key) {
+                Some(names) => names
+                    .as_array()
+                    .ok_or_else(|| invalid(format!("{} needs a list of names", key)))?,
+                None => continue,
+            };
+            for name in names {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| invalid(format!("invalid name {}", name)))?;
+                let found: Vec<_> = (0..self.variables.len())
+                    .filter(|&i| name == "*" || self.variables[i].name == name)
+                    .collect();
+                if found.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("variable {} not found", name),
+                    ));
+                }
+                for i in found {
+                    self.fresh[i] = *subscribe && !self.subscribed[i];
+                    self.subscribed[i] = *subscribe;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the process image and returns the changed or fresh subscribed variables.
+    fn update(&mut self) -> io::Result<Option<Json>> {
+        let image = self.driver.read(0, PROCESS_IMAGE_SIZE)?;
+        let mut values = Map::new();
+        for (i, variable) in self.variables.iter().enumerate() {
+            if !self.subscribed[i] {
+                continue;
+            }
+            let value = variable.decode(&image)?;
+            let changed = match &self.previous {
+                Some(previous) => variable.decode(previous)? != value,
+                None => true,
+            };
+            if changed || self.fresh[i] {
+                values.insert(variable.name.clone(), json_value(value));
+            }
+            self.fresh[i] = false;
+        }
+        self.previous = Some(image);
+        if values.is_empty() {
+            return Ok(None);
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Ok(Some(json!({ "time": time, "values": values })))
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, json: &Json) -> io::Result<()> {
+    socket
+        .send(Message::Text(json.to_string()))
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::CONFIG;
+    use crate::emulator::Emulator;
+    use crate::value::Value;
+
+    fn next(socket: &mut WebSocket<TcpStream>) -> Json {
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn stream_changes() {
+        let config = Config::parse(CONFIG).unwrap();
+        let emulator = Arc::new(Emulator::from_config(config.clone()).unwrap());
+        let server = WebSocketServer::bind("127.0.0.1:0", Arc::clone(&emulator), &config)
+            .unwrap()
+            .min_interval(Duration::from_millis(10));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let (mut socket, _) = tungstenite::client(format!("ws://{}/", addr), stream).unwrap();
+        let subscribe = r#"{"subscribe": ["I_1", "O_1"], "interval_ms": 20}"#;
+        socket.send(Message::Text(subscribe.to_owned())).unwrap();
+        assert_eq!(
+            next(&mut socket)["values"],
+            json!({"I_1": false, "O_1": false})
+        );
+
+        emulator.set("I_1", Value::Bool(true)).unwrap();
+        emulator.set("I_2", Value::Bool(true)).unwrap();
+        assert_eq!(next(&mut socket)["values"], json!({"I_1": true}));
+
+        let unknown = r#"{"subscribe": ["I_99"]}"#;
+        socket.send(Message::Text(unknown.to_owned())).unwrap();
+        assert!(next(&mut socket)["error"].is_string());
+
+        socket
+            .send(Message::Text(
+                r#"{"unsubscribe": ["*"], "subscribe": ["I_2"]}"#.to_owned(),
+            ))
+            .unwrap();
+        emulator.set("I_1", Value::Bool(false)).unwrap();
+        assert_eq!(next(&mut socket)["values"], json!({"I_2": true}));
+    }
+}